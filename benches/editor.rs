@@ -0,0 +1,83 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use eframe::egui;
+use minimalist_notes::editor::SimpleEditor;
+
+// Common editing operations on a big note. Run with `cargo bench`.
+
+// About 1 MB of text, with the word searched for only near the end
+fn big_note() -> String {
+    let mut text = String::new();
+    let mut line = 0;
+    while text.len() < 1024 * 1024 {
+        text.push_str(&format!("Line {} of a long note, with a few words on it to move around in\n", line));
+        line += 1;
+    }
+    text.push_str("the needle is here\n");
+    text
+}
+
+fn press(editor: &mut SimpleEditor, text: &mut String, key: egui::Key) {
+    editor.handle_key_press(key, text, &egui::Modifiers::NONE);
+}
+
+// An editor on the note with the cursor halfway through it
+fn setup(note: &str) -> (SimpleEditor, String) {
+    let mut editor = SimpleEditor::new();
+    editor.reset_history(note);
+    editor.move_to_line(note, note.lines().count() / 2);
+    (editor, note.to_string())
+}
+
+fn insert(c: &mut Criterion) {
+    let note = big_note();
+    c.bench_function("insert 100 characters", |b| {
+        b.iter_batched(
+            || setup(&note),
+            |(mut editor, mut text)| {
+                press(&mut editor, &mut text, egui::Key::I);
+                for c in "The quick brown fox jumps over the lazy dog. ".chars().cycle().take(100) {
+                    editor.handle_text_input(c, &mut text);
+                }
+                press(&mut editor, &mut text, egui::Key::Escape);
+                text
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn delete_line(c: &mut Criterion) {
+    let note = big_note();
+    c.bench_function("dd on a 1 MB note", |b| {
+        b.iter_batched(
+            || setup(&note),
+            |(mut editor, mut text)| {
+                press(&mut editor, &mut text, egui::Key::D);
+                press(&mut editor, &mut text, egui::Key::D);
+                text
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn search(c: &mut Criterion) {
+    let note = big_note();
+    c.bench_function("/needle on a 1 MB note", |b| {
+        b.iter_batched(
+            || setup(&note),
+            |(mut editor, mut text)| {
+                editor.handle_normal_text('/', &mut text);
+                for c in "needle".chars() {
+                    editor.handle_text_input(c, &mut text);
+                }
+                press(&mut editor, &mut text, egui::Key::Enter);
+                editor.cursor_position
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, insert, delete_line, search);
+criterion_main!(benches);
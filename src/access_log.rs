@@ -0,0 +1,183 @@
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// With `access_log = true` in config.toml, when each note is opened and
+// edited goes into .vimnote/access.jsonl, a line per event, and `:properties`
+// shows the open note's log by day: for "what was I working on Tuesday", or
+// a light audit trail. Edits are logged when they start and then at most
+// every EDIT_INTERVAL, not per keystroke. Entries older than KEEP_DAYS are
+// dropped at startup.
+
+pub const ACCESS_FILE: &str = "access.jsonl";
+const EDIT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const KEEP_DAYS: i64 = 90;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Opened,
+    Edited,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub note: String,
+    pub access: Access,
+    pub at: String, // Local time, "2024-05-12 09:30"
+}
+
+pub struct AccessLog {
+    path: PathBuf,
+    last_edit: HashMap<String, Instant>, // When an edit of each note was last logged
+}
+
+impl AccessLog {
+    // The vault's log, without the entries that are too old to keep
+    pub fn open(data_dir: &Path) -> Self {
+        let log = Self { path: data_dir.join(ACCESS_FILE), last_edit: HashMap::new() };
+        if let Err(err) = log.prune() {
+            println!("Could not prune the access log: {}", err);
+        }
+        log
+    }
+
+    pub fn opened(&mut self, note: &str) {
+        // Edits after opening again are a new session
+        self.last_edit.remove(note);
+        self.append(note, Access::Opened);
+    }
+
+    pub fn edited(&mut self, note: &str) {
+        if self.last_edit.get(note).is_some_and(|logged| logged.elapsed() < EDIT_INTERVAL) {
+            return;
+        }
+        self.last_edit.insert(note.to_string(), Instant::now());
+        self.append(note, Access::Edited);
+    }
+
+    fn append(&self, note: &str, access: Access) {
+        let entry = Entry { note: note.to_string(), access, at: Local::now().format(TIME_FORMAT).to_string() };
+        let written = serde_json::to_string(&entry)
+            .map_err(io::Error::other)
+            .and_then(|json| {
+                if let Some(dir) = self.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                writeln!(file, "{}", json)
+            });
+        if let Err(err) = written {
+            println!("Could not write to the access log: {}", err);
+        }
+    }
+
+    // The note's entries, oldest first
+    pub fn entries(&self, note: &str) -> Vec<Entry> {
+        read(&self.path).into_iter().filter(|entry| entry.note == note).collect()
+    }
+
+    // Keep the log pointing at a note that was renamed
+    pub fn rename_note(&mut self, old_name: &str, new_name: &str) -> io::Result<()> {
+        if let Some(logged) = self.last_edit.remove(old_name) {
+            self.last_edit.insert(new_name.to_string(), logged);
+        }
+        let mut entries = read(&self.path);
+        if !entries.iter().any(|entry| entry.note == old_name) {
+            return Ok(());
+        }
+        for entry in entries.iter_mut().filter(|entry| entry.note == old_name) {
+            entry.note = new_name.to_string();
+        }
+        write(&self.path, &entries)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let entries = read(&self.path);
+        let oldest = (Local::now() - chrono::Duration::days(KEEP_DAYS)).naive_local();
+        let kept: Vec<Entry> = entries
+            .iter()
+            .filter(|entry| NaiveDateTime::parse_from_str(&entry.at, TIME_FORMAT).map_or(true, |at| at >= oldest))
+            .cloned()
+            .collect();
+        if kept.len() == entries.len() {
+            return Ok(());
+        }
+        write(&self.path, &kept)
+    }
+}
+
+// Lines that can't be read, like one cut short by a crash, are skipped
+fn read(path: &Path) -> Vec<Entry> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn write(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut jsonl = String::new();
+    for entry in entries {
+        let json = serde_json::to_string(entry).map_err(io::Error::other)?;
+        jsonl.push_str(&json);
+        jsonl.push('\n');
+    }
+    fs::write(path, jsonl)
+}
+
+// Entries by day, the latest day first: ("Tue 2024-05-14", ["09:30 opened", "09:41 edited"])
+pub fn by_day(entries: &[Entry]) -> Vec<(String, Vec<String>)> {
+    let mut days: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries.iter().rev() {
+        let at = match NaiveDateTime::parse_from_str(&entry.at, TIME_FORMAT) {
+            Ok(at) => at,
+            Err(_) => continue,
+        };
+        let day = at.format("%a %Y-%m-%d").to_string();
+        let access = match entry.access {
+            Access::Opened => "opened",
+            Access::Edited => "edited",
+        };
+        let line = format!("{} {}", at.format("%H:%M"), access);
+        match days.last_mut() {
+            Some((last, lines)) if *last == day => lines.insert(0, line),
+            _ => days.push((day, vec![line])),
+        }
+    }
+    days
+}
+
+// What `:properties` shows about the open note, as it was when it ran
+pub struct NoteProperties {
+    pub note: String,
+    pub words: usize,
+    pub lines: usize,
+    pub bytes: usize,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+    pub access: Option<Vec<(String, Vec<String>)>>, // See by_day; None with the log off
+}
+
+impl NoteProperties {
+    pub fn collect(notes_dir: &Path, note: &str, content: &str, log: Option<&AccessLog>) -> Self {
+        let metadata = fs::metadata(notes_dir.join(note)).ok();
+        let format_time = |time: io::Result<std::time::SystemTime>| {
+            time.ok().map(|time| chrono::DateTime::<Local>::from(time).format(TIME_FORMAT).to_string())
+        };
+        Self {
+            note: note.to_string(),
+            words: content.split_whitespace().count(),
+            lines: content.lines().count(),
+            bytes: content.len(),
+            created: metadata.as_ref().and_then(|metadata| format_time(metadata.created())),
+            modified: metadata.as_ref().and_then(|metadata| format_time(metadata.modified())),
+            access: log.map(|log| by_day(&log.entries(note))),
+        }
+    }
+}
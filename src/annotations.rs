@@ -0,0 +1,104 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Highlight colors offered by `:highlight {color}`
+pub const HIGHLIGHT_COLORS: [&str; 5] = ["yellow", "green", "blue", "pink", "orange"];
+
+// A highlighted span of a read-only note, optionally with a comment. Kept in a
+// sidecar file so the note itself is never modified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub start: usize, // Byte offsets into the note
+    pub end: usize,
+    // The highlighted text, used to find the span again if the note changes
+    pub quote: String,
+    pub color: String,
+    pub comment: Option<String>,
+}
+
+impl Annotation {
+    pub fn new(text: &str, start: usize, end: usize, color: &str, comment: Option<String>) -> Self {
+        Self {
+            start,
+            end,
+            quote: text[start..end].to_string(),
+            color: color.to_string(),
+            comment,
+        }
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    pub fn background(&self, dark_mode: bool) -> egui::Color32 {
+        let (r, g, b) = match self.color.as_str() {
+            "green" => (120, 200, 120),
+            "blue" => (120, 170, 240),
+            "pink" => (240, 140, 200),
+            "orange" => (250, 170, 90),
+            _ => (250, 220, 80), // yellow
+        };
+        if dark_mode {
+            // Darker shades so light text stays readable
+            egui::Color32::from_rgb(r / 3, g / 3, b / 3)
+        } else {
+            egui::Color32::from_rgb(r, g, b)
+        }
+    }
+
+    // Make sure the span still covers its quote; if the note was edited
+    // elsewhere, move it to the nearest occurrence. False if the quote is gone.
+    fn reanchor(&mut self, text: &str) -> bool {
+        if text.get(self.start..self.end) == Some(self.quote.as_str()) {
+            return true;
+        }
+        match text
+            .match_indices(&self.quote)
+            .map(|(i, _)| i)
+            .min_by_key(|i| i.abs_diff(self.start))
+        {
+            Some(start) if !self.quote.is_empty() => {
+                self.start = start;
+                self.end = start + self.quote.len();
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+// Annotations for one note, in the order they were made
+pub fn load(path: &Path, text: &str) -> Vec<Annotation> {
+    let annotations: Vec<Annotation> = match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+            println!("Invalid annotations file {}: {}", path.display(), err);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    annotations
+        .into_iter()
+        .filter_map(|mut annotation| {
+            if annotation.reanchor(text) { Some(annotation) } else { None }
+        })
+        .collect()
+}
+
+pub fn save(path: &Path, annotations: &[Annotation]) -> io::Result<()> {
+    if annotations.is_empty() {
+        // Don't leave empty sidecars behind
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(annotations).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
@@ -1,12 +1,87 @@
 use eframe::egui;
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::modes::{AppMode, VimMode};
-use crate::editor::SimpleEditor;
+use crate::editor::{Change, MarkInfo, Marks, Register, RegisterKind, SimpleEditor};
 use crate::operations::VimOperation;
+use crate::note_options::NoteOptions;
+use crate::platform;
+use crate::links::{self, LinkIndex};
+use crate::large_file::{self, LargeFile};
+use crate::config::Config;
+use crate::templates::{self, Template, TemplatePicker, TemplateVars};
+use crate::importers::{email, ics};
+use crate::people;
+use crate::bookmarks::{self, Bookmark, Bookmarks};
+use crate::layouts::{self, Layout, Layouts};
+use crate::annotations::{self, Annotation, HIGHLIGHT_COLORS};
+use crate::local_api::{self, ApiEvent, LocalApi};
+use crate::feeds::{self, FeedEvent, FeedReader};
+use crate::attachments;
+use crate::blocks::{Blocks, LineKind};
+use crate::search::{self, SearchScreen};
+use crate::grep::{Grep, Hit};
+use crate::index::{self, IndexRebuild};
+use crate::site::{self, Rendering, SiteExport};
+use crate::startup::{NotesLoader, StartupProfile};
+use crate::tags::{self, TagStats};
+use crate::tasks::{self, SectionProgress};
+use crate::trash;
+use crate::conflicts::{self, Hunk, Merge, Pick};
+use crate::settings::{self, Settings};
+use crate::maintenance;
+use crate::backups;
+use crate::onboarding::{Onboarding, Setup};
+use crate::tutor::{self, Progress};
+use crate::feedback::{self, Feedback};
+use crate::pomodoro::{self, Pomodoro};
+use crate::journal::{self, Journal, Operation};
+use crate::file_names;
+use crate::vault::{self, VaultWatch};
+use crate::encoding::{self, Encoding};
+use crate::background::{Task, TaskId};
+use crate::recovery::{self, Recovery};
+use crate::access_log::{AccessLog, NoteProperties};
+use crate::projects::{self, Board};
+use crate::recurring::{self, Made};
+use crate::citations::{self, Bibliography};
+use crate::lock::{self, DialogResult, IdleTimer, LockScreen, PassphraseDialog};
+use crate::views::{self, GlobalMark, NoteView, ViewOptions};
+use crate::jobs::{self, Job, JobKind, JobResult, Jobs};
+
+// How long status line messages stay visible
+const STATUS_MESSAGE_SECS: u64 = 4;
+
+// App data kept inside the notes folder, e.g. undo files
+pub const DATA_DIR: &str = ".vimnote";
+
+// Where the global marks A-Z are kept between sessions, in DATA_DIR
+const GLOBAL_MARKS_FILE: &str = "marks.json";
+
+// Most @mention completions shown at once
+const MAX_COMPLETIONS: usize = 8;
+
+// The text box of a search hit expanded on the search screen
+const SEARCH_CONTEXT_ID: &str = "search_context";
+
+// Used by `:meetings` when there is no templates/meeting.md
+const DEFAULT_MEETING_TEMPLATE: &str = "# {{title}}
+
+Date: {{date}} {{time}}
+Attendees: {{attendees}}
+
+## Agenda
+
+## Notes
+
+## Action items
+";
 
 pub struct NotesApp {
     pub notes_dir: PathBuf,
@@ -20,7 +95,65 @@ pub struct NotesApp {
     pub dark_mode: bool,
     pub app_mode: AppMode,
     pub rename_buffer: String,
+    rename_collision: Option<(String, String)>, // A rename's target and the note that has it, while asking what to do
     pub just_entered_insert_mode: bool, // Track when we've just entered insert mode
+    pub note_options: NoteOptions, // Options from the current note's frontmatter/modeline
+    set_options: Vec<(String, Option<String>)>, // and from `:set` since it was opened
+    pub status_message: Option<(String, Instant)>, // Short-lived feedback shown in the status line
+    pub config: Config,
+    pub link_index: LinkIndex,
+    pub index_rebuild: Option<IndexRebuild>, // Running `:index rebuild`
+    pub site_export: Option<SiteExport>, // Running `:export site`
+    pub show_tasks: bool, // The :tasks window
+    pub template_picker: Option<TemplatePicker>,
+    pub search: Option<SearchScreen>, // The search screen, while it's open
+    pub projects: Option<Board>, // The :projects board, while it's open
+    pub due_today: Vec<String>, // Recurring notes for today, listed over the notes
+    pub grep: Option<Grep>, // ripgrep, when it backs the search
+    pub quickfix: Vec<Hit>, // The last search's hits, for :cnext and :cprev
+    pub quickfix_index: Option<usize>, // Hit last jumped to
+    pub people: Vec<String>, // Names with a page in the people folder, for @mention completion
+    pub bibliography: Bibliography, // `bibliography` of config.toml, for @citekey completion and exports
+    pub completion_index: usize, // Highlighted entry in the @mention completion popup
+    pub bookmarks: Bookmarks,
+    pub show_bookmarks: bool,
+    pub layouts: Layouts, // Saved with :layout save
+    pub register_list: Option<Vec<(char, Register)>>, // Shown by :registers, as they were when it ran
+    pub mark_list: Option<Vec<MarkInfo>>, // Shown by :marks, as they were when it ran
+    pub tag_stats: Option<TagStats>, // The :tags dashboard
+    properties: Option<NoteProperties>, // The :properties panel
+    access_log: Option<AccessLog>, // With `access_log` in config.toml
+    pub merge: Option<Merge>, // A sync conflict copy being merged with :merge
+    pub note_marks: HashMap<String, Marks>, // Marks of the notes that aren't open; the open one's are in the editor
+    pub global_marks: HashMap<char, String>, // Note each global mark A-Z is in
+    pub view_options: ViewOptions, // What's saved of each note's view
+    pub alternate_note: Option<String>, // The note open before this one, for Ctrl+^
+    pub annotations: Vec<Annotation>, // Highlights and comments on the current (read-only) note
+    pub large_file: Option<LargeFile>, // A note too big to edit, shown instead of the editor
+    pub note_changes: Receiver<Change>, // The open note's edits, opens and saves, from the editor
+    pub section_progress: Option<Vec<SectionProgress>>, // Task counts by heading, until the note changes
+    pub tutor: Option<Progress>, // Lessons done, while the tutor note is open
+    pub blocks: Blocks, // Which lines are headings, code and quotes, for coloring them
+    pub local_api: Option<LocalApi>,
+    pub feeds: Option<FeedReader>,
+    calendar_fetch: Option<Receiver<(String, io::Result<String>)>>, // `:meetings <url>` being downloaded
+    pub jobs: Jobs, // OCR etc. running on attachments
+    pub startup_pending: bool, // Before the first frame is done
+    pub loading: Option<NotesLoader>, // Scanning the notes folder at startup
+    pub startup_profile: Option<StartupProfile>, // With --profile-startup, until loading is done
+    pub onboarding: Option<Onboarding>, // The first-run setup, until it's finished
+    pub feedback: Feedback, // The flash from config.toml's [feedback], while it's shown
+    pub pomodoro: Option<Pomodoro>,
+    vault: VaultWatch, // Whether the notes folder is there, for a vault on a share or drive
+    note_encoding: Option<Encoding>, // What the open note was read as when it isn't UTF-8
+    pub cursor_view_offset: f32, // How far down the view the cursor was drawn last
+    pub journal: Journal, // Deletes, renames and tag merges that `:undo-vault` can take back
+    pub crash_recovery: Option<Recovery>, // Edits the last run didn't save before it crashed, until restored or discarded
+    pub offline: bool, // Everything that uses the network is off, see set_offline
+    lock_hash: Option<String>, // The vault's lock passphrase, hashed, once one is set
+    locked: Option<LockScreen>, // Shown instead of everything else while locked
+    idle_lock: IdleTimer,
+    passphrase_dialog: Option<PassphraseDialog>, // `:lock set`
 }
 
 impl NotesApp {
@@ -30,187 +163,3490 @@ impl NotesApp {
             fs::create_dir_all(&notes_dir).expect("Failed to create notes directory");
         }
 
-        let notes_files = Self::scan_notes_dir(&notes_dir);
+        let bookmarks = Bookmarks::load(&notes_dir.join(DATA_DIR).join("bookmarks.json"));
+        let layouts = Layouts::load(&notes_dir.join(DATA_DIR).join("layouts.json"));
+        let journal = Journal::load(&notes_dir.join(DATA_DIR).join(journal::JOURNAL_FILE));
+        let crash_recovery = recovery::load(&notes_dir.join(DATA_DIR));
+        let mut editor = SimpleEditor::new();
+        let note_changes = editor.changes.subscribe();
+        let config = Config::load();
+        let offline = config.offline;
+        let lock_hash = lock::load(&notes_dir.join(DATA_DIR));
+        let idle_lock = IdleTimer::new(config.lock_after_minutes);
+        let access_log = config.access_log.then(|| AccessLog::open(&notes_dir.join(DATA_DIR)));
+        // Global marks from earlier sessions go back into their notes' marks
+        let mut note_marks: HashMap<String, Marks> = HashMap::new();
+        let mut global_marks = HashMap::new();
+        for (name, mark) in views::load_global_marks(&notes_dir.join(DATA_DIR).join(GLOBAL_MARKS_FILE)) {
+            if Marks::is_global(name) {
+                note_marks.entry(mark.note.clone()).or_default().set(name, mark.pos);
+                global_marks.insert(name, mark.note);
+            }
+        }
+        let view_options = ViewOptions::parse(&config.view_options).unwrap_or_else(|err| {
+            println!("{} in config, keeping cursor and marks", err);
+            ViewOptions { cursor: true, marks: true }
+        });
         
-        // Initialize the app state
-        let mut app = Self {
+        // Initialize the app state; the notes are loaded after the first frame
+        Self {
             notes_dir,
-            notes_files,
+            notes_files: Vec::new(),
             selected_index: 0,
             current_note_content: String::new(),
             current_note_file: None,
-            editor: SimpleEditor::new(),
+            editor,
             last_save_time: Instant::now(),
             start_time: Instant::now(),
-            dark_mode: false,
+            dark_mode: config.dark_mode,
             app_mode: AppMode::List,
             rename_buffer: String::new(),
+            rename_collision: None,
             just_entered_insert_mode: false,
+            note_options: NoteOptions::default(),
+            set_options: Vec::new(),
+            status_message: None,
+            config,
+            link_index: LinkIndex::default(),
+            index_rebuild: None,
+            site_export: None,
+            show_tasks: false,
+            template_picker: None,
+            projects: None,
+            due_today: Vec::new(),
+            search: None,
+            grep: None,
+            quickfix: Vec::new(),
+            quickfix_index: None,
+            people: Vec::new(),
+            bibliography: Bibliography::default(),
+            completion_index: 0,
+            bookmarks,
+            show_bookmarks: false,
+            layouts,
+            register_list: None,
+            mark_list: None,
+            tag_stats: None,
+            properties: None,
+            access_log,
+            merge: None,
+            note_marks,
+            global_marks,
+            view_options,
+            alternate_note: None,
+            annotations: Vec::new(),
+            large_file: None,
+            note_changes,
+            section_progress: None,
+            tutor: None,
+            blocks: Blocks::new(),
+            local_api: None,
+            feeds: None,
+            calendar_fetch: None,
+            jobs: Jobs::default(),
+            startup_pending: true,
+            loading: None,
+            startup_profile: None,
+            onboarding: None,
+            feedback: Feedback::default(),
+            pomodoro: None,
+            vault: VaultWatch::new(),
+            note_encoding: None,
+            cursor_view_offset: 0.0,
+            journal,
+            crash_recovery,
+            offline,
+            lock_hash,
+            locked: None,
+            idle_lock,
+            passphrase_dialog: None,
+        }
+    }
+
+    // Start over in the notes folder that was picked, with the config that
+    // was just written, and open the tutorial note if one was made
+    fn finish_onboarding(&mut self, setup: Setup) {
+        let profile = self.startup_profile.take();
+        *self = Self::new(setup.notes_dir);
+        self.startup_profile = profile;
+        if let Some(note) = setup.tutorial {
+            self.open_note(&note);
+        }
+    }
+
+    // Right after the first frame: load the notes in the background
+    fn start_loading(&mut self, ctx: &egui::Context) {
+        self.startup_pending = false;
+        if let Some(profile) = &mut self.startup_profile {
+            profile.record("first frame");
+        }
+        let ripgrep_command = self.config.ripgrep.then(|| self.config.ripgrep_command.clone());
+        self.loading = Some(NotesLoader::start(&self.notes_dir, ripgrep_command, self.config.large_file_size(), ctx));
+    }
+
+    // Notes found so far go in the sidebar. Once they're all loaded: open the
+    // first one and start the local API and feeds.
+    fn handle_notes_loaded(&mut self, ctx: &egui::Context) {
+        let loader = match &mut self.loading {
+            Some(loader) => loader,
+            None => return,
         };
+        let loaded = loader.poll();
+        let found = loader.take_found();
+        self.add_notes_files(found);
+        let loaded = match loaded {
+            Some(loaded) => loaded,
+            None => return,
+        };
+        self.loading = None;
+        if let Some(profile) = &mut self.startup_profile {
+            profile.record("loading notes");
+            for (phase, took) in &loaded.timings {
+                profile.add(&format!("  {}", phase), *took);
+            }
+        }
+        // Notes created while loading are kept
+        self.add_notes_files(loaded.notes_files);
+        self.link_index = loaded.link_index;
+        self.people = loaded.people;
+        self.load_bibliography();
+        self.grep = loaded.grep;
         
-        // Load the first note if any notes exist
-        if !app.notes_files.is_empty() {
-            app.load_note_by_index(0);
+        // Load the first note if any notes exist, unless one was opened meanwhile
+        if self.current_note_file.is_none() && !self.notes_files.is_empty() {
+            self.load_note_by_index(0);
         }
         
-        app
+        self.start_local_api(ctx);
+        self.start_feeds(ctx);
+        if self.config.maintenance_limits().any() {
+            self.run_maintenance(false);
+        }
+        self.create_recurring_notes();
+        let conflict_count = self.notes_files.iter().filter(|file| conflicts::original_of(file).is_some()).count();
+        if conflict_count > 0 {
+            self.set_status(format!("{} sync conflict copies; open one or its note and :merge it", conflict_count));
+        }
+        if let Some((a, b)) = file_names::case_clashes(&self.notes_files).first() {
+            self.set_status(format!("{} and {} differ only in case; one would be lost on macOS or Windows", a, b));
+        }
+        if let Some(mut profile) = self.startup_profile.take() {
+            profile.record("first note and services");
+            profile.report();
+        }
+    }
+
+    // Add notes to the sorted list, keeping the same note selected
+    fn add_notes_files(&mut self, files: Vec<String>) {
+        if files.is_empty() {
+            return;
+        }
+        let selected = self.notes_files.get(self.selected_index).cloned();
+        self.notes_files.extend(files);
+        conflicts::sort_notes(&mut self.notes_files);
+        self.notes_files.dedup();
+        if let Some(index) = selected.and_then(|selected| self.notes_files.iter().position(|f| *f == selected)) {
+            self.selected_index = index;
+        }
     }
 
     pub fn scan_notes_dir(dir: &Path) -> Vec<String> {
+        Self::scan_notes_dir_with(dir, |_| {})
+    }
+
+    // Like `scan_notes_dir`, calling `found` with each note as soon as it's found
+    pub fn scan_notes_dir_with(dir: &Path, mut found: impl FnMut(&str)) -> Vec<String> {
         let start = Instant::now();
         let mut files = Vec::new();
 
+        Self::scan_folder(dir, "", &mut |file_name| {
+            found(&file_name);
+            files.push(file_name);
+        });
+
+        // Sort files alphabetically, with sync conflict copies under their note
+        conflicts::sort_notes(&mut files);
+        
+        println!("Scanned directory in {:?}", start.elapsed());
+        files
+    }
+
+    // Collect the notes in a folder and its subfolders (e.g. clippings/) as paths
+    // relative to the notes folder. Hidden folders like .vimnote are skipped.
+    // Symlinked folders and notes are followed, but no folder is scanned
+    // twice, so a link back up to a folder being scanned can't loop.
+    fn scan_folder(dir: &Path, prefix: &str, found: &mut dyn FnMut(String)) {
+        Self::scan_folder_once(dir, prefix, &mut HashSet::new(), found);
+    }
+
+    fn scan_folder_once(dir: &Path, prefix: &str, scanned: &mut HashSet<PathBuf>, found: &mut dyn FnMut(String)) {
+        // Where the folder really is, to tell whether it's been scanned already
+        if !fs::canonicalize(dir).is_ok_and(|real_dir| scanned.insert(real_dir)) {
+            return;
+        }
         if let Ok(entries) = fs::read_dir(dir) {
             for entry in entries.flatten() {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        if let Some(file_name) = entry.file_name().to_str() {
-                            if file_name.ends_with(".md") || file_name.ends_with(".txt") {
-                                files.push(file_name.to_string());
+                // A symlink counts as what it points at; broken ones are skipped
+                let file_type = match entry.file_type() {
+                    Ok(file_type) if file_type.is_symlink() => fs::metadata(entry.path()).map(|metadata| metadata.file_type()),
+                    file_type => file_type,
+                };
+                if let (Ok(file_type), Some(file_name)) = (file_type, entry.file_name().to_str()) {
+                    if file_type.is_dir() && !file_name.starts_with('.') {
+                        Self::scan_folder_once(&entry.path(), &format!("{}{}/", prefix, file_name), scanned, found);
+                    } else if file_type.is_file() && (file_name.ends_with(".md") || file_name.ends_with(".txt")) {
+                        found(format!("{}{}", prefix, file_name));
+                    }
+                }
+            }
+        }
+    }
+
+    // Rescan the notes folder and rebuild everything derived from note contents
+    pub fn refresh_notes(&mut self) {
+        self.notes_files = Self::scan_notes_dir(&self.notes_dir);
+        self.link_index = LinkIndex::build(&self.notes_dir, &self.notes_files, self.config.large_file_size());
+        self.people = people::load_people(&self.notes_dir);
+        self.load_bibliography();
+    }
+
+    // Read the `bibliography` file again; a reference manager may have
+    // exported it since
+    fn load_bibliography(&mut self) {
+        let path = match &self.config.bibliography {
+            Some(path) => self.notes_dir.join(path),
+            None => {
+                self.bibliography = Bibliography::default();
+                return;
+            },
+        };
+        match Bibliography::load(&path) {
+            Ok(bibliography) => self.bibliography = bibliography,
+            Err(err) => self.set_status(format!("Could not read the bibliography {}: {}", path.display(), err)),
+        }
+    }
+
+    // Open any note by its path relative to the notes folder, creating it if needed
+    pub fn open_note(&mut self, file_name: &str) {
+        // A link may have the name in another case than the note
+        let listed = match self.notes_files.iter().any(|note| note == file_name) {
+            true => None,
+            false => self.notes_files.iter().find(|note| links::same_name(note, file_name)).cloned(),
+        };
+        let file_name = listed.as_deref().unwrap_or(file_name);
+        let path = self.notes_dir.join(file_name);
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let title = links::note_stem(file_name.rsplit('/').next().unwrap_or(file_name));
+            let content = match self.config.folder_template(file_name) {
+                Some(template) => {
+                    let template = templates::template_or_default(&self.notes_dir, template, "# {{title}}\n");
+                    templates::render(&template, &TemplateVars::now(title))
+                },
+                None => format!("# {}\n", title),
+            };
+            if let Err(err) = fs::write(&path, content) {
+                self.set_status(format!("Could not create {}: {}", file_name, err));
+                return;
+            }
+            self.people = people::load_people(&self.notes_dir);
+        }
+        
+        self.save_current_note();
+        self.load_note(file_name);
+        if let Some(index) = self.notes_files.iter().position(|f| f == file_name) {
+            self.selected_index = index;
+        }
+        self.editor.vim_mode = VimMode::Normal;
+        self.app_mode = AppMode::Editor;
+    }
+
+    fn save_bookmarks(&mut self) {
+        let path = self.notes_dir.join(DATA_DIR).join("bookmarks.json");
+        if let Err(err) = self.bookmarks.save(&path) {
+            self.set_status(format!("Could not save bookmarks: {}", err));
+        }
+    }
+
+    // `:bookmark add|del|go "label"`
+    pub fn handle_bookmark_command(&mut self, args: &str) {
+        let (subcommand, label) = args.split_once(' ').unwrap_or((args, ""));
+        let label = bookmarks::parse_label(label);
+        match subcommand {
+            "add" => self.add_bookmark(label),
+            "del" | "delete" => {
+                if self.bookmarks.remove(&label) {
+                    self.save_bookmarks();
+                    self.set_status(format!("Removed bookmark {}", label));
+                } else {
+                    self.set_status(format!("No bookmark named {}", label));
+                }
+            },
+            "go" => match self.bookmarks.position(&label) {
+                Some(index) => self.jump_to_bookmark(index),
+                None => self.set_status(format!("No bookmark named {}", label)),
+            },
+            _ => self.set_status("Usage: :bookmark add|del|go \"label\""),
+        }
+    }
+
+    fn save_layouts(&mut self) {
+        let path = self.notes_dir.join(DATA_DIR).join("layouts.json");
+        if let Err(err) = self.layouts.save(&path) {
+            self.set_status(format!("Could not save layouts: {}", err));
+        }
+    }
+
+    // `:layout` lists the saved layouts, `:layout save|delete {name}` and `:layout {name}`
+    pub fn handle_layout_command(&mut self, args: &str) {
+        let (subcommand, name) = args.split_once(' ').map_or((args, ""), |(subcommand, name)| (subcommand, name.trim()));
+        match (subcommand, name) {
+            ("", _) if self.layouts.items.is_empty() => self.set_status("No layouts yet. Save one with :layout save {name}"),
+            ("", _) => {
+                let names: Vec<&str> = self.layouts.items.iter().map(|l| l.name.as_str()).collect();
+                self.set_status(format!("Layouts: {}", names.join(", ")));
+            },
+            ("save", name) if !name.is_empty() => {
+                let open = [self.show_bookmarks, self.register_list.is_some(), self.mark_list.is_some(), self.tag_stats.is_some()];
+                self.layouts.add(Layout {
+                    name: name.to_string(),
+                    panels: layouts::PANELS.iter().zip(open).filter(|(_, open)| *open).map(|(panel, _)| panel.to_string()).collect(),
+                    note: self.current_note_file.clone(),
+                    line: self.editor.cursor_line,
+                    dark_mode: self.dark_mode,
+                });
+                self.save_layouts();
+                self.set_status(format!("Saved layout {}", name));
+            },
+            ("del" | "delete", name) if !name.is_empty() => {
+                if self.layouts.remove(name) {
+                    self.save_layouts();
+                    self.set_status(format!("Removed layout {}", name));
+                } else {
+                    self.set_status(format!("No layout named {}", name));
+                }
+            },
+            ("save" | "del" | "delete", _) => self.set_status("Usage: :layout save|delete {name}"),
+            _ => match self.layouts.get(args.trim()) {
+                Some(layout) => self.apply_layout(layout.clone()),
+                None => self.set_status(format!("No layout named {}", args.trim())),
+            },
+        }
+    }
+
+    fn apply_layout(&mut self, layout: Layout) {
+        if let Some(note) = &layout.note {
+            if self.current_note_file.as_ref() != Some(note) {
+                if !self.notes_dir.join(note).exists() {
+                    self.set_status(format!("{} no longer exists", note));
+                    return;
+                }
+                self.open_note(note);
+            }
+            self.editor.move_to_line(&self.current_note_content, layout.line);
+            self.editor.scroll_to_cursor = true;
+            self.editor.vim_mode = VimMode::Normal;
+            self.app_mode = AppMode::Editor;
+        }
+        let open = |panel: &str| layout.panels.iter().any(|p| p == panel);
+        self.show_bookmarks = open("bookmarks");
+        self.register_list = open("registers").then(|| self.editor.registers.list(""));
+        self.mark_list = open("marks").then(|| self.list_marks(""));
+        self.tag_stats = None;
+        if open("tags") {
+            self.open_tags();
+        }
+        self.dark_mode = layout.dark_mode;
+        self.set_status(format!("Layout {}", layout.name));
+    }
+
+    // Bookmark the cursor line of the current note; without a label the note and line are used
+    fn add_bookmark(&mut self, label: String) {
+        let note = match &self.current_note_file {
+            Some(note) => note.clone(),
+            None => return,
+        };
+        let line = self.editor.cursor_line;
+        let label = if label.is_empty() {
+            format!("{}:{}", links::note_stem(&note), line + 1)
+        } else {
+            label
+        };
+        let line_text = self.current_note_content.lines().nth(line).unwrap_or("").to_string();
+        self.bookmarks.add(Bookmark {
+            label: label.clone(),
+            note,
+            line,
+            line_text,
+        });
+        self.save_bookmarks();
+        self.set_status(format!("Bookmarked {}", label));
+    }
+
+    pub fn jump_to_bookmark(&mut self, index: usize) {
+        let bookmark = match self.bookmarks.items.get(index) {
+            Some(bookmark) => bookmark.clone(),
+            None => return,
+        };
+        if self.current_note_file.as_deref() != Some(bookmark.note.as_str()) {
+            if !self.notes_dir.join(&bookmark.note).exists() {
+                self.set_status(format!("{} no longer exists", bookmark.note));
+                return;
+            }
+            self.open_note(&bookmark.note);
+        }
+        let line = bookmark.resolve_line(&self.current_note_content);
+        self.editor.move_to_line(&self.current_note_content, line);
+        self.editor.vim_mode = VimMode::Normal;
+        self.app_mode = AppMode::Editor;
+    }
+
+    fn show_bookmarks_panel(&mut self, ctx: &egui::Context) {
+        let mut jump_to = None;
+        let mut remove = None;
+        
+        egui::SidePanel::right("bookmarks_panel")
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                ui.heading("Bookmarks");
+                ui.separator();
+                
+                if self.bookmarks.items.is_empty() {
+                    ui.weak("No bookmarks yet. Add one with :bookmark add \"label\"");
+                }
+                
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for (index, bookmark) in self.bookmarks.items.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("✕").on_hover_text("Remove bookmark").clicked() {
+                                    remove = Some(index);
+                                }
+                                let response = ui.selectable_label(false, &bookmark.label)
+                                    .on_hover_text(&bookmark.line_text);
+                                if response.clicked() {
+                                    jump_to = Some(index);
+                                }
+                                ui.weak(format!("{}:{}", bookmark.note, bookmark.line + 1));
+                            });
+                        }
+                    });
+            });
+        
+        if let Some(index) = jump_to {
+            self.jump_to_bookmark(index);
+        }
+        if let Some(index) = remove {
+            self.bookmarks.items.remove(index);
+            self.save_bookmarks();
+        }
+    }
+
+    fn show_registers_panel(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+
+        if let Some(registers) = &self.register_list {
+            egui::SidePanel::right("registers_panel")
+                .resizable(true)
+                .default_width(260.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Registers");
+                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.separator();
+
+                    if registers.is_empty() {
+                        ui.weak("All registers are empty. Yank or delete something first");
+                    }
+
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            for (name, register) in registers {
+                                // Like vim's :registers, newlines show as ^J
+                                let preview: String = register.text.replace('\n', "^J").replace('\t', "^I").chars().take(200).collect();
+                                let kind = match register.kind {
+                                    RegisterKind::Linewise => "lines",
+                                    RegisterKind::Charwise => "text",
+                                };
+                                ui.horizontal(|ui| {
+                                    ui.monospace(format!("\"{}", name)).on_hover_text(kind);
+                                    ui.label(egui::RichText::new(preview).monospace()).on_hover_text(&register.text);
+                                });
+                            }
+                        });
+                });
+        }
+
+        if close {
+            self.register_list = None;
+        }
+    }
+
+    // The open note's marks and the global ones, for `:marks`
+    fn list_marks(&self, names: &str) -> Vec<MarkInfo> {
+        let mut marks: Vec<MarkInfo> = self.editor.marks
+            .list(names)
+            .into_iter()
+            .filter(|(name, _)| !Marks::is_global(*name))
+            .map(|(name, pos)| MarkInfo::new(name, None, pos, &self.current_note_content))
+            .collect();
+        for name in ('A'..='Z').filter(|name| names.is_empty() || names.contains(*name)) {
+            let note = match self.global_marks.get(&name) {
+                Some(note) => note,
+                None => continue,
+            };
+            let position = if self.current_note_file.as_ref() == Some(note) {
+                self.editor.marks.get(name).map(|pos| (pos, self.current_note_content.clone()))
+            } else {
+                let pos = self.note_marks.get(note).and_then(|marks| marks.get(name));
+                pos.zip(fs::read_to_string(self.notes_dir.join(note)).ok())
+            };
+            if let Some((pos, content)) = position {
+                marks.push(MarkInfo::new(name, Some(note.clone()), pos, &content));
+            }
+        }
+        marks
+    }
+
+    fn show_marks_panel(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+
+        if let Some(marks) = &self.mark_list {
+            egui::SidePanel::right("marks_panel")
+                .resizable(true)
+                .default_width(260.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Marks");
+                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.separator();
+
+                    if marks.is_empty() {
+                        ui.weak("No marks set. Set one with m{a-z}, or m{A-Z} for a global mark");
+                    }
+
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            for mark in marks {
+                                let location = match &mark.note {
+                                    Some(note) => format!("{} {}", note, mark.text),
+                                    None => mark.text.clone(),
+                                };
+                                ui.horizontal(|ui| {
+                                    ui.monospace(format!("{} {:>5} {:>3}", mark.name, mark.line + 1, mark.column));
+                                    ui.label(egui::RichText::new(location).monospace()).on_hover_text(&mark.text);
+                                });
                             }
+                        });
+                });
+        }
+
+        if close {
+            self.mark_list = None;
+        }
+    }
+
+    // The open note's details and access log for `:properties`
+    fn open_properties(&mut self) {
+        self.properties = self.current_note_file.as_ref().map(|note| {
+            NoteProperties::collect(&self.notes_dir, note, &self.current_note_content, self.access_log.as_ref())
+        });
+    }
+
+    fn show_properties_panel(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+
+        if let Some(properties) = &self.properties {
+            egui::SidePanel::right("properties_panel")
+                .resizable(true)
+                .default_width(260.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Properties");
+                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.separator();
+
+                    egui::Grid::new("properties_grid").num_columns(2).show(ui, |ui| {
+                        let rows = [
+                            ("Note", properties.note.clone()),
+                            ("Words", properties.words.to_string()),
+                            ("Lines", properties.lines.to_string()),
+                            ("Size", format!("{} bytes", properties.bytes)),
+                            ("Created", properties.created.clone().unwrap_or_else(|| "-".to_string())),
+                            ("Modified", properties.modified.clone().unwrap_or_else(|| "-".to_string())),
+                        ];
+                        for (name, value) in rows {
+                            ui.weak(name);
+                            ui.label(value);
+                            ui.end_row();
                         }
+                    });
+                    ui.separator();
+
+                    ui.strong("Opened and edited");
+                    match &properties.access {
+                        None => {
+                            ui.weak("Set access_log = true in config.toml to keep track of when notes are opened and edited");
+                        },
+                        Some(days) if days.is_empty() => {
+                            ui.weak("Not opened since the access log was turned on");
+                        },
+                        Some(days) => {
+                            egui::ScrollArea::vertical()
+                                .auto_shrink([false, false])
+                                .show(ui, |ui| {
+                                    for (day, entries) in days {
+                                        ui.label(day);
+                                        for entry in entries {
+                                            ui.monospace(format!("  {}", entry));
+                                        }
+                                    }
+                                });
+                        },
                     }
+                });
+        }
+
+        if close {
+            self.properties = None;
+        }
+    }
+
+    // Count the tags of every note for `:tags`
+    fn open_tags(&mut self) {
+        self.save_current_note();
+        let trash_prefix = format!("{}/{}/", DATA_DIR, trash::TRASH_DIR);
+        let notes = search::load_notes(&self.notes_dir, &self.notes_files, &trash_prefix, true, self.config.large_file_size());
+        self.tag_stats = Some(TagStats::collect(&notes, chrono::Local::now().date_naive()));
+    }
+
+    // Rename the `from` tag to `into` everywhere and count the tags again
+    fn merge_tags(&mut self, from: &str, into: &str) {
+        self.save_current_note();
+        match tags::merge(&self.notes_dir, &self.config, &self.notes_files, from, into) {
+            Ok(before) => {
+                let changed: Vec<String> = before.iter().map(|(note, _)| note.clone()).collect();
+                if !before.is_empty() {
+                    self.record_vault_operation(Operation::TagMerged { from: from.to_string(), into: into.to_string(), notes: before });
+                }
+                for note in &changed {
+                    self.link_index.update(note, &fs::read_to_string(self.notes_dir.join(note)).unwrap_or_default());
                 }
+                // The open note would be saved over the change otherwise
+                if let Some(current) = self.current_note_file.clone().filter(|current| changed.contains(current)) {
+                    self.load_note(&current);
+                }
+                self.set_status(format!("Merged #{} into #{} in {} notes", from, into, changed.len()));
+            },
+            Err(err) => self.set_status(format!("Could not merge #{} into #{}: {}", from, into, err)),
+        }
+        self.open_tags();
+    }
+
+    fn show_tags_panel(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+        let mut merge = None;
+
+        if let Some(stats) = &self.tag_stats {
+            egui::SidePanel::right("tags_panel")
+                .resizable(true)
+                .default_width(300.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Tags");
+                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.label(format!("{} tags in {} notes, {} untagged", stats.tags.len(), stats.note_count, stats.untagged));
+                    ui.separator();
+
+                    if !stats.duplicates.is_empty() {
+                        ui.strong("Possible duplicates");
+                        for (from, into) in &stats.duplicates {
+                            ui.horizontal(|ui| {
+                                ui.monospace(format!("#{} → #{}", from, into));
+                                if ui.small_button("Merge").on_hover_text(format!("Rename #{} to #{} in every note", from, into)).clicked() {
+                                    merge = Some((from.clone(), into.clone()));
+                                }
+                            });
+                        }
+                        ui.separator();
+                    }
+
+                    if stats.tags.is_empty() {
+                        ui.weak("No tags yet. Add #tags to notes, or a `tags:` frontmatter key");
+                    }
+
+                    let first = stats.months.first().map(|month| month.format("%b %Y").to_string()).unwrap_or_default();
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            for tag in &stats.tags {
+                                let history: Vec<String> = stats.months
+                                    .iter()
+                                    .zip(&tag.by_month)
+                                    .map(|(month, count)| format!("{}: {}", month.format("%b %Y"), count))
+                                    .collect();
+                                ui.horizontal(|ui| {
+                                    ui.monospace(format!("{:>4} {}", tag.notes, tags::sparkline(&tag.by_month)))
+                                        .on_hover_text(format!("New notes per month since {}\n{}", first, history.join("\n")));
+                                    ui.monospace(format!("#{}", tag.tag));
+                                });
+                            }
+                        });
+                });
+        }
+
+        if close {
+            self.tag_stats = None;
+        }
+        if let Some((from, into)) = merge {
+            self.merge_tags(&from, &into);
+        }
+    }
+
+    // `:merge`: compare the open note with its first sync conflict copy, or
+    // the open conflict copy with its note
+    fn open_merge(&mut self) {
+        let current = match &self.current_note_file {
+            Some(current) => current.clone(),
+            None => return,
+        };
+        let first_copy = conflicts::copies_of(&self.notes_files, &current).next().cloned();
+        let (note, copy) = match conflicts::original_of(&current) {
+            Some(original) => (original, current),
+            None => match first_copy {
+                Some(copy) => (current, copy),
+                None => {
+                    self.set_status("No sync conflict copies of this note");
+                    return;
+                },
+            },
+        };
+        self.save_current_note();
+        let ours = match fs::read_to_string(self.notes_dir.join(&note)) {
+            Ok(ours) => ours,
+            Err(err) => {
+                self.set_status(format!("Could not read {}: {}", note, err));
+                return;
+            },
+        };
+        let theirs = match fs::read_to_string(self.notes_dir.join(&copy)) {
+            Ok(theirs) => theirs,
+            Err(err) => {
+                self.set_status(format!("Could not read {}: {}", copy, err));
+                return;
+            },
+        };
+        self.merge = Some(Merge::new(note, copy, &ours, &theirs));
+    }
+
+    // Save the merged note and move the conflict copy to the trash
+    fn apply_merge(&mut self) {
+        let merge = match self.merge.take() {
+            Some(merge) => merge,
+            None => return,
+        };
+        // Edits made while the panel was open are saved first; if they, or
+        // anything else, changed either note, the merge is made again from
+        // what's on disk now so nothing is lost by writing it
+        self.save_current_note();
+        let read = |file: &str| fs::read_to_string(self.notes_dir.join(file)).map_err(|err| format!("Could not read {}: {}", file, err));
+        let (ours, theirs) = match read(&merge.note).and_then(|ours| Ok((ours, read(&merge.copy)?))) {
+            Ok(sides) => sides,
+            Err(err) => {
+                self.set_status(err);
+                self.merge = Some(merge);
+                return;
+            },
+        };
+        let current = self.current_note_file.clone();
+        let unsaved = [(&merge.note, &ours), (&merge.copy, &theirs)]
+            .iter()
+            .any(|(file, text)| current.as_ref() == Some(*file) && self.current_note_content != **text);
+        if unsaved {
+            self.set_status("The open note could not be saved, so the merge was not applied");
+            self.merge = Some(merge);
+            return;
+        }
+        if !merge.is_of(&ours, &theirs) {
+            self.set_status(format!("{} or {} changed since the merge was opened; look over it again", merge.note, merge.copy));
+            self.merge = Some(Merge::new(merge.note, merge.copy, &ours, &theirs));
+            return;
+        }
+        let merged = merge.merged();
+        if let Err(err) = fs::write(self.notes_dir.join(&merge.note), &merged) {
+            self.set_status(format!("Could not save {}: {}", merge.note, err));
+            return;
+        }
+        let trash_dir = self.notes_dir.join(DATA_DIR).join(trash::TRASH_DIR);
+        match trash::move_to_trash(&self.notes_dir, &trash_dir, &merge.copy) {
+            Err(err) => self.set_status(format!("Merged into {}, but could not remove {}: {}", merge.note, merge.copy, err)),
+            Ok(trashed) => {
+                self.record_vault_operation(Operation::Trashed { note: merge.copy.clone(), trashed_as: trash::relative_path(&trash_dir, &trashed) });
+                let _ = fs::remove_file(self.undo_file_path(&merge.copy));
+                let _ = fs::remove_file(self.view_file_path(&merge.copy));
+                self.notes_files.retain(|file| *file != merge.copy);
+                self.set_status(format!("Merged {} into {}", merge.copy, merge.note));
+            },
+        }
+        self.link_index.update(&merge.note, &merged);
+        // Nothing of either is unsaved; show the merged note
+        self.current_note_file = None;
+        self.load_note(&merge.note);
+        if let Some(index) = self.notes_files.iter().position(|file| *file == merge.note) {
+            self.selected_index = index;
+        }
+    }
+
+    fn show_merge_panel(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+        let mut apply = false;
+
+        if let Some(merge) = &mut self.merge {
+            egui::SidePanel::right("merge_panel")
+                .resizable(true)
+                .default_width(520.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Merge");
+                        if ui.small_button("✕").on_hover_text("Close without merging").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.label(format!("{} ← {}", merge.note, merge.copy));
+                    ui.label(format!("{} changed parts; pick which version of each to keep", merge.changed_count()));
+                    ui.horizontal(|ui| {
+                        if ui.button("Merge").on_hover_text("Save the note and move the conflict copy to the trash").clicked() {
+                            apply = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            close = true;
+                        }
+                    });
+                    ui.separator();
+
+                    let ours_color = egui::Color32::from_rgb(200, 80, 80);
+                    let theirs_color = egui::Color32::from_rgb(70, 160, 90);
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            for (index, hunk) in merge.hunks.iter().enumerate() {
+                                match hunk {
+                                    Hunk::Same(lines) if lines.len() > 6 => {
+                                        for line in &lines[..2] {
+                                            ui.monospace(format!("  {}", line));
+                                        }
+                                        ui.weak(format!("  … {} lines the same", lines.len() - 4));
+                                        for line in &lines[lines.len() - 2..] {
+                                            ui.monospace(format!("  {}", line));
+                                        }
+                                    },
+                                    Hunk::Same(lines) => {
+                                        for line in lines {
+                                            ui.monospace(format!("  {}", line));
+                                        }
+                                    },
+                                    Hunk::Changed { ours, theirs } => {
+                                        ui.separator();
+                                        ui.horizontal(|ui| {
+                                            let pick = &mut merge.picks[index];
+                                            ui.radio_value(pick, Pick::Ours, "This note");
+                                            ui.radio_value(pick, Pick::Theirs, "Conflict copy");
+                                            ui.radio_value(pick, Pick::Both, "Both");
+                                        });
+                                        for line in ours {
+                                            ui.label(egui::RichText::new(format!("- {}", line)).monospace().color(ours_color));
+                                        }
+                                        for line in theirs {
+                                            ui.label(egui::RichText::new(format!("+ {}", line)).monospace().color(theirs_color));
+                                        }
+                                        ui.separator();
+                                    },
+                                }
+                            }
+                        });
+                });
+        }
+
+        if close {
+            self.merge = None;
+        }
+        if apply {
+            self.apply_merge();
+        }
+    }
+
+    // What the @mention or @citekey before the cursor can be completed to:
+    // where its name starts, and each name with its label in the popup.
+    // People come first, then the bibliography's entries.
+    fn completions(&self, text: &str) -> Option<(usize, Vec<(String, String)>)> {
+        let mut start = None;
+        let mut completions = Vec::new();
+        if let Some((name_start, prefix)) = people::mention_prefix(text, self.editor.cursor_position) {
+            start = Some(name_start);
+            completions.extend(people::matching_people(&self.people, prefix).into_iter().map(|name| (name.clone(), format!("@{}", name))));
+        }
+        if let Some((key_start, prefix)) = citations::key_prefix(text, self.editor.cursor_position) {
+            if start.is_none() || start == Some(key_start) {
+                start = Some(key_start);
+                completions.extend(
+                    self.bibliography
+                        .matching(prefix)
+                        .into_iter()
+                        .map(|entry| (entry.key.clone(), format!("@{}  {}", entry.key, entry.label()))),
+                );
+            }
+        }
+        completions.truncate(MAX_COMPLETIONS);
+        Some((start?, completions)).filter(|(_, completions)| !completions.is_empty())
+    }
+
+    // Tab accepts and Ctrl+N/Ctrl+P cycle the @mention completion.
+    // Returns true if the key was used by the completion.
+    fn handle_completion_key(&mut self, key: egui::Key, modifiers: &egui::Modifiers, text: &mut String) -> bool {
+        let (start, matches) = match self.completions(text) {
+            Some(completions) => completions,
+            None => return false,
+        };
+        
+        match key {
+            egui::Key::Tab => {
+                let (name, _) = &matches[self.completion_index % matches.len()];
+                self.editor.complete_before_cursor(text, start, name);
+                self.completion_index = 0;
+                true
+            },
+            egui::Key::N if modifiers.ctrl => {
+                self.completion_index = (self.completion_index + 1) % matches.len();
+                true
+            },
+            egui::Key::P if modifiers.ctrl => {
+                self.completion_index = (self.completion_index + matches.len() - 1) % matches.len();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Keep the open note's marks and bring back those of the note being opened
+    fn switch_marks(&mut self, file_name: &str) {
+        if let Some(current) = &self.current_note_file {
+            self.note_marks.insert(current.clone(), std::mem::take(&mut self.editor.marks));
+        }
+        self.editor.marks = self.note_marks.remove(file_name).unwrap_or_default();
+    }
+
+    // The editor has set a global mark in the open note; no other note has it now
+    fn set_global_mark(&mut self, name: char) {
+        let file_name = match &self.current_note_file {
+            Some(file_name) => file_name.clone(),
+            None => return,
+        };
+        if let Some(previous) = self.global_marks.insert(name, file_name.clone()) {
+            if previous != file_name {
+                if let Some(marks) = self.note_marks.get_mut(&previous) {
+                    marks.remove(name);
+                }
+            }
+        }
+        self.save_global_marks();
+    }
+
+    // Write where every global mark is now, so they're there in the next session too
+    fn save_global_marks(&self) {
+        let marks = self.global_marks
+            .iter()
+            .filter_map(|(&name, note)| {
+                let pos = if self.current_note_file.as_ref() == Some(note) {
+                    self.editor.marks.get(name)
+                } else {
+                    self.note_marks.get(note).and_then(|marks| marks.get(name))
+                };
+                pos.map(|pos| (name, GlobalMark { note: note.clone(), pos }))
+            })
+            .collect();
+        let path = self.notes_dir.join(DATA_DIR).join(GLOBAL_MARKS_FILE);
+        if let Err(err) = views::save_global_marks(&path, &marks) {
+            println!("Could not write global marks {}: {}", path.display(), err);
+        }
+    }
+
+    // Ctrl+^ / `:e #`: switch to the note that was open before this one, which
+    // makes this one the alternate. False if there's none.
+    fn open_alternate_note(&mut self) -> bool {
+        let file_name = match self.alternate_note.clone() {
+            Some(file_name) if self.notes_dir.join(&file_name).exists() => file_name,
+            Some(file_name) => {
+                self.set_status(format!("{} no longer exists", file_name));
+                return false;
+            },
+            None => {
+                self.set_status("No alternate note");
+                return false;
+            },
+        };
+        self.save_current_note();
+        self.load_note(&file_name);
+        true
+    }
+
+    // Open the note a global mark was set in and jump to it
+    fn jump_to_global_mark(&mut self, name: char, exact: bool) {
+        let file_name = match self.global_marks.get(&name) {
+            Some(file_name) => file_name.clone(),
+            None => {
+                self.set_status(format!("Mark {} not set", name));
+                return;
+            }
+        };
+        if !self.notes_dir.join(&file_name).exists() {
+            self.set_status(format!("{} no longer exists", file_name));
+            return;
+        }
+        if self.current_note_file.as_deref() != Some(file_name.as_str()) {
+            self.open_note(&file_name);
+        }
+        if let Some(pos) = self.editor.marks.get(name) {
+            self.editor.jump_to(&self.current_note_content, pos, exact);
+        }
+    }
+
+    pub fn load_note(&mut self, file_name: &str) {
+        let previous = self.current_note_file.clone().or_else(|| self.large_file.as_ref().map(|file| file.file_name.clone()));
+        // Not a note that was just deleted
+        if previous.as_deref() != Some(file_name) {
+            self.set_options.clear();
+        }
+        if let Some(previous) = previous.filter(|previous| previous != file_name && self.notes_dir.join(previous).exists()) {
+            self.alternate_note = Some(previous);
+        }
+        if large_file::is_large(&self.notes_dir.join(file_name), self.config.large_file_size()) {
+            self.open_large_file(file_name);
+        } else {
+            self.load_note_fully(file_name);
+        }
+    }
+
+    // Load a note into the editor, however big it is
+    pub fn load_note_fully(&mut self, file_name: &str) {
+        let start = Instant::now();
+        self.large_file = None;
+        self.switch_marks(file_name);
+        let file_path = self.notes_dir.join(file_name);
+        
+        self.note_encoding = None;
+        match fs::read(&file_path) {
+            Ok(bytes) => {
+                self.current_note_content = match String::from_utf8(bytes) {
+                    Ok(content) => content,
+                    Err(err) => {
+                        // Read-only until it's converted, see apply_note_options
+                        let (content, encoding) = encoding::decode(err.as_bytes());
+                        self.note_encoding = Some(encoding);
+                        content
+                    },
+                };
+                self.current_note_file = Some(file_name.to_string());
+                self.editor.cursor_position = 0;
+                self.editor.update_cursor_line_column(&self.current_note_content);
+            },
+            Err(_) => {
+                self.current_note_content = String::new();
+                self.current_note_file = Some(file_name.to_string());
+                self.editor.cursor_position = 0;
+                self.editor.update_cursor_line_column(&self.current_note_content);
+            }
+        }
+        
+        let undo_file = self.undo_file_path(file_name);
+        self.editor.restore_history(&undo_file, &self.current_note_content);
+        self.restore_view(file_name);
+        self.annotations = annotations::load(&self.annotations_file_path(file_name), &self.current_note_content);
+        self.apply_note_options();
+        self.editor.changes.publish(Change::Opened { note: file_name.to_string() });
+        println!("Loaded note in {:?}", start.elapsed());
+    }
+
+    // Show a note that's too big to edit in the large-file viewer. No note is
+    // open in the editor meanwhile, so nothing gets saved over it.
+    fn open_large_file(&mut self, file_name: &str) {
+        match LargeFile::open(&self.notes_dir, file_name) {
+            Ok(file) => {
+                if let Some(current) = self.current_note_file.take() {
+                    self.note_marks.insert(current, std::mem::take(&mut self.editor.marks));
+                }
+                self.note_encoding = None;
+                self.current_note_content.clear();
+                self.editor.reset_history(&self.current_note_content);
+                self.editor.cursor_position = 0;
+                self.editor.update_cursor_line_column(&self.current_note_content);
+                self.annotations.clear();
+                self.set_status(format!("{} is {}, showing it read-only a piece at a time", file_name, large_file::format_size(file.size)));
+                self.large_file = Some(file);
+            },
+            Err(err) => self.set_status(format!("Could not open {}: {}", file_name, err)),
+        }
+    }
+
+    // The large-file viewer, in place of the editor
+    fn show_large_file(&mut self, ui: &mut egui::Ui) {
+        let file = match &mut self.large_file {
+            Some(file) => file,
+            None => return,
+        };
+        let file_name = file.file_name.clone();
+        let mut open_fully = false;
+        let mut moved = Ok(());
+        ui.horizontal(|ui| {
+            ui.heading(&file.file_name);
+            ui.label(format!(" - read-only, {}", file.position_text()));
+            if ui.add_enabled(!file.at_start(), egui::Button::new("⏮")).on_hover_text("Start (Home)").clicked() {
+                moved = file.first_part();
+            }
+            if ui.add_enabled(!file.at_start(), egui::Button::new("◀")).on_hover_text("Previous part (Page Up)").clicked() {
+                moved = file.previous_part();
+            }
+            if ui.add_enabled(!file.at_end(), egui::Button::new("▶")).on_hover_text("Next part (Page Down)").clicked() {
+                moved = file.next_part();
+            }
+            if ui.add_enabled(!file.at_end(), egui::Button::new("⏭")).on_hover_text("End (End)").clicked() {
+                moved = file.last_part();
+            }
+            if ui.button("Open fully").on_hover_text("Load all of it into the editor; may be slow").clicked() {
+                open_fully = true;
+            }
+        });
+        ui.separator();
+
+        if self.app_mode == AppMode::Editor {
+            ui.input(|i| {
+                if i.key_pressed(egui::Key::PageDown) {
+                    moved = file.next_part();
+                } else if i.key_pressed(egui::Key::PageUp) {
+                    moved = file.previous_part();
+                } else if i.key_pressed(egui::Key::Home) {
+                    moved = file.first_part();
+                } else if i.key_pressed(egui::Key::End) {
+                    moved = file.last_part();
+                }
+            });
+        }
+
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .id_source(("large_file", file.offset))
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(egui::RichText::new(&file.chunk).monospace()).wrap(false));
+            });
+
+        if let Err(err) = moved {
+            self.set_status(format!("Could not read {}: {}", file_name, err));
+        }
+        if open_fully {
+            self.load_note_fully(&file_name);
+        }
+    }
+
+    pub fn save_current_note(&mut self) {
+        // The banner says edits wait until the notes folder is back
+        if !self.vault.is_available() {
+            return;
+        }
+        if let Some(file_name) = self.current_note_file.clone() {
+            let start = Instant::now();
+            
+            // Read-only notes are never written; their annotations live in a sidecar
+            if !self.note_options.readonly {
+                if self.config.backlinks {
+                    self.refresh_backlinks_section(&file_name);
+                }
+                
+                if self.config.backups {
+                    let backups_dir = self.notes_dir.join(DATA_DIR).join(backups::BACKUPS_DIR);
+                    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+                    if let Err(err) = backups::back_up(&self.notes_dir, &backups_dir, &file_name, &today) {
+                        println!("Could not back up {}: {}", file_name, err);
+                    }
+                }
+                
+                let file_path = self.notes_dir.join(&file_name);
+                
+                // Autosave tries again after the usual wait when it fails
+                self.last_save_time = Instant::now();
+                match encoding::write_note(&file_path, &self.current_note_content, self.note_encoding) {
+                    Ok(()) => {
+                        self.editor.changes.publish(Change::Saved { note: file_name.clone() });
+                        println!("Saved note in {:?}", start.elapsed());
+                    },
+                    Err(err) => {
+                        self.set_status(format!("Could not save {}: {}", file_name, err));
+                        self.editor.changes.publish(Change::SaveFailed { note: file_name.clone() });
+                        // Maybe because the whole folder is gone
+                        self.check_vault(true);
+                    },
+                }
+            }
+            
+            let undo_file = self.undo_file_path(&file_name);
+            if let Err(err) = self.editor.save_history(&undo_file, &self.current_note_content) {
+                println!("Could not write undo file {}: {}", undo_file.display(), err);
+            }
+            self.save_view(&file_name);
+            // They move along with edits
+            if self.global_marks.values().any(|note| *note == file_name) {
+                self.save_global_marks();
+            }
+            
+            // Pick up any edits to the frontmatter or modeline
+            self.apply_note_options();
+        }
+    }
+
+    // Sidecar file that keeps a note's undo history between sessions
+    fn undo_file_path(&self, file_name: &str) -> PathBuf {
+        self.notes_dir.join(DATA_DIR).join("undo").join(format!("{}.undo", file_name))
+    }
+
+    // Sidecar file with where the cursor was in a note and the marks set in it
+    fn view_file_path(&self, file_name: &str) -> PathBuf {
+        self.notes_dir.join(DATA_DIR).join("views").join(format!("{}.json", file_name))
+    }
+
+    // Put the cursor and marks back where they were when the note was last open.
+    // Marks set since the app started are newer than the saved ones.
+    fn restore_view(&mut self, file_name: &str) {
+        if !self.view_options.any() {
+            return;
+        }
+        let view = NoteView::load(&self.view_file_path(file_name));
+        let text = &self.current_note_content;
+        // The note may have been changed by something else since
+        let valid = |pos: usize| pos <= text.len() && text.is_char_boundary(pos);
+        if self.view_options.marks {
+            for (name, pos) in view.marks {
+                if name.is_ascii_lowercase() && valid(pos) && self.editor.marks.get(name).is_none() {
+                    self.editor.marks.set(name, pos);
+                }
+            }
+        }
+        if let Some(pos) = view.cursor.filter(|&pos| self.view_options.cursor && valid(pos)) {
+            self.editor.cursor_position = pos;
+            self.editor.update_cursor_line_column(text);
+            self.editor.desired_column = self.editor.cursor_column;
+            self.editor.scroll_to_cursor = true;
+        }
+    }
+
+    fn save_view(&self, file_name: &str) {
+        if !self.view_options.any() {
+            return;
+        }
+        let view = NoteView {
+            cursor: self.view_options.cursor.then_some(self.editor.cursor_position),
+            marks: if self.view_options.marks {
+                self.editor.marks.list("").into_iter().filter(|(name, _)| name.is_ascii_lowercase()).collect()
+            } else {
+                Vec::new()
+            },
+        };
+        let view_file = self.view_file_path(file_name);
+        if let Err(err) = view.save(&view_file) {
+            println!("Could not write view file {}: {}", view_file.display(), err);
+        }
+    }
+
+    // Sidecar file with the highlights and comments made on a read-only note
+    fn annotations_file_path(&self, file_name: &str) -> PathBuf {
+        self.notes_dir.join(DATA_DIR).join("annotations").join(format!("{}.json", file_name))
+    }
+
+    // `:'<,'>highlight {color}` / `:'<,'>annotate {comment}` on a selection of a read-only note
+    pub fn add_annotation(&mut self, start: usize, end: usize, color: &str, comment: Option<String>) {
+        if !self.note_options.readonly {
+            self.set_status("Annotations are for read-only notes (add `readonly: true` to the frontmatter)");
+            return;
+        }
+        if !HIGHLIGHT_COLORS.contains(&color) {
+            self.set_status(format!("Unknown color {}; use one of {}", color, HIGHLIGHT_COLORS.join(", ")));
+            return;
+        }
+        let text = &self.current_note_content;
+        if start >= end || end > text.len() || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+            return;
+        }
+        
+        let annotation = Annotation::new(text, start, end, color, comment);
+        self.annotations.push(annotation);
+        self.save_annotations();
+    }
+
+    // `:unannotate` removes the annotations under the cursor
+    pub fn remove_annotations_at(&mut self, pos: usize) {
+        let count = self.annotations.len();
+        self.annotations.retain(|a| !a.contains(pos));
+        if self.annotations.len() == count {
+            self.set_status("No annotation under the cursor");
+        } else {
+            self.save_annotations();
+        }
+    }
+
+    fn save_annotations(&mut self) {
+        if let Some(file_name) = self.current_note_file.clone() {
+            if let Err(err) = annotations::save(&self.annotations_file_path(&file_name), &self.annotations) {
+                self.set_status(format!("Could not save annotations: {}", err));
+            }
+        }
+    }
+
+    // `:set {option}...` changes the current note's options until they are read from the note again
+    pub fn set_options(&mut self, args: &str, ctx: &egui::Context) {
+        for setting in args.split_whitespace() {
+            if setting == "offline" || setting == "nooffline" {
+                self.set_offline(setting == "offline", ctx);
+                continue;
+            }
+            // Not a note option: it's the same for every note
+            if let Some(value) = setting.strip_prefix("viewoptions=").or_else(|| setting.strip_prefix("vop=")) {
+                match ViewOptions::parse(value) {
+                    Ok(options) => self.view_options = options,
+                    Err(err) => self.set_status(err),
+                }
+                continue;
+            }
+            let (name, value) = match setting.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (setting, None),
+            };
+            if self.note_options.set_option(name, value) {
+                if let (Some(encoding), false) = (self.note_encoding, self.note_options.readonly) {
+                    self.set_status(format!("The note was read as {}; :convert-utf8 before editing it", encoding.name()));
+                }
+                // Kept over what's read from the note again when it's saved
+                self.set_options.push((name.to_string(), value.map(str::to_string)));
+            } else {
+                self.set_status(format!("Unknown option: {}", setting));
+            }
+        }
+        self.push_note_options();
+    }
+
+    // Keep what's derived from the open note up to date with its changes:
+    // task progress is counted again after edits, the link index after saves
+    fn handle_note_changes(&mut self) {
+        let changes: Vec<Change> = self.note_changes.try_iter().collect();
+        let (mut edited, mut opened) = (false, false);
+        let mut unsaved = None; // Whether the open note has edits that aren't saved, if that changed
+        for change in changes {
+            match change {
+                Change::Edited { start, old_end, new_end } => {
+                    self.section_progress = None;
+                    self.blocks.edited(start, old_end, new_end);
+                    edited = true;
+                    unsaved = Some(true);
+                    if let (Some(log), Some(note)) = (&mut self.access_log, &self.current_note_file) {
+                        log.edited(note);
+                    }
+                },
+                Change::Opened { note } => {
+                    self.section_progress = None;
+                    self.blocks.reset();
+                    opened = true;
+                    unsaved = Some(false);
+                    if let Some(log) = &mut self.access_log {
+                        log.opened(&note);
+                    }
+                },
+                Change::Saved { note } => {
+                    if self.current_note_file.as_deref() == Some(note.as_str()) {
+                        unsaved = Some(false);
+                    }
+                    let content = if self.current_note_file.as_deref() == Some(note.as_str()) {
+                        self.current_note_content.clone()
+                    } else {
+                        fs::read_to_string(self.notes_dir.join(&note)).unwrap_or_default()
+                    };
+                    let linked_before = self.link_index.links(&note).to_vec();
+                    self.link_index.update(&note, &content);
+                    if self.config.backlinks {
+                        self.refresh_linked_notes(&note, &linked_before);
+                    }
+                },
+                Change::SaveFailed { .. } => {
+                    self.feedback.notify(&self.config.feedback, feedback::Event::SaveFailed);
+                },
+                Change::ModeChanged { from, to } => {
+                    if (from == VimMode::Insert) != (to == VimMode::Insert) {
+                        self.feedback.notify(&self.config.feedback, feedback::Event::InsertMode);
+                    }
+                },
+            }
+        }
+        if edited || opened {
+            self.check_tutor(opened);
+        }
+        if opened && self.properties.is_some() {
+            self.open_properties();
+        }
+        // For the panic hook to write out, see recovery
+        match (unsaved, &self.current_note_file) {
+            (Some(true), Some(file_name)) => recovery::track(&self.notes_dir.join(DATA_DIR), file_name, &self.current_note_content),
+            (Some(_), _) => recovery::clear(),
+            (None, _) => {},
+        }
+    }
+
+    // Say when a lesson of the tutor note is done, or how far it's got when
+    // it's opened
+    fn check_tutor(&mut self, opened: bool) {
+        if self.current_note_file.as_deref() != Some(tutor::TUTOR_NOTE) {
+            self.tutor = None;
+            return;
+        }
+        match &mut self.tutor {
+            Some(progress) if !opened => {
+                let finished = progress.update(&self.current_note_content);
+                if let Some(&lesson) = finished.last() {
+                    let message = if progress.done_count() == progress.total() {
+                        format!("All {} lessons done. That's the basics!", progress.total())
+                    } else {
+                        format!("Lesson {} done: {} ({}/{})", lesson + 1, progress.title(lesson), progress.done_count(), progress.total())
+                    };
+                    self.set_status(message);
+                }
+            },
+            _ => {
+                let progress = Progress::new(&self.current_note_content);
+                self.set_status(format!("Vim tutor: {} of {} lessons done", progress.done_count(), progress.total()));
+                self.tutor = Some(progress);
+            },
+        }
+    }
+
+    fn record_vault_operation(&mut self, operation: Operation) {
+        self.journal.push(operation);
+        self.save_journal();
+    }
+
+    fn save_journal(&mut self) {
+        let path = self.notes_dir.join(DATA_DIR).join(journal::JOURNAL_FILE);
+        if let Err(err) = self.journal.save(&path) {
+            self.set_status(format!("Could not save the vault journal: {}", err));
+        }
+    }
+
+    // `:undo-vault`: take back the last delete, rename, move or tag merge.
+    // It's dropped from the journal even if it can't be undone any more.
+    fn undo_vault(&mut self) {
+        let operation = match self.journal.operations.pop() {
+            Some(operation) => operation,
+            None => {
+                self.set_status("Nothing to undo in the vault");
+                return;
+            },
+        };
+        self.save_journal();
+        self.save_current_note();
+        let trash_dir = self.notes_dir.join(DATA_DIR).join(trash::TRASH_DIR);
+        let result = match &operation {
+            Operation::Trashed { note, trashed_as } => trash::restore(&self.notes_dir, &trash_dir, trashed_as, note).map(|()| {
+                let content = fs::read_to_string(self.notes_dir.join(note)).unwrap_or_default();
+                self.link_index.update(note, &content);
+                self.add_notes_files(vec![note.clone()]);
+                format!("Restored {} from the trash", note)
+            }),
+            Operation::Renamed { from, to } => {
+                if self.rename_note(to, from) {
+                    Ok(format!("Renamed {} back to {}", to, from))
+                } else {
+                    Err(io::Error::other(format!("{} is gone or {} is taken", to, from)))
+                }
+            },
+            Operation::TagMerged { from, into, notes } => tags::unmerge(&self.notes_dir, notes, from, into).map(|restored| {
+                for note in &restored {
+                    let content = fs::read_to_string(self.notes_dir.join(note)).unwrap_or_default();
+                    self.link_index.update(note, &content);
+                }
+                // The open note would be saved over it otherwise
+                if let Some(current) = self.current_note_file.clone().filter(|current| restored.contains(current)) {
+                    self.load_note(&current);
+                }
+                let skipped = notes.len() - restored.len();
+                if skipped > 0 {
+                    format!("Put #{} back in {} notes; {} edited since were left alone", from, restored.len(), skipped)
+                } else {
+                    format!("Put #{} back in {} notes", from, restored.len())
+                }
+            }),
+        };
+        match result {
+            Ok(message) => self.set_status(message),
+            Err(err) => self.set_status(format!("Could not undo {}: {}", operation.description(), err)),
+        }
+    }
+
+    // `:pomodoro [minutes]` starts a timer, `:pomodoro stop` stops it
+    fn handle_pomodoro_command(&mut self, args: &str) {
+        if args == "stop" {
+            if self.pomodoro.take().is_some() {
+                self.set_status("Pomodoro stopped");
+            }
+            return;
+        }
+        let minutes = if args.is_empty() { Some(pomodoro::DEFAULT_MINUTES) } else { args.parse().ok() };
+        match minutes.filter(|minutes| (1..=24 * 60).contains(minutes)) {
+            Some(minutes) => {
+                self.pomodoro = Some(Pomodoro::start(minutes));
+                self.set_status(format!("Pomodoro started: {} minutes", minutes));
+            },
+            None => self.set_status("Usage: :pomodoro [minutes], up to a day, or :pomodoro stop"),
+        }
+    }
+
+    // `:lock` locks now, `:lock set` sets the vault's passphrase and `:lock off` removes it
+    fn handle_lock_command(&mut self, args: &str) {
+        match args {
+            "" => self.lock(),
+            "set" => self.passphrase_dialog = Some(PassphraseDialog::default()),
+            "off" => match lock::remove(&self.notes_dir.join(DATA_DIR)) {
+                Ok(()) => {
+                    self.lock_hash = None;
+                    self.set_status("Lock passphrase removed");
+                },
+                Err(err) => self.set_status(format!("Could not remove the lock passphrase: {}", err)),
+            },
+            _ => self.set_status("Usage: :lock, :lock set or :lock off"),
+        }
+    }
+
+    fn lock(&mut self) {
+        if self.lock_hash.is_none() {
+            self.set_status("No lock passphrase yet; set one with :lock set");
+            return;
+        }
+        self.save_current_note();
+        self.locked = Some(LockScreen::default());
+    }
+
+    fn show_passphrase_dialog(&mut self, ctx: &egui::Context) {
+        let result = match &mut self.passphrase_dialog {
+            Some(dialog) => dialog.show(ctx),
+            None => return,
+        };
+        match result {
+            DialogResult::Open => {},
+            DialogResult::Cancelled => self.passphrase_dialog = None,
+            DialogResult::Set(passphrase) => {
+                self.passphrase_dialog = None;
+                match lock::set_passphrase(&self.notes_dir.join(DATA_DIR), &passphrase) {
+                    Ok(hash) => {
+                        self.lock_hash = Some(hash);
+                        self.set_status("Lock passphrase set; :lock locks now");
+                    },
+                    Err(err) => self.set_status(format!("Could not set the lock passphrase: {}", err)),
+                }
+            },
+        }
+    }
+
+    fn check_pomodoro(&mut self) {
+        if self.pomodoro.as_ref().is_some_and(Pomodoro::is_over) {
+            if let Some(pomodoro) = self.pomodoro.take() {
+                self.set_status(format!("Pomodoro done: {} minutes", pomodoro.minutes));
+                self.feedback.notify(&self.config.feedback, feedback::Event::PomodoroEnded);
+            }
+        }
+    }
+
+    // Notice the notes folder going away, like a network share dropping,
+    // and coming back; `now` looks right away instead of every few seconds.
+    // Saving waits while it's gone, and what was kept is saved when it's back.
+    fn check_vault(&mut self, now: bool) {
+        match self.vault.check(&self.notes_dir, !self.notes_files.is_empty(), now) {
+            Some(true) => {
+                self.set_status("The notes folder is back");
+                self.save_current_note();
+            },
+            Some(false) => self.set_status(format!("{} isn't available; saving is paused", self.notes_dir.display())),
+            None => {},
+        }
+    }
+
+    // `:convert-utf8`: write a note that was read in another encoding back as
+    // UTF-8, keeping the original in the backups
+    fn convert_to_utf8(&mut self) {
+        let (file_name, encoding) = match (self.current_note_file.clone(), self.note_encoding) {
+            (Some(file_name), Some(encoding)) => (file_name, encoding),
+            _ => {
+                self.set_status("The note is UTF-8 already");
+                return;
+            },
+        };
+        let backups_dir = self.notes_dir.join(DATA_DIR).join(backups::BACKUPS_DIR);
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let written = backups::back_up(&self.notes_dir, &backups_dir, &file_name, &today)
+            .and_then(|_| fs::write(self.notes_dir.join(&file_name), &self.current_note_content));
+        match written {
+            Ok(()) => {
+                self.note_encoding = None;
+                self.apply_note_options();
+                self.set_status(format!("Converted {} from {} to UTF-8; the original is in the backups for {}", file_name, encoding.name(), today));
+            },
+            Err(err) => self.set_status(format!("Could not convert {}: {}", file_name, err)),
+        }
+    }
+
+    // Shown while the open note isn't UTF-8
+    fn show_encoding_banner(&mut self, ctx: &egui::Context) {
+        let (file_name, encoding) = match (&self.current_note_file, self.note_encoding) {
+            (Some(file_name), Some(encoding)) => (file_name.clone(), encoding),
+            _ => return,
+        };
+        let mut convert = false;
+        egui::TopBottomPanel::top("encoding_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} isn't UTF-8; it was read as {} and is read-only.", file_name, encoding.name()),
+                );
+                convert = ui.button("Convert to UTF-8").on_hover_text("Same as :convert-utf8").clicked();
+            });
+        });
+        if convert {
+            self.convert_to_utf8();
+        }
+    }
+
+    // Shown while the notes folder is gone
+    fn show_vault_banner(&mut self, ctx: &egui::Context) {
+        let mut reconnect = false;
+        egui::TopBottomPanel::top("vault_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} isn't available. Saving is paused; edits to the open note are kept until it's back.", self.notes_dir.display()),
+                );
+                reconnect = ui.button("Reconnect").clicked();
+            });
+        });
+        if reconnect {
+            self.check_vault(true);
+        }
+        ctx.request_repaint_after(vault::CHECK_INTERVAL);
+    }
+
+    // Shown after a crash that left edits unsaved, until they're restored or discarded
+    fn show_recovery_banner(&mut self, ctx: &egui::Context) {
+        let (note, crashed_at) = match &self.crash_recovery {
+            Some(recovery) => (recovery.note.clone(), recovery.crashed_at.clone()),
+            None => return,
+        };
+        let (mut restore, mut discard) = (false, false);
+        egui::TopBottomPanel::top("recovery_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("VimNote crashed at {} with unsaved changes to {}.", crashed_at, note),
+                );
+                restore = ui.button("Restore").on_hover_text("Open the note with the changes; u takes them back").clicked();
+                discard = ui.button("Discard").clicked();
+            });
+        });
+        if restore {
+            self.restore_crash_recovery();
+        } else if discard {
+            self.crash_recovery = None;
+            if let Err(err) = recovery::discard(&self.notes_dir.join(DATA_DIR)) {
+                self.set_status(format!("Could not remove {}: {}", recovery::RECOVERY_FILE, err));
+            }
+        }
+    }
+
+    // Open the note the crash left edits to and put them in, unsaved, as one
+    // change to undo
+    fn restore_crash_recovery(&mut self) {
+        let recovery = match self.crash_recovery.take() {
+            Some(recovery) => recovery,
+            None => return,
+        };
+        self.open_note(&recovery.note);
+        if self.current_note_file.as_deref() != Some(recovery.note.as_str()) || self.large_file.is_some() || self.note_options.readonly {
+            self.set_status(format!("Could not restore {}; the changes are still in .vimnote/{}", recovery.note, recovery::RECOVERY_FILE));
+            return;
+        }
+        if let Some(edit) = Change::edit(&self.current_note_content, &recovery.content) {
+            self.editor.changes.publish(edit);
+            self.current_note_content = recovery.content;
+            if self.editor.cursor_position > self.current_note_content.len() {
+                self.editor.cursor_position = self.current_note_content.len();
+            }
+            self.editor.update_cursor_line_column(&self.current_note_content);
+        }
+        if let Err(err) = recovery::discard(&self.notes_dir.join(DATA_DIR)) {
+            println!("Could not remove {}: {}", recovery::RECOVERY_FILE, err);
+        }
+        self.set_status(format!("Restored the unsaved changes to {}", recovery.note));
+    }
+
+    // `:tutor`, making the tutor note if it isn't there; `:tutor reset`
+    // makes it again
+    fn open_tutor(&mut self, reset: bool) {
+        // Before the note is written, so the open one can't overwrite it
+        self.save_current_note();
+        let path = self.notes_dir.join(tutor::TUTOR_NOTE);
+        if reset || !path.exists() {
+            if let Err(err) = fs::write(&path, tutor::tutor_note()) {
+                self.set_status(format!("Could not write {}: {}", tutor::TUTOR_NOTE, err));
+                return;
+            }
+            self.add_notes_files(vec![tutor::TUTOR_NOTE.to_string()]);
+        }
+        if self.current_note_file.as_deref() == Some(tutor::TUTOR_NOTE) {
+            self.load_note(tutor::TUTOR_NOTE);
+        } else {
+            self.open_note(tutor::TUTOR_NOTE);
+        }
+    }
+
+    // Regenerate the note's generated backlinks section from the link index
+    fn refresh_backlinks_section(&mut self, file_name: &str) {
+        let backlinks = self.link_index.backlinks(file_name);
+        let updated = links::with_backlinks_section(&self.current_note_content, &backlinks);
+        if let Some(edit) = Change::edit(&self.current_note_content, &updated) {
+            self.editor.changes.publish(edit);
+            self.current_note_content = updated;
+            // The section may have shrunk out from under the cursor
+            if self.editor.cursor_position > self.current_note_content.len() {
+                self.editor.cursor_position = self.current_note_content.len();
+            }
+            self.editor.update_cursor_line_column(&self.current_note_content);
+        }
+    }
+
+    // Regenerate the backlinks of the notes `note` started or stopped linking
+    // to, on disk. The open note waits for its own save.
+    fn refresh_linked_notes(&mut self, note: &str, linked_before: &[String]) {
+        let linked_now = self.link_index.links(note);
+        let changed: Vec<&String> = linked_before
+            .iter()
+            .filter(|target| !linked_now.contains(target))
+            .chain(linked_now.iter().filter(|target| !linked_before.contains(target)))
+            .collect();
+        let targets: Vec<String> = self.notes_files
+            .iter()
+            .filter(|file_name| file_name.as_str() != note && self.current_note_file.as_ref() != Some(*file_name))
+            .filter(|file_name| changed.iter().any(|target| links::same_name(target, file_name) || links::same_name(target, links::note_stem(file_name))))
+            .cloned()
+            .collect();
+
+        for file_name in targets {
+            let path = self.notes_dir.join(&file_name);
+            if large_file::is_large(&path, self.config.large_file_size()) {
+                continue;
+            }
+            // Notes that aren't UTF-8 aren't written without converting them
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            if NoteOptions::from_content(self.config.note_defaults(&file_name), &content).readonly {
+                continue;
+            }
+            let updated = links::with_backlinks_section(&content, &self.link_index.backlinks(&file_name));
+            if updated == content {
+                continue;
+            }
+            match fs::write(&path, &updated) {
+                Ok(()) => self.link_index.update(&file_name, &updated),
+                Err(err) => println!("Could not update the backlinks of {}: {}", file_name, err),
+            }
+        }
+    }
+
+    // Re-read per-note options and push the ones the editor cares about into it
+    pub fn apply_note_options(&mut self) {
+        let defaults = match &self.current_note_file {
+            Some(file_name) => self.config.note_defaults(file_name),
+            None => NoteOptions::default(),
+        };
+        self.note_options = NoteOptions::from_content(defaults, &self.current_note_content);
+        for (name, value) in &self.set_options {
+            self.note_options.set_option(name, value.as_deref());
+        }
+        self.push_note_options();
+    }
+
+    fn push_note_options(&mut self) {
+        // Saving would write it back as UTF-8, so only `:convert-utf8` does,
+        // whatever `:set noreadonly` says
+        if self.note_encoding.is_some() {
+            self.note_options.readonly = true;
+        }
+        self.editor.text_width = self.note_options.textwidth;
+        self.editor.shift_width = self.note_options.shiftwidth;
+        self.editor.read_only = self.note_options.readonly;
+        self.editor.ignore_case = self.note_options.ignorecase;
+        self.editor.smart_case = self.note_options.smartcase;
+    }
+
+    pub fn create_new_note(&mut self) {
+        self.create_note_from_template(None);
+    }
+
+    // Create a new note, optionally filled in from a template
+    pub fn create_note_from_template(&mut self, template: Option<&Template>) {
+        let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+        self.create_note(template, format!("note_{}.md", timestamp));
+    }
+
+    // `:new {name}`: a new note named as the file name options say, with a
+    // number added if the name is taken
+    pub fn create_named_note(&mut self, name: &str) {
+        let wanted = file_names::with_extension(&self.config.file_names.apply(name));
+        let file_name = file_names::numbered(&self.notes_dir, &self.notes_files, &wanted);
+        if let Some(dir) = self.notes_dir.join(&file_name).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        self.save_current_note();
+        self.create_note(None, file_name.clone());
+        if let Some(taken) = (file_name != wanted).then(|| file_names::collision(&self.notes_dir, &self.notes_files, &wanted)).flatten() {
+            self.set_status(format!("{} is taken; named the note {}", taken, file_name));
+        }
+    }
+
+    fn create_note(&mut self, template: Option<&Template>, new_file_name: String) {
+        self.current_note_content = match template {
+            Some(template) => templates::render(&template.content, &TemplateVars::now(links::note_stem(&new_file_name))),
+            None => String::new(),
+        };
+        self.large_file = None;
+        self.note_encoding = None;
+        self.switch_marks(&new_file_name);
+        self.set_options.clear();
+        self.current_note_file = Some(new_file_name.clone());
+        self.editor.reset_history(&self.current_note_content);
+        self.apply_note_options();
+        self.editor.changes.publish(Change::Opened { note: new_file_name.clone() });
+        self.notes_files.push(new_file_name.clone());
+        conflicts::sort_notes(&mut self.notes_files); // Keep alphabetical order
+        
+        // Find the index of the new file
+        if let Some(index) = self.notes_files.iter().position(|f| f == &new_file_name) {
+            self.selected_index = index;
+        } else {
+            self.selected_index = self.notes_files.len() - 1;
+        }
+        
+        self.editor.cursor_position = 0;
+        self.editor.vim_mode = VimMode::Insert; // Start in insert mode for new notes
+        self.app_mode = AppMode::Editor; // Switch to editor mode
+        self.save_current_note();
+    }
+
+    pub fn open_template_picker(&mut self) {
+        let templates = templates::load_templates(&self.notes_dir);
+        if templates.is_empty() {
+            self.set_status(format!("No templates found in {}/", templates::TEMPLATES_DIR));
+            return;
+        }
+        self.template_picker = Some(TemplatePicker::new(templates));
+        self.app_mode = AppMode::TemplatePicker;
+    }
+
+    fn close_template_picker(&mut self) {
+        self.template_picker = None;
+        self.app_mode = AppMode::List;
+    }
+
+    // Keyboard handling for the template picker: j/k to move, / to filter, Enter to create
+    fn handle_template_picker_input(&mut self, ctx: &egui::Context) {
+        let events = ctx.input(|i| i.events.clone());
+        let mut chosen = false;
+        let mut cancelled = false;
+        
+        if let Some(picker) = self.template_picker.as_mut() {
+            for event in events {
+                match event {
+                    egui::Event::Text(text) => {
+                        if picker.editing_filter {
+                            picker.filter.push_str(&text);
+                            picker.selected = 0;
+                        } else if text == "/" {
+                            picker.editing_filter = true;
+                        }
+                    },
+                    egui::Event::Key { key, pressed: true, .. } => {
+                        match key {
+                            egui::Key::Backspace if picker.editing_filter => {
+                                picker.filter.pop();
+                                picker.selected = 0;
+                            },
+                            egui::Key::Enter | egui::Key::Escape if picker.editing_filter => {
+                                picker.editing_filter = false;
+                            },
+                            egui::Key::J | egui::Key::ArrowDown if !picker.editing_filter => picker.move_selection(1),
+                            egui::Key::K | egui::Key::ArrowUp if !picker.editing_filter => picker.move_selection(-1),
+                            egui::Key::ArrowDown => picker.move_selection(1),
+                            egui::Key::ArrowUp => picker.move_selection(-1),
+                            egui::Key::Enter => chosen = true,
+                            egui::Key::Escape => cancelled = true,
+                            _ => {}
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+        
+        if chosen {
+            // Don't let the same Enter press reach the new note's editor
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+            self.create_from_selected_template();
+        } else if cancelled {
+            self.close_template_picker();
+        }
+    }
+
+    fn create_from_selected_template(&mut self) {
+        let template = self.template_picker
+            .as_ref()
+            .and_then(|picker| picker.selected_template().cloned());
+        if let Some(template) = template {
+            self.template_picker = None;
+            self.create_note_from_template(Some(&template));
+        }
+    }
+
+    fn show_template_picker(&mut self, ctx: &egui::Context) {
+        let mut clicked_index = None;
+        let mut double_clicked = false;
+        
+        if let Some(picker) = &self.template_picker {
+            let sample_vars = TemplateVars::sample();
+            let filtered = picker.filtered();
+            
+            egui::Window::new("New note from template")
+                .collapsible(false)
+                .default_size(egui::vec2(640.0, 400.0))
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    let filter_text = if picker.editing_filter {
+                        format!("Filter: {}_", picker.filter)
+                    } else if picker.filter.is_empty() {
+                        "j/k to move, / to filter, Enter to create, Esc to cancel".to_string()
+                    } else {
+                        format!("Filter: {}", picker.filter)
+                    };
+                    ui.label(filter_text);
+                    ui.separator();
+                    
+                    ui.columns(2, |columns| {
+                        egui::ScrollArea::vertical()
+                            .id_source("template_list")
+                            .show(&mut columns[0], |ui| {
+                                for (index, template) in filtered.iter().enumerate() {
+                                    let response = ui.selectable_label(index == picker.selected, &template.name);
+                                    if response.clicked() {
+                                        clicked_index = Some(index);
+                                    }
+                                    if response.double_clicked() {
+                                        double_clicked = true;
+                                    }
+                                }
+                                if filtered.is_empty() {
+                                    ui.label("No matching templates");
+                                }
+                            });
+                        
+                        egui::ScrollArea::vertical()
+                            .id_source("template_preview")
+                            .show(&mut columns[1], |ui| {
+                                if let Some(template) = filtered.get(picker.selected) {
+                                    let preview = templates::render(&template.content, &sample_vars);
+                                    ui.label(egui::RichText::new(preview).monospace());
+                                }
+                            });
+                    });
+                });
+        }
+        
+        if let (Some(index), Some(picker)) = (clicked_index, self.template_picker.as_mut()) {
+            picker.selected = index;
+        }
+        if double_clicked {
+            self.create_from_selected_template();
+        }
+    }
+
+    // Open the search screen, reading every note including archived and
+    // trashed ones, or only their dates when ripgrep does the searching
+    pub fn open_search(&mut self, query: &str, ctx: &egui::Context) {
+        self.save_current_note();
+        let mut names = self.notes_files.clone();
+        let trash_prefix = format!("{}/{}/", DATA_DIR, trash::TRASH_DIR);
+        Self::scan_folder(&self.notes_dir.join(DATA_DIR).join(trash::TRASH_DIR), &trash_prefix, &mut |file_name| names.push(file_name));
+        let notes = search::load_notes(&self.notes_dir, &names, &trash_prefix, self.grep.is_none(), self.config.large_file_size());
+        let mut screen = SearchScreen::new(notes, query);
+        if self.grep.is_some() {
+            screen.hits = Some(Vec::new());
+        }
+        self.search = Some(screen);
+        self.app_mode = AppMode::Search;
+        self.start_grep(ctx, false);
+    }
+
+    // Have ripgrep look for the query's terms, unless it already has
+    fn start_grep(&mut self, ctx: &egui::Context, again: bool) {
+        if let (Some(grep), Some(screen)) = (self.grep.as_mut(), self.search.as_mut()) {
+            if screen.parsed.terms.is_empty() || (screen.hits_for == screen.parsed.terms && !again) {
+                return;
+            }
+            screen.hits_for = screen.parsed.terms.clone();
+            let trash_dir = format!("{}/{}", DATA_DIR, trash::TRASH_DIR);
+            grep.spawn(&self.notes_dir, &screen.parsed.terms, &trash_dir, ctx);
+        }
+    }
+
+    // `:settings export [file]` / `:settings import {file}`; the file is
+    // vimnote-settings.toml in the notes folder unless given
+    pub fn handle_settings_command(&mut self, args: &str) {
+        let (subcommand, file) = args.split_once(' ').unwrap_or((args, ""));
+        let path = match file.trim() {
+            "" => self.notes_dir.join(settings::DEFAULT_FILE),
+            file => PathBuf::from(file),
+        };
+        let layouts_path = self.notes_dir.join(DATA_DIR).join("layouts.json");
+        match subcommand {
+            "export" => match Settings::collect(&self.notes_dir, &layouts_path).save(&path) {
+                Ok(()) => self.set_status(format!("Exported settings to {}", path.display())),
+                Err(err) => self.set_status(format!("Could not export settings: {}", err)),
+            },
+            "import" => {
+                let installed = Settings::load(&path).and_then(|settings| settings.install(&self.notes_dir, &layouts_path));
+                match installed {
+                    Ok(installed) if installed.is_empty() => self.set_status(format!("No settings in {}", path.display())),
+                    Ok(installed) => {
+                        self.reload_config();
+                        self.layouts = Layouts::load(&layouts_path);
+                        self.set_status(format!("Imported {}; the API and feeds change after a restart", installed.join(", ")));
+                    },
+                    Err(err) => self.set_status(format!("Could not import settings from {}: {}", path.display(), err)),
+                }
+            },
+            _ => self.set_status("Usage: :settings export|import [file]"),
+        }
+    }
+
+    // Read config.toml again and apply what can change while the app runs
+    fn reload_config(&mut self) {
+        self.config = Config::load();
+        match ViewOptions::parse(&self.config.view_options) {
+            Ok(options) => self.view_options = options,
+            Err(err) => self.set_status(err),
+        }
+        self.apply_note_options();
+        self.load_bibliography();
+    }
+
+    // Make the recurring notes whose date has come since the app was last opened
+    fn create_recurring_notes(&mut self) {
+        if self.config.recurring.is_empty() {
+            return;
+        }
+        let path = self.notes_dir.join(DATA_DIR).join(recurring::RECURRING_FILE);
+        let mut made = Made::load(&path);
+        let result = recurring::create_due(&self.notes_dir, &self.config, &mut made, chrono::Local::now().date_naive());
+        if let Err(err) = made.save(&path) {
+            println!("Could not save {}: {}", path.display(), err);
+        }
+        self.due_today = result.due_today;
+        if let Some(err) = result.errors.first() {
+            self.set_status(err.clone());
+        } else if !result.created.is_empty() {
+            self.set_status(format!("Created {}", result.created.join(", ")));
+        }
+        self.add_notes_files(result.created);
+    }
+
+    // Expire and cap the trash and undo histories. Said on the status line
+    // when something was removed, or always for `:maintenance`.
+    fn run_maintenance(&mut self, always_report: bool) {
+        let data_dir = self.notes_dir.join(DATA_DIR);
+        let report = maintenance::run(
+            &data_dir.join(trash::TRASH_DIR),
+            &data_dir.join("undo"),
+            &data_dir.join(backups::BACKUPS_DIR),
+            &self.config.maintenance_limits(),
+        );
+        println!("Maintenance: {}", report);
+        if always_report || !report.is_empty() {
+            self.set_status(report.to_string());
+        }
+    }
+
+    // `:index status` / `:index rebuild`
+    pub fn handle_index_command(&mut self, subcommand: &str, ctx: &egui::Context) {
+        match subcommand {
+            "status" => {
+                let pending = self.link_index.pending(&self.notes_dir, &self.notes_files, self.config.large_file_size());
+                self.set_status(index::status_text(&self.link_index, &pending));
+            },
+            "rebuild" if self.index_rebuild.is_some() => self.set_status("The index is already being rebuilt"),
+            "rebuild" => {
+                self.save_current_note();
+                self.notes_files = Self::scan_notes_dir(&self.notes_dir);
+                self.index_rebuild = Some(IndexRebuild::start(&self.notes_dir, self.notes_files.clone(), self.config.large_file_size(), ctx));
+            },
+            _ => self.set_status("Usage: :index status|rebuild"),
+        }
+    }
+
+    fn handle_index_rebuild(&mut self) {
+        let index = match self.index_rebuild.as_mut().and_then(|rebuild| rebuild.poll()) {
+            Some(index) => index,
+            None => return,
+        };
+        self.index_rebuild = None;
+        self.link_index = index;
+        // Edits made while it ran were read from the file or not at all
+        if let Some(file_name) = self.current_note_file.clone() {
+            self.link_index.update(&file_name, &self.current_note_content);
+        }
+        self.set_status(format!("Rebuilt the link index: {} notes, {} links", self.link_index.note_count(), self.link_index.link_count()));
+    }
+
+    fn handle_grep_results(&mut self) {
+        let hits = match self.grep.as_mut().and_then(|grep| grep.poll()) {
+            Some(hits) => hits,
+            None => return,
+        };
+        match (hits, self.search.as_mut()) {
+            (Ok(hits), Some(screen)) => screen.set_hits(hits),
+            (Err(err), _) => self.set_status(format!("ripgrep failed: {}", err)),
+            // The search screen was closed meanwhile
+            (Ok(_), None) => {},
+        }
+    }
+
+    fn close_search(&mut self) {
+        self.keep_quickfix();
+        self.search = None;
+        self.app_mode = AppMode::List;
+    }
+
+    // Keep the search screen's hits for :cnext and :cprev
+    fn keep_quickfix(&mut self) {
+        if let Some(screen) = &self.search {
+            if !screen.results.is_empty() {
+                self.quickfix = screen.quickfix();
+                self.quickfix_index = None;
+            }
+        }
+    }
+
+    // `:cnext` / `:cprev`: open the next or previous hit of the last search
+    pub fn quickfix_jump(&mut self, delta: isize) {
+        if self.quickfix.is_empty() {
+            self.set_status("No search results; search with Alt+F or :search");
+            return;
+        }
+        // The first jump goes to the first hit either way
+        let index = self.quickfix_index.map_or(0, |index| index as isize + delta);
+        if index < 0 || index >= self.quickfix.len() as isize {
+            self.set_status(if delta > 0 { "No more items" } else { "Already at the first item" });
+            return;
+        }
+        let index = index as usize;
+        self.quickfix_index = Some(index);
+        let hit = self.quickfix[index].clone();
+        if self.current_note_file.as_deref() != Some(hit.note.as_str()) {
+            if !self.notes_dir.join(&hit.note).exists() {
+                self.set_status(format!("{} no longer exists", hit.note));
+                return;
+            }
+            self.open_note(&hit.note);
+        }
+        self.editor.move_to_line(&self.current_note_content, hit.line);
+        self.set_status(format!("({} of {}) {}", index + 1, self.quickfix.len(), hit.text));
+    }
+
+    // Open a search result, at a matching line if one was picked
+    fn open_search_result(&mut self, index: usize, line: Option<usize>) {
+        let result = self.search.as_ref().and_then(|screen| {
+            let result = screen.results.get(index)?;
+            Some((result.note.clone(), line.or(result.lines.first().map(|(line, _)| *line))))
+        });
+        if let Some((note, line)) = result {
+            self.keep_quickfix();
+            if let Some(hit) = self.quickfix.iter().position(|hit| hit.note == note && Some(hit.line) == line) {
+                self.quickfix_index = Some(hit);
+            }
+            self.search = None;
+            self.open_note(&note);
+            if let Some(line) = line {
+                self.editor.move_to_line(&self.current_note_content, line);
+            }
+        }
+    }
+
+    // `:projects`: the notes with a `status:`, by status
+    fn open_projects(&mut self) {
+        self.save_current_note();
+        let trash_prefix = format!("{}/{}/", DATA_DIR, trash::TRASH_DIR);
+        let notes = search::load_notes(&self.notes_dir, &self.notes_files, &trash_prefix, true, self.config.large_file_size());
+        self.projects = Some(Board::collect(&notes));
+        self.app_mode = AppMode::Projects;
+    }
+
+    fn close_projects(&mut self) {
+        self.projects = None;
+        self.app_mode = AppMode::List;
+    }
+
+    // Set the status of the card at `column`, `card` to the `to` column's
+    fn move_project(&mut self, column: usize, card: usize, to: usize) {
+        let (note, status) = match &self.projects {
+            Some(board) if column != to => match (board.columns.get(column).and_then(|c| c.cards.get(card)), board.columns.get(to)) {
+                (Some(card), Some(to)) => (card.note.clone(), to.status.clone()),
+                _ => return,
+            },
+            _ => return,
+        };
+        self.save_current_note();
+        match projects::move_note(&self.notes_dir, &self.config, &note, &status) {
+            Ok(()) => {
+                if let Some(board) = self.projects.as_mut() {
+                    board.move_card(column, card, to);
+                }
+                // The open note would be saved over the change otherwise
+                if self.current_note_file.as_deref() == Some(note.as_str()) {
+                    self.load_note(&note);
+                }
+                self.link_index.update(&note, &fs::read_to_string(self.notes_dir.join(&note)).unwrap_or_default());
+                self.set_status(format!("{} is {}", note, status));
+            },
+            Err(err) => self.set_status(format!("Could not move {}: {}", note, err)),
+        }
+    }
+
+    fn open_project(&mut self) {
+        let note = self.projects.as_ref().and_then(|board| board.selected()).map(|card| card.note.clone());
+        if let Some(note) = note {
+            self.projects = None;
+            self.open_note(&note);
+        }
+    }
+
+    // Keyboard handling for the projects board: h/l and j/k (or the arrows)
+    // move the selection, H and L move the selected note to the column on
+    // that side, Enter opens it and Escape or q closes the board
+    fn handle_projects_input(&mut self, ctx: &egui::Context) {
+        let events = ctx.input(|i| i.events.clone());
+        let mut moved = None;
+        let mut chosen = false;
+        let mut cancelled = false;
+
+        if let Some(board) = self.projects.as_mut() {
+            for event in events {
+                if let egui::Event::Key { key, pressed: true, modifiers, .. } = event {
+                    match key {
+                        egui::Key::H | egui::Key::L if modifiers.shift => {
+                            let to = if key == egui::Key::H { board.column.checked_sub(1) } else { Some(board.column + 1) };
+                            if let Some(to) = to {
+                                moved = Some((board.column, board.card, to));
+                            }
+                        },
+                        egui::Key::H | egui::Key::ArrowLeft => board.move_selection(-1, 0),
+                        egui::Key::L | egui::Key::ArrowRight => board.move_selection(1, 0),
+                        egui::Key::K | egui::Key::ArrowUp => board.move_selection(0, -1),
+                        egui::Key::J | egui::Key::ArrowDown => board.move_selection(0, 1),
+                        egui::Key::Enter => chosen = true,
+                        egui::Key::Escape | egui::Key::Q => cancelled = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if let Some((column, card, to)) = moved {
+            self.move_project(column, card, to);
+        } else if chosen {
+            // Don't let the same Enter press reach the note's editor
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+            self.open_project();
+        } else if cancelled {
+            self.close_projects();
+        }
+    }
+
+    // The board, with a column per status; cards can be dragged between them
+    fn show_projects(&mut self, ctx: &egui::Context) {
+        let mut clicked = None;
+        let mut double_clicked = false;
+        let mut dropped = None;
+
+        if let Some(board) = &self.projects {
+            egui::Window::new("Projects")
+                .collapsible(false)
+                .default_size(egui::vec2(760.0, 440.0))
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label("h/l j/k to move, H/L or drag to change a note's status, Enter to open, Esc to close");
+                    ui.separator();
+                    if board.columns.iter().all(|column| column.cards.is_empty()) {
+                        ui.weak("No notes have a status yet; add `status: idea`, `active` or `done` to a note's frontmatter");
+                    }
+
+                    let mut column_rects = Vec::new();
+                    let mut released = None;
+                    ui.columns(board.columns.len(), |columns| {
+                        for (index, (ui, column)) in columns.iter_mut().zip(&board.columns).enumerate() {
+                            column_rects.push(ui.max_rect());
+                            ui.strong(format!("{} ({})", column.status, column.cards.len()));
+                            egui::ScrollArea::vertical()
+                                .id_source(("project_column", index))
+                                .show(ui, |ui| {
+                                    for (card_index, card) in column.cards.iter().enumerate() {
+                                        let selected = board.column == index && board.card == card_index;
+                                        let response = ui
+                                            .selectable_label(selected, &card.title)
+                                            .interact(egui::Sense::drag())
+                                            .on_hover_text(&card.note);
+                                        if response.clicked() {
+                                            clicked = Some((index, card_index));
+                                        }
+                                        if response.double_clicked() {
+                                            double_clicked = true;
+                                        }
+                                        if response.dragged() {
+                                            // The card follows the pointer until it's dropped
+                                            if let Some(pointer) = ctx.pointer_interact_pos() {
+                                                egui::Area::new("project_drag")
+                                                    .order(egui::Order::Tooltip)
+                                                    .fixed_pos(pointer + egui::vec2(8.0, 8.0))
+                                                    .show(ctx, |ui| {
+                                                        egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(&card.title));
+                                                    });
+                                            }
+                                        }
+                                        if response.drag_released() {
+                                            released = Some((index, card_index));
+                                        }
+                                    }
+                                });
+                        }
+                    });
+
+                    if let (Some((index, card_index)), Some(pointer)) = (released, ctx.pointer_interact_pos()) {
+                        if let Some(to) = column_rects.iter().position(|rect| rect.x_range().contains(pointer.x)) {
+                            dropped = Some((index, card_index, to));
+                        }
+                    }
+                });
+        }
+
+        if let (Some((column, card)), Some(board)) = (clicked, self.projects.as_mut()) {
+            board.column = column;
+            board.card = card;
+        }
+        if let Some((column, card, to)) = dropped {
+            self.move_project(column, card, to);
+        } else if double_clicked {
+            self.open_project();
+        }
+    }
+
+    // Keyboard handling for the search screen: typing edits the query, arrows
+    // move through the results, Enter opens one and Tab expands its first hit.
+    // While an expanded hit is being edited, Ctrl+Enter saves it and Escape
+    // closes it.
+    fn handle_search_input(&mut self, ctx: &egui::Context) {
+        let events = ctx.input(|i| i.events.clone());
+        // Alt+F, which opened the screen, also types an f on some platforms
+        let alt_held = ctx.input(|i| i.modifiers.alt);
+        let mut chosen = None;
+        let mut cancelled = false;
+        let mut save = false;
+        
+        if let Some(screen) = self.search.as_mut() {
+            if screen.expanded.as_ref().is_some_and(|expanded| expanded.focused) {
+                // The text box gets everything else
+                for event in events {
+                    match event {
+                        egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => screen.expanded = None,
+                        egui::Event::Key { key: egui::Key::Enter, pressed: true, modifiers, .. } if modifiers.ctrl => save = true,
+                        _ => {}
+                    }
+                }
+            } else {
+                let mut query_changed = false;
+                for event in events {
+                    match event {
+                        egui::Event::Text(text) if !alt_held => {
+                            screen.query.push_str(&text);
+                            query_changed = true;
+                        },
+                        egui::Event::Key { key, pressed: true, modifiers, .. } => {
+                            match key {
+                                egui::Key::Backspace => {
+                                    screen.query.pop();
+                                    query_changed = true;
+                                },
+                                egui::Key::ArrowDown => screen.move_selection(1),
+                                egui::Key::ArrowUp => screen.move_selection(-1),
+                                egui::Key::N if modifiers.ctrl => screen.move_selection(1),
+                                egui::Key::P if modifiers.ctrl => screen.move_selection(-1),
+                                egui::Key::Tab => {
+                                    let hit = screen.results.get(screen.selected).and_then(|result| {
+                                        result.lines.first().map(|(line, _)| (result.note.clone(), *line))
+                                    });
+                                    if let Some((note, line)) = hit {
+                                        screen.toggle_expanded(&self.notes_dir, &note, line);
+                                        ctx.memory_mut(|memory| memory.request_focus(egui::Id::new(SEARCH_CONTEXT_ID)));
+                                    }
+                                },
+                                egui::Key::Enter => chosen = Some(screen.selected),
+                                egui::Key::Escape => cancelled = true,
+                                _ => {}
+                            }
+                        },
+                        _ => {}
+                    }
+                }
+                if query_changed {
+                    screen.selected = 0;
+                    screen.run();
+                }
+            }
+        }
+        
+        self.start_grep(ctx, false);
+        if save {
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::Enter));
+            self.save_search_context(ctx);
+        } else if let Some(index) = chosen {
+            // Don't let the same Enter press reach the note's editor
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+            self.open_search_result(index, None);
+        } else if cancelled {
+            self.close_search();
+        }
+    }
+
+    // Write an expanded search hit's edits back to its note
+    fn save_search_context(&mut self, ctx: &egui::Context) {
+        let saved = match self.search.as_mut() {
+            Some(screen) => screen.write_back(&self.notes_dir, &self.config),
+            None => return,
+        };
+        match saved {
+            Ok(note) => {
+                // The open note would be saved over the change otherwise
+                if self.current_note_file.as_deref() == Some(note.as_str()) {
+                    self.load_note(&note);
+                }
+                self.link_index.update(&note, &fs::read_to_string(self.notes_dir.join(&note)).unwrap_or_default());
+                self.set_status(format!("Saved {}", note));
+                // ripgrep's hits have the old lines
+                self.start_grep(ctx, true);
+            },
+            Err(err) => self.set_status(format!("Could not save: {}", err)),
+        }
+    }
+
+    fn show_search(&mut self, ctx: &egui::Context) {
+        let mut toggled = None;
+        let mut clicked = None;
+        let mut expand = None;
+        let mut save = false;
+        
+        let grep_running = self.grep.as_ref().is_some_and(|grep| grep.running);
+        if let Some(screen) = self.search.as_mut() {
+            let chips: Vec<(&str, &str, bool)> = search::FILTER_CHIPS
+                .iter()
+                .map(|(label, token)| (*label, *token, screen.has_filter(token)))
+                .collect();
+            
+            egui::Window::new("Search notes")
+                .collapsible(false)
+                .default_size(egui::vec2(640.0, 420.0))
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label(egui::RichText::new(format!("/{}_", screen.query)).monospace());
+                    
+                    // Filter chips, kept in step with the query text
+                    ui.horizontal(|ui| {
+                        for (label, token, active) in &chips {
+                            if ui.selectable_label(*active, *label).clicked() {
+                                toggled = Some(*token);
+                            }
+                        }
+                    });
+                    for error in &screen.parsed.errors {
+                        ui.colored_label(egui::Color32::from_rgb(220, 80, 80), error);
+                    }
+                    if grep_running {
+                        ui.weak("Searching...");
+                    }
+                    ui.separator();
+                    
+                    egui::ScrollArea::vertical()
+                        .id_source("search_results")
+                        .show(ui, |ui| {
+                            for (index, result) in screen.results.iter().enumerate() {
+                                let title = match result.location {
+                                    search::Location::Notes => result.note.clone(),
+                                    search::Location::Archive => format!("{} (archived)", result.note),
+                                    search::Location::Trash => format!("{} (trash)", result.note),
+                                };
+                                if ui.selectable_label(index == screen.selected, egui::RichText::new(title).strong()).clicked() {
+                                    clicked = Some((index, None));
+                                }
+                                for (line, text) in &result.lines {
+                                    ui.horizontal(|ui| {
+                                        if ui.small_button("⋯").on_hover_text("Show the lines around it").clicked() {
+                                            expand = Some((result.note.clone(), *line));
+                                        }
+                                        let label = egui::RichText::new(format!("{:>4}: {}", line + 1, text)).monospace().weak();
+                                        if ui.selectable_label(false, label).clicked() {
+                                            clicked = Some((index, Some(*line)));
+                                        }
+                                    });
+                                    
+                                    // The expanded hit, editable in place
+                                    if let Some(expanded) = screen.expanded.as_mut().filter(|expanded| expanded.note == result.note && expanded.line == *line) {
+                                        let response = ui.add(
+                                            egui::TextEdit::multiline(&mut expanded.text)
+                                                .id(egui::Id::new(SEARCH_CONTEXT_ID))
+                                                .code_editor()
+                                                .desired_width(f32::INFINITY),
+                                        );
+                                        expanded.focused = response.has_focus();
+                                        ui.horizontal(|ui| {
+                                            if ui.add_enabled(expanded.is_changed(), egui::Button::new("Save")).clicked() {
+                                                save = true;
+                                            }
+                                            ui.weak("Ctrl+Enter to save, Esc to close");
+                                        });
+                                    }
+                                }
+                            }
+                            if screen.results.is_empty() && !screen.query.trim().is_empty() {
+                                ui.label("No matching notes");
+                            } else if screen.results.is_empty() {
+                                ui.label("Type to search; in:archive, in:trash, modified:7d and created:2024-05 filter the results");
+                            }
+                        });
+                });
+        }
+        
+        if let (Some(token), Some(screen)) = (toggled, self.search.as_mut()) {
+            screen.toggle_filter(token);
+        }
+        if let (Some((note, line)), Some(screen)) = (expand, self.search.as_mut()) {
+            screen.toggle_expanded(&self.notes_dir, &note, line);
+            ctx.memory_mut(|memory| memory.request_focus(egui::Id::new(SEARCH_CONTEXT_ID)));
+        }
+        if save {
+            self.save_search_context(ctx);
+        }
+        if let Some((index, line)) = clicked {
+            self.open_search_result(index, line);
+        }
+    }
+
+    // Export the current note as a standalone HTML page, by default to .vimnote/export
+    pub fn export_note_html(&mut self, path: &str) {
+        let file_name = match self.current_note_file.clone() {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        let out_path = if path.is_empty() {
+            let page = format!("{}.html", links::note_stem(&file_name));
+            self.notes_dir.join(DATA_DIR).join("export").join(page)
+        } else {
+            PathBuf::from(path)
+        };
+
+        match site::export_note(&self.notes_dir, &file_name, &self.current_note_content, &out_path, &self.rendering()) {
+            Ok(()) => self.set_status(format!("Exported to {}", out_path.display())),
+            Err(err) => self.set_status(format!("Could not export note: {}", err)),
+        }
+    }
+
+    // Export a folder of notes as a static site, by default to .vimnote/site
+    pub fn export_site(&mut self, args: &str, ctx: &egui::Context) {
+        if self.site_export.is_some() {
+            self.set_status("A site is already being exported");
+            return;
+        }
+        let mut args = args.split_whitespace();
+        let folder = args.next().filter(|folder| *folder != ".").unwrap_or("");
+        let out_dir = match args.next() {
+            Some(dir) => PathBuf::from(dir),
+            None => self.notes_dir.join(DATA_DIR).join("site"),
+        };
+
+        // Pages are rendered from the files, so they need the latest edits
+        self.save_current_note();
+        self.site_export = Some(SiteExport::start(
+            &self.notes_dir,
+            self.notes_files.clone(),
+            folder,
+            out_dir,
+            self.rendering(),
+            ctx,
+        ));
+    }
+
+    fn rendering(&self) -> Rendering {
+        Rendering {
+            diagram_commands: self.config.diagram_commands.clone(),
+            redaction: self.config.redaction,
+            bibliography: self.bibliography.clone(),
+        }
+    }
+
+    fn handle_site_export(&mut self) {
+        let result = match self.site_export.as_mut().and_then(|export| export.poll()) {
+            Some(result) => result,
+            None => return,
+        };
+        let out_dir = match self.site_export.take() {
+            Some(export) => export.out_dir,
+            None => return,
+        };
+        match result {
+            Ok(export) => self.set_status(format!(
+                "Exported {} notes and {} attachments to {}",
+                export.pages,
+                export.assets,
+                out_dir.display()
+            )),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => self.set_status("Stopped exporting the site"),
+            Err(err) => self.set_status(format!("Could not export site: {}", err)),
+        }
+    }
+
+    // Everything running in the background, for the status line and :tasks
+    fn background_tasks(&self) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self.jobs.running
+            .iter()
+            .enumerate()
+            .map(|(index, job)| Task {
+                id: TaskId::Job(index),
+                name: format!("{} {}", job.kind.name(), job.asset.rsplit('/').next().unwrap_or(&job.asset)),
+                progress: None,
+                started: job.started,
+                cancelled: job.cancel.is_cancelled(),
+            })
+            .collect();
+        if let Some(rebuild) = &self.index_rebuild {
+            tasks.push(Task {
+                id: TaskId::IndexRebuild,
+                name: "Indexing".to_string(),
+                progress: Some((rebuild.done, rebuild.total)),
+                started: rebuild.started,
+                cancelled: rebuild.cancel.is_cancelled(),
+            });
+        }
+        if let Some(export) = &self.site_export {
+            tasks.push(Task {
+                id: TaskId::SiteExport,
+                name: "Exporting site".to_string(),
+                // The number of notes isn't known until the export has started on them
+                progress: (export.total > 0).then_some((export.done, export.total)),
+                started: export.started,
+                cancelled: export.cancel.is_cancelled(),
+            });
+        }
+        tasks
+    }
+
+    // A job stops with an error that says it was cancelled. The old index is
+    // kept as soon as a rebuild is cancelled; an export says when it's stopped.
+    fn cancel_task(&mut self, id: TaskId) {
+        match id {
+            TaskId::Job(index) => {
+                if let Some(job) = self.jobs.running.get(index) {
+                    job.cancel.cancel();
+                }
+            },
+            TaskId::IndexRebuild => {
+                if let Some(rebuild) = self.index_rebuild.take() {
+                    rebuild.cancel.cancel();
+                    self.set_status("Stopped rebuilding the link index");
+                }
+            },
+            TaskId::SiteExport => {
+                if let Some(export) = &self.site_export {
+                    export.cancel.cancel();
+                }
+            },
+        }
+    }
+
+    // `:tasks`: what's running in the background, how far along it is, and a
+    // button to cancel each
+    fn show_tasks_window(&mut self, ctx: &egui::Context) {
+        let tasks = self.background_tasks();
+        let mut open = true;
+        let mut cancel = None;
+
+        egui::Window::new("Background tasks")
+            .collapsible(false)
+            .resizable(false)
+            .default_width(360.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if tasks.is_empty() {
+                    ui.weak("Nothing is running");
+                }
+                for task in &tasks {
+                    ui.horizontal(|ui| {
+                        ui.label(&task.name);
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if task.cancelled {
+                                ui.weak("Cancelling...");
+                            } else if ui.small_button("Cancel").clicked() {
+                                cancel = Some(task.id);
+                            }
+                        });
+                    });
+                    let bar = match task.fraction() {
+                        Some(fraction) => egui::ProgressBar::new(fraction).text(task.label()),
+                        // No telling how far along an external command is
+                        None => egui::ProgressBar::new(0.0).text(format!("Running for {}s", task.started.elapsed().as_secs())),
+                    };
+                    ui.add(bar);
+                    ui.add_space(4.0);
+                }
+            });
+
+        if !tasks.is_empty() {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+        if let Some(id) = cancel {
+            self.cancel_task(id);
+        }
+        if !open {
+            self.show_tasks = false;
+        }
+    }
+
+    // Create a meeting note for each of today's calendar events
+    // A calendar URL is downloaded in the background, see handle_calendar_fetch
+    pub fn import_meetings(&mut self, source: &str, ctx: &egui::Context) {
+        let source = if source.is_empty() {
+            match &self.config.calendar {
+                Some(calendar) => calendar.clone(),
+                None => {
+                    self.set_status("Usage: :meetings <file.ics|url>, or set `calendar` in config.toml");
+                    return;
+                }
+            }
+        } else {
+            source.to_string()
+        };
+        
+        if source.starts_with("http://") || source.starts_with("https://") {
+            if self.offline {
+                self.set_status(format!("Offline; not fetching {}", source));
+                return;
+            }
+            if self.calendar_fetch.is_some() {
+                self.set_status("Already fetching a calendar");
+                return;
+            }
+            let (sender, receiver) = mpsc::channel();
+            let ctx = ctx.clone();
+            thread::spawn(move || {
+                let calendar = platform::fetch_url(&source);
+                let _ = sender.send((source, calendar));
+                ctx.request_repaint();
+            });
+            self.calendar_fetch = Some(receiver);
+            self.set_status("Fetching calendar...");
+            return;
+        }
+        let calendar = fs::read_to_string(&source);
+        self.create_meeting_notes(&source, calendar);
+    }
+
+    fn handle_calendar_fetch(&mut self) {
+        let fetched = match self.calendar_fetch.as_ref().map(Receiver::try_recv) {
+            Some(Ok(fetched)) => fetched,
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                self.calendar_fetch = None;
+                return;
+            },
+        };
+        self.calendar_fetch = None;
+        let (source, calendar) = fetched;
+        self.create_meeting_notes(&source, calendar);
+    }
+
+    // Notes for today's events in the calendar read from `source`
+    fn create_meeting_notes(&mut self, source: &str, calendar: io::Result<String>) {
+        let calendar = match calendar {
+            Ok(calendar) => calendar,
+            Err(err) => {
+                self.set_status(format!("Could not read calendar {}: {}", source, err));
+                return;
+            }
+        };
+        
+        let today = chrono::Local::now().date_naive();
+        let events = ics::parse_events(&calendar);
+        let template = templates::template_or_default(&self.notes_dir, "meeting", DEFAULT_MEETING_TEMPLATE);
+        let mut created = 0;
+        
+        for event in ics::events_on(&events, today) {
+            let time = if event.all_day { "all day".to_string() } else { event.start.format("%H:%M").to_string() };
+            let file_name = format!(
+                "meeting_{}_{}_{}.md",
+                event.start.format("%Y-%m-%d"),
+                event.start.format("%H%M"),
+                templates::slugify(&event.summary)
+            );
+            let path = self.notes_dir.join(&file_name);
+            if path.exists() {
+                continue; // Never overwrite notes taken in an earlier import
+            }
+            
+            let vars = TemplateVars {
+                title: event.summary.clone(),
+                date: event.start.format("%Y-%m-%d").to_string(),
+                time,
+                extra: Vec::new(),
+            }
+            .with("end", event.end.map(|end| end.format("%H:%M").to_string()).unwrap_or_default())
+            .with("location", event.location.clone().unwrap_or_default())
+            .with("attendees", event.attendees.join(", "));
+            
+            if fs::write(&path, templates::render(&template, &vars)).is_ok() {
+                created += 1;
+            }
+        }
+        
+        self.refresh_notes();
+        self.set_status(format!("Created {} meeting note(s) for {}", created, today.format("%Y-%m-%d")));
+    }
+
+    // Turn the messages of an .eml or mbox file into notes, optionally only the
+    // ones whose subject or sender contains `filter`
+    pub fn import_email(&mut self, args: &str) {
+        let (path, filter) = match args.split_once(' ') {
+            Some((path, filter)) => (path, filter.trim().to_lowercase()),
+            None => (args, String::new()),
+        };
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.set_status(format!("Could not read {}: {}", path, err));
+                return;
+            }
+        };
+
+        let mut created = 0;
+        let mut last_note = None;
+        for message in email::parse_file(&data) {
+            let matches = filter.is_empty()
+                || message.subject.to_lowercase().contains(&filter)
+                || message.from.to_lowercase().contains(&filter);
+            if !matches {
+                continue;
+            }
+            let date = message.date.unwrap_or_else(|| chrono::Local::now().fixed_offset());
+            let file_name = format!(
+                "mail_{}_{}_{}.md",
+                date.format("%Y-%m-%d"),
+                date.format("%H%M"),
+                templates::slugify(&message.subject)
+            );
+            let note_path = self.notes_dir.join(&file_name);
+            if note_path.exists() {
+                continue; // Already imported
+            }
+
+            let attachments = match message.save_attachments(&self.notes_dir) {
+                Ok(attachments) => attachments,
+                Err(err) => {
+                    self.set_status(format!("Could not save attachments of \"{}\": {}", message.subject, err));
+                    return;
+                }
+            };
+            if fs::write(&note_path, message.to_note(&attachments)).is_ok() {
+                created += 1;
+                last_note = Some(file_name);
+            }
+        }
+
+        self.refresh_notes();
+        if let Some(file_name) = last_note.filter(|_| created == 1) {
+            self.open_note(&file_name);
+        }
+        self.set_status(format!("Imported {} message(s) from {}", created, path));
+    }
+
+    pub fn delete_current_note(&mut self) {
+        if let Some(file_name) = self.current_note_file.clone() {
+            let trash_dir = self.notes_dir.join(DATA_DIR).join(trash::TRASH_DIR);
+            
+            if let Ok(trashed) = trash::move_to_trash(&self.notes_dir, &trash_dir, &file_name) {
+                self.record_vault_operation(Operation::Trashed { note: file_name.clone(), trashed_as: trash::relative_path(&trash_dir, &trashed) });
+                let _ = fs::remove_file(self.undo_file_path(&file_name));
+                let _ = fs::remove_file(self.annotations_file_path(&file_name));
+                let _ = fs::remove_file(self.view_file_path(&file_name));
+                self.editor.marks = Marks::default();
+                self.global_marks.retain(|_, note| *note != file_name);
+                self.save_global_marks();
+                if self.bookmarks.items.iter().any(|b| b.note == file_name) {
+                    self.bookmarks.remove_note(&file_name);
+                    self.save_bookmarks();
+                }
+                if let Some(index) = self.notes_files.iter().position(|f| *f == file_name) {
+                    self.notes_files.remove(index);
+                    
+                    // Adjust selected index
+                    if self.notes_files.is_empty() {
+                        self.selected_index = 0;
+                        self.current_note_file = None;
+                        self.current_note_content.clear();
+                        self.editor.cursor_position = 0;
+                        self.app_mode = AppMode::List; // Go back to list mode
+                    } else {
+                        self.selected_index = if index >= self.notes_files.len() {
+                            self.notes_files.len() - 1
+                        } else {
+                            index
+                        };
+                        
+                        if !self.notes_files.is_empty() {
+                            self.load_note_by_index(self.selected_index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn rename_current_note(&mut self, new_name: &str) -> bool {
+        if let Some(old_name) = self.current_note_file.clone() {
+            // Ensure the new name has a valid extension
+            let new_name = file_names::with_extension(new_name);
+            if self.rename_note(&old_name, &new_name) {
+                self.record_vault_operation(Operation::Renamed { from: old_name, to: new_name });
+                return true;
+            }
+        }
+        false
+    }
+
+    // Rename the current note to `new_name` after the file name options,
+    // or ask what to do if another note has that name. Returns whether the
+    // rename dialog is done.
+    fn confirm_rename(&mut self, new_name: &str) -> bool {
+        let new_name = file_names::with_extension(&self.config.file_names.apply(new_name));
+        let current = self.current_note_file.clone().unwrap_or_default();
+        if current == new_name {
+            return true;
+        }
+        // Only the case changing isn't a clash with the note itself
+        if !links::same_name(&current, &new_name) {
+            if let Some(taken) = file_names::collision(&self.notes_dir, &self.notes_files, &new_name) {
+                self.rename_collision = Some((new_name, taken));
+                return false;
+            }
+        }
+        if !self.rename_current_note(&new_name) {
+            self.set_status(format!("Could not rename the note to {}", new_name));
+            return false;
+        }
+        true
+    }
+
+    // Asked when the rename target is taken: overwrite that note, number
+    // this one, or cancel
+    fn show_rename_collision(&mut self, ui: &mut egui::Ui) {
+        let (new_name, taken) = match self.rename_collision.clone() {
+            Some(collision) => collision,
+            None => return,
+        };
+        if taken == new_name {
+            ui.label(format!("{} already exists.", taken));
+        } else {
+            ui.label(format!("{} already exists; names that differ only in case are the same file on macOS and Windows.", taken));
+        }
+        let numbered = file_names::numbered(&self.notes_dir, &self.notes_files, &new_name);
+        let mut choice = None;
+        if ui.button("Overwrite (o)").on_hover_text(format!("{} goes to the trash", taken)).clicked() {
+            choice = Some(true);
+        }
+        if ui.button(format!("Name it {} (n)", numbered)).clicked() {
+            choice = Some(false);
+        }
+        if ui.button("Cancel").clicked() {
+            self.rename_collision = None;
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::O)) {
+            choice = Some(true);
+        } else if ui.input(|i| i.key_pressed(egui::Key::N)) {
+            choice = Some(false);
+        }
+        if let Some(overwrite) = choice {
+            if self.resolve_rename_collision(overwrite) {
+                self.app_mode = AppMode::List;
+                self.rename_buffer.clear();
+            }
+        }
+    }
+
+    // The rename target was taken: replace that note, which goes to the
+    // trash so `:undo-vault` can bring it back, or take the name with a number
+    fn resolve_rename_collision(&mut self, overwrite: bool) -> bool {
+        let (new_name, taken) = match self.rename_collision.take() {
+            Some(collision) => collision,
+            None => return false,
+        };
+        if !overwrite {
+            let numbered = file_names::numbered(&self.notes_dir, &self.notes_files, &new_name);
+            return self.rename_current_note(&numbered);
+        }
+        let trash_dir = self.notes_dir.join(DATA_DIR).join(trash::TRASH_DIR);
+        match trash::move_to_trash(&self.notes_dir, &trash_dir, &taken) {
+            Ok(trashed) => {
+                self.record_vault_operation(Operation::Trashed { note: taken.clone(), trashed_as: trash::relative_path(&trash_dir, &trashed) });
+                self.notes_files.retain(|f| *f != taken);
+                self.bookmarks.remove_note(&taken);
+                self.rename_current_note(&new_name)
+            },
+            Err(err) => {
+                self.set_status(format!("Could not move {} to the trash: {}", taken, err));
+                false
+            },
+        }
+    }
+
+    // Rename or move any note, with its sidecar files, bookmarks and marks
+    fn rename_note(&mut self, old_name: &str, new_name: &str) -> bool {
+        // Create the file paths
+        let old_path = self.notes_dir.join(old_name);
+        let new_path = self.notes_dir.join(new_name);
+        
+        // Don't overwrite existing files. Only the case changing is fine: on
+        // macOS and Windows the note is there under the new name already.
+        if new_path.exists() && !links::same_name(old_name, new_name) {
+            return false;
+        }
+        
+        // Rename the file on disk
+        if fs::rename(&old_path, &new_path).is_ok() {
+            // Take the undo history along
+            let _ = fs::rename(self.undo_file_path(old_name), self.undo_file_path(new_name));
+            let _ = fs::rename(self.annotations_file_path(old_name), self.annotations_file_path(new_name));
+            if let Some(dir) = self.view_file_path(new_name).parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::rename(self.view_file_path(old_name), self.view_file_path(new_name));
+            self.bookmarks.rename_note(old_name, new_name);
+            self.layouts.rename_note(old_name, new_name);
+            if let Some(log) = &mut self.access_log {
+                if let Err(err) = log.rename_note(old_name, new_name) {
+                    println!("Could not update the access log: {}", err);
+                }
+            }
+            for note in self.global_marks.values_mut().filter(|note| *note == old_name) {
+                *note = new_name.to_string();
+            }
+            if self.current_note_file.as_deref() == Some(old_name) {
+                self.current_note_file = Some(new_name.to_string());
+            }
+            self.save_global_marks();
+            self.save_bookmarks();
+            self.save_layouts();
+            // Update the files list
+            if let Some(index) = self.notes_files.iter().position(|f| f == old_name) {
+                self.notes_files.remove(index);
+                self.notes_files.push(new_name.to_string());
+                conflicts::sort_notes(&mut self.notes_files);
+                
+                // Find the new index
+                if let Some(new_index) = self.notes_files.iter().position(|f| f == new_name) {
+                    self.selected_index = new_index;
+                }
+                
+                return true;
+            }
+        }
+        false
+    }
+
+    // Start the local HTTP API if a port is configured
+    pub fn start_local_api(&mut self, ctx: &egui::Context) {
+        if self.offline {
+            return;
+        }
+        if let Some(port) = self.config.api_port {
+            let token = match self.config.api_token.clone() {
+                Some(token) => token,
+                None => {
+                    let token = local_api::generate_token();
+                    self.config.api_token = Some(token.clone());
+                    match self.config.save() {
+                        Ok(()) => self.set_status("The local API's token is now api_token in config.toml; clients send it as X-VimNote-Token"),
+                        Err(err) => self.set_status(format!("Could not save the local API's new token to config.toml: {}", err)),
+                    }
+                    token
+                },
+            };
+            match LocalApi::start(port, token, self.config.api_origins.clone(), self.notes_dir.clone(), ctx.clone()) {
+                Ok(api) => self.local_api = Some(api),
+                Err(err) => self.set_status(format!("Could not start the local API on port {}: {}", port, err)),
+            }
+        }
+    }
+
+    // `:set offline` turns off everything that uses the network at once: the
+    // feeds, the local API the web clipper posts to, and fetching calendars
+    // from URLs for `:meetings`. `:set nooffline` starts them again.
+    fn set_offline(&mut self, offline: bool, ctx: &egui::Context) {
+        if offline == self.offline {
+            return;
+        }
+        self.offline = offline;
+        if offline {
+            self.feeds = None;
+            self.local_api = None;
+            self.set_status("Offline: feeds, the local API and calendar URLs are off");
+        } else if self.loading.is_none() {
+            // Otherwise they start once the notes are loaded
+            self.start_local_api(ctx);
+            self.start_feeds(ctx);
+            self.set_status("Back online");
+        }
+    }
+
+    // Start fetching the configured feeds in the background
+    pub fn start_feeds(&mut self, ctx: &egui::Context) {
+        if !self.offline && !self.config.feeds.is_empty() {
+            let interval = Duration::from_secs(self.config.feed_interval.max(1) * 60);
+            let seen_file = self.notes_dir.join(DATA_DIR).join("feeds.json");
+            self.feeds = Some(FeedReader::start(self.config.feeds.clone(), interval, seen_file, ctx.clone()));
+        }
+    }
+
+    fn handle_feed_events(&mut self) {
+        let events = match &self.feeds {
+            Some(reader) => reader.poll(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                FeedEvent::NewItems { feed, items } => {
+                    // The digest note may be open; save it before appending and reload it after
+                    self.save_current_note();
+                    match feeds::write_items(&self.notes_dir, &feed, &items, self.config.feed_digest) {
+                        Ok(notes) => {
+                            if let Some(current) = self.current_note_file.clone().filter(|f| notes.contains(f)) {
+                                if let Ok(content) = fs::read_to_string(self.notes_dir.join(&current)) {
+                                    if let Some(edit) = Change::edit(&self.current_note_content, &content) {
+                                        self.editor.changes.publish(edit);
+                                    }
+                                    self.current_note_content = content;
+                                    self.editor.cursor_position = self.editor.cursor_position.min(self.current_note_content.len());
+                                    self.editor.update_cursor_line_column(&self.current_note_content);
+                                }
+                            }
+                            self.refresh_notes();
+                            self.set_status(format!("{} new item(s) from {}", items.len(), feed));
+                        },
+                        Err(err) => self.set_status(format!("Could not save items from {}: {}", feed, err)),
+                    }
+                },
+                FeedEvent::Failed(message) => self.set_status(message),
+            }
+        }
+    }
+
+    // Copy a file into assets/ and link it from the open note
+    pub fn attach_file(&mut self, path: &Path, ctx: &egui::Context) {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name.to_string(),
+            None => {
+                self.set_status(format!("Not a file: {}", path.display()));
+                return;
+            }
+        };
+        match fs::read(path) {
+            Ok(data) => self.insert_attachment(&file_name, &data, ctx),
+            Err(err) => self.set_status(format!("Could not read {}: {}", path.display(), err)),
+        }
+    }
+
+    // Save the image on the clipboard as a PNG attachment
+    pub fn paste_image(&mut self, ctx: &egui::Context) {
+        match platform::clipboard_image() {
+            Ok((width, height, rgba)) => {
+                let file_name = format!("pasted-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+                self.insert_attachment(&file_name, &attachments::encode_png(width, height, &rgba), ctx);
+            },
+            Err(err) => self.set_status(format!("No image on the clipboard: {}", err)),
+        }
+    }
+
+    // Save an attachment and put a link to it on a new line below the cursor.
+    // Images are OCR'd and audio transcribed in the background when an
+    // `ocr_command` or `transcribe_command` is configured.
+    fn insert_attachment(&mut self, file_name: &str, data: &[u8], ctx: &egui::Context) {
+        let note = match self.current_note_file.clone() {
+            Some(note) if !self.note_options.readonly => note,
+            _ => {
+                self.set_status("Open a note that isn't read-only to attach files");
+                return;
+            }
+        };
+        let asset = match attachments::save(&self.notes_dir, file_name, data) {
+            Ok(asset) => asset,
+            Err(err) => {
+                self.set_status(format!("Could not save {}: {}", file_name, err));
+                return;
+            }
+        };
+
+        let link = attachments::markdown_link(&asset);
+        let content = &mut self.current_note_content;
+        let edit = if content.is_empty() {
+            content.push_str(&link);
+            self.editor.cursor_position = 0;
+            Change::Edited { start: 0, old_end: 0, new_end: link.len() }
+        } else {
+            let cursor = self.editor.cursor_position.min(content.len());
+            let line_end = content[cursor..].find('\n').map_or(content.len(), |i| cursor + i);
+            content.insert_str(line_end, &format!("\n{}", link));
+            self.editor.cursor_position = line_end + 1;
+            Change::Edited { start: line_end, old_end: line_end, new_end: line_end + 1 + link.len() }
+        };
+        self.editor.changes.publish(edit);
+        self.editor.update_cursor_line_column(&self.current_note_content);
+        self.last_save_time = Instant::now();
+
+        let job = if attachments::is_image(&asset) {
+            self.config.ocr_command.clone().map(|command| (command, JobKind::Ocr))
+        } else if attachments::is_audio(&asset) {
+            self.config.transcribe_command.clone().map(|command| (command, JobKind::Transcription))
+        } else {
+            None
+        };
+        match job {
+            Some((command, kind)) => {
+                let file = self.notes_dir.join(&asset);
+                self.jobs.spawn(&command, file, Job { kind, note, asset: asset.clone() }, ctx);
+                self.set_status(format!("Attached {}; {} running in the background", asset, kind.name()));
+            },
+            None => self.set_status(format!("Attached {}", asset)),
+        }
+    }
+
+    // Add the output of finished jobs to the notes that link the attachments
+    fn handle_job_results(&mut self) {
+        for JobResult { job, output } in self.jobs.poll() {
+            let text = match output {
+                Ok(text) if text.is_empty() => {
+                    self.set_status(format!("{}: nothing found in {}", job.kind.name(), job.asset));
+                    continue;
+                },
+                Ok(text) => text,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    self.set_status(format!("Cancelled {} of {}", job.kind.name(), job.asset));
+                    continue;
+                },
+                Err(err) => {
+                    self.set_status(format!("{} failed on {}: {}", job.kind.name(), job.asset, err));
+                    continue;
+                },
+            };
+
+            let added = if self.current_note_file.as_deref() == Some(job.note.as_str()) {
+                let inserted = jobs::insert_output(&mut self.current_note_content, &job, &text);
+                if let Some((offset, len)) = inserted {
+                    self.editor.changes.publish(Change::Edited { start: offset, old_end: offset, new_end: offset + len });
+                    if self.editor.cursor_position >= offset {
+                        self.editor.cursor_position += len;
+                    }
+                    self.editor.update_cursor_line_column(&self.current_note_content);
+                    self.save_current_note();
+                }
+                inserted.is_some()
+            } else {
+                // The user has moved on to another note
+                let path = self.notes_dir.join(&job.note);
+                match fs::read_to_string(&path) {
+                    Ok(mut content) => jobs::insert_output(&mut content, &job, &text).is_some() && fs::write(&path, content).is_ok(),
+                    Err(_) => false,
+                }
+            };
+            if added {
+                self.set_status(format!("{}: added the text of {}", job.kind.name(), job.asset));
+            } else {
+                self.set_status(format!("{}: {} is no longer linked from {}", job.kind.name(), job.asset, job.note));
+            }
+        }
+    }
+
+    fn handle_api_events(&mut self) {
+        let events = match &self.local_api {
+            Some(api) => api.poll(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                ApiEvent::Clipped(note) => {
+                    self.refresh_notes();
+                    self.set_status(format!("Clipped {}", note));
+                },
+                ApiEvent::Failed(message) => self.set_status(message),
             }
         }
+    }
 
-        // Sort files alphabetically
-        files.sort();
-        
-        println!("Scanned directory in {:?}", start.elapsed());
-        files
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
     }
 
-    pub fn load_note(&mut self, file_name: &str) {
-        let start = Instant::now();
-        let file_path = self.notes_dir.join(file_name);
-        
-        match File::open(&file_path) {
-            Ok(mut file) => {
-                self.current_note_content.clear();
-                if file.read_to_string(&mut self.current_note_content).is_ok() {
-                    self.current_note_file = Some(file_name.to_string());
-                    self.editor.cursor_position = 0;
-                    self.editor.update_cursor_line_column(&self.current_note_content);
-                }
-            },
-            Err(_) => {
-                self.current_note_content = String::new();
-                self.current_note_file = Some(file_name.to_string());
-                self.editor.cursor_position = 0;
-                self.editor.update_cursor_line_column(&self.current_note_content);
+    // Absolute path of the open note on disk
+    pub fn current_note_path(&self) -> Option<PathBuf> {
+        self.current_note_file.as_ref().map(|file_name| {
+            let path = self.notes_dir.join(file_name);
+            path.canonicalize().unwrap_or(path)
+        })
+    }
+
+    pub fn reveal_current_note(&mut self) {
+        if let Some(path) = self.current_note_path() {
+            match platform::reveal_in_file_manager(&path) {
+                Ok(()) => self.set_status(format!("Revealed {}", path.display())),
+                Err(err) => self.set_status(format!("Could not open file manager: {}", err)),
             }
         }
-        
-        println!("Loaded note in {:?}", start.elapsed());
     }
 
-    pub fn save_current_note(&mut self) {
-        if let Some(file_name) = &self.current_note_file {
-            let start = Instant::now();
-            let file_path = self.notes_dir.join(file_name);
-            
-            if let Ok(mut file) = File::create(file_path) {
-                if file.write_all(self.current_note_content.as_bytes()).is_ok() {
-                    self.last_save_time = Instant::now();
-                    println!("Saved note in {:?}", start.elapsed());
-                }
-            }
+    pub fn open_url(&mut self, url: &str) {
+        match platform::open_with_default_app(url) {
+            Ok(()) => self.set_status(format!("Opened {}", url)),
+            Err(err) => self.set_status(format!("Could not open {}: {}", url, err)),
         }
     }
 
-    pub fn create_new_note(&mut self) {
-        let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S");
-        let new_file_name = format!("note_{}.md", timestamp);
-        
-        self.current_note_content = String::new();
-        self.current_note_file = Some(new_file_name.clone());
-        self.notes_files.push(new_file_name.clone());
-        self.notes_files.sort(); // Keep alphabetical order
-        
-        // Find the index of the new file
-        if let Some(index) = self.notes_files.iter().position(|f| f == &new_file_name) {
-            self.selected_index = index;
+    // `gf` on a file path: notes open in the editor, anything else (PDFs,
+    // spreadsheets...) in its default app. Relative paths are from the open
+    // note's folder, then the notes folder. True if it opened a note.
+    pub fn open_file(&mut self, path: &str) -> bool {
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().map_or_else(|| PathBuf::from(path), |home| home.join(rest)),
+            None => PathBuf::from(path),
+        };
+        let path = if path.is_absolute() {
+            path
         } else {
-            self.selected_index = self.notes_files.len() - 1;
+            let note_dir = self.current_note_path()
+                .and_then(|note| note.parent().map(Path::to_path_buf))
+                .unwrap_or_else(|| self.notes_dir.clone());
+            [note_dir.join(&path), self.notes_dir.join(&path)]
+                .into_iter()
+                .find(|candidate| candidate.exists())
+                .unwrap_or_else(|| note_dir.join(&path))
+        };
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => {
+                self.set_status(format!("No file {}", path.display()));
+                return false;
+            },
+        };
+
+        let notes_dir = self.notes_dir.canonicalize().unwrap_or_else(|_| self.notes_dir.clone());
+        let note = path.strip_prefix(&notes_dir).ok()
+            .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            .filter(|relative| relative.ends_with(".md") || relative.ends_with(".txt"));
+        if let Some(note) = note {
+            self.open_note(&note);
+            return true;
         }
-        
-        self.editor.cursor_position = 0;
-        self.editor.vim_mode = VimMode::Insert; // Start in insert mode for new notes
-        self.app_mode = AppMode::Editor; // Switch to editor mode
-        self.save_current_note();
+        if path.is_dir() {
+            self.set_status(format!("{} is a folder", path.display()));
+            return false;
+        }
+        let target = path.to_string_lossy().into_owned();
+        // An attachment could be a program; opening it would run it
+        if platform::is_executable(&path) {
+            self.set_status(format!("Not opening {}: it's a program or script", target));
+            return false;
+        }
+        match platform::open_with_default_app(&target) {
+            Ok(()) => self.set_status(format!("Opened {}", target)),
+            Err(err) => self.set_status(format!("Could not open {}: {}", target, err)),
+        }
+        false
     }
 
-    pub fn delete_current_note(&mut self) {
-        if let Some(file_name) = &self.current_note_file {
-            let file_path = self.notes_dir.join(file_name);
-            
-            if fs::remove_file(file_path).is_ok() {
-                if let Some(index) = self.notes_files.iter().position(|f| f == file_name) {
-                    self.notes_files.remove(index);
-                    
-                    // Adjust selected index
-                    if self.notes_files.is_empty() {
-                        self.selected_index = 0;
-                        self.current_note_file = None;
-                        self.current_note_content.clear();
-                        self.editor.cursor_position = 0;
-                        self.app_mode = AppMode::List; // Go back to list mode
-                    } else {
-                        self.selected_index = if index >= self.notes_files.len() {
-                            self.notes_files.len() - 1
-                        } else {
-                            index
-                        };
-                        
-                        if !self.notes_files.is_empty() {
-                            self.load_note_by_index(self.selected_index);
-                        }
-                    }
-                }
-            }
+    // Text color of a heading, code or quote line
+    fn line_color(kind: LineKind, dark_mode: bool) -> egui::Color32 {
+        match (kind, dark_mode) {
+            (LineKind::Heading(_), true) => egui::Color32::from_rgb(120, 170, 255),
+            (LineKind::Heading(_), false) => egui::Color32::from_rgb(20, 80, 170),
+            (LineKind::Quote, true) => egui::Color32::from_gray(160),
+            (LineKind::Quote, false) => egui::Color32::from_gray(100),
+            (LineKind::Text, true) => egui::Color32::WHITE,
+            (LineKind::Text, false) => egui::Color32::BLACK,
+            (_, true) => egui::Color32::from_rgb(190, 210, 150),
+            (_, false) => egui::Color32::from_rgb(120, 90, 20),
         }
     }
 
-    pub fn rename_current_note(&mut self, new_name: &str) -> bool {
-        if let Some(old_name) = &self.current_note_file {
-            // Ensure the new name has a valid extension
-            let new_name = if !new_name.ends_with(".md") && !new_name.ends_with(".txt") {
-                format!("{}.md", new_name) // Default to .md extension
-            } else {
-                new_name.to_string()
-            };
-            
-            // Create the file paths
-            let old_path = self.notes_dir.join(old_name);
-            let new_path = self.notes_dir.join(&new_name);
-            
-            // Don't overwrite existing files
-            if new_path.exists() {
-                return false;
-            }
-            
-            // Rename the file on disk
-            if fs::rename(&old_path, &new_path).is_ok() {
-                // Update the files list
-                if let Some(index) = self.notes_files.iter().position(|f| f == old_name) {
-                    self.notes_files.remove(index);
-                    self.notes_files.push(new_name.clone());
-                    self.notes_files.sort();
-                    
-                    // Update the current note file
-                    self.current_note_file = Some(new_name.clone());
-                    
-                    // Find the new index
-                    if let Some(new_index) = self.notes_files.iter().position(|f| f == &new_name) {
-                        self.selected_index = new_index;
-                    }
-                    
-                    return true;
-                }
+    // Whether `start..end` lies within one of the sorted, non-overlapping `ranges`
+    fn in_ranges(ranges: &[(usize, usize)], start: usize, end: usize) -> bool {
+        let index = ranges.partition_point(|&(_, range_end)| range_end <= start);
+        ranges.get(index).is_some_and(|&(range_start, range_end)| range_start <= start && end <= range_end)
+    }
+
+    // Character index in the tab-expanded display text of a byte offset in the note
+    fn expanded_index_from_byte_offset(text: &str, offset: usize, tab_width: usize) -> usize {
+        text[..offset]
+            .chars()
+            .map(|c| if c == '\t' { tab_width } else { 1 })
+            .sum()
+    }
+
+    // Map a character index in the tab-expanded display text back to a byte offset in the note
+    fn byte_offset_from_expanded_index(text: &str, expanded_index: usize, tab_width: usize) -> usize {
+        let mut expanded = 0;
+        for (offset, c) in text.char_indices() {
+            let width = if c == '\t' { tab_width } else { 1 };
+            if expanded + width > expanded_index {
+                return offset;
             }
+            expanded += width;
         }
-        false
+        text.len()
     }
 
     pub fn load_note_by_index(&mut self, index: usize) {
@@ -223,11 +3659,53 @@ impl NotesApp {
 
 impl eframe::App for NotesApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Nothing is loaded until the first-run setup is done
+        if let Some(onboarding) = &mut self.onboarding {
+            ctx.set_visuals(if onboarding.dark_mode { egui::Visuals::dark() } else { egui::Visuals::light() });
+            if let Some(setup) = onboarding.show(ctx) {
+                self.finish_onboarding(setup);
+            }
+            return;
+        }
+
+        // Nothing shows while locked, and nothing gets the keys but the lock screen
+        if self.lock_hash.is_some() && self.locked.is_none() && self.idle_lock.check(ctx) {
+            self.lock();
+        }
+        if let (Some(screen), Some(hash)) = (&mut self.locked, &self.lock_hash) {
+            if screen.show(ctx, hash) {
+                self.locked = None;
+                self.idle_lock.reset();
+            }
+            return;
+        }
+
+        self.check_vault(false);
         // Auto-save every 5 seconds if there's an active note
-        if self.current_note_file.is_some() && self.last_save_time.elapsed().as_secs() > 5 {
+        if self.config.autosave && self.current_note_file.is_some() && self.last_save_time.elapsed().as_secs() > 5 {
             self.save_current_note();
         }
 
+        self.handle_notes_loaded(ctx);
+        self.handle_api_events();
+        self.handle_feed_events();
+        self.handle_calendar_fetch();
+        self.handle_job_results();
+        self.handle_grep_results();
+        self.handle_index_rebuild();
+        self.handle_site_export();
+        self.check_pomodoro();
+        // After the handlers above, which may edit the note too
+        self.handle_note_changes();
+
+        // Files dropped on the window are attached to the open note
+        let dropped_files: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        if self.app_mode == AppMode::Editor {
+            for path in dropped_files {
+                self.attach_file(&path, ctx);
+            }
+        }
+
         // Set theme
         if self.dark_mode {
             ctx.set_visuals(egui::Visuals::dark());
@@ -238,7 +3716,7 @@ impl eframe::App for NotesApp {
         // Global key handlers that work in any mode
         if ctx.input(|i| i.key_pressed(egui::Key::F5)) {
             // Refresh notes list
-            self.notes_files = Self::scan_notes_dir(&self.notes_dir);
+            self.refresh_notes();
         }
         
         if ctx.input(|i| i.key_pressed(egui::Key::S) && i.modifiers.ctrl) {
@@ -251,11 +3729,26 @@ impl eframe::App for NotesApp {
             self.dark_mode = !self.dark_mode;
         }
         
-        if ctx.input(|i| i.key_pressed(egui::Key::N) && i.modifiers.alt) {
+        if ctx.input(|i| i.key_pressed(egui::Key::B) && i.modifiers.alt) {
+            // Toggle the bookmarks panel with Alt+B
+            self.show_bookmarks = !self.show_bookmarks;
+        }
+        
+        if ctx.input(|i| i.key_pressed(egui::Key::N) && i.modifiers.alt && !i.modifiers.shift) {
             // Create new note with Alt+N
             self.create_new_note();
         }
         
+        if ctx.input(|i| i.key_pressed(egui::Key::N) && i.modifiers.alt && i.modifiers.shift) {
+            // Pick a template for the new note with Alt+Shift+N
+            self.open_template_picker();
+        }
+        
+        if ctx.input(|i| i.key_pressed(egui::Key::F) && i.modifiers.alt) && self.app_mode != AppMode::Search {
+            // Search all notes with Alt+F
+            self.open_search("", ctx);
+        }
+        
         if ctx.input(|i| i.key_pressed(egui::Key::D) && i.modifiers.alt) {
             // Delete current note with Alt+D
             self.delete_current_note();
@@ -269,14 +3762,11 @@ impl eframe::App for NotesApp {
             match self.app_mode {
                 AppMode::Editor => {
                     match self.editor.vim_mode {
-                        VimMode::Insert => {
-                            // From Insert -> Normal
-                            self.editor.vim_mode = VimMode::Normal;
-                            // In vim, Escape in insert mode moves cursor back one char
-                            if self.editor.cursor_position > 0 && !self.current_note_content.is_empty() {
-                                self.editor.cursor_position -= 1;
-                                self.editor.update_cursor_line_column(&self.current_note_content);
-                            }
+                        VimMode::Insert | VimMode::Replace | VimMode::Command | VimMode::Visual | VimMode::VisualBlock => {
+                            // Back to Normal through the editor, which finishes a block insert,
+                            // clears the command line or keeps the selection for gv. A macro
+                            // being recorded gets the Escape too.
+                            self.editor.handle_key_press(egui::Key::Escape, &mut self.current_note_content, &egui::Modifiers::NONE);
                         },
                         VimMode::Normal => {
                             // From Normal -> List 
@@ -284,11 +3774,6 @@ impl eframe::App for NotesApp {
                             self.save_current_note(); // Auto-save when exiting editor mode
                             println!("Switching to List mode from Normal mode");
                         },
-                        VimMode::Command => {
-                            // From Command -> Normal
-                            self.editor.vim_mode = VimMode::Normal;
-                            self.editor.command_buffer.clear();
-                        },
                     }
                 },
                 AppMode::List => {
@@ -298,10 +3783,76 @@ impl eframe::App for NotesApp {
                     // Cancel rename mode and go back to list mode
                     self.app_mode = AppMode::List;
                     self.rename_buffer.clear();
+                    self.rename_collision = None;
+                },
+                AppMode::TemplatePicker => {
+                    // The picker handles escape itself so it can leave filter editing first
+                },
+                AppMode::Search => {
+                    // Handled with the rest of the search screen's keys
+                },
+                AppMode::Projects => {
+                    // Handled with the rest of the board's keys
                 },
             }
         }
         
+        if !self.vault.is_available() {
+            self.show_vault_banner(ctx);
+        }
+        if self.note_encoding.is_some() {
+            self.show_encoding_banner(ctx);
+        }
+        if self.crash_recovery.is_some() {
+            self.show_recovery_banner(ctx);
+        }
+        
+        if self.app_mode == AppMode::TemplatePicker {
+            self.handle_template_picker_input(ctx);
+            self.show_template_picker(ctx);
+        }
+        
+        if self.app_mode == AppMode::Search {
+            self.handle_search_input(ctx);
+            self.show_search(ctx);
+        }
+
+        if self.app_mode == AppMode::Projects {
+            self.handle_projects_input(ctx);
+            self.show_projects(ctx);
+        }
+        
+        if self.show_bookmarks {
+            self.show_bookmarks_panel(ctx);
+        }
+
+        if self.register_list.is_some() {
+            self.show_registers_panel(ctx);
+        }
+
+        if self.mark_list.is_some() {
+            self.show_marks_panel(ctx);
+        }
+
+        if self.tag_stats.is_some() {
+            self.show_tags_panel(ctx);
+        }
+
+        if self.properties.is_some() {
+            self.show_properties_panel(ctx);
+        }
+
+        if self.merge.is_some() {
+            self.show_merge_panel(ctx);
+        }
+
+        if self.show_tasks {
+            self.show_tasks_window(ctx);
+        }
+        if self.passphrase_dialog.is_some() {
+            self.show_passphrase_dialog(ctx);
+        }
+        
         egui::SidePanel::left("notes_list_panel")
             .resizable(true)
             .default_width(200.0)
@@ -313,8 +3864,11 @@ impl eframe::App for NotesApp {
                     if ui.button("New").clicked() {
                         self.create_new_note();
                     }
+                    if ui.button("Template").clicked() {
+                        self.open_template_picker();
+                    }
                     if ui.button("Refresh").clicked() {
-                        self.notes_files = Self::scan_notes_dir(&self.notes_dir);
+                        self.refresh_notes();
                     }
                     if ui.button("🌙").clicked() {
                         self.dark_mode = !self.dark_mode;
@@ -323,6 +3877,28 @@ impl eframe::App for NotesApp {
                 
                 ui.separator();
                 
+                if let Some(loader) = &self.loading {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.weak(format!("Loading notes... {}", loader.found));
+                    });
+                }
+                
+                if !self.due_today.is_empty() {
+                    ui.strong("Due today");
+                    let mut open = None;
+                    for note in &self.due_today {
+                        let current = self.current_note_file.as_ref() == Some(note);
+                        if ui.selectable_label(current, note).clicked() {
+                            open = Some(note.clone());
+                        }
+                    }
+                    if let Some(note) = open {
+                        self.open_note(&note);
+                    }
+                    ui.separator();
+                }
+                
                 // File listing with keyboard navigation
                 egui::ScrollArea::vertical()
                     .auto_shrink([false, false])
@@ -336,10 +3912,18 @@ impl eframe::App for NotesApp {
                             |ui| {
                                 for (index, file_name) in self.notes_files.iter().enumerate() {
                                     let is_selected = index == self.selected_index;
-                                    let text = egui::RichText::new(file_name);
+                                    let original = conflicts::original_of(file_name);
+                                    let text = match &original {
+                                        // Listed right under their note
+                                        Some(_) => egui::RichText::new(format!("   ⚠ {}", file_name)).color(ui.visuals().warn_fg_color),
+                                        None => egui::RichText::new(file_name),
+                                    };
                                     let text = if is_selected { text.strong() } else { text };
                                     
-                                    let response = ui.selectable_label(is_selected, text);
+                                    let mut response = ui.selectable_label(is_selected, text);
+                                    if let Some(original) = &original {
+                                        response = response.on_hover_text(format!("Sync conflict copy of {}; :merge to merge it", original));
+                                    }
                                     
                                     if response.clicked() {
                                         if self.selected_index != index {
@@ -416,7 +4000,9 @@ impl eframe::App for NotesApp {
             });
         
         egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(file_name) = &self.current_note_file {
+            if self.large_file.is_some() {
+                self.show_large_file(ui);
+            } else if let Some(file_name) = &self.current_note_file {
                 // Capture immutable data first
                 let file_name = file_name.clone(); // Clone to avoid borrow issues
                 let vim_mode_text = self.editor.get_mode_display();
@@ -425,7 +4011,7 @@ impl eframe::App for NotesApp {
                 // UI header
                 ui.horizontal(|ui| {
                     match app_mode {
-                        AppMode::Editor | AppMode::List => {
+                        AppMode::Editor | AppMode::List | AppMode::TemplatePicker | AppMode::Search | AppMode::Projects => {
                             ui.heading(&file_name);
                             ui.label(format!(" - {} mode", vim_mode_text));
                             
@@ -433,6 +4019,10 @@ impl eframe::App for NotesApp {
                                 self.save_current_note();
                             }
                         },
+                        AppMode::Rename if self.rename_collision.is_some() => {
+                            ui.heading("Rename Note");
+                            self.show_rename_collision(ui);
+                        },
                         AppMode::Rename => {
                             ui.heading("Rename Note");
                             
@@ -504,8 +4094,8 @@ impl eframe::App for NotesApp {
                                     }
                                 }
                                 
-                                if self.rename_current_note(&new_name) {
-                                    // Rename successful
+                                if self.confirm_rename(&new_name) {
+                                    // Renamed, or the name didn't change
                                     self.app_mode = AppMode::List;
                                     self.rename_buffer.clear();
                                 }
@@ -521,6 +4111,9 @@ impl eframe::App for NotesApp {
                             AppMode::List => "LIST MODE",
                             AppMode::Editor => "EDITOR MODE",
                             AppMode::Rename => "RENAME MODE",
+                            AppMode::TemplatePicker => "TEMPLATE PICKER",
+                            AppMode::Search => "SEARCH",
+                            AppMode::Projects => "PROJECTS",
                         };
                         ui.label(mode_text);
                     });
@@ -531,8 +4124,9 @@ impl eframe::App for NotesApp {
                 // Create a custom text display without using TextEdit widget
                 let mut text_to_edit = self.current_note_content.clone();
 
-                // Use a ScrollArea to contain the text
-                egui::ScrollArea::vertical()
+                // Use a ScrollArea to contain the text; scroll sideways too when the note disables wrapping
+                let wrap_lines = self.note_options.wrap;
+                let clicked_url = egui::ScrollArea::new([!wrap_lines, true])
                     .auto_shrink([false, false])
                     .show(ui, |ui| {
                         // Fill the background of the available area
@@ -565,25 +4159,96 @@ impl eframe::App for NotesApp {
                         
                         // Handle tab characters explicitly to ensure proper spacing and alignment
                         let tab_spaces = "    "; // 4 spaces per tab
-                        let text_with_tabs_expanded = text_to_edit.replace('\t', tab_spaces);
                         
-                        job.append(
-                            &text_with_tabs_expanded, 
-                            0.0, 
-                            egui::TextFormat {
-                                font_id: font_id.clone(),
-                                color: text_color,
-                                ..Default::default()
+                        let text_format = egui::TextFormat {
+                            font_id: font_id.clone(),
+                            color: text_color,
+                            ..Default::default()
+                        };
+                        let selection_format = egui::TextFormat {
+                            background: if self.dark_mode {
+                                egui::Color32::from_rgb(38, 79, 120)
+                            } else {
+                                egui::Color32::from_rgb(173, 214, 255)
+                            },
+                            ..text_format.clone()
+                        };
+                        
+                        let incsearch_format = egui::TextFormat {
+                            background: if self.dark_mode {
+                                egui::Color32::from_rgb(150, 100, 20)
+                            } else {
+                                egui::Color32::from_rgb(255, 190, 90)
+                            },
+                            ..text_format.clone()
+                        };
+                        
+                        let search_match_format = egui::TextFormat {
+                            background: if self.dark_mode {
+                                egui::Color32::from_rgb(90, 80, 20)
+                            } else {
+                                egui::Color32::from_rgb(255, 240, 120)
+                            },
+                            ..text_format.clone()
+                        };
+                        
+                        // Split the text wherever its background changes: the visual
+                        // selection first, then the match of a search being typed, the
+                        // last search's matches, and annotation highlights
+                        let text_len = text_to_edit.len();
+                        let selection = self.editor.selection_ranges(&text_to_edit);
+                        let incsearch = self.editor.incsearch.filter(|&(_, end)| end <= text_len);
+                        let search_matches = self.editor.search_matches(&text_to_edit);
+                        // Headings, code and quotes are colored by the line
+                        self.blocks.update(&text_to_edit);
+                        let styled_lines = self.blocks.styled_lines();
+                        let mut boundaries = vec![0, text_len];
+                        for &(start, end) in selection.iter().chain(&incsearch).chain(&search_matches) {
+                            boundaries.extend([start, end]);
+                        }
+                        for &(start, end, _) in &styled_lines {
+                            boundaries.extend([start, end]);
+                        }
+                        for annotation in &self.annotations {
+                            boundaries.extend([annotation.start.min(text_len), annotation.end.min(text_len)]);
+                        }
+                        boundaries.sort_unstable();
+                        boundaries.dedup();
+                        
+                        for range in boundaries.windows(2) {
+                            let (start, end) = (range[0], range[1]);
+                            let mut format = if selection.iter().any(|&(s, e)| s <= start && end <= e) {
+                                selection_format.clone()
+                            } else if incsearch.is_some_and(|(s, e)| s <= start && end <= e) {
+                                incsearch_format.clone()
+                            } else if Self::in_ranges(&search_matches, start, end) {
+                                search_match_format.clone()
+                            } else if let Some(annotation) = self.annotations.iter().rev().find(|a| a.start <= start && end <= a.end) {
+                                egui::TextFormat {
+                                    background: annotation.background(self.dark_mode),
+                                    ..text_format.clone()
+                                }
+                            } else {
+                                text_format.clone()
+                            };
+                            let line = styled_lines.partition_point(|&(_, line_end, _)| line_end <= start);
+                            if let Some(&(_, _, kind)) = styled_lines.get(line).filter(|&&(line_start, _, _)| line_start <= start) {
+                                format.color = Self::line_color(kind, self.dark_mode);
                             }
-                        );
+                            job.append(&text_to_edit[start..end].replace('\t', tab_spaces), 0.0, format);
+                        }
+                        if job.sections.is_empty() {
+                            // Still lay out an (empty) first row for the cursor
+                            job.append("", 0.0, text_format.clone());
+                        }
                         
                         // Set layout options for exact character positioning
-                        job.wrap.max_width = text_area.width();
+                        job.wrap.max_width = if wrap_lines { text_area.width() } else { f32::INFINITY };
                         job.halign = egui::Align::LEFT;
                         job.justify = false; // Don't justify text to ensure character-by-character alignment
                         
                         // Allocate the entire area for interaction
-                        let _editor_response = ui.allocate_rect(text_area, egui::Sense::click());
+                        let editor_response = ui.allocate_rect(text_area, egui::Sense::click());
                         
                         // Create the text galley with our detailed job
                         let text_galley = ui.ctx().fonts(|f| f.layout_job(job));
@@ -591,6 +4256,85 @@ impl eframe::App for NotesApp {
                         // Draw the text
                         ui.painter().galley(text_area.min, text_galley.clone());
                         
+                        // Commented annotations get a marker in the right margin; hovering
+                        // the marker or the highlighted text shows the comment
+                        let mut hovered_comment = None;
+                        let hover_pos = editor_response.hover_pos();
+                        for annotation in &self.annotations {
+                            if let Some(comment) = &annotation.comment {
+                                let index = Self::expanded_index_from_byte_offset(&text_to_edit, annotation.start, tab_spaces.len());
+                                let row_rect = text_galley.pos_from_cursor(&text_galley.from_ccursor(egui::text::CCursor::new(index)));
+                                let marker = egui::Rect::from_min_size(
+                                    egui::pos2(text_area.max.x - 8.0, text_area.min.y + row_rect.min.y),
+                                    egui::vec2(4.0, row_rect.height()),
+                                );
+                                ui.painter().rect_filled(marker, 1.0, annotation.background(!self.dark_mode));
+                                if hover_pos.is_some_and(|pos| marker.expand(2.0).contains(pos)) {
+                                    hovered_comment = Some(comment.clone());
+                                }
+                            }
+                        }
+                        // Headings with tasks under them show how many are done, left of the annotation markers
+                        if self.config.section_progress {
+                            let progress_color = if self.dark_mode {
+                                egui::Color32::from_gray(130)
+                            } else {
+                                egui::Color32::from_gray(120)
+                            };
+                            // Counted again only once the note has changed
+                            let sections = self.section_progress.get_or_insert_with(|| tasks::section_progress(&text_to_edit)).clone();
+                            for section in sections {
+                                let index = Self::expanded_index_from_byte_offset(&text_to_edit, section.offset, tab_spaces.len());
+                                let row_rect = text_galley.pos_from_cursor(&text_galley.from_ccursor(egui::text::CCursor::new(index)));
+                                let color = if section.done == section.total {
+                                    egui::Color32::from_rgb(80, 160, 80)
+                                } else {
+                                    progress_color
+                                };
+                                ui.painter().text(
+                                    egui::pos2(text_area.max.x - 14.0, text_area.min.y + row_rect.center().y),
+                                    egui::Align2::RIGHT_CENTER,
+                                    section.label(),
+                                    egui::FontId::monospace(11.0),
+                                    color,
+                                );
+                            }
+                        }
+                        if let (None, Some(pointer)) = (&hovered_comment, hover_pos) {
+                            let galley_cursor = text_galley.cursor_from_pos(pointer - text_area.min);
+                            let offset = Self::byte_offset_from_expanded_index(&text_to_edit, galley_cursor.ccursor.index, tab_spaces.len());
+                            hovered_comment = self.annotations
+                                .iter()
+                                .rev()
+                                .find(|a| a.contains(offset))
+                                .and_then(|a| a.comment.clone());
+                        }
+                        if let Some(comment) = hovered_comment {
+                            egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("annotation_comment"), |ui| {
+                                ui.label(comment);
+                            });
+                        } else if let Some(pointer) = hover_pos.filter(|_| !self.bibliography.entries.is_empty()) {
+                            // A citation shows its reference
+                            let galley_cursor = text_galley.cursor_from_pos(pointer - text_area.min);
+                            let offset = Self::byte_offset_from_expanded_index(&text_to_edit, galley_cursor.ccursor.index, tab_spaces.len());
+                            if let Some(entry) = citations::key_at(&text_to_edit, offset).and_then(|key| self.bibliography.get(key)) {
+                                egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("citation"), |ui| {
+                                    ui.set_max_width(400.0);
+                                    ui.label(entry.reference(false));
+                                });
+                            }
+                        }
+                        
+                        // Ctrl+Click opens the URL under the mouse pointer
+                        let mut clicked_url = None;
+                        if editor_response.clicked() && ui.input(|i| i.modifiers.ctrl || i.modifiers.command) {
+                            if let Some(pointer) = editor_response.interact_pointer_pos() {
+                                let galley_cursor = text_galley.cursor_from_pos(pointer - text_area.min);
+                                let offset = Self::byte_offset_from_expanded_index(&text_to_edit, galley_cursor.ccursor.index, tab_spaces.len());
+                                clicked_url = links::url_at(&text_to_edit, offset);
+                            }
+                        }
+                        
                         // Draw the cursor
                         if self.app_mode == AppMode::Editor {
                             let line = self.editor.cursor_line;
@@ -693,6 +4437,24 @@ impl eframe::App for NotesApp {
                                 egui::Color32::BLACK // Black cursor for light mode
                             };
                             
+                            let view = ui.clip_rect();
+                            self.editor.page_lines = ((view.height() / cursor_line_height) as usize).max(1);
+                            self.editor.top_line = ((view.top() - text_area.min.y) / cursor_line_height).ceil().max(0.0) as usize;
+                            if std::mem::take(&mut self.editor.scroll_with_cursor) {
+                                // Ctrl+D and Ctrl+U: the view moves as far as the cursor did
+                                ui.scroll_with_delta(egui::vec2(0.0, view.top() + self.cursor_view_offset - cursor_pos.y));
+                            } else if let Some(top) = self.editor.scroll_to_line.take() {
+                                // Ctrl+F and Ctrl+B: a page on, the cursor already moved into it
+                                ui.scroll_with_delta(egui::vec2(0.0, view.top() - (text_area.min.y + top as f32 * cursor_line_height)));
+                            } else {
+                                // After a jump, like to a search match
+                                if std::mem::take(&mut self.editor.scroll_to_cursor) {
+                                    let cursor_rect = egui::Rect::from_min_size(cursor_pos, egui::vec2(cursor_width, cursor_line_height));
+                                    ui.scroll_to_rect(cursor_rect, None);
+                                }
+                                self.cursor_view_offset = cursor_pos.y - view.top();
+                            }
+                            
                             // Draw different cursors based on vim mode
                             match self.editor.vim_mode {
                                 VimMode::Insert => {
@@ -706,6 +4468,17 @@ impl eframe::App for NotesApp {
                                         cursor_color,
                                     );
                                 },
+                                VimMode::Replace => {
+                                    // Underline cursor for replace mode, under the character typing goes over
+                                    ui.painter().rect_filled(
+                                        egui::Rect::from_min_size(
+                                            egui::pos2(cursor_pos.x, cursor_pos.y + cursor_line_height - 2.0),
+                                            egui::vec2(cursor_width, 2.0),
+                                        ),
+                                        0.0,
+                                        cursor_color,
+                                    );
+                                },
                                 VimMode::Command => {
                                     // Command mode cursor (underline)
                                     ui.painter().rect_filled(
@@ -720,8 +4493,8 @@ impl eframe::App for NotesApp {
                                         egui::Color32::from_rgb(255, 0, 0), // Red for command mode
                                     );
                                 },
-                                VimMode::Normal => {
-                                    // Block cursor for normal mode
+                                VimMode::Normal | VimMode::Visual | VimMode::VisualBlock => {
+                                    // Block cursor for normal and visual mode
                                     ui.painter().rect_filled(
                                         egui::Rect::from_min_size(
                                             cursor_pos,
@@ -737,23 +4510,49 @@ impl eframe::App for NotesApp {
                                     );
                                 },
                             }
+                            
+                            // @mention and @citekey completion popup just below the cursor
+                            if self.editor.vim_mode == VimMode::Insert {
+                                if let Some((_, matches)) = self.completions(&text_to_edit) {
+                                    egui::Area::new("mention_completion")
+                                        .fixed_pos(cursor_pos + egui::vec2(0.0, cursor_line_height))
+                                        .order(egui::Order::Foreground)
+                                        .show(ui.ctx(), |ui| {
+                                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                                for (index, (_, label)) in matches.iter().enumerate() {
+                                                    let selected = index == self.completion_index % matches.len();
+                                                    let _ = ui.selectable_label(selected, label);
+                                                }
+                                            });
+                                        });
+                                }
+                            }
                         }
-                    });
+                        
+                        clicked_url
+                    }).inner;
+                
+                if let Some(url) = clicked_url {
+                    self.open_url(&url);
+                }
                 
                 // Handle key events for editing only when in Editor mode
                 let mut editor_changed = false;
 
-                if self.app_mode == AppMode::Editor {
+                // Not while the passphrase for `:lock set` is being typed
+                if self.app_mode == AppMode::Editor && self.passphrase_dialog.is_none() {
                     // Handle key events for editing
                     let mut editor_events = Vec::new();
                     
+                    // Text typed in normal or visual mode is only used for commands
+                    // whose key has no egui::Key of its own, like `"`
+                    let normal_text = matches!(self.editor.vim_mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock);
+                    
                     ctx.input(|i| {
                         for event in &i.events {
                             match event {
-                                egui::Event::Text(_) => {
-                                    if matches!(self.editor.vim_mode, VimMode::Insert | VimMode::Command) {
-                                        editor_events.push(event.clone());
-                                    }
+                                egui::Event::Text(_) if matches!(self.editor.vim_mode, VimMode::Insert | VimMode::Replace | VimMode::Command) || normal_text => {
+                                    editor_events.push(event.clone());
                                 },
                                 egui::Event::Key {
                                     pressed: true,
@@ -767,8 +4566,33 @@ impl eframe::App for NotesApp {
                     });
                     
                     // Process captured events
-                    for event in editor_events {
+                    let mut note_switched = false;
+                    let mut editor_events = editor_events.into_iter();
+                    loop {
+                        // Keys of a macro played with @ come first and are handled like typed ones
+                        let (event, normal_text) = match self.editor.next_macro_event() {
+                            Some(event) => {
+                                // They're only what was used when recording, so go by the current mode
+                                let normal_text = matches!(self.editor.vim_mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock);
+                                (event, normal_text)
+                            },
+                            None => match editor_events.next() {
+                                Some(event) => (event, normal_text),
+                                None => break,
+                            },
+                        };
                         match event {
+                            egui::Event::Text(text) if normal_text => {
+                                for c in text.chars() {
+                                    let (handled, action) = self.editor.handle_normal_text(c, &mut text_to_edit);
+                                    if handled {
+                                        editor_changed = true;
+                                    }
+                                    if let Some(message) = action.as_deref().and_then(|action| action.strip_prefix("status ")) {
+                                        self.set_status(message);
+                                    }
+                                }
+                            },
                             egui::Event::Text(text) => {
                                 // Skip text input if we just entered insert mode via key press
                                 if self.just_entered_insert_mode {
@@ -776,7 +4600,7 @@ impl eframe::App for NotesApp {
                                     continue; // Skip all text input in this frame
                                 }
                                 
-                                if matches!(self.editor.vim_mode, VimMode::Insert | VimMode::Command) {
+                                if matches!(self.editor.vim_mode, VimMode::Insert | VimMode::Replace | VimMode::Command) {
                                     // Check for colon in normal mode to enter command mode
                                     if self.editor.vim_mode == VimMode::Normal && text == ":" {
                                         self.editor.vim_mode = VimMode::Command;
@@ -784,9 +4608,10 @@ impl eframe::App for NotesApp {
                                         continue; // Skip adding the character to the text
                                     }
                                     
-                                    for c in text.chars() {
+    for c in text.chars() {
                                         if c >= ' ' || c == '\n' || c == '\t' {  // Printable characters, newlines, and tabs
                                             self.editor.handle_text_input(c, &mut text_to_edit);
+                                            self.completion_index = 0;
                                             editor_changed = true;
                                         }
                                     }
@@ -798,6 +4623,11 @@ impl eframe::App for NotesApp {
                                 modifiers,
                                 ..
                             } => {
+                                if self.editor.vim_mode == VimMode::Insert && self.handle_completion_key(key, &modifiers, &mut text_to_edit) {
+                                    editor_changed = true;
+                                    continue;
+                                }
+                                
                                 // Process keys for vim normal mode navigation
                                 let (key_handled, command_action) = self.editor.handle_key_press(key, &mut text_to_edit, &modifiers);
                                 if key_handled {
@@ -817,19 +4647,238 @@ impl eframe::App for NotesApp {
                                             self.save_current_note();
                                             self.app_mode = AppMode::List;
                                         },
-                                        _ => {}
+                                        "reveal" => {
+                                            self.reveal_current_note();
+                                        },
+                                        "copy_path" => {
+                                            if let Some(path) = self.current_note_path() {
+                                                let path = path.display().to_string();
+                                                ctx.output_mut(|o| o.copied_text = path.clone());
+                                                self.set_status(format!("Copied {}", path));
+                                            }
+                                        },
+                                        "bookmarks" => {
+                                            self.show_bookmarks = !self.show_bookmarks;
+                                        },
+                                        "index status" | "index rebuild" => {
+                                            self.handle_index_command(&action["index ".len()..], ctx);
+                                        },
+                                        "cnext" | "cprev" => {
+                                            // May switch notes; keep this frame's edits first
+                                            if editor_changed {
+                                                self.current_note_content = text_to_edit.clone();
+                                            }
+                                            self.quickfix_jump(if action == "cnext" { 1 } else { -1 });
+                                            note_switched = true;
+                                        },
+                                        "tasks" => self.show_tasks = true,
+                                        "projects" => {
+                                            if editor_changed {
+                                                self.current_note_content = text_to_edit.clone();
+                                            }
+                                            self.open_projects();
+                                        },
+                                        "feeds" => {
+                                            match &self.feeds {
+                                                Some(reader) => {
+                                                    reader.refresh();
+                                                    self.set_status("Fetching feeds...");
+                                                },
+                                                None if self.offline => self.set_status("Offline; feeds are off until :set nooffline"),
+                                                None => self.set_status("No feeds configured; add `feeds = [...]` to config.toml"),
+                                            }
+                                        },
+                                        "paste_image" => {
+                                            // The link is added to the note content directly
+                                            if editor_changed {
+                                                self.current_note_content = text_to_edit.clone();
+                                            }
+                                            self.paste_image(ctx);
+                                            note_switched = true;
+                                        },
+                                        "copy_relative_path" => {
+                                            // Note names are already relative to the notes folder
+                                            let relative = file_name.replace('\\', "/");
+                                            ctx.output_mut(|o| o.copied_text = relative.clone());
+                                            self.set_status(format!("Copied {}", relative));
+                                        },
+                                        other => {
+                                            if let Some(url) = other.strip_prefix("open_url ") {
+                                                self.open_url(url);
+                                            } else if let Some(message) = other.strip_prefix("status ") {
+                                                self.set_status(message);
+                                            } else if let Some(args) = other.strip_prefix("mail ") {
+                                                // Importing a single message opens it
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.import_email(args);
+                                                note_switched = true;
+                                            } else if let Some(path) = other.strip_prefix("attach ") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.attach_file(Path::new(path), ctx);
+                                                note_switched = true;
+                                            } else if let Some(path) = other.strip_prefix("export_html") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.export_note_html(path.trim());
+                                            } else if let Some(args) = other.strip_prefix("export_site") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.export_site(args, ctx);
+                                                note_switched = true;
+                                            } else if let Some(query) = other.strip_prefix("search") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_search(query.trim_start(), ctx);
+                                            } else if let Some(names) = other.strip_prefix("registers") {
+                                                // Running it again refreshes the list
+                                                self.register_list = Some(self.editor.registers.list(names.trim()));
+                                            } else if let Some(names) = other.strip_prefix("marks") {
+                                                // Running it again refreshes the list
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.mark_list = Some(self.list_marks(names.trim()));
+                                            } else if let Some(args) = other.strip_prefix("layout") {
+                                                // May switch notes; keep this frame's edits first
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.handle_layout_command(args.trim_start());
+                                                note_switched = true;
+                                            } else if let Some(args) = other.strip_prefix("settings ") {
+                                                self.handle_settings_command(args);
+                                            } else if other == "merge" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_merge();
+                                            } else if other == "convert_utf8" {
+                                                self.convert_to_utf8();
+                                            } else if let Some(name) = other.strip_prefix("new_note ") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.create_named_note(name);
+                                                note_switched = true;
+                                            } else if other == "tutor" || other == "tutor reset" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_tutor(other == "tutor reset");
+                                            } else if other == "undo_vault" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.undo_vault();
+                                            } else if let Some(args) = other.strip_prefix("pomodoro") {
+                                                self.handle_pomodoro_command(args.trim());
+                                            } else if let Some(args) = other.strip_prefix("lock") {
+                                                self.handle_lock_command(args.trim());
+                                            } else if other == "maintenance" {
+                                                self.run_maintenance(true);
+                                            } else if other == "tags" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_tags();
+                                            } else if other == "properties" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_properties();
+                                            } else if let Some(source) = other.strip_prefix("meetings") {
+                                                self.import_meetings(source.trim(), ctx);
+                                            } else if let Some(args) = other.strip_prefix("set ") {
+                                                self.set_options(args, ctx);
+                                            } else if let Some(args) = other.strip_prefix("highlight ") {
+                                                let parts: Vec<&str> = args.splitn(3, ' ').collect();
+                                                if let [start, end, color] = parts[..] {
+                                                    if let (Ok(start), Ok(end)) = (start.parse(), end.parse()) {
+                                                        self.add_annotation(start, end, color, None);
+                                                    }
+                                                }
+                                            } else if let Some(args) = other.strip_prefix("annotate ") {
+                                                let parts: Vec<&str> = args.splitn(3, ' ').collect();
+                                                if let [start, end, comment] = parts[..] {
+                                                    if let (Ok(start), Ok(end)) = (start.parse(), end.parse()) {
+                                                        let comment = Some(comment.to_string()).filter(|c| !c.is_empty());
+                                                        self.add_annotation(start, end, "yellow", comment);
+                                                    }
+                                                }
+                                            } else if let Some(pos) = other.strip_prefix("unannotate ") {
+                                                if let Ok(pos) = pos.parse() {
+                                                    self.remove_annotations_at(pos);
+                                                }
+                                            } else if let Some(args) = other.strip_prefix("bookmark ") {
+                                                // Jumping may switch notes; keep this frame's edits first
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.handle_bookmark_command(args);
+                                                note_switched = true;
+                                            } else if let Some(name) = other.strip_prefix("global_mark ") {
+                                                if let Some(name) = name.chars().next() {
+                                                    self.set_global_mark(name);
+                                                }
+                                            } else if let Some(args) = other.strip_prefix("jump_global_mark ") {
+                                                // Keep this frame's edits before switching notes
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                if let Some((name, exact)) = args.split_once(' ') {
+                                                    if let Some(name) = name.chars().next() {
+                                                        self.jump_to_global_mark(name, exact == "true");
+                                                    }
+                                                }
+                                                note_switched = true;
+                                            } else if let Some(file_name) = other.strip_prefix("open_note ") {
+                                                // Keep this frame's edits before switching notes
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                self.open_note(file_name);
+                                                note_switched = true;
+                                            } else if let Some(path) = other.strip_prefix("open_file ") {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                note_switched = self.open_file(path);
+                                            } else if other == "alternate_note" {
+                                                if editor_changed {
+                                                    self.current_note_content = text_to_edit.clone();
+                                                }
+                                                note_switched = self.open_alternate_note();
+                                            }
+                                        }
                                     }
                                 }
                             },
                             _ => {}
                         }
+                        
+                        // The remaining events belong to the note we just left
+                        if note_switched {
+                            break;
+                        }
                     }
 
                     // Update content if editor has changed
-                    if editor_changed {
+                    if editor_changed && !note_switched {
                         self.current_note_content = text_to_edit;
                         self.last_save_time = Instant::now(); // Reset auto-save timer
                     }
+                    if editor_changed || self.editor.macros.is_playing() {
+                        // Show the result right away rather than on the next input event,
+                        // and finish a macro that switched notes in the next frame
+                        ctx.request_repaint();
+                    }
                 }
                 
                 // Show editor status line
@@ -841,12 +4890,49 @@ impl eframe::App for NotesApp {
                         self.editor.cursor_column + 1
                     ));
                     
+                    let options_text = self.note_options.status_text();
+                    if !options_text.is_empty() {
+                        ui.label(options_text);
+                    }
+                    
+                    // Background tasks: the one running with how far along it
+                    // is, or how many there are; a click opens :tasks
+                    let tasks = self.background_tasks();
+                    let tasks_text = match tasks.as_slice() {
+                        [] => None,
+                        [task] => Some(task.label()),
+                        _ => Some(format!("{} tasks", tasks.len())),
+                    };
+                    if let Some(tasks_text) = tasks_text {
+                        let label = egui::Label::new(egui::RichText::new(tasks_text).weak()).sense(egui::Sense::click());
+                        if ui.add(label).on_hover_text("Background tasks (:tasks)").clicked() {
+                            self.show_tasks = true;
+                        }
+                        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                    }
+                    
+                    if let Some(pomodoro) = &self.pomodoro {
+                        ui.label(pomodoro.label());
+                        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                    }
+                    
+                    if let Some((message, shown_at)) = &self.status_message {
+                        if shown_at.elapsed().as_secs() < STATUS_MESSAGE_SECS {
+                            ui.label(egui::RichText::new(message).italics());
+                            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                        }
+                    }
+                    
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.label(format!("Uptime: {:02}:{:02}:{:02}", 
                             elapsed.as_secs() / 3600,
                             (elapsed.as_secs() % 3600) / 60,
                             elapsed.as_secs() % 60
                         ));
+                        if self.offline {
+                            ui.colored_label(ui.visuals().warn_fg_color, "OFFLINE")
+                                .on_hover_text("Feeds, the local API and calendar URLs are off; :set nooffline");
+                        }
                     });
                 });
             } else {
@@ -855,5 +4941,11 @@ impl eframe::App for NotesApp {
                 });
             }
         });
+        
+        self.feedback.show(ctx);
+        
+        if self.startup_pending {
+            self.start_loading(ctx);
+        }
     }
 } 
\ No newline at end of file
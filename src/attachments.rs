@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Files attached to notes (dropped on the window, `:attach`, pasted images,
+// email attachments). They are kept in assets/ and linked from the note.
+
+pub const ASSETS_DIR: &str = "assets";
+
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "webp", "bmp", "tiff"];
+const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "wav", "m4a", "ogg", "oga", "opus", "flac", "aac"];
+
+// Write `data` to assets/ and return its path relative to the notes folder.
+// A file with the same name but different contents gets a -2, -3... suffix;
+// attaching the same file twice reuses the first copy.
+pub fn save(notes_dir: &Path, file_name: &str, data: &[u8]) -> io::Result<String> {
+    fs::create_dir_all(notes_dir.join(ASSETS_DIR))?;
+
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+        _ => (file_name.to_string(), String::new()),
+    };
+    let mut path = format!("{}/{}", ASSETS_DIR, file_name);
+    let mut counter = 2;
+    loop {
+        match fs::read(notes_dir.join(&path)) {
+            Ok(existing) if existing == data => return Ok(path),
+            Ok(_) => {
+                path = format!("{}/{}-{}{}", ASSETS_DIR, stem, counter, extension);
+                counter += 1;
+            },
+            Err(_) => {
+                fs::write(notes_dir.join(&path), data)?;
+                return Ok(path);
+            },
+        }
+    }
+}
+
+pub fn is_image(file_name: &str) -> bool {
+    has_extension(file_name, &IMAGE_EXTENSIONS)
+}
+
+pub fn is_audio(file_name: &str) -> bool {
+    has_extension(file_name, &AUDIO_EXTENSIONS)
+}
+
+fn has_extension(file_name: &str, extensions: &[&str]) -> bool {
+    file_name
+        .rsplit_once('.')
+        .is_some_and(|(_, extension)| extensions.contains(&extension.to_lowercase().as_str()))
+}
+
+// Markdown link to an attachment; images are embedded
+pub fn markdown_link(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let target = path.replace(' ', "%20");
+    if is_image(path) {
+        format!("![{}]({})", name, target)
+    } else {
+        format!("[{}]({})", name, target)
+    }
+}
+
+// Encode 8-bit RGBA pixels (e.g. a clipboard image) as a PNG. The image data
+// is stored uncompressed, which keeps this small; screenshots are rarely
+// large enough for that to matter.
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    // Each row starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks(width * 4).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // zlib stream of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(if blocks.peek().is_none() { 1 } else { 0 });
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8-bit RGBA, no interlacing
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = 0xffff_ffffu32;
+    for &byte in kind.iter().chain(data) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    png.extend_from_slice(&(!crc).to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+// Work running on background threads, gathered up for the status line and
+// the `:tasks` window: OCR and transcription of attachments, `:index rebuild`
+// and `:export site`. Each one can be cancelled; its thread looks at its
+// Cancel between steps and stops there.
+
+// Shared with a background thread to tell it to stop
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Which task a row of `:tasks` is, to cancel it with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskId {
+    Job(usize), // Index in Jobs::running
+    IndexRebuild,
+    SiteExport,
+}
+
+pub struct Task {
+    pub id: TaskId,
+    pub name: String,                     // "OCR scan.png", "Indexing"
+    pub progress: Option<(usize, usize)>, // Done out of how many, when that's known
+    pub started: Instant,
+    pub cancelled: bool, // Told to stop, and about to
+}
+
+impl Task {
+    // For the status line: "Indexing 120/512" or "OCR scan.png 4s"
+    pub fn label(&self) -> String {
+        match self.progress {
+            Some((done, total)) => format!("{} {}/{}", self.name, done, total),
+            None => format!("{} {}s", self.name, self.started.elapsed().as_secs()),
+        }
+    }
+
+    // How far along it is, for a progress bar
+    pub fn fraction(&self) -> Option<f32> {
+        self.progress.map(|(done, total)| if total == 0 { 1.0 } else { done as f32 / total as f32 })
+    }
+}
@@ -0,0 +1,21 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// With `backups = true` in config.toml, the first save of a note each day
+// copies the note as it was before into .vimnote/backups/{date}/, keeping its
+// folders, so there's a version per day to go back to.
+
+pub const BACKUPS_DIR: &str = "backups";
+
+pub fn back_up(notes_dir: &Path, backups_dir: &Path, file_name: &str, date: &str) -> io::Result<()> {
+    let source = notes_dir.join(file_name);
+    let target = backups_dir.join(date).join(file_name);
+    if target.exists() || !source.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(source, target).map(|_| ())
+}
@@ -0,0 +1,153 @@
+use crate::markdown;
+
+// What each line of the open note is, for coloring headings, code blocks and
+// quotes in the editor. Kept from frame to frame and only parsed again around
+// what was edited: from the first edited line until the lines after the edit
+// are back in the state they were in before (e.g. not inside a code block the
+// edit opened), so typing in a long note doesn't re-parse all of it.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    Text,
+    Heading(usize),
+    Quote,
+    FenceOpen(&'static str), // ``` or ~~~ starting a code block
+    Code(&'static str),      // Inside a code block opened with this fence
+    FenceClose,
+}
+
+impl LineKind {
+    fn parse(line: &str, fence: Option<&'static str>) -> Self {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(current) if trimmed.starts_with(current) => LineKind::FenceClose,
+            Some(current) => LineKind::Code(current),
+            None => {
+                if let Some(opening) = markdown::code_fence(trimmed) {
+                    LineKind::FenceOpen(opening)
+                } else if let Some((level, _)) = markdown::heading(trimmed) {
+                    LineKind::Heading(level)
+                } else if trimmed.starts_with('>') {
+                    LineKind::Quote
+                } else {
+                    LineKind::Text
+                }
+            },
+        }
+    }
+
+    // The fence of the code block the next line is in, if any
+    fn fence_after(self) -> Option<&'static str> {
+        match self {
+            LineKind::FenceOpen(fence) | LineKind::Code(fence) => Some(fence),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Line {
+    start: usize,
+    kind: LineKind,
+}
+
+#[derive(Debug, Default)]
+pub struct Blocks {
+    lines: Vec<Line>,
+    parsed_len: usize, // Length of the text the lines were parsed from
+    text_len: usize,   // and of the text after the edits since
+    // The edits since, merged into one: where the first of them starts and
+    // how much of the end of the text none of them touched
+    pending: Option<(usize, usize)>,
+    stale: bool, // Parse all of the text again
+}
+
+impl Blocks {
+    pub fn new() -> Self {
+        Self { stale: true, ..Self::default() }
+    }
+
+    // A different note was opened
+    pub fn reset(&mut self) {
+        self.stale = true;
+        self.pending = None;
+    }
+
+    // Bytes start..old_end were replaced with what's now at start..new_end
+    pub fn edited(&mut self, start: usize, old_end: usize, new_end: usize) {
+        if old_end > self.text_len {
+            self.stale = true;
+            return;
+        }
+        let unchanged_end = self.text_len - old_end;
+        self.text_len = self.text_len - old_end + new_end;
+        self.pending = Some(match self.pending {
+            Some((first_start, first_unchanged)) => (first_start.min(start), first_unchanged.min(unchanged_end)),
+            None => (start, unchanged_end),
+        });
+    }
+
+    // Bring the lines up to date with `text`
+    pub fn update(&mut self, text: &str) {
+        let pending = self.pending.take();
+        // An edit went by unnoticed if the lengths don't add up
+        if self.stale || self.text_len != text.len() {
+            self.lines.clear();
+            self.reparse(text, 0, 0, 0);
+            self.stale = false;
+        } else if let Some((start, unchanged_end)) = pending {
+            let old_end = self.parsed_len - unchanged_end;
+            let new_end = text.len() - unchanged_end;
+            self.reparse(text, start.min(old_end).min(new_end), old_end, new_end);
+        }
+        self.parsed_len = text.len();
+        self.text_len = text.len();
+    }
+
+    // Parse the lines from the one with `start` in it until they're the same
+    // as before again, and move the ones after by how much the edit added or removed
+    fn reparse(&mut self, text: &str, start: usize, old_end: usize, new_end: usize) {
+        let first = self.lines.partition_point(|line| line.start <= start).saturating_sub(1);
+        let mut next_old = self.lines.partition_point(|line| line.start <= old_end);
+        let moved = |line: &Line| line.start + new_end - old_end;
+        let mut fence = first.checked_sub(1).and_then(|i| self.lines[i].kind.fence_after());
+        let mut pos = self.lines.get(first).map_or(0, |line| line.start);
+        let mut parsed = Vec::new();
+        let mut resume = self.lines.len();
+        while pos <= text.len() {
+            if pos > new_end {
+                while next_old < self.lines.len() && moved(&self.lines[next_old]) < pos {
+                    next_old += 1;
+                }
+                let fence_before = |i: usize| self.lines[i - 1].kind.fence_after();
+                if next_old < self.lines.len() && moved(&self.lines[next_old]) == pos && fence_before(next_old) == fence {
+                    resume = next_old;
+                    break;
+                }
+            }
+            let end = text[pos..].find('\n').map_or(text.len(), |newline| pos + newline);
+            let kind = LineKind::parse(&text[pos..end], fence);
+            fence = kind.fence_after();
+            parsed.push(Line { start: pos, kind });
+            pos = end + 1;
+        }
+        let parsed_count = parsed.len();
+        self.lines.splice(first..resume, parsed);
+        for line in &mut self.lines[first + parsed_count..] {
+            line.start = line.start + new_end - old_end;
+        }
+    }
+
+    // Start, end and kind of the lines that aren't plain text
+    pub fn styled_lines(&self) -> Vec<(usize, usize, LineKind)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.kind != LineKind::Text)
+            .map(|(i, line)| {
+                let end = self.lines.get(i + 1).map_or(self.parsed_len, |next| next.start);
+                (line.start, end, line.kind)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A named "continue reading here" spot in a note. Unlike vim marks these are
+// kept on disk and shared by all notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub label: String,
+    pub note: String,
+    pub line: usize, // 0-based
+    // Text of the bookmarked line, used to find it again after edits above it
+    pub line_text: String,
+}
+
+impl Bookmark {
+    // Line to jump to in the note's current content: the saved line if it
+    // still has the same text, else the nearest line that does, else the
+    // saved line number
+    pub fn resolve_line(&self, content: &str) -> usize {
+        let lines: Vec<&str> = content.lines().collect();
+        if lines.get(self.line) == Some(&self.line_text.as_str()) || self.line_text.trim().is_empty() {
+            return self.line.min(lines.len().saturating_sub(1));
+        }
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| **line == self.line_text)
+            .min_by_key(|(i, _)| i.abs_diff(self.line))
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| self.line.min(lines.len().saturating_sub(1)))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bookmarks {
+    pub items: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    // Load saved bookmarks; a missing or unreadable file means no bookmarks
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid bookmarks file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    // Add a bookmark, replacing any existing one with the same label
+    pub fn add(&mut self, bookmark: Bookmark) {
+        self.items.retain(|b| b.label != bookmark.label);
+        self.items.push(bookmark);
+    }
+
+    pub fn remove(&mut self, label: &str) -> bool {
+        let count = self.items.len();
+        self.items.retain(|b| b.label != label);
+        self.items.len() != count
+    }
+
+    pub fn position(&self, label: &str) -> Option<usize> {
+        self.items.iter().position(|b| b.label == label)
+    }
+
+    // Keep bookmarks pointing at a note that was renamed
+    pub fn rename_note(&mut self, old_name: &str, new_name: &str) {
+        for bookmark in self.items.iter_mut().filter(|b| b.note == old_name) {
+            bookmark.note = new_name.to_string();
+        }
+    }
+
+    pub fn remove_note(&mut self, note: &str) {
+        self.items.retain(|b| b.note != note);
+    }
+}
+
+// Bookmark labels may be quoted to allow spaces: `:bookmark add "chapter 3"`
+pub fn parse_label(arg: &str) -> String {
+    let arg = arg.trim();
+    arg.strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or(arg)
+        .to_string()
+}
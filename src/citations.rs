@@ -0,0 +1,524 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::markdown::{self, Cited};
+
+// Citations for academic notes: `@citekey` refers to an entry of the BibTeX
+// file set as `bibliography` in config.toml (Zotero's Better BibTeX export,
+// say). Keys complete in insert mode like @mentions, hovering one shows its
+// reference, and HTML exports render them author-date, `@key` as "Smith
+// (2020)" and `[@key, p. 3; @other]` as "(Smith 2020, p. 3; Jones 2019)", with
+// a References list at the end. An `@word` that isn't a key stays a mention.
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub kind: String, // article, book, inproceedings...
+    pub fields: HashMap<String, String>, // Lowercase names, values without braces or TeX
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bibliography {
+    pub entries: Vec<Entry>,
+}
+
+impl Bibliography {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // The entries of a .bib file, skipping what can't be read
+    pub fn parse(bib: &str) -> Self {
+        let mut strings: HashMap<String, String> = HashMap::new();
+        let mut entries = Vec::new();
+        let mut rest = bib;
+
+        while let Some(at) = rest.find('@') {
+            let mut parser = Parser { text: &rest[at + 1..] };
+            let kind = parser.word().to_lowercase();
+            let close = match parser.open() {
+                Some(close) => close,
+                None => {
+                    rest = parser.text;
+                    continue;
+                },
+            };
+            match kind.as_str() {
+                "comment" | "preamble" => parser.skip_block(close),
+                "string" => {
+                    for (name, value) in parser.fields(close, &strings) {
+                        strings.insert(name, value);
+                    }
+                },
+                _ => {
+                    let key = parser.until_comma(close).trim().to_string();
+                    let fields = parser.fields(close, &strings).into_iter().collect();
+                    if !key.is_empty() {
+                        entries.push(Entry { key, kind, fields });
+                    }
+                },
+            }
+            parser.skip_past(close);
+            rest = parser.text;
+        }
+        Self { entries }
+    }
+
+    // Keys are matched in any case, like BibTeX does
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key.eq_ignore_ascii_case(key))
+    }
+
+    // Entries whose key starts with the prefix, then those whose key, author
+    // or title merely contains it
+    pub fn matching(&self, prefix: &str) -> Vec<&Entry> {
+        let prefix = prefix.to_lowercase();
+        let contains = |entry: &&Entry| {
+            [entry.key.as_str(), entry.field("author"), entry.field("title")]
+                .iter()
+                .any(|text| text.to_lowercase().contains(&prefix))
+        };
+        let (mut matches, others): (Vec<&Entry>, Vec<&Entry>) =
+            self.entries.iter().filter(contains).partition(|entry| entry.key.to_lowercase().starts_with(&prefix));
+        matches.extend(others);
+        matches
+    }
+}
+
+impl Entry {
+    pub fn field(&self, name: &str) -> &str {
+        self.fields.get(name).map_or("", String::as_str)
+    }
+
+    // Authors as written, or the editors of a book without any
+    fn names(&self) -> Vec<&str> {
+        let names = if self.field("author").is_empty() { self.field("editor") } else { self.field("author") };
+        names.split(" and ").map(str::trim).filter(|name| !name.is_empty()).collect()
+    }
+
+    // "Smith", "Smith & Jones" or "Smith et al."; the title without any names
+    pub fn authors(&self) -> String {
+        let last_names: Vec<&str> = self.names().into_iter().map(last_name).collect();
+        match last_names.as_slice() {
+            [] => self.field("title").to_string(),
+            [one] => one.to_string(),
+            [one, two] => format!("{} & {}", one, two),
+            [one, ..] => format!("{} et al.", one),
+        }
+    }
+
+    pub fn year(&self) -> &str {
+        let year = self.field("year");
+        let year = if year.is_empty() { self.field("date").get(..4).unwrap_or("") } else { year };
+        if year.is_empty() { "n.d." } else { year }
+    }
+
+    pub fn cited(&self) -> Cited {
+        Cited { key: self.key.clone(), authors: self.authors(), year: self.year().to_string() }
+    }
+
+    // "Smith 2020", as in a bracketed citation
+    pub fn label(&self) -> String {
+        format!("{} {}", self.authors(), self.year())
+    }
+
+    // The entry for a reference list, author-date: "Smith, J., & Jones, K.
+    // (2020). Title. Journal, 12(3), 45–67." With `html` the title of a book or thesis, or else the journal or
+    // book it's in, is in italics, and a DOI or URL is a link.
+    pub fn reference(&self, html: bool) -> String {
+        let text = |text: &str| if html { markdown::escape_html(text) } else { text.to_string() };
+        let italic = |text: String| if html { format!("<em>{}</em>", text) } else { text };
+        let standalone = matches!(self.kind.as_str(), "book" | "phdthesis" | "mastersthesis" | "thesis" | "techreport" | "report" | "manual");
+        let names: Vec<String> = self.names().into_iter().map(|name| text(&initials(name))).collect();
+        let mut reference = match names.split_last() {
+            None => String::new(),
+            Some((last, [])) => format!("{} ", last),
+            Some((last, others)) => format!("{}, {} {} ", others.join(", "), if html { "&amp;" } else { "&" }, last),
+        };
+        reference.push_str(&format!("({}).", text(self.year())));
+        let title = text(self.field("title").trim_end_matches('.'));
+        if !title.is_empty() {
+            reference.push_str(&format!(" {}.", if standalone { italic(title) } else { title }));
+        }
+
+        let container = ["journal", "journaltitle", "booktitle", "publisher", "institution", "school"]
+            .into_iter()
+            .map(|name| self.field(name))
+            .find(|container| !container.is_empty());
+        if let Some(container) = container {
+            let mut details = if standalone { text(container) } else { italic(text(container)) };
+            if !self.field("volume").is_empty() {
+                details.push_str(&format!(", {}", text(self.field("volume"))));
+                if !self.field("number").is_empty() {
+                    details.push_str(&format!("({})", text(self.field("number"))));
+                }
+            }
+            if !self.field("pages").is_empty() {
+                details.push_str(&format!(", {}", text(self.field("pages"))));
+            }
+            reference.push_str(&format!(" {}.", details));
+        }
+
+        let link = match self.field("doi") {
+            "" => self.field("url").to_string(),
+            doi if doi.starts_with("http") => doi.to_string(),
+            doi => format!("https://doi.org/{}", doi),
+        };
+        if !link.is_empty() {
+            if html {
+                reference.push_str(&format!(" <a href=\"{0}\">{0}</a>", text(&link)));
+            } else {
+                reference.push_str(&format!(" {}", link));
+            }
+        }
+        reference
+    }
+}
+
+// "Smith, John" and "John Smith" are both Smith, "van Dijk, Anna" is van Dijk
+fn last_name(name: &str) -> &str {
+    split_name(name).0
+}
+
+// The last name and the first names
+fn split_name(name: &str) -> (&str, &str) {
+    match name.split_once(',') {
+        Some((last, first)) => (last.trim(), first.trim()),
+        None => match name.rsplit_once(' ') {
+            Some((first, last)) => (last, first.trim()),
+            None => (name, ""),
+        },
+    }
+}
+
+// "John Ronald Smith" as "Smith, J. R.", "Jean-Paul Sartre" as "Sartre, J.-P."
+fn initials(name: &str) -> String {
+    let (last, first) = split_name(name);
+    let initials: Vec<String> = first
+        .split_whitespace()
+        .map(|part| {
+            part.split('-')
+                .filter_map(|part| part.chars().next())
+                .map(|initial| format!("{}.", initial))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect();
+    if initials.is_empty() {
+        last.to_string()
+    } else {
+        format!("{}, {}", last, initials.join(" "))
+    }
+}
+
+// Reads a .bib file a piece at a time
+struct Parser<'a> {
+    text: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_blanks(&mut self) {
+        self.text = self.text.trim_start();
+    }
+
+    fn word(&mut self) -> &'a str {
+        self.skip_blanks();
+        let end = self.text.find(|c: char| !(c.is_alphanumeric() || "_-:.+/".contains(c))).unwrap_or(self.text.len());
+        let (word, rest) = self.text.split_at(end);
+        self.text = rest;
+        word
+    }
+
+    // The `{` or `(` an entry starts with, returning what closes it
+    fn open(&mut self) -> Option<char> {
+        self.skip_blanks();
+        let close = match self.text.chars().next()? {
+            '{' => '}',
+            '(' => ')',
+            _ => return None,
+        };
+        self.text = &self.text[1..];
+        Some(close)
+    }
+
+    // The text up to the next `,`, which is skipped, or the entry's end
+    fn until_comma(&mut self, close: char) -> &'a str {
+        let end = self.text.find([',', close]).unwrap_or(self.text.len());
+        let (before, rest) = self.text.split_at(end);
+        self.text = rest.strip_prefix(',').unwrap_or(rest);
+        before
+    }
+
+    // The rest of an entry, with the braces in it kept balanced
+    fn skip_block(&mut self, close: char) {
+        let mut depth = 0;
+        for (i, c) in self.text.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => {
+                    self.text = &self.text[i..];
+                    return;
+                },
+                _ => {},
+            }
+        }
+        self.text = "";
+    }
+
+    fn skip_past(&mut self, close: char) {
+        self.skip_blanks();
+        if self.text.starts_with(close) {
+            self.text = &self.text[1..];
+        }
+    }
+
+    // `name = value` pairs up to the entry's end
+    fn fields(&mut self, close: char, strings: &HashMap<String, String>) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        loop {
+            self.skip_blanks();
+            if self.text.is_empty() || self.text.starts_with(close) {
+                break;
+            }
+            let name = self.word().to_lowercase();
+            self.skip_blanks();
+            if name.is_empty() || !self.text.starts_with('=') {
+                // Not a field; skip to the next one
+                self.until_comma(close);
+                continue;
+            }
+            self.text = &self.text[1..];
+            let value = self.value(strings);
+            fields.push((name, clean(&value)));
+            self.skip_blanks();
+            if self.text.starts_with(',') {
+                self.text = &self.text[1..];
+            }
+        }
+        fields
+    }
+
+    // A value: `{braced}`, `"quoted"`, a number or an @string name, joined by `#`
+    fn value(&mut self, strings: &HashMap<String, String>) -> String {
+        let mut value = String::new();
+        loop {
+            self.skip_blanks();
+            match self.text.chars().next() {
+                Some('{') => value.push_str(self.delimited('{', '}')),
+                Some('"') => value.push_str(self.delimited('"', '"')),
+                Some(_) => {
+                    let word = self.word();
+                    if word.is_empty() {
+                        break;
+                    }
+                    match strings.get(&word.to_lowercase()) {
+                        Some(string) => value.push_str(string),
+                        None => value.push_str(month(word).unwrap_or(word)),
+                    }
+                },
+                None => break,
+            }
+            self.skip_blanks();
+            if !self.text.starts_with('#') {
+                break;
+            }
+            self.text = &self.text[1..];
+        }
+        value
+    }
+
+    // What's between `open` and `close`, with the braces inside kept balanced
+    fn delimited(&mut self, open: char, close: char) -> &'a str {
+        let mut depth = 0;
+        for (i, c) in self.text.char_indices().skip(1) {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => {
+                    let inner = &self.text[open.len_utf8()..i];
+                    self.text = &self.text[i + 1..];
+                    return inner;
+                },
+                _ => {},
+            }
+        }
+        let inner = &self.text[open.len_utf8()..];
+        self.text = "";
+        inner
+    }
+}
+
+fn month(word: &str) -> Option<&'static str> {
+    let months = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+    months.into_iter().find(|month| month[..3].eq_ignore_ascii_case(word))
+}
+
+// A value as text: no braces, TeX accents and escapes as the characters
+// they stand for, `--` as a dash and runs of whitespace as one space
+fn clean(value: &str) -> String {
+    let mut cleaned = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {},
+            '~' => cleaned.push(' '),
+            '-' if chars.peek() == Some(&'-') => {
+                while chars.peek() == Some(&'-') {
+                    chars.next();
+                }
+                cleaned.push('–');
+            },
+            '\\' => {
+                let command = match chars.next() {
+                    Some(command) => command,
+                    None => break,
+                };
+                if command.is_alphabetic() {
+                    // \emph{...}, \textit{...}: keep the text; \ss, \o and \aa are letters
+                    let mut name = command.to_string();
+                    while let Some(&c) = chars.peek().filter(|c| c.is_alphabetic()) {
+                        name.push(c);
+                        chars.next();
+                    }
+                    match name.as_str() {
+                        "ss" => cleaned.push('ß'),
+                        "o" => cleaned.push('ø'),
+                        "O" => cleaned.push('Ø'),
+                        "aa" => cleaned.push('å'),
+                        "AA" => cleaned.push('Å'),
+                        "c" => {
+                            let letter = chars.find(|c| !matches!(c, '{' | ' '));
+                            cleaned.push(match letter {
+                                Some('c') => 'ç',
+                                Some('C') => 'Ç',
+                                Some(other) => other,
+                                None => continue,
+                            });
+                        },
+                        _ => {},
+                    }
+                    continue;
+                }
+                let accents = match command {
+                    '\'' => Some(("aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ")),
+                    '`' => Some(("aeiouAEIOU", "àèìòùÀÈÌÒÙ")),
+                    '^' => Some(("aeiouAEIOU", "âêîôûÂÊÎÔÛ")),
+                    '"' => Some(("aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ")),
+                    '~' => Some(("anoANO", "ãñõÃÑÕ")),
+                    _ => None,
+                };
+                match accents {
+                    Some((plain, accented)) => {
+                        // \i and \j are the dotless letters, under an accent
+                        let letter = match chars.find(|&c| !matches!(c, '{' | '\\')) {
+                            Some(letter) => letter,
+                            None => break,
+                        };
+                        let accented = plain.chars().position(|c| c == letter).and_then(|i| accented.chars().nth(i));
+                        cleaned.push(accented.unwrap_or(letter));
+                    },
+                    // \&, \%, \$, \_ and the like are the character itself
+                    None => cleaned.push(command),
+                }
+            },
+            c if c.is_whitespace() => {
+                if !cleaned.ends_with(' ') {
+                    cleaned.push(' ');
+                }
+            },
+            c => cleaned.push(c),
+        }
+    }
+    cleaned.trim().to_string()
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-.:/".contains(c)
+}
+
+// A citation key at the start of `text` (just after an `@`). A sentence's
+// closing period or colon isn't part of it.
+pub fn key_at_start(text: &str) -> &str {
+    let end = text.find(|c: char| !is_key_char(c)).unwrap_or(text.len());
+    text[..end].trim_end_matches(['.', ':'])
+}
+
+// An `@` only starts a citation at the beginning of a word, like a mention
+pub fn is_citation_start(text: &str, at_pos: usize) -> bool {
+    text[..at_pos].chars().next_back().is_none_or(|c| !is_key_char(c) && c != '@')
+}
+
+// The key being typed right before the cursor: byte offset where it starts
+// (just after the `@`) and the key so far
+pub fn key_prefix(text: &str, cursor: usize) -> Option<(usize, &str)> {
+    let before = text.get(..cursor)?;
+    let key_start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_key_char(c))
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    if key_start == 0 || !before[..key_start].ends_with('@') || !is_citation_start(text, key_start - 1) {
+        return None;
+    }
+    Some((key_start, &before[key_start..]))
+}
+
+// The key of the `@citekey` at a byte position
+pub fn key_at(text: &str, pos: usize) -> Option<&str> {
+    if pos >= text.len() || !text.is_char_boundary(pos) {
+        return None;
+    }
+    let word_start = text[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_key_char(c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let at_pos = if text[pos..].starts_with('@') {
+        pos
+    } else if word_start > 0 && text[..word_start].ends_with('@') {
+        word_start - 1
+    } else {
+        return None;
+    };
+    if !is_citation_start(text, at_pos) {
+        return None;
+    }
+    Some(key_at_start(&text[at_pos + 1..])).filter(|key| !key.is_empty())
+}
+
+// The bibliography's entries cited in a note, for its References: sorted by
+// author and year, each once
+pub fn cited<'a>(bibliography: &'a Bibliography, text: &str) -> Vec<&'a Entry> {
+    let mut cited: Vec<&Entry> = Vec::new();
+    for (at_pos, _) in text.match_indices('@') {
+        if !is_citation_start(text, at_pos) {
+            continue;
+        }
+        if let Some(entry) = bibliography.get(key_at_start(&text[at_pos + 1..])) {
+            if !cited.iter().any(|cited| cited.key == entry.key) {
+                cited.push(entry);
+            }
+        }
+    }
+    cited.sort_by_cached_key(|entry| (entry.authors().to_lowercase(), entry.year().to_string()));
+    cited
+}
+
+// The References section of an exported note, or nothing if it cites nothing
+pub fn references_html(bibliography: &Bibliography, text: &str) -> String {
+    let cited = cited(bibliography, text);
+    if cited.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<section class=\"references\">\n<h2>References</h2>\n<ul>\n");
+    for entry in cited {
+        html.push_str(&format!("<li id=\"ref-{}\">{}</li>\n", markdown::escape_html(&entry.key), entry.reference(true)));
+    }
+    html.push_str("</ul>\n</section>\n");
+    html
+}
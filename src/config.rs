@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::diagrams;
+use crate::maintenance::Limits;
+use crate::feedback::FeedbackConfig;
+use crate::file_names::FileNamesConfig;
+use crate::note_options::NoteOptions;
+use crate::portable;
+use crate::recurring::Recurring;
+use crate::redaction::Redaction;
+
+// User settings, stored as TOML in the OS config directory, or next to the
+// executable in portable mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Where the notes are; Documents/vimnote when unset
+    pub notes_dir: Option<PathBuf>,
+    // Start in the dark theme
+    pub dark_mode: bool,
+    // Save the open note a few seconds after the last change
+    pub autosave: bool,
+    // Copy each note into .vimnote/backups/ before its first save of the day
+    pub backups: bool,
+    // Keep a generated `## Backlinks` section at the bottom of each note
+    pub backlinks: bool,
+    // .ics file path or URL that `:meetings` reads today's events from
+    pub calendar: Option<String>,
+    // Port of the local HTTP API (web clipper etc.) on 127.0.0.1; off when unset
+    pub api_port: Option<u16>,
+    // Secret that API clients must send in an `X-VimNote-Token` header; made
+    // up when the API first starts without one
+    pub api_token: Option<String>,
+    // Origins of browser extensions allowed to call the API, like
+    // "moz-extension://..."; requests from any other web page are refused
+    pub api_origins: Vec<String>,
+    // RSS/Atom feed URLs fetched in the background into feeds/
+    pub feeds: Vec<String>,
+    // Minutes between feed fetches
+    pub feed_interval: u64,
+    // Collect new feed items in a daily digest note instead of a note per item
+    pub feed_digest: bool,
+    // Start with everything that uses the network off (`:set offline`)
+    pub offline: bool,
+    // Lock the window after this many minutes without input, once the vault
+    // has a passphrase (`:lock set`)
+    pub lock_after_minutes: Option<u64>,
+    // Log when each note is opened and edited in .vimnote/access.jsonl, for `:properties`
+    pub access_log: bool,
+    // Command that prints the text in an image, e.g. "tesseract {file} stdout".
+    // When set, attached images are OCR'd so screenshots become searchable.
+    pub ocr_command: Option<String>,
+    // Command that prints the transcript of an audio file, e.g. whisper.cpp's
+    // "whisper-cli -m ggml-base.bin -nt -np -f {file}"; runs on attached audio
+    pub transcribe_command: Option<String>,
+    // Commands that turn a diagram's source on stdin into SVG, by code block
+    // language ("mermaid", "dot"), for HTML exports
+    pub diagram_commands: HashMap<String, String>,
+    // What HTML exports do with `::secret::` text and secret fields: "mask"
+    // them with dots or "strip" them out
+    pub redaction: Redaction,
+    // BibTeX file that `@citekey` citations come from, relative to the notes folder
+    pub bibliography: Option<PathBuf>,
+    // Show "3/7 done" next to headings whose section has checkbox tasks
+    pub section_progress: bool,
+    // Search with ripgrep instead of reading every note, for big vaults.
+    // The built-in search is used if the command isn't found.
+    pub ripgrep: bool,
+    pub ripgrep_command: String,
+    // Notes bigger than this open in a read-only viewer that reads them a
+    // piece at a time, and aren't indexed or searched in full
+    pub large_file_mb: u64,
+    // What's kept of a note's view between sessions, like `:set viewoptions`:
+    // "cursor" for where the cursor was, "marks" for its marks a-z
+    pub view_options: String,
+    // Trashed notes older than this many days are deleted for good
+    pub trash_days: Option<u64>,
+    // Most MB the trash and the undo histories in .vimnote/ may take; the
+    // oldest files are deleted to make them fit
+    pub trash_mb: Option<u64>,
+    pub undo_history_mb: Option<u64>,
+    pub backups_mb: Option<u64>,
+    // Sounds and flashes for failed saves, insert mode and `:pomodoro`
+    pub feedback: FeedbackConfig,
+    // How the names of new and renamed notes are written: lowercase, dashes, no emoji
+    pub file_names: FileNamesConfig,
+    // Note options (`textwidth = 72`, `spell = true`, ...) for every note,
+    // before a folder's, the note's own frontmatter or modeline and `:set`
+    pub options: HashMap<String, toml::Value>,
+    // Options and a template for new notes, by folder: `[folders.journal]`
+    pub folders: HashMap<String, FolderConfig>,
+    // Notes made from a template every day, week or month: `[[recurring]]`
+    pub recurring: Vec<Recurring>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderConfig {
+    // Template that new notes in the folder start from, by name
+    pub template: Option<String>,
+    #[serde(flatten)]
+    pub options: HashMap<String, toml::Value>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            notes_dir: None,
+            dark_mode: false,
+            autosave: true,
+            backups: false,
+            backlinks: false,
+            calendar: None,
+            api_port: None,
+            api_token: None,
+            api_origins: Vec::new(),
+            feeds: Vec::new(),
+            feed_interval: 60,
+            feed_digest: false,
+            offline: false,
+            lock_after_minutes: None,
+            access_log: false,
+            ocr_command: None,
+            transcribe_command: None,
+            diagram_commands: diagrams::default_commands(),
+            redaction: Redaction::default(),
+            bibliography: None,
+            section_progress: true,
+            ripgrep: false,
+            ripgrep_command: "rg".to_string(),
+            large_file_mb: 10,
+            view_options: "cursor,marks".to_string(),
+            trash_days: None,
+            trash_mb: None,
+            undo_history_mb: None,
+            backups_mb: None,
+            feedback: FeedbackConfig::default(),
+            file_names: FileNamesConfig::default(),
+            options: HashMap::new(),
+            folders: HashMap::new(),
+            recurring: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // `large_file_mb` in bytes
+    pub fn large_file_size(&self) -> u64 {
+        self.large_file_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn maintenance_limits(&self) -> Limits {
+        let megabytes = |mb: u64| mb.saturating_mul(1024 * 1024);
+        Limits {
+            trash_age: self.trash_days.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60))),
+            trash_size: self.trash_mb.map(megabytes),
+            history_size: self.undo_history_mb.map(megabytes),
+            backup_size: self.backups_mb.map(megabytes),
+        }
+    }
+
+    // The settings of the folders a note is in, outermost first
+    fn folders_of<'a>(&'a self, note: &'a str) -> impl Iterator<Item = &'a FolderConfig> + 'a {
+        note.match_indices('/').filter_map(move |(end, _)| {
+            let folder = &note[..end];
+            self.folders
+                .iter()
+                .find(|(name, _)| name.trim_matches('/') == folder)
+                .map(|(_, config)| config)
+        })
+    }
+
+    // A note's options before its own: the vault's, then its folders'
+    pub fn note_defaults(&self, note: &str) -> NoteOptions {
+        let mut options = NoteOptions::default();
+        let layers = std::iter::once(&self.options).chain(self.folders_of(note).map(|folder| &folder.options));
+        for layer in layers {
+            for (name, value) in layer {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+                if !options.set_option(name, Some(&value)) {
+                    println!("Unknown option in config: {}", name);
+                }
+            }
+        }
+        options
+    }
+
+    // Template for new notes in the innermost of the note's folders that has one
+    pub fn folder_template<'a>(&'a self, note: &'a str) -> Option<&'a str> {
+        self.folders_of(note).filter_map(|folder| folder.template.as_deref()).last()
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        portable::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // User stylesheet for HTML exports, next to the config file
+    pub fn stylesheet_path() -> Option<PathBuf> {
+        portable::config_dir().map(|dir| dir.join("style.css"))
+    }
+
+    // No config file yet, so this is the first run
+    pub fn is_missing() -> bool {
+        Self::path().is_some_and(|path| !path.exists())
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    // Load the config file, falling back to defaults if it is missing or invalid
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str(&contents) {
+                Ok(config) => config,
+                Err(err) => {
+                    println!("Invalid config file {}: {}", path.display(), err);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+}
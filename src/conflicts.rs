@@ -0,0 +1,174 @@
+// Copies of a note that a sync tool couldn't merge, named the way Syncthing
+// names them: `note.sync-conflict-20240512-093011-ABC1234.md`. They're listed
+// right under their note, and `:merge` compares the two line by line so each
+// changed part can be taken from either of them, or from both.
+
+const CONFLICT_MARKER: &str = ".sync-conflict-";
+// Most cells of the table `diff_lines` may use; past that the part that
+// differs is shown as one changed hunk
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// The note a conflict copy belongs to, or None if it isn't one
+pub fn original_of(file_name: &str) -> Option<String> {
+    let start = file_name.rfind(CONFLICT_MARKER)?;
+    let rest = &file_name[start + CONFLICT_MARKER.len()..];
+    let extension = rest.rfind('.').map_or("", |dot| &rest[dot..]);
+    Some(format!("{}{}", &file_name[..start], extension))
+}
+
+// Alphabetical order, except that conflict copies come right after their note
+pub fn sort_notes(files: &mut [String]) {
+    files.sort_by_cached_key(|file| match original_of(file) {
+        Some(original) => (original, true, file.clone()),
+        None => (file.clone(), false, String::new()),
+    });
+}
+
+// The conflict copies of `note` among `files`
+pub fn copies_of<'a>(files: &'a [String], note: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+    files.iter().filter(move |file| original_of(file).as_deref() == Some(note))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hunk {
+    Same(Vec<String>),
+    Changed { ours: Vec<String>, theirs: Vec<String> },
+}
+
+// Which side a changed hunk is taken from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pick {
+    Ours,
+    Theirs,
+    Both, // Ours, then theirs
+}
+
+// The lines of `ours` and `theirs` as runs that are the same in both and
+// runs that differ, from their longest common subsequence of lines
+pub fn diff_lines(ours: &str, theirs: &str) -> Vec<Hunk> {
+    let ours: Vec<&str> = ours.split('\n').collect();
+    let theirs: Vec<&str> = theirs.split('\n').collect();
+    // Only the middle part that differs needs the table
+    let prefix = ours.iter().zip(&theirs).take_while(|(a, b)| a == b).count();
+    let max_suffix = ours.len().min(theirs.len()) - prefix;
+    let suffix = ours.iter().rev().zip(theirs.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&ours[prefix..ours.len() - suffix], &theirs[prefix..theirs.len() - suffix]);
+
+    let mut hunks = Vec::new();
+    for line in &ours[..prefix] {
+        push_same(&mut hunks, line);
+    }
+    if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_DIFF_CELLS {
+        let (ours, theirs) = changed_hunk(&mut hunks);
+        ours.extend(a.iter().map(|line| line.to_string()));
+        theirs.extend(b.iter().map(|line| line.to_string()));
+    } else {
+        // common[i][j]: length of the common subsequence of a[i..] and b[j..]
+        let mut common = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i][j] = if a[i] == b[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                push_same(&mut hunks, a[i]);
+                i += 1;
+                j += 1;
+            } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+                changed_hunk(&mut hunks).0.push(a[i].to_string());
+                i += 1;
+            } else {
+                changed_hunk(&mut hunks).1.push(b[j].to_string());
+                j += 1;
+            }
+        }
+    }
+    for line in &ours[ours.len() - suffix..] {
+        push_same(&mut hunks, line);
+    }
+    hunks
+}
+
+fn push_same(hunks: &mut Vec<Hunk>, line: &str) {
+    match hunks.last_mut() {
+        Some(Hunk::Same(lines)) => lines.push(line.to_string()),
+        _ => hunks.push(Hunk::Same(vec![line.to_string()])),
+    }
+}
+
+// The lines of the changed hunk at the end, started if there's none
+fn changed_hunk(hunks: &mut Vec<Hunk>) -> (&mut Vec<String>, &mut Vec<String>) {
+    if !matches!(hunks.last(), Some(Hunk::Changed { .. })) {
+        hunks.push(Hunk::Changed { ours: Vec::new(), theirs: Vec::new() });
+    }
+    match hunks.last_mut() {
+        Some(Hunk::Changed { ours, theirs }) => (ours, theirs),
+        _ => unreachable!(),
+    }
+}
+
+// A note and one of its conflict copies being merged
+#[derive(Debug)]
+pub struct Merge {
+    pub note: String,
+    pub copy: String,
+    pub hunks: Vec<Hunk>,
+    pub picks: Vec<Pick>, // One per hunk; unused for the same ones
+}
+
+impl Merge {
+    pub fn new(note: String, copy: String, ours: &str, theirs: &str) -> Self {
+        let hunks = diff_lines(ours, theirs);
+        let picks = vec![Pick::Ours; hunks.len()];
+        Self { note, copy, hunks, picks }
+    }
+
+    pub fn changed_count(&self) -> usize {
+        self.hunks.iter().filter(|hunk| matches!(hunk, Hunk::Changed { .. })).count()
+    }
+
+    // Whether `ours` and `theirs` are still the texts the merge was made from
+    pub fn is_of(&self, ours: &str, theirs: &str) -> bool {
+        self.side(Pick::Ours) == ours && self.side(Pick::Theirs) == theirs
+    }
+
+    // One of the two texts, whole
+    fn side(&self, pick: Pick) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        for hunk in &self.hunks {
+            match hunk {
+                Hunk::Same(same) => lines.extend(same.iter().map(String::as_str)),
+                Hunk::Changed { ours, theirs } => {
+                    let side = if pick == Pick::Theirs { theirs } else { ours };
+                    lines.extend(side.iter().map(String::as_str));
+                },
+            }
+        }
+        lines.join("\n")
+    }
+
+    // The note with each changed hunk taken from the side picked for it
+    pub fn merged(&self) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        for (hunk, pick) in self.hunks.iter().zip(&self.picks) {
+            match hunk {
+                Hunk::Same(same) => lines.extend(same.iter().map(String::as_str)),
+                Hunk::Changed { ours, theirs } => {
+                    if *pick != Pick::Theirs {
+                        lines.extend(ours.iter().map(String::as_str));
+                    }
+                    if *pick != Pick::Ours {
+                        lines.extend(theirs.iter().map(String::as_str));
+                    }
+                },
+            }
+        }
+        lines.join("\n")
+    }
+}
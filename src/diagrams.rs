@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::markdown;
+
+// Diagrams in ```mermaid and ```dot code blocks, drawn when notes are
+// exported to HTML. The note keeps the diagram's source, so it stays editable.
+//
+// A block is piped to the command configured for its language, which prints
+// an SVG that goes into the page. Mermaid blocks without a working command
+// are drawn in the browser by mermaid.js instead; other blocks stay code.
+
+pub const LANGUAGES: [&str; 3] = ["mermaid", "dot", "graphviz"];
+
+const MERMAID_SCRIPT: &str = "<script type=\"module\">\n\
+    import mermaid from \"https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.esm.min.mjs\";\n\
+    mermaid.initialize({ startOnLoad: true, theme: matchMedia(\"(prefers-color-scheme: dark)\").matches ? \"dark\" : \"default\" });\n\
+    </script>";
+
+// The commands `diagram_commands` starts out with
+pub fn default_commands() -> HashMap<String, String> {
+    HashMap::from([("dot".to_string(), "dot -Tsvg".to_string())])
+}
+
+// HTML for a code block in `language`, or None to show it as code
+pub fn render(language: &str, source: &str, commands: &HashMap<String, String>) -> Option<String> {
+    let language = if language == "graphviz" { "dot" } else { language };
+    if !LANGUAGES.contains(&language) {
+        return None;
+    }
+
+    if let Some(command) = commands.get(language) {
+        match run_command(command, source) {
+            Ok(svg) => return Some(format!("<figure class=\"diagram\">\n{}\n</figure>\n", svg)),
+            Err(err) => println!("Could not render {} diagram with `{}`: {}", language, command, err),
+        }
+    }
+    if language == "mermaid" {
+        return Some(format!("<pre class=\"mermaid\">\n{}\n</pre>\n", markdown::escape_html(source)));
+    }
+    None
+}
+
+// Script a page needs for the mermaid diagrams it has, if any
+pub fn page_script(body: &str) -> Option<&'static str> {
+    body.contains("<pre class=\"mermaid\">").then_some(MERMAID_SCRIPT)
+}
+
+fn run_command(command: &str, source: &str) -> io::Result<String> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut child = Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(source.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+    let svg = String::from_utf8_lossy(&output.stdout);
+    // Keep the <svg> element, without the XML declaration and doctype before it
+    match svg.find("<svg") {
+        Some(start) => Ok(svg[start..].trim_end().to_string()),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "no SVG in the output")),
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::modes::VimMode;
+
+// What happens to the open note, sent to whatever keeps something derived
+// from it up to date (task progress, the link index, plugins) so it only
+// redoes its work when the note actually changed. Mode switches and failed
+// saves are sent too, for feedback like a sound or a flash.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    // Bytes start..old_end of the text were replaced with what's now at start..new_end
+    Edited { start: usize, old_end: usize, new_end: usize },
+    Opened { note: String },
+    Saved { note: String },
+    SaveFailed { note: String },
+    ModeChanged { from: VimMode, to: VimMode },
+}
+
+impl Change {
+    // The edit that turns `before` into `after`, if they differ
+    pub fn edit(before: &str, after: &str) -> Option<Self> {
+        if before == after {
+            return None;
+        }
+        let mut start = before.bytes().zip(after.bytes()).take_while(|(a, b)| a == b).count();
+        while !before.is_char_boundary(start) || !after.is_char_boundary(start) {
+            start -= 1;
+        }
+        let max_suffix = before.len().min(after.len()) - start;
+        let mut suffix = before.bytes().rev().zip(after.bytes().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+        while !before.is_char_boundary(before.len() - suffix) || !after.is_char_boundary(after.len() - suffix) {
+            suffix -= 1;
+        }
+        Some(Change::Edited { start, old_end: before.len() - suffix, new_end: after.len() - suffix })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    subscribers: Vec<Sender<Change>>,
+}
+
+impl Changes {
+    // Every change from now on; dropping the receiver unsubscribes
+    pub fn subscribe(&mut self) -> Receiver<Change> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn publish(&mut self, change: Change) {
+        self.subscribers.retain(|subscriber| subscriber.send(change.clone()).is_ok());
+    }
+}
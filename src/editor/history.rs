@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+// Edit history for undo/redo, kept as a vim-style undo tree so that undoing
+// and then making a new change starts a new branch instead of discarding the
+// undone changes. Each node stores the edit that turns its parent's text into
+// its own, so memory grows with the size of the edits, not the note.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoState {
+    pub text: String,
+    pub cursor_position: usize,
+}
+
+impl UndoState {
+    pub fn capture(text: &str, cursor_position: usize) -> Self {
+        Self {
+            text: text.to_string(),
+            cursor_position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UndoNode {
+    parent: usize,
+    // Replace `removed` at `start` with `inserted` to go from the parent to this node
+    start: usize,
+    removed: String,
+    inserted: String,
+    cursor_before: usize,
+    time: SystemTime,
+    // Child that redo follows: the most recently created or visited branch
+    redo_child: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditHistory {
+    // nodes[0] is the root (the text as loaded); indexes double as change numbers
+    nodes: Vec<UndoNode>,
+    current: usize,
+    // Text of the current node, used to notice changes made outside the editor
+    text: String,
+}
+
+// Result of moving through the history: the text to show and where to put the cursor
+pub struct HistoryMove {
+    pub text: String,
+    pub cursor_position: usize,
+}
+
+impl EditHistory {
+    pub fn new(text: &str) -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: 0,
+                start: 0,
+                removed: String::new(),
+                inserted: String::new(),
+                cursor_before: 0,
+                time: SystemTime::now(),
+                redo_child: None,
+            }],
+            current: 0,
+            text: text.to_string(),
+        }
+    }
+
+    // Start over with `text` as the root state
+    pub fn reset(&mut self, text: &str) {
+        *self = Self::new(text);
+    }
+
+    // Record a change from `before` to `after` as a new node under the current one
+    pub fn record(&mut self, before: &str, after: &str, cursor_before: usize) {
+        if after == self.text {
+            return; // Already there, e.g. after undo or :earlier
+        }
+        // The buffer may have been changed behind our back (e.g. a regenerated section on save);
+        // keep that as its own step so the diffs stay consistent
+        self.sync(before, cursor_before);
+        self.push_node(after, cursor_before);
+    }
+
+    // Catch up with `text` if it differs from the current state
+    pub fn sync(&mut self, text: &str, cursor_position: usize) {
+        if text != self.text {
+            self.push_node(text, cursor_position);
+        }
+    }
+
+    fn push_node(&mut self, after: &str, cursor_before: usize) {
+        let (start, removed, inserted) = diff(&self.text, after);
+        let index = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            start,
+            removed,
+            inserted,
+            cursor_before,
+            time: SystemTime::now(),
+            redo_child: None,
+        });
+        self.nodes[self.current].redo_child = Some(index);
+        self.current = index;
+        self.text = after.to_string();
+    }
+
+    pub fn undo(&mut self) -> Option<HistoryMove> {
+        if self.current == 0 {
+            return None;
+        }
+        let cursor = self.step_up();
+        Some(self.finish_move(cursor))
+    }
+
+    pub fn redo(&mut self) -> Option<HistoryMove> {
+        let child = self.nodes[self.current].redo_child?;
+        let cursor = self.step_down(child);
+        Some(self.finish_move(cursor))
+    }
+
+    // `:earlier {count}` / `:later {count}` move by change number,
+    // `:earlier 10s` / `:later 1h` by time relative to the current change
+    pub fn earlier(&mut self, amount: TimeTravel) -> Option<HistoryMove> {
+        let target = match amount {
+            TimeTravel::Steps(steps) => self.current.saturating_sub(steps),
+            TimeTravel::Duration(duration) => {
+                let current_time = self.nodes[self.current].time;
+                let target_time = current_time.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH);
+                self.latest_node_at(target_time).min(self.current)
+            },
+        };
+        self.travel_to(target)
+    }
+
+    pub fn later(&mut self, amount: TimeTravel) -> Option<HistoryMove> {
+        let last = self.nodes.len() - 1;
+        let target = match amount {
+            TimeTravel::Steps(steps) => self.current.saturating_add(steps).min(last),
+            TimeTravel::Duration(duration) => {
+                // Past what SystemTime can hold is after every change
+                let target = match self.nodes[self.current].time.checked_add(duration) {
+                    Some(target_time) => self.latest_node_at(target_time),
+                    None => last,
+                };
+                target.max(self.current)
+            },
+        };
+        self.travel_to(target)
+    }
+
+    // The newest change made at or before `time` (the root if there is none)
+    fn latest_node_at(&self, time: SystemTime) -> usize {
+        self.nodes
+            .iter()
+            .rposition(|node| node.time <= time)
+            .unwrap_or(0)
+    }
+
+    // Walk the tree to any node: undo up to the common ancestor, then redo down
+    fn travel_to(&mut self, target: usize) -> Option<HistoryMove> {
+        if target == self.current {
+            return None;
+        }
+
+        let mut target_path = vec![target];
+        while *target_path.last().unwrap() != 0 {
+            let parent = self.nodes[*target_path.last().unwrap()].parent;
+            target_path.push(parent);
+        }
+
+        let mut cursor = 0;
+        while !target_path.contains(&self.current) {
+            cursor = self.step_up();
+        }
+        let ancestor_index = target_path.iter().position(|&n| n == self.current).unwrap();
+        for &node in target_path[..ancestor_index].iter().rev() {
+            cursor = self.step_down(node);
+        }
+        Some(self.finish_move(cursor))
+    }
+
+    // Undo the current node's edit; returns where the cursor should go
+    fn step_up(&mut self) -> usize {
+        let node = &self.nodes[self.current];
+        let end = node.start + node.inserted.len();
+        self.text.replace_range(node.start..end, &node.removed);
+        let cursor = node.start.min(node.cursor_before);
+        let (parent, child) = (node.parent, self.current);
+        self.nodes[parent].redo_child = Some(child);
+        self.current = parent;
+        cursor
+    }
+
+    // Redo the edit of `child`, which must be a child of the current node
+    fn step_down(&mut self, child: usize) -> usize {
+        let node = &self.nodes[child];
+        let end = node.start + node.removed.len();
+        self.text.replace_range(node.start..end, &node.inserted);
+        let cursor = node.start.min(node.cursor_before);
+        self.nodes[self.current].redo_child = Some(child);
+        self.current = child;
+        cursor
+    }
+
+    fn finish_move(&self, cursor: usize) -> HistoryMove {
+        let mut cursor_position = cursor.min(self.text.len());
+        while !self.text.is_char_boundary(cursor_position) {
+            cursor_position -= 1;
+        }
+        HistoryMove {
+            text: self.text.clone(),
+            cursor_position,
+        }
+    }
+
+    // Write the whole tree to an undo file, like vim's 'undofile'
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    // Read an undo file back. Only usable if it was saved for exactly this text;
+    // if the note was changed elsewhere the stored diffs no longer apply.
+    pub fn load(path: &Path, text: &str) -> Option<Self> {
+        let json = fs::read_to_string(path).ok()?;
+        let history: Self = serde_json::from_str(&json).ok()?;
+        let valid = history.text == text
+            && history.current < history.nodes.len()
+            && history.nodes.iter().enumerate().all(|(i, node)| i == 0 || node.parent < i);
+        if valid { Some(history) } else { None }
+    }
+
+    // Change number of the current state, 0 being the original text
+    pub fn change_number(&self) -> usize {
+        self.current
+    }
+}
+
+// How far `:earlier` / `:later` should go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeTravel {
+    Steps(usize),
+    Duration(Duration),
+}
+
+impl TimeTravel {
+    // Parse vim's `{N}`, `{N}s`, `{N}m`, `{N}h` and `{N}d` arguments (an empty argument means 1)
+    pub fn parse(arg: &str) -> Option<Self> {
+        let arg = arg.trim();
+        if arg.is_empty() {
+            return Some(TimeTravel::Steps(1));
+        }
+        let unit_start = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+        let count: u64 = arg[..unit_start].parse().ok()?;
+        let unit = match &arg[unit_start..] {
+            "" => return Some(TimeTravel::Steps(usize::try_from(count).unwrap_or(usize::MAX))),
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            _ => return None,
+        };
+        // Longer than any history, so clamped to the oldest or newest state
+        let seconds = count.saturating_mul(unit);
+        Some(TimeTravel::Duration(Duration::from_secs(seconds)))
+    }
+}
+
+// Smallest single replacement that turns `before` into `after`:
+// (start, removed text, inserted text), found by trimming the common prefix and suffix
+fn diff(before: &str, after: &str) -> (usize, String, String) {
+    let mut prefix = before.bytes().zip(after.bytes()).take_while(|(a, b)| a == b).count();
+    while !before.is_char_boundary(prefix) || !after.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+
+    let max_suffix = before.len().min(after.len()) - prefix;
+    let mut suffix = before.bytes().rev()
+        .zip(after.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !before.is_char_boundary(before.len() - suffix) || !after.is_char_boundary(after.len() - suffix) {
+        suffix -= 1;
+    }
+
+    (
+        prefix,
+        before[prefix..before.len() - suffix].to_string(),
+        after[prefix..after.len() - suffix].to_string(),
+    )
+}
@@ -0,0 +1,151 @@
+// The `=` operator: indentation put right the way markdown nests lines. A
+// list item goes at the text column of the item it's nested in (2 under
+// `- `, 3 under `1. `), so sloppy nesting like one stray space, tabs, or
+// `1.` children indented by two comes out consistent, and a line that
+// continues an item lines up with its text. Headings, paragraphs and
+// top-level items go to the margin, and blank lines lose their whitespace.
+// Code blocks keep their own indentation, moving along with their fence;
+// frontmatter and indented code blocks outside lists are left alone.
+
+// Columns a tab is drawn as
+const TAB_COLUMNS: usize = 4;
+
+// An item the lines after it may be nested in
+struct Item {
+    indent: usize,  // Its marker's column, as the line was
+    content: usize, // Its text's column after reindenting
+}
+
+// `text` with the lines `first..=last` (0-based) reindented. The lines
+// before them are read for the lists and code blocks they're in.
+pub fn reindent(text: &str, first: usize, last: usize) -> String {
+    let mut reindented = String::with_capacity(text.len());
+    let mut items: Vec<Item> = Vec::new();
+    let mut fence: Option<(&str, isize)> = None; // The open fence, how far its block moves
+    let frontmatter = frontmatter_lines(text);
+    let mut previous_blank = true;
+    let mut indented_code = false;
+
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let line_break = &line[content.len()..];
+        let in_range = (first..=last).contains(&i);
+        let keep = |reindented: &mut String| reindented.push_str(line);
+        let trimmed = content.trim_start_matches([' ', '\t']);
+        let column = columns(&content[..content.len() - trimmed.len()]);
+
+        if i < frontmatter {
+            keep(&mut reindented);
+            continue;
+        }
+        if let Some((opening, shift)) = fence {
+            if trimmed.starts_with(opening) {
+                fence = None;
+            }
+            if in_range && !trimmed.is_empty() {
+                reindented.push_str(&shifted(content, shift));
+                reindented.push_str(line_break);
+            } else {
+                keep(&mut reindented);
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            previous_blank = true;
+            if in_range {
+                reindented.push_str(line_break);
+            } else {
+                keep(&mut reindented);
+            }
+            continue;
+        }
+
+        // Outside lists, four columns after a blank line start an indented code block
+        indented_code = items.is_empty() && column >= 4 && (previous_blank || indented_code);
+        previous_blank = false;
+        if indented_code {
+            keep(&mut reindented);
+            continue;
+        }
+
+        let after_hashes = trimmed.trim_start_matches('#');
+        if trimmed.starts_with('#') && (after_hashes.is_empty() || after_hashes.starts_with([' ', '\t'])) {
+            items.clear();
+        }
+        while items.last().is_some_and(|item| column <= item.indent) {
+            items.pop();
+        }
+        let target = if in_range {
+            items.last().map_or(0, |item| item.content)
+        } else {
+            column
+        };
+        if let Some(width) = marker_width(trimmed) {
+            items.push(Item { indent: column, content: target + width });
+        }
+        if let Some(opening) = ["```", "~~~"].into_iter().find(|opening| trimmed.starts_with(opening)) {
+            fence = Some((opening, target as isize - column as isize));
+        }
+        if in_range {
+            reindented.push_str(&" ".repeat(target));
+            reindented.push_str(trimmed);
+            reindented.push_str(line_break);
+        } else {
+            keep(&mut reindented);
+        }
+    }
+    reindented
+}
+
+// How many lines the leading `---` frontmatter block takes, with its fences
+fn frontmatter_lines(text: &str) -> usize {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim_end) != Some("---") {
+        return 0;
+    }
+    lines
+        .position(|line| matches!(line.trim_end(), "---" | "..."))
+        .map_or(0, |closing| closing + 2)
+}
+
+fn columns(indent: &str) -> usize {
+    indent.chars().fold(0, |column, c| if c == '\t' { column - column % TAB_COLUMNS + TAB_COLUMNS } else { column + 1 })
+}
+
+// A code block line moved `shift` columns, as far as its indentation allows
+fn shifted(line: &str, shift: isize) -> String {
+    if shift >= 0 {
+        return format!("{}{}", " ".repeat(shift as usize), line);
+    }
+    let mut removed = 0;
+    let mut rest = line;
+    while removed < shift.unsigned_abs() {
+        match rest.chars().next() {
+            Some(' ') => removed += 1,
+            Some('\t') => removed += TAB_COLUMNS,
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+    rest.to_string()
+}
+
+// How far the text of a list item is from its `-`, `*`, `+`, `1.` or `1)`
+// marker: the marker and a space
+fn marker_width(line: &str) -> Option<usize> {
+    let marker = if line.starts_with(['-', '*', '+']) {
+        // `- - -` and `***` are rules
+        if line.chars().filter(|c| !c.is_whitespace()).count() >= 3 && line.chars().all(|c| c.is_whitespace() || c == line.as_bytes()[0] as char) {
+            return None;
+        }
+        1
+    } else {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 || digits > 9 || !line[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        digits + 1
+    };
+    let after = &line[marker..];
+    (after.is_empty() || after.starts_with([' ', '\t'])).then_some(marker + 1)
+}
@@ -0,0 +1,248 @@
+use eframe::egui;
+use std::collections::VecDeque;
+
+use crate::modes::VimMode;
+use super::simple_editor::{key_digit, key_letter};
+
+// Macros recorded with q{register} are kept in the register as text in vim's
+// key notation, e.g. `cwnew<Esc>j`, so they show up in :registers and can be
+// put, edited and yanked back like in vim. A letter or digit stands for its
+// key in normal and visual mode and for typed text in insert and command mode;
+// other keys are written like <Esc>, <CR> or <C-r>.
+
+// Keys a macro may play in one go, so one that calls itself can't hang the app
+const MAX_PLAYED_KEYS: usize = 100_000;
+
+// Keys written by name, in vim's spelling where vim has one
+const KEY_NAMES: [(egui::Key, &str); 29] = [
+    (egui::Key::Escape, "Esc"),
+    (egui::Key::Enter, "CR"),
+    (egui::Key::Backspace, "BS"),
+    (egui::Key::Tab, "Tab"),
+    (egui::Key::Space, "Space"),
+    (egui::Key::Delete, "Del"),
+    (egui::Key::Insert, "Insert"),
+    (egui::Key::Home, "Home"),
+    (egui::Key::End, "End"),
+    (egui::Key::PageUp, "PageUp"),
+    (egui::Key::PageDown, "PageDown"),
+    (egui::Key::ArrowLeft, "Left"),
+    (egui::Key::ArrowRight, "Right"),
+    (egui::Key::ArrowUp, "Up"),
+    (egui::Key::ArrowDown, "Down"),
+    (egui::Key::Minus, "-"),
+    (egui::Key::PlusEquals, "="),
+    (egui::Key::F1, "F1"),
+    (egui::Key::F2, "F2"),
+    (egui::Key::F3, "F3"),
+    (egui::Key::F4, "F4"),
+    (egui::Key::F5, "F5"),
+    (egui::Key::F6, "F6"),
+    (egui::Key::F7, "F7"),
+    (egui::Key::F8, "F8"),
+    (egui::Key::F9, "F9"),
+    (egui::Key::F10, "F10"),
+    (egui::Key::F11, "F11"),
+    (egui::Key::F12, "F12"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PendingMacro {
+    Record, // Got `q`, waiting for the register to record into
+    Play,   // Got `@`, waiting for the register to play
+}
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    pub recording: Option<char>, // Register that q is recording into
+    recorded: String,
+    pub last_played: Option<char>, // Register that `@@` plays again
+    queue: VecDeque<String>,       // Keys still to be played, in key notation
+    played: usize,
+    replaying: bool, // The key being handled came from the queue, so isn't recorded again
+}
+
+impl Macros {
+    pub fn start_recording(&mut self, name: char) {
+        self.recording = Some(name);
+        self.recorded.clear();
+    }
+
+    // The register and the keys recorded into it
+    pub fn stop_recording(&mut self) -> Option<(char, String)> {
+        self.recording.take().map(|name| (name, std::mem::take(&mut self.recorded)))
+    }
+
+    pub fn record_key(&mut self, key: egui::Key, modifiers: &egui::Modifiers) {
+        if self.recording.is_some() && !self.replaying {
+            self.recorded.push_str(&key_notation(key, modifiers));
+        }
+    }
+
+    pub fn record_text(&mut self, c: char) {
+        if self.recording.is_some() && !self.replaying {
+            if c == '<' {
+                self.recorded.push_str("<lt>");
+            } else {
+                self.recorded.push(c);
+            }
+        }
+    }
+
+    // Queue the keys of a macro `count` times. They go before anything still
+    // queued, so a macro that plays another one continues after it.
+    pub fn play(&mut self, keys: &str, count: usize) {
+        let tokens = split_keys(keys);
+        for _ in 0..count {
+            for token in tokens.iter().rev() {
+                self.queue.push_front(token.clone());
+            }
+            if self.queue.len() > MAX_PLAYED_KEYS {
+                break;
+            }
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    // The next key to play as an input event, read the way it would have been
+    // typed in the current mode
+    pub fn next_event(&mut self, mode: VimMode) -> Option<egui::Event> {
+        let token = match self.queue.pop_front() {
+            Some(token) if self.played < MAX_PLAYED_KEYS => token,
+            _ => {
+                if self.played >= MAX_PLAYED_KEYS {
+                    println!("Stopped a macro after {} keys", self.played);
+                }
+                self.queue.clear();
+                self.played = 0;
+                self.replaying = false;
+                return None;
+            },
+        };
+        self.played += 1;
+        self.replaying = true;
+        Some(token_event(&token, mode))
+    }
+}
+
+// A key in key notation
+fn key_notation(key: egui::Key, modifiers: &egui::Modifiers) -> String {
+    if !modifiers.ctrl && !modifiers.alt {
+        if let Some(letter) = key_letter(key) {
+            return if modifiers.shift { letter.to_ascii_uppercase() } else { letter }.to_string();
+        }
+        match (key_digit(key), modifiers.shift) {
+            (Some(digit), false) => return digit.to_string(),
+            // The shifted digit normal mode uses as `$`
+            (Some('4'), true) => return "$".to_string(),
+            _ => {},
+        }
+    }
+
+    let name = match KEY_NAMES.iter().find(|(named, _)| *named == key) {
+        Some((_, name)) => name.to_string(),
+        None => key_letter(key).or(key_digit(key)).map_or_else(|| key.name().to_string(), |c| c.to_string()),
+    };
+    let mut prefix = String::new();
+    if modifiers.ctrl {
+        prefix.push_str("C-");
+    }
+    if modifiers.alt {
+        prefix.push_str("M-");
+    }
+    if modifiers.shift {
+        prefix.push_str("S-");
+    }
+    format!("<{}{}>", prefix, name)
+}
+
+// Split recorded keys into single characters and <...> keys
+pub(super) fn split_keys(keys: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|after| after.find('>').map(|end| &after[..end]))
+            .filter(|name| *name == "lt" || parse_key(name).is_some());
+        match special {
+            Some(name) => {
+                tokens.push(format!("<{}>", name));
+                rest = &rest[name.len() + 2..];
+            },
+            None => {
+                tokens.push(c.to_string());
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    tokens
+}
+
+// Key and modifiers of the inside of a <...> key, like `C-r` or `Esc`
+pub(super) fn parse_key(name: &str) -> Option<(egui::Key, egui::Modifiers)> {
+    let mut modifiers = egui::Modifiers::NONE;
+    let mut name = name;
+    loop {
+        if let Some(rest) = name.strip_prefix("C-") {
+            modifiers.ctrl = true;
+            modifiers.command = !cfg!(target_os = "macos");
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("M-") {
+            modifiers.alt = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("S-") {
+            modifiers.shift = true;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+
+    if let Some((key, _)) = KEY_NAMES.iter().find(|(_, key_name)| *key_name == name) {
+        return Some((*key, modifiers));
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => char_key(c.to_ascii_lowercase()).map(|key| (key, modifiers)),
+        _ => None,
+    }
+}
+
+pub(super) fn char_key(c: char) -> Option<egui::Key> {
+    use egui::Key::*;
+    let keys = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    ];
+    keys.into_iter().find(|&key| key_letter(key) == Some(c) || key_digit(key) == Some(c))
+}
+
+fn token_event(token: &str, mode: VimMode) -> egui::Event {
+    let key_event = |key, modifiers| egui::Event::Key { key, pressed: true, repeat: false, modifiers };
+
+    if token == "<lt>" {
+        return egui::Event::Text("<".to_string());
+    }
+    if let Some((key, modifiers)) = token.strip_prefix('<').and_then(|t| t.strip_suffix('>')).and_then(parse_key) {
+        return key_event(key, modifiers);
+    }
+
+    let c = token.chars().next().unwrap_or(' ');
+    if matches!(mode, VimMode::Insert | VimMode::Replace | VimMode::Command) {
+        return egui::Event::Text(token.to_string());
+    }
+    let shift = egui::Modifiers::SHIFT;
+    match c {
+        '$' => key_event(egui::Key::Num4, shift),
+        ' ' => key_event(egui::Key::Space, egui::Modifiers::NONE),
+        c if c.is_ascii_alphanumeric() => match char_key(c.to_ascii_lowercase()) {
+            Some(key) => key_event(key, if c.is_ascii_uppercase() { shift } else { egui::Modifiers::NONE }),
+            None => egui::Event::Text(token.to_string()),
+        },
+        _ => egui::Event::Text(token.to_string()),
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+// Marks set with m{a-z} and jumped to with '{a-z} (the line) or `{a-z} (the
+// exact position). They belong to the note they were set in and follow the
+// text they point at as the note is edited.
+//
+// Global marks A-Z are kept with the note they were set in too, so they
+// follow its edits the same way; the app remembers which note has each one
+// and opens it when they're jumped to from another note.
+
+// The mark `''` and ``` `` ``` jump to: where the cursor was before the last jump
+pub const CONTEXT_MARK: char = '\'';
+
+// Places of recent changes remembered for g; and g,
+const MAX_CHANGES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PendingMark {
+    Set,                  // Got `m`, waiting for the mark name
+    Jump { exact: bool }, // Got `'` (line) or `` ` `` (position), waiting for the mark name
+}
+
+// A mark as `:marks` lists it
+#[derive(Debug, Clone)]
+pub struct MarkInfo {
+    pub name: char,
+    pub note: Option<String>, // For global marks
+    pub line: usize,          // From 0
+    pub column: usize,        // In characters, from 0
+    pub text: String,         // The mark's line
+}
+
+impl MarkInfo {
+    pub fn new(name: char, note: Option<String>, pos: usize, content: &str) -> Self {
+        let mut pos = pos.min(content.len());
+        while !content.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        let line_start = content[..pos].rfind('\n').map_or(0, |p| p + 1);
+        let line_end = content[line_start..].find('\n').map_or(content.len(), |p| line_start + p);
+        Self {
+            name,
+            note,
+            line: content[..line_start].matches('\n').count(),
+            column: content[line_start..pos].chars().count(),
+            text: content[line_start..line_end].trim().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Marks {
+    positions: HashMap<char, usize>, // Byte offsets in the note
+    changes: Vec<usize>,             // Where the note was changed, oldest first
+    change_index: Option<usize>,     // The change g; or g, went to last
+}
+
+impl Marks {
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphabetic() || name == CONTEXT_MARK || name == '`'
+    }
+
+    pub fn is_global(name: char) -> bool {
+        name.is_ascii_uppercase()
+    }
+
+    pub fn set(&mut self, name: char, pos: usize) {
+        self.positions.insert(Self::canonical(name), pos);
+    }
+
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.positions.get(&Self::canonical(name)).copied()
+    }
+
+    pub fn remove(&mut self, name: char) {
+        self.positions.remove(&Self::canonical(name));
+    }
+
+    // The marks that are set, in the order `:marks` lists them: `'`, a-z,
+    // then A-Z. Only those in `names` if it isn't empty.
+    pub fn list(&self, names: &str) -> Vec<(char, usize)> {
+        std::iter::once(CONTEXT_MARK)
+            .chain('a'..='z')
+            .chain('A'..='Z')
+            .filter(|name| names.is_empty() || names.contains(*name))
+            .filter_map(|name| self.get(name).map(|pos| (name, pos)))
+            .collect()
+    }
+
+    // `` ` `` and `'` are the same mark
+    fn canonical(name: char) -> char {
+        if name == '`' {
+            CONTEXT_MARK
+        } else {
+            name
+        }
+    }
+
+    // Remember where a change was made. Changes on the same line as the
+    // last one replace it, so typing a sentence is one entry.
+    pub fn record_change(&mut self, text: &str, pos: usize) {
+        let line_start = |pos: usize| text.get(..pos).and_then(|before| before.rfind('\n'));
+        if self.changes.last().is_some_and(|last| line_start(*last) == line_start(pos)) {
+            self.changes.pop();
+        }
+        self.changes.push(pos);
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.remove(0);
+        }
+        self.change_index = None;
+    }
+
+    // The position of an older (g;) or newer (g,) change than the one went to last
+    pub fn change(&mut self, older: bool) -> Result<usize, &'static str> {
+        if self.changes.is_empty() {
+            return Err("Change list is empty");
+        }
+        let index = match (self.change_index, older) {
+            (None, true) => self.changes.len() - 1,
+            (Some(0), true) => return Err("At start of change list"),
+            (Some(index), true) => index - 1,
+            (Some(index), false) if index + 1 < self.changes.len() => index + 1,
+            (_, false) => return Err("At end of change list"),
+        };
+        self.change_index = Some(index);
+        Ok(self.changes[index])
+    }
+
+    // Move the marks along with an edit that turned `before` into `after`.
+    // Marks after the changed part shift by how much it grew or shrank; marks
+    // inside text that was replaced move to where the change starts.
+    pub fn adjust(&mut self, before: &str, after: &str) {
+        if (self.positions.is_empty() && self.changes.is_empty()) || before == after {
+            return;
+        }
+        let prefix = before
+            .char_indices()
+            .zip(after.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(before.len().min(after.len()), |((index, _), _)| index);
+        let suffix = before[prefix..]
+            .chars()
+            .rev()
+            .zip(after[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+        let changed_end = before.len() - suffix;
+
+        for pos in self.positions.values_mut().chain(self.changes.iter_mut()) {
+            if *pos >= changed_end {
+                // Can't underflow: the unchanged end is in both texts
+                *pos = *pos + after.len() - before.len();
+            } else if *pos > prefix {
+                *pos = prefix;
+            }
+        }
+    }
+}
@@ -1,3 +1,17 @@
 mod simple_editor;
+mod history;
+mod registers;
+mod macros;
+mod marks;
+mod search;
+mod motions;
+mod text_objects;
+mod indent;
+mod changes;
+mod script;
 
-pub use simple_editor::SimpleEditor; 
\ No newline at end of file
+pub use simple_editor::SimpleEditor;
+pub use registers::{Register, RegisterKind};
+pub use marks::{MarkInfo, Marks};
+pub use changes::{Change, Changes};
+pub use script::Script;
@@ -0,0 +1,194 @@
+// Word and sentence motions, like vim's. A word is a run of letters, digits
+// and underscores, or a run of other non-blank characters; a WORD (`W`, `B`,
+// `E`) is any run of non-blank characters, so it takes a URL or a path with
+// its punctuation in one go. A sentence ends with `.`, `!` or `?`, and any
+// closing `)`, `]`, `"` or `'`, before a blank or the end of the line; blank
+// lines end one too.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+pub(super) fn class(c: char, big: bool) -> Class {
+    if c.is_whitespace() {
+        Class::Blank
+    } else if big || c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+// Past the characters from `pos` on for which `keep` holds
+pub(super) fn skip_forward(text: &str, mut pos: usize, keep: impl Fn(char) -> bool) -> usize {
+    while let Some(c) = text[pos..].chars().next().filter(|&c| keep(c)) {
+        pos += c.len_utf8();
+    }
+    pos
+}
+
+// Back over the characters before `pos` for which `keep` holds
+pub(super) fn skip_back(text: &str, mut pos: usize, keep: impl Fn(char) -> bool) -> usize {
+    while let Some(c) = text[..pos].chars().next_back().filter(|&c| keep(c)) {
+        pos -= c.len_utf8();
+    }
+    pos
+}
+
+// w / W: the start of the next word, or the end of the text
+pub fn next_word_start(text: &str, pos: usize, big: bool) -> usize {
+    let pos = match text[pos..].chars().next() {
+        Some(c) if !c.is_whitespace() => skip_forward(text, pos, |next| class(next, big) == class(c, big)),
+        _ => pos,
+    };
+    skip_forward(text, pos, char::is_whitespace)
+}
+
+// b / B: the start of the word before `pos`, or of the one it's in
+pub fn prev_word_start(text: &str, pos: usize, big: bool) -> usize {
+    let pos = skip_back(text, pos, char::is_whitespace);
+    match text[..pos].chars().next_back() {
+        Some(c) => skip_back(text, pos, |prev| class(prev, big) == class(c, big)),
+        None => pos,
+    }
+}
+
+// e / E: the last character of the word after `pos`, or of the one it's in
+// if `pos` isn't already on its last character. Stays put at the end of the text.
+pub fn word_end(text: &str, pos: usize, big: bool) -> usize {
+    let after = match text[pos..].chars().next() {
+        Some(c) => pos + c.len_utf8(),
+        None => return pos,
+    };
+    let start = skip_forward(text, after, char::is_whitespace);
+    let c = match text[start..].chars().next() {
+        Some(c) => c,
+        None => return pos,
+    };
+    let end = skip_forward(text, start, |next| class(next, big) == class(c, big));
+    end - text[..end].chars().next_back().map_or(0, char::len_utf8)
+}
+
+// Where sentences start: the first non-blank of the text, after a sentence's
+// end and after blank lines, and the first of each run of blank lines
+fn sentence_starts(text: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut boundary = true; // The next non-blank starts a sentence
+    let mut blank_run = false;
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        if line.trim().is_empty() {
+            if !blank_run {
+                starts.push(line_start);
+            }
+            blank_run = true;
+            boundary = true;
+        } else {
+            blank_run = false;
+            let mut after_end = false; // Past `.`, `!` or `?` and any closing punctuation
+            for (i, c) in line.char_indices() {
+                if c.is_whitespace() {
+                    boundary |= after_end;
+                    after_end = false;
+                    continue;
+                }
+                if boundary {
+                    starts.push(line_start + i);
+                    boundary = false;
+                }
+                after_end = matches!(c, '.' | '!' | '?') || (after_end && matches!(c, ')' | ']' | '"' | '\''));
+            }
+            boundary |= after_end;
+        }
+        line_start += line.len() + 1;
+    }
+    starts
+}
+
+// ( and ): the start of the `count`th sentence before `pos`, or after it.
+// Past the first or the last one that's the start or the end of the text.
+pub fn sentence_start(text: &str, pos: usize, forward: bool, count: usize) -> usize {
+    let starts = sentence_starts(text);
+    if forward {
+        let next = starts.partition_point(|&start| start <= pos);
+        starts.get(next + count - 1).copied().unwrap_or(text.len())
+    } else {
+        let prev = starts.partition_point(|&start| start < pos);
+        prev.checked_sub(count).map_or(0, |i| starts[i])
+    }
+}
+
+// %: the bracket matching the first (), [] or {} at or after `pos` on its
+// line, or the other fence of a code block when `pos` is on a ``` or ~~~ line
+pub fn matching_pair(text: &str, pos: usize) -> Option<usize> {
+    let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+    let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+    if let Some(fence) = matching_fence(text, line_start) {
+        return Some(fence);
+    }
+
+    let (offset, bracket) = text[pos..line_end].char_indices().find(|(_, c)| "()[]{}".contains(*c))?;
+    let start = pos + offset;
+    // The bracket to count nesting with, and the one that closes it
+    let (nested, closing, forward) = match bracket {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => (')', '(', false),
+        ']' => (']', '[', false),
+        _ => ('}', '{', false),
+    };
+    let chars: Box<dyn Iterator<Item = (usize, char)>> = if forward {
+        Box::new(text[start..].char_indices().map(move |(i, c)| (start + i, c)))
+    } else {
+        Box::new(text[..=start].char_indices().rev())
+    };
+    let mut depth = 0usize;
+    for (i, c) in chars {
+        if c == nested {
+            depth += 1;
+        } else if c == closing {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+// The first non-blank of the code fence that pairs with the one on the line
+// starting at `line_start`, if that line is a fence
+fn matching_fence(text: &str, line_start: usize) -> Option<usize> {
+    let fence_of = |line: &str| {
+        let trimmed = line.trim_start();
+        ["```", "~~~"].into_iter().find(|fence| trimmed.starts_with(fence))
+    };
+    let mut open: Option<(usize, &str)> = None;
+    let mut start = 0;
+    for line in text.split('\n') {
+        let first = start + (line.len() - line.trim_start().len());
+        match (open, fence_of(line)) {
+            (None, Some(fence)) => open = Some((first, fence)),
+            (Some((open_first, fence)), Some(closing)) if closing == fence => {
+                let open_start = text[..open_first].rfind('\n').map_or(0, |p| p + 1);
+                if open_start == line_start {
+                    return Some(first);
+                }
+                if start == line_start {
+                    return Some(open_first);
+                }
+                open = None;
+            },
+            _ => {},
+        }
+        if start > line_start && open.is_none() {
+            return None;
+        }
+        start += line.len() + 1;
+    }
+    None
+}
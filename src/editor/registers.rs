@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::platform;
+
+// How a register's text is put back: as whole lines (from yy, dd, cc) or inline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterKind {
+    Charwise,
+    Linewise,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+// Vim-style register file. Every yank and delete goes to the unnamed register
+// `"`; with "a-"z in front it also goes to that register, and "A-"Z append to it.
+// "+ and "* are the system clipboard (and the primary selection on Linux).
+// Without a register name, yanks also go to "0, deleted lines to "1 (shifting
+// the older ones down to "9) and deletes within a line to "-.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+}
+
+pub const UNNAMED_REGISTER: char = '"';
+
+fn is_clipboard(name: char) -> bool {
+    name == '+' || name == '*'
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Whether `"{name}` refers to a register
+    pub fn is_valid_name(name: char) -> bool {
+        name == UNNAMED_REGISTER || name == '-' || name.is_ascii_alphanumeric() || is_clipboard(name)
+    }
+
+    pub fn get(&self, name: char) -> Option<Register> {
+        if is_clipboard(name) {
+            return match platform::clipboard_text(name == '*') {
+                // Like vim, text ending in a newline is put as lines
+                Ok(text) => Some(Register {
+                    kind: if text.ends_with('\n') { RegisterKind::Linewise } else { RegisterKind::Charwise },
+                    text: text.replace("\r\n", "\n"),
+                }),
+                Err(err) => {
+                    println!("Could not read the clipboard: {}", err);
+                    None
+                },
+            };
+        }
+        self.registers.get(&name.to_ascii_lowercase()).cloned()
+    }
+
+    // Registers that hold something, in the order `:registers` lists them,
+    // optionally only those named in `names`. The clipboard is read too.
+    pub fn list(&self, names: &str) -> Vec<(char, Register)> {
+        "\"0123456789abcdefghijklmnopqrstuvwxyz-*+"
+            .chars()
+            .filter(|name| names.is_empty() || names.to_ascii_lowercase().contains(*name))
+            .filter_map(|name| self.get(name).map(|register| (name, register)))
+            .filter(|(_, register)| !register.text.is_empty())
+            .collect()
+    }
+
+    pub fn yank(&mut self, name: Option<char>, text: String, kind: RegisterKind) {
+        if name.is_none() {
+            self.registers.insert('0', Register { text: text.clone(), kind });
+        }
+        self.store(name, text, kind);
+    }
+
+    pub fn delete(&mut self, name: Option<char>, text: String, kind: RegisterKind) {
+        if name.is_none() {
+            if kind == RegisterKind::Linewise || text.contains('\n') {
+                // "9 falls off the end
+                for number in (1..9).rev() {
+                    if let Some(register) = self.registers.remove(&char::from(b'0' + number)) {
+                        self.registers.insert(char::from(b'1' + number), register);
+                    }
+                }
+                self.registers.insert('1', Register { text: text.clone(), kind });
+            } else {
+                self.registers.insert('-', Register { text: text.clone(), kind });
+            }
+        }
+        self.store(name, text, kind);
+    }
+
+    // Keep the keys recorded with q{name}; "A-"Z append to a macro. Unlike
+    // yanks this leaves the unnamed register alone.
+    pub fn record_macro(&mut self, name: char, keys: String) {
+        let unnamed = self.registers.get(&UNNAMED_REGISTER).cloned();
+        self.store(Some(name), keys, RegisterKind::Charwise);
+        match unnamed {
+            Some(register) => self.registers.insert(UNNAMED_REGISTER, register),
+            None => self.registers.remove(&UNNAMED_REGISTER),
+        };
+    }
+
+    // Store yanked or deleted text, in `name` as well if one was given
+    fn store(&mut self, name: Option<char>, text: String, kind: RegisterKind) {
+        let register = match name {
+            Some(name) if name.is_ascii_uppercase() => {
+                let name = name.to_ascii_lowercase();
+                let register = match self.registers.remove(&name) {
+                    Some(existing) => Self::append(existing, text, kind),
+                    None => Register { text, kind },
+                };
+                self.registers.insert(name, register.clone());
+                register
+            },
+            Some(name) if name.is_ascii_lowercase() || name.is_ascii_digit() || name == '-' => {
+                let register = Register { text, kind };
+                self.registers.insert(name, register.clone());
+                register
+            },
+            Some(name) if is_clipboard(name) => {
+                if let Err(err) = platform::set_clipboard_text(&text, name == '*') {
+                    println!("Could not write to the clipboard: {}", err);
+                }
+                Register { text, kind }
+            },
+            _ => Register { text, kind },
+        };
+        self.registers.insert(UNNAMED_REGISTER, register);
+    }
+
+    // Like vim, appending lines to anything (or anything to lines) gives lines
+    fn append(mut existing: Register, text: String, kind: RegisterKind) -> Register {
+        if existing.kind == RegisterKind::Charwise && kind == RegisterKind::Charwise {
+            existing.text.push_str(&text);
+            return existing;
+        }
+        if !existing.text.ends_with('\n') {
+            existing.text.push('\n');
+        }
+        existing.text.push_str(&text);
+        if !existing.text.ends_with('\n') {
+            existing.text.push('\n');
+        }
+        existing.kind = RegisterKind::Linewise;
+        existing
+    }
+}
@@ -0,0 +1,137 @@
+use eframe::egui;
+
+use crate::modes::VimMode;
+use super::macros::{char_key, parse_key, split_keys};
+use super::simple_editor::SimpleEditor;
+
+// Scripted input, for tests of the vim keys. Keys are written in vim's key
+// notation, like a macro's (`wdw`, `ci"new<Esc>`, `<C-r>`), and typed into
+// the editor one frame each, routed the way NotesApp::update routes a frame's
+// events. A letter, digit or space comes as its key and then its text, the
+// way egui sends them; other characters come as text only, and <Esc>, <CR>,
+// <C-d> and the like as keys only. The actions the editor gives the app, like
+// `save` for `:w`, are kept in `actions`.
+//
+//     let mut script = Script::new("one two");
+//     script.keys("wdw");
+//     assert_eq!(script.state(), "one |");
+
+pub struct Script {
+    pub editor: SimpleEditor,
+    pub text: String,
+    pub actions: Vec<String>,
+}
+
+impl Script {
+    // An editor on `text`, in normal mode with the cursor at the start
+    pub fn new(text: &str) -> Self {
+        let mut editor = SimpleEditor::new();
+        editor.reset_history(text);
+        Self { editor, text: text.to_string(), actions: Vec::new() }
+    }
+
+    // Type `keys`, one frame each
+    pub fn keys(&mut self, keys: &str) -> &mut Self {
+        for token in split_keys(keys) {
+            self.frame(token_events(&token));
+        }
+        self
+    }
+
+    // Run the ex command `command` (without its `:`), and return the action
+    // the editor gives the app for it, if any
+    pub fn command(&mut self, command: &str) -> Option<String> {
+        let before = self.actions.len();
+        self.keys(":");
+        // Typed as text, so `<` and the like needn't be written as keys
+        for c in command.chars() {
+            self.frame(vec![egui::Event::Text(c.to_string())]);
+        }
+        self.keys("<CR>");
+        self.actions[before..].last().cloned()
+    }
+
+    // The text with a `|` at the cursor, e.g. "one |two"
+    pub fn state(&self) -> String {
+        let mut state = self.text.clone();
+        state.insert(self.editor.cursor_position.min(state.len()), '|');
+        state
+    }
+
+    fn frame(&mut self, events: Vec<egui::Event>) {
+        let mode = self.editor.vim_mode;
+        // Text typed in normal or visual mode only goes to commands
+        let normal_text = matches!(mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock);
+        let collect_text = normal_text || matches!(mode, VimMode::Insert | VimMode::Replace | VimMode::Command);
+
+        // The app takes Escape back to normal mode before the editor gets the frame's keys
+        let escape = events.iter().any(|event| matches!(event, egui::Event::Key { key: egui::Key::Escape, .. }));
+        if escape && mode != VimMode::Normal {
+            self.editor.handle_key_press(egui::Key::Escape, &mut self.text, &egui::Modifiers::NONE);
+        }
+
+        let mut events = events.into_iter().filter(|event| collect_text || !matches!(event, egui::Event::Text(_)));
+        loop {
+            // Keys of a macro played with @ come first, read in the mode they're played in
+            let (event, normal_text) = match self.editor.next_macro_event() {
+                Some(event) => (event, matches!(self.editor.vim_mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock)),
+                None => match events.next() {
+                    Some(event) => (event, normal_text),
+                    None => break,
+                },
+            };
+            match event {
+                egui::Event::Text(text) if normal_text => {
+                    for c in text.chars() {
+                        let (_, action) = self.editor.handle_normal_text(c, &mut self.text);
+                        self.actions.extend(action);
+                    }
+                },
+                egui::Event::Text(text) => {
+                    if matches!(self.editor.vim_mode, VimMode::Insert | VimMode::Replace | VimMode::Command) {
+                        for c in text.chars().filter(|&c| c >= ' ' || c == '\n' || c == '\t') {
+                            self.editor.handle_text_input(c, &mut self.text);
+                        }
+                    }
+                },
+                egui::Event::Key { key, pressed: true, modifiers, .. } => {
+                    let (_, action) = self.editor.handle_key_press(key, &mut self.text, &modifiers);
+                    self.actions.extend(action);
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+// The events egui sends when a key of key notation is typed
+fn token_events(token: &str) -> Vec<egui::Event> {
+    let key = |key, modifiers| egui::Event::Key { key, pressed: true, repeat: false, modifiers };
+    let text = egui::Event::Text(token.to_string());
+
+    if token == "<lt>" {
+        return vec![egui::Event::Text("<".to_string())];
+    }
+    if let Some((named, modifiers)) = token.strip_prefix('<').and_then(|t| t.strip_suffix('>')).and_then(parse_key) {
+        return vec![key(named, modifiers)];
+    }
+
+    let c = token.chars().next().unwrap_or(' ');
+    let shift = egui::Modifiers::SHIFT;
+    let typed = match c {
+        ' ' => Some((egui::Key::Space, egui::Modifiers::NONE)),
+        '-' => Some((egui::Key::Minus, egui::Modifiers::NONE)),
+        '_' => Some((egui::Key::Minus, shift)),
+        '=' => Some((egui::Key::PlusEquals, egui::Modifiers::NONE)),
+        '+' => Some((egui::Key::PlusEquals, shift)),
+        c if c.is_ascii_alphanumeric() => {
+            char_key(c.to_ascii_lowercase()).map(|typed| (typed, if c.is_ascii_uppercase() { shift } else { egui::Modifiers::NONE }))
+        },
+        // Shifted digits on a US keyboard
+        c => ")!@#$%^&*(".find(c).and_then(|digit| char_key((b'0' + digit as u8) as char)).map(|typed| (typed, shift)),
+    };
+    match typed {
+        Some((typed, modifiers)) => vec![key(typed, modifiers), text],
+        None => vec![text],
+    }
+}
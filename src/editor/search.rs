@@ -0,0 +1,196 @@
+use regex::{Captures, Regex, RegexBuilder};
+
+// `/` and `?` searches within the note, `*` and `#` for the word under the
+// cursor, and `:s` to replace what they find. Patterns are regular expressions
+// in the regex crate's syntax, e.g. `\d{4}-\d{2}`, with `\<` and `\>` for the
+// start and end of a word like in vim.
+
+// What a search looks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub text: String,
+    pub ignore_case: bool,
+}
+
+impl Pattern {
+    pub fn new(text: &str) -> Self {
+        Self { text: text.to_string(), ignore_case: false }
+    }
+
+    // The word on its own, not as part of a longer word, like `*` and `#` search
+    pub fn word(word: &str) -> Self {
+        Self::new(&format!("\\<{}\\>", regex::escape(word)))
+    }
+
+    // The pattern with the `ignorecase` and `smartcase` options applied: with
+    // both set, only patterns with capitals match case exactly. Escapes like
+    // `\S` don't count as capitals.
+    pub fn with_case(&self, ignorecase: bool, smartcase: bool) -> Self {
+        let mut has_capitals = false;
+        let mut escaped = false;
+        for c in self.text.chars() {
+            has_capitals |= !escaped && c.is_uppercase();
+            escaped = !escaped && c == '\\';
+        }
+        Self { ignore_case: ignorecase && !(smartcase && has_capitals), ..self.clone() }
+    }
+
+    // `^` and `$` match at the start and end of every line
+    pub fn regex(&self) -> Result<Regex, String> {
+        RegexBuilder::new(&self.text)
+            .case_insensitive(self.ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|err| match err {
+                // The last line says what's wrong, below the pattern and a pointer into it
+                regex::Error::Syntax(message) => message.lines().last().unwrap_or("").trim_start_matches("error: ").to_string(),
+                err => err.to_string(),
+            })
+    }
+
+    // Start and end of every match, in order; none for an invalid pattern
+    pub fn matches(&self, text: &str) -> Vec<(usize, usize)> {
+        if self.text.is_empty() {
+            return Vec::new();
+        }
+        match self.regex() {
+            Ok(regex) => regex.find_iter(text).map(|m| (m.start(), m.end())).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Where a search found its pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub wrapped: bool, // Went past the end (or start) of the note to find it
+}
+
+// The next match of `pattern` after `from`, or before it going backwards,
+// continuing from the other end of the note if there's none
+pub fn find(text: &str, pattern: &Pattern, from: usize, forward: bool) -> Option<Match> {
+    let matches = pattern.matches(text);
+    let from = from.min(text.len());
+    let found = |&(start, end): &(usize, usize), wrapped: bool| Match { start, end, wrapped };
+    if forward {
+        // Not the match the cursor is already on
+        matches.iter()
+            .find(|&&(start, _)| start > from)
+            .map(|m| found(m, false))
+            .or_else(|| matches.first().map(|m| found(m, true)))
+    } else {
+        matches.iter()
+            .rev()
+            .find(|&&(start, _)| start < from)
+            .map(|m| found(m, false))
+            .or_else(|| matches.last().map(|m| found(m, true)))
+    }
+}
+
+// Every match of `pattern`, for highlighting them
+pub fn find_all(text: &str, pattern: &Pattern) -> Vec<(usize, usize)> {
+    pattern.matches(text)
+}
+
+// `:s/pattern/replacement/flags`; like vim, any punctuation can stand in for
+// `/`, and a backslash before it keeps it in the pattern or replacement
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substitute {
+    pub pattern: String, // Empty for the last search pattern
+    pub replacement: String,
+    pub global: bool, // `g` flag: every match on a line, not just the first
+}
+
+impl Substitute {
+    // `args` is what comes after the `s`, e.g. `/old/new/g`
+    pub fn parse(args: &str) -> Option<Self> {
+        let delimiter = args.chars().next().filter(|c| c.is_ascii_punctuation() && *c != '"' && *c != '\\')?;
+        let mut parts = Vec::new();
+        let mut part = String::new();
+        let mut chars = args[delimiter.len_utf8()..].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(next) if next == delimiter => part.push(next),
+                    Some(next) => {
+                        part.push('\\');
+                        part.push(next);
+                    },
+                    None => part.push('\\'),
+                },
+                c if c == delimiter && parts.len() < 2 => parts.push(std::mem::take(&mut part)),
+                c => part.push(c),
+            }
+        }
+        parts.push(part);
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+        if flags.trim().chars().any(|c| c != 'g') {
+            return None;
+        }
+        Some(Self { pattern, replacement, global: flags.contains('g') })
+    }
+}
+
+// The text a match is replaced with: `\1`..`\9` are what the groups in the
+// pattern matched, `&` and `\0` the whole match, and `\n` a line break
+fn expand_replacement(replacement: &str, captures: &Captures) -> String {
+    let group = |i: usize| captures.get(i).map_or("", |m| m.as_str());
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str(group(0)),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => expanded.push_str(group(digit as usize - '0' as usize)),
+                Some('n') | Some('r') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some(other) => expanded.push(other),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+// Replace matches of `pattern` in the lines of `text[start..end]`, the first
+// on each line or all of them. Returns the new text, how many were replaced
+// and where the last replacement is in the new text.
+pub fn substitute(text: &str, start: usize, end: usize, pattern: &Pattern, replacement: &str, global: bool) -> Result<(String, usize, usize), String> {
+    let regex = pattern.regex()?;
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut count = 0;
+    let mut last = 0;
+    // Where the line of the last replacement ends
+    let mut line_end = None;
+    for captures in regex.captures_iter(text) {
+        let whole = captures.get(0).expect("group 0 is the whole match");
+        let (match_start, match_end) = (whole.start(), whole.end());
+        if match_start < start || match_end > end {
+            continue;
+        }
+        if !global && line_end.is_some_and(|line_end| match_start <= line_end) {
+            continue;
+        }
+        line_end = Some(text[match_end..].find('\n').map_or(text.len(), |p| match_end + p));
+        result.push_str(&text[copied..match_start]);
+        last = result.len();
+        result.push_str(&expand_replacement(replacement, &captures));
+        copied = match_end;
+        count += 1;
+    }
+    result.push_str(&text[copied..]);
+    Ok((result, count, last))
+}
@@ -1,6 +1,45 @@
 use eframe::egui;
+use std::io;
+use std::path::Path;
 use crate::modes::VimMode;
 use crate::operations::VimOperation;
+use crate::links;
+use crate::people;
+use super::history::{EditHistory, HistoryMove, TimeTravel, UndoState};
+use super::registers::{RegisterKind, Registers, UNNAMED_REGISTER};
+use super::macros::{Macros, PendingMacro};
+use super::motions;
+use super::text_objects::{self, Scope};
+use super::indent;
+use super::search::{self, Pattern, Substitute};
+use super::changes::{Change, Changes};
+use super::marks::{Marks, PendingMark, CONTEXT_MARK};
+
+// An `I`/`A` started in visual block mode: what gets typed on the first line
+// is repeated on the others when insert mode ends
+#[derive(Debug, Clone, Copy)]
+struct BlockInsert {
+    start: usize, // Where typing started on the first line
+    first_line: usize,
+    last_line: usize,
+    column: usize, // Character column to insert at on the other lines
+    append: bool, // `A` pads short lines with spaces; `I` skips them
+}
+
+// A visual selection remembered for `gv` and `:'<,'>`
+#[derive(Debug, Clone, Copy)]
+pub struct VisualSelection {
+    pub mode: VimMode, // Visual or VisualBlock
+    pub anchor: usize,
+    pub cursor: usize,
+}
+
+// f, F, t or T: find a character on the cursor's line
+#[derive(Debug, Clone, Copy)]
+struct CharFind {
+    forward: bool, // f and t; F and T look back
+    till: bool,    // t and T stop next to the character instead of on it
+}
 
 // A simple editor that focuses on basic text editing functionality with vim-like keybindings
 pub struct SimpleEditor {
@@ -12,7 +51,42 @@ pub struct SimpleEditor {
     pub command_buffer: String,
     // Fields for key register system
     pub current_operation: VimOperation,
-    pub register_buffer: String,
+    pub registers: Registers,
+    pub selected_register: Option<char>, // Register picked with `"x` for the next yank, delete or put
+    pub pending_register_name: bool, // Got `"`, waiting for the register name
+    pending_object: Option<Scope>, // Got the `i` or `a` of `diw` or `yaw`, waiting for the text object
+    pub macros: Macros,
+    pub pending_macro: Option<PendingMacro>, // Got `q` or `@`, waiting for the register name
+    pub count: Option<usize>, // Count typed before a command, like the 10 in 10@a
+    pub marks: Marks,
+    pending_mark: Option<PendingMark>,
+    pub text_width: Option<usize>, // Hard-wrap column while typing, from the note's options
+    pub shift_width: usize, // Spaces >> and << shift by, from the note's options
+    auto_indent: Option<usize>, // Where the indent o, O or Enter started a line with ends
+    pub pending_g: bool, // Waiting for the second key of a g-prefixed command
+    pending_find: Option<CharFind>, // Got f, F, t or T, waiting for the character
+    last_find: Option<(CharFind, char)>, // For ; and ,
+    pending_replace: bool, // Got r, waiting for the character
+    pub visual_anchor: usize, // Where the visual selection started; the cursor is the other end
+    pub last_visual: Option<VisualSelection>, // The selection before leaving visual mode
+    pub read_only: bool, // Reject anything that would change the text
+    pub last_search: Option<(Pattern, bool)>, // Pattern of the last forward (true) or backward (false) search, for n and N
+    pub incsearch: Option<(usize, usize)>, // Match shown while a search pattern is typed
+    pub highlight_search: bool, // Highlight the last search's matches, until :noh
+    pub ignore_case: bool, // The `ignorecase` option, for searches and :s
+    pub smart_case: bool, // The `smartcase` option: patterns with capitals don't ignore case
+    search_origin: Option<usize>, // Cursor position when the search being typed started
+    pub scroll_to_cursor: bool, // Bring the cursor into view on the next frame
+    pub scroll_with_cursor: bool, // Scroll the view as far as the cursor moved, on the next frame
+    pub page_lines: usize, // Lines that fit in the view, from the app
+    pub top_line: usize, // First line in the view, from the app
+    pub scroll_to_line: Option<usize>, // Line to put at the top of the view, on the next frame
+    scroll_lines: Option<usize>, // Lines Ctrl+D and Ctrl+U move, once set with a count
+    block_insert: Option<BlockInsert>,
+    replaced: Vec<Option<char>>, // In replace mode, what each typed character went over, None where it was added; for Backspace
+    pub history: EditHistory,
+    insert_undo_state: Option<UndoState>, // State before the current insert session, committed as one undo step
+    pub changes: Changes, // Edits made here, and notes opened and saved as the app reports them
 }
 
 impl SimpleEditor {
@@ -25,16 +99,1391 @@ impl SimpleEditor {
             vim_mode: VimMode::Normal,
             command_buffer: String::new(),
             current_operation: VimOperation::None,
-            register_buffer: String::new(),
+            registers: Registers::new(),
+            selected_register: None,
+            pending_register_name: false,
+            pending_object: None,
+            macros: Macros::default(),
+            pending_macro: None,
+            count: None,
+            marks: Marks::default(),
+            pending_mark: None,
+            text_width: None,
+            shift_width: 2,
+            auto_indent: None,
+            pending_g: false,
+            pending_find: None,
+            last_find: None,
+            pending_replace: false,
+            visual_anchor: 0,
+            last_visual: None,
+            read_only: false,
+            last_search: None,
+            incsearch: None,
+            highlight_search: false,
+            ignore_case: false,
+            smart_case: false,
+            search_origin: None,
+            scroll_to_cursor: false,
+            scroll_with_cursor: false,
+            page_lines: 40,
+            top_line: 0,
+            scroll_to_line: None,
+            scroll_lines: None,
+            block_insert: None,
+            replaced: Vec::new(),
+            history: EditHistory::new(""),
+            insert_undo_state: None,
+            changes: Changes::default(),
+        }
+    }
+    
+    pub fn handle_key_press(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        if let Some(result) = self.handle_macro_key(key, modifiers) {
+            return result;
+        }
+        let mode = self.vim_mode;
+        let result = self.handle_key(key, text, modifiers);
+        if result.0 {
+            self.macros.record_key(key, modifiers);
+        }
+        self.publish_mode_change(mode);
+        result
+    }
+    
+    fn publish_mode_change(&mut self, from: VimMode) {
+        if self.vim_mode != from {
+            self.changes.publish(Change::ModeChanged { from, to: self.vim_mode });
+        }
+    }
+    
+    fn handle_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        // Undo and redo restore snapshots instead of being recorded as changes themselves
+        let idle_normal_mode = self.vim_mode == VimMode::Normal
+            && self.current_operation == VimOperation::None
+            && !self.pending_g
+            && self.pending_find.is_none()
+            && !self.pending_replace
+            && !self.read_only;
+        if idle_normal_mode && key == egui::Key::U && !modifiers.ctrl && !modifiers.shift {
+            return (true, self.undo(text));
+        }
+        if idle_normal_mode && key == egui::Key::R && modifiers.ctrl {
+            return (true, self.redo(text));
+        }
+        
+        if self.pending_register_name && self.select_register_with_key(key, modifiers) {
+            return (true, None);
+        }
+        if let Some(pending) = self.pending_mark {
+            return self.handle_mark_key(pending, key, text, modifiers);
+        }
+        
+        // A count before a command, like the 10 in 10@a, or 3> on a selection
+        let counting = matches!(self.vim_mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock)
+            && self.current_operation == VimOperation::None
+            && !self.pending_g
+            && self.pending_find.is_none()
+            && !self.pending_replace
+            && !modifiers.shift
+            && !modifiers.ctrl;
+        if let Some(digit) = key_digit(key).and_then(|d| d.to_digit(10)).filter(|_| counting) {
+            if digit != 0 || self.count.is_some() {
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+                return (true, None);
+            }
+        }
+        
+        let before = UndoState::capture(text, self.cursor_position);
+        let registers_before = self.registers.clone();
+        let visual_before = self.current_visual();
+        let result = match self.vim_mode {
+            _ if self.pending_find.is_some() => self.handle_find_key(key, text, modifiers),
+            _ if self.pending_replace => self.handle_replace_key(key, text, modifiers),
+            VimMode::Normal => self.handle_normal_mode_key(key, text, modifiers),
+            VimMode::Insert => self.handle_insert_mode_key(key, text, modifiers),
+            VimMode::Replace => self.handle_replace_mode_key(key, text, modifiers),
+            VimMode::Command => self.handle_command_mode_key(key, text, modifiers),
+            VimMode::Visual => self.handle_visual_mode_key(key, text, modifiers),
+            VimMode::VisualBlock => self.handle_visual_block_mode_key(key, text, modifiers),
+        };
+        if visual_before.is_some() && self.current_visual().is_none() {
+            self.last_visual = visual_before;
+        }
+        if self.current_operation == VimOperation::None && !self.pending_g {
+            // A register only applies to the command right after it
+            self.selected_register = None;
+        }
+        let pending = self.pending_g || self.pending_find.is_some() || self.pending_replace || self.current_operation != VimOperation::None;
+        if (result.0 && !pending) || key == egui::Key::Escape {
+            // Keys that weren't handled may be followed by their text, like the
+            // `@` of Shift+2, and `g`, `f` and operators are followed by the
+            // rest of the command, which the count is for (`2dw`, `3daw`)
+            self.count = None;
+        }
+        if self.read_only && (before.text != *text || matches!(self.vim_mode, VimMode::Insert | VimMode::Replace)) {
+            // Read-only notes can be moved around in and annotated, not edited
+            *text = before.text;
+            self.cursor_position = before.cursor_position;
+            self.registers = registers_before;
+            self.update_cursor_line_column(text);
+            self.vim_mode = VimMode::Normal;
+            return (true, Some("status Note is read-only".to_string()));
+        }
+        self.record_change(before, text);
+        result
+    }
+    
+    // `q` starts and stops recording a macro, and the key after `q` or `@` is
+    // the register to record into or play. Returns None for other keys.
+    fn handle_macro_key(&mut self, key: egui::Key, modifiers: &egui::Modifiers) -> Option<(bool, Option<String>)> {
+        if let Some(pending) = self.pending_macro {
+            let name = match (key_letter(key), key_digit(key)) {
+                (Some(letter), _) if modifiers.shift => letter.to_ascii_uppercase(),
+                (Some(letter), _) => letter,
+                (_, Some(digit)) if !modifiers.shift => digit,
+                _ if key == egui::Key::Escape => {
+                    self.pending_macro = None;
+                    self.count = None;
+                    return Some((true, None));
+                },
+                // Shift+2 is followed by its text, `@`, for `@@`
+                _ => return Some((true, None)),
+            };
+            self.pending_macro = None;
+            return Some(match pending {
+                PendingMacro::Record => {
+                    self.macros.start_recording(name);
+                    (true, None)
+                },
+                PendingMacro::Play => {
+                    self.macros.record_key(key, modifiers);
+                    (true, self.play_macro(name.to_ascii_lowercase()))
+                },
+            });
+        }
+        
+        let idle_normal_mode = self.vim_mode == VimMode::Normal
+            && self.current_operation == VimOperation::None
+            && !self.pending_g
+            && self.pending_find.is_none()
+            && !self.pending_replace
+            && !self.pending_register_name
+            && self.pending_mark.is_none();
+        if idle_normal_mode && key == egui::Key::Q && !modifiers.shift && !modifiers.ctrl {
+            match self.macros.stop_recording() {
+                Some((name, keys)) => self.registers.record_macro(name, keys),
+                None => self.pending_macro = Some(PendingMacro::Record),
+            }
+            return Some((true, None));
+        }
+        None
+    }
+    
+    // @{name}: queue the keys in the register, `count` times. The app feeds them
+    // back through handle_key_press, see `next_macro_event`.
+    fn play_macro(&mut self, name: char) -> Option<String> {
+        let count = self.count.take().unwrap_or(1);
+        let name = if name == '@' {
+            match self.macros.last_played {
+                Some(name) => name,
+                None => return Some("status No previous macro".to_string()),
+            }
+        } else {
+            name
+        };
+        match self.registers.get(name) {
+            Some(register) if !register.text.is_empty() => {
+                self.macros.last_played = Some(name);
+                self.macros.play(&register.text, count);
+                None
+            },
+            _ => Some(format!("status Register {} is empty", name)),
+        }
+    }
+    
+    // The next key of a macro being played, to be handled like a typed one
+    pub fn next_macro_event(&mut self) -> Option<egui::Event> {
+        self.macros.next_event(self.vim_mode)
+    }
+    
+    // The second key of `"x` when it's a letter or digit. Register names without a key
+    // of their own arrive as text instead, see `handle_normal_text`.
+    fn select_register_with_key(&mut self, key: egui::Key, modifiers: &egui::Modifiers) -> bool {
+        if key == egui::Key::Escape {
+            self.pending_register_name = false;
+            return true;
+        }
+        let name = match (key_letter(key), key_digit(key)) {
+            (Some(letter), _) if modifiers.shift => letter.to_ascii_uppercase(),
+            (Some(letter), _) => letter,
+            (_, Some(digit)) if !modifiers.shift => digit,
+            // Keys like Shift+8 are followed by their text ('*'), so keep waiting
+            _ => return true,
+        };
+        self.pending_register_name = false;
+        self.selected_register = Some(name);
+        true
+    }
+    
+    // Characters typed in normal and visual mode that have no egui::Key of
+    // their own. Returns false for characters that aren't commands.
+    pub fn handle_normal_text(&mut self, c: char, text: &mut String) -> (bool, Option<String>) {
+        let before = UndoState::capture(text, self.cursor_position);
+        let mode = self.vim_mode;
+        let handled = self.handle_command_text(c, text);
+        if handled.0 {
+            self.macros.record_text(c);
+        }
+        self.record_change(before, text);
+        self.publish_mode_change(mode);
+        handled
+    }
+    
+    fn handle_command_text(&mut self, c: char, text: &mut String) -> (bool, Option<String>) {
+        if self.pending_register_name {
+            self.pending_register_name = false;
+            if Registers::is_valid_name(c) {
+                self.selected_register = Some(c);
+            }
+            return (true, None);
+        }
+        match self.pending_macro.take() {
+            // `@@` plays the last macro again, `@"` the unnamed register, and so on
+            Some(PendingMacro::Play) => return (true, self.play_macro(c)),
+            Some(PendingMacro::Record) if c.is_ascii_alphanumeric() => {
+                // The text of the `q` key itself, or of the register name key
+                // handled in handle_macro_key
+                self.pending_macro = Some(PendingMacro::Record);
+                return (false, None);
+            },
+            Some(PendingMacro::Record) => {
+                if c == UNNAMED_REGISTER {
+                    self.macros.start_recording(c);
+                }
+                return (true, None);
+            },
+            None => {},
+        }
+        if let Some(find) = self.pending_find {
+            if c.is_ascii_alphanumeric() {
+                // The text of the `f` key, or of the character's key handled in handle_find_key
+                return (false, None);
+            }
+            self.pending_find = None;
+            return (true, self.find_in_line(text, find, c, false));
+        }
+        if self.pending_replace {
+            if c.is_ascii_alphanumeric() {
+                // The text of the `r` key, or of the character's key handled in handle_replace_key
+                return (false, None);
+            }
+            self.pending_replace = false;
+            self.replace_chars(text, c);
+            return (true, None);
+        }
+        if self.pending_object.is_some() {
+            if c.is_ascii_alphanumeric() {
+                // The text of the `i` or `a` key, or of an object's key like
+                // `w` handled in handle_normal_mode_key
+                return (false, None);
+            }
+            // di", ya', ci(, da{...
+            self.apply_text_object(text, c);
+            return (true, None);
+        }
+        if let Some(pending) = self.pending_mark {
+            if c.is_ascii_alphanumeric() {
+                // The text of the `m` key, or of the mark name key handled in handle_mark_key
+                return (false, None);
+            }
+            // `''` and ``` `` ``` jump back; other names aren't marks, which cancels
+            self.pending_mark = None;
+            if Marks::is_valid_name(c) {
+                return (true, self.use_mark(pending, c, text));
+            }
+            return (true, None);
+        }
+        match c {
+            ';' | ',' if self.pending_g => {
+                // g; and g, - go to an older or newer change
+                self.pending_g = false;
+                let action = match self.marks.change(c == ';') {
+                    Ok(pos) => {
+                        self.cursor_position = pos.min(text.len());
+                        while !text.is_char_boundary(self.cursor_position) {
+                            self.cursor_position -= 1;
+                        }
+                        self.update_cursor_line_column(text);
+                        self.desired_column = self.cursor_column;
+                        None
+                    },
+                    Err(message) => Some(format!("status {}", message)),
+                };
+                (true, action)
+            },
+            ';' | ',' => {
+                // Repeat the last f/F/t/T, the other way for `,`
+                match self.last_find {
+                    Some((find, found)) => {
+                        let forward = find.forward == (c == ';');
+                        (true, self.find_in_line(text, CharFind { forward, ..find }, found, true))
+                    },
+                    None => {
+                        self.current_operation = VimOperation::None;
+                        (true, None)
+                    },
+                }
+            },
+            '~' if matches!(self.vim_mode, VimMode::Visual | VimMode::VisualBlock) => {
+                // ~ in visual mode (and g~) - switch the case of the selection
+                self.pending_g = false;
+                self.change_case_selection(text, VimOperation::ToggleCase);
+                (true, None)
+            },
+            '~' if self.pending_g => {
+                // g~ - switch case over a motion, see apply_operator
+                self.pending_g = false;
+                self.current_operation = VimOperation::ToggleCase;
+                (true, None)
+            },
+            '~' if self.current_operation == VimOperation::ToggleCase => {
+                // g~~ - switch the case of the whole line
+                self.current_operation = VimOperation::None;
+                self.apply_to_lines(text, VimOperation::ToggleCase);
+                (true, None)
+            },
+            '~' if self.current_operation == VimOperation::None => {
+                self.toggle_case(text);
+                (true, None)
+            },
+            '=' if matches!(self.vim_mode, VimMode::Visual | VimMode::VisualBlock) => {
+                // = in visual mode - reindent the selected lines
+                let ranges = self.selection_ranges(text);
+                if let (Some(&(from, _)), Some(&(_, to))) = (ranges.first(), ranges.last()) {
+                    self.reindent_lines(text, from, to);
+                }
+                self.vim_mode = VimMode::Normal;
+                (true, None)
+            },
+            '=' => {
+                // == - reindent the line, `3==` three lines; = takes a motion
+                self.pending_g = false;
+                if self.current_operation == VimOperation::Reindent {
+                    self.current_operation = VimOperation::None;
+                    self.apply_to_lines(text, VimOperation::Reindent);
+                } else if self.current_operation == VimOperation::None {
+                    self.current_operation = VimOperation::Reindent;
+                } else {
+                    self.current_operation = VimOperation::None;
+                }
+                (true, None)
+            },
+            '>' | '<' if matches!(self.vim_mode, VimMode::Visual | VimMode::VisualBlock) => {
+                // > and < in visual mode - shift the selected lines, `3>` three times
+                let times = self.count.take().unwrap_or(1).max(1);
+                let ranges = self.selection_ranges(text);
+                if let (Some(&(from, _)), Some(&(_, to))) = (ranges.first(), ranges.last()) {
+                    self.shift_lines(text, from, to, c == '>', times);
+                }
+                self.vim_mode = VimMode::Normal;
+                (true, None)
+            },
+            '>' | '<' => {
+                // >> and << - shift the line, `3>>` three lines; > and < take a motion
+                self.pending_g = false;
+                let operation = if c == '>' { VimOperation::ShiftRight } else { VimOperation::ShiftLeft };
+                if self.current_operation == operation {
+                    self.current_operation = VimOperation::None;
+                    self.apply_to_lines(text, operation);
+                } else if self.current_operation == VimOperation::None {
+                    self.current_operation = operation;
+                } else {
+                    self.current_operation = VimOperation::None;
+                }
+                (true, None)
+            },
+            '^' | '_' => {
+                self.move_to_first_non_blank(text);
+                (true, None)
+            },
+            '%' => {
+                self.move_to_matching_pair(text);
+                (true, None)
+            },
+            '(' | ')' => {
+                self.move_to_sentence(text, c == ')');
+                (true, None)
+            },
+            ':' if self.current_operation == VimOperation::None => {
+                // Command mode; from visual mode on the selection, like vim's :'<,'>
+                self.count = None;
+                self.command_buffer = match self.current_visual() {
+                    Some(selection) => {
+                        self.last_visual = Some(selection);
+                        ":'<,'>".to_string()
+                    },
+                    None => ":".to_string(),
+                };
+                self.vim_mode = VimMode::Command;
+                (true, None)
+            },
+            '\'' | '`' if self.current_operation == VimOperation::None => {
+                self.pending_mark = Some(PendingMark::Jump { exact: c == '`' });
+                (true, None)
+            },
+            '/' | '?' if self.vim_mode == VimMode::Normal && self.current_operation == VimOperation::None => {
+                // Search forward or backward; typed in the command line
+                self.vim_mode = VimMode::Command;
+                self.command_buffer = c.to_string();
+                self.search_origin = Some(self.cursor_position);
+                (true, None)
+            },
+            '*' | '#' if self.vim_mode == VimMode::Normal && self.current_operation == VimOperation::None => {
+                (true, self.search_word(text, c == '*'))
+            },
+            '"' => {
+                self.pending_register_name = true;
+                (true, None)
+            },
+            '@' if self.vim_mode == VimMode::Normal => {
+                self.pending_macro = Some(PendingMacro::Play);
+                (true, None)
+            },
+            _ => (false, None),
+        }
+    }
+    
+    // The character after f/F/t/T when it's a letter or digit key. Others, like
+    // `.` or `$`, arrive as text, see `handle_command_text`.
+    fn handle_find_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        let c = match (key_letter(key), key_digit(key)) {
+            (Some(letter), _) if modifiers.shift => letter.to_ascii_uppercase(),
+            (Some(letter), _) => letter,
+            (_, Some(digit)) if !modifiers.shift => digit,
+            _ if key == egui::Key::Escape => {
+                self.pending_find = None;
+                self.current_operation = VimOperation::None;
+                return (true, None);
+            },
+            // Keys like Space or Shift+4 are followed by their text
+            _ => return (false, None),
+        };
+        match self.pending_find.take() {
+            Some(find) => (true, self.find_in_line(text, find, c, false)),
+            None => (false, None),
+        }
+    }
+    
+    // The character after `r`
+    fn handle_replace_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        let c = match (key_letter(key), key_digit(key)) {
+            (Some(letter), _) if modifiers.shift => letter.to_ascii_uppercase(),
+            (Some(letter), _) => letter,
+            (_, Some(digit)) if !modifiers.shift => digit,
+            _ if key == egui::Key::Escape => {
+                self.pending_replace = false;
+                return (true, None);
+            },
+            // Keys like Space or Shift+4 are followed by their text
+            _ => return (false, None),
+        };
+        self.pending_replace = false;
+        self.replace_chars(text, c);
+        (true, None)
+    }
+    
+    // Ctrl+D / Ctrl+U: half a page down or up, with the view scrolling along
+    // so the cursor stays on the same row. A count sets how many lines they
+    // move from then on, like vim's 'scroll'.
+    fn scroll_half_page(&mut self, text: &str, down: bool) {
+        if let Some(count) = self.count.take() {
+            self.scroll_lines = Some(count);
+        }
+        let lines = self.scroll_lines.unwrap_or(self.page_lines / 2).max(1);
+        let desired_column = self.desired_column;
+        for _ in 0..lines {
+            let next = if down {
+                self.find_position_on_next_line(text)
+            } else {
+                self.find_position_on_previous_line(text)
+            };
+            match next {
+                Some(pos) => {
+                    self.cursor_position = pos;
+                    self.update_cursor_line_column(text);
+                },
+                None => break,
+            }
+        }
+        self.desired_column = desired_column;
+        self.scroll_with_cursor = true;
+    }
+    
+    // Ctrl+F / Ctrl+B: a page down or up, less two lines so a little of the
+    // page before stays in view. The cursor is kept on the page it lands on,
+    // at the first non-blank of its line. With nowhere left to scroll they go
+    // to the last or the first line instead.
+    fn scroll_page(&mut self, text: &str, down: bool) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let last_line = text.matches('\n').count();
+        let page_lines = self.page_lines.max(1);
+        let page = page_lines.saturating_sub(2).max(1) * count;
+        let top = if down {
+            (self.top_line + page).min(last_line.saturating_sub(page_lines - 1))
+        } else {
+            self.top_line.saturating_sub(page)
+        };
+        let line = if (down && top <= self.top_line) || (!down && top == self.top_line) {
+            if down { last_line } else { 0 }
+        } else {
+            self.scroll_to_line = Some(top);
+            self.cursor_line.clamp(top, (top + page_lines - 1).min(last_line))
+        };
+        if line != self.cursor_line {
+            self.move_to_line(text, line);
+            self.cursor_position = first_non_blank(text, self.cursor_position);
+            self.update_cursor_line_column(text);
+            self.desired_column = self.cursor_column;
+        }
+    }
+    
+    // Where `count` w, b or e motions (W, B or E with `big`) go from the cursor
+    fn word_target(&self, text: &str, key: egui::Key, big: bool, count: usize) -> usize {
+        let mut pos = self.cursor_position.min(text.len());
+        for _ in 0..count {
+            pos = match key {
+                egui::Key::W => motions::next_word_start(text, pos, big),
+                egui::Key::B => motions::prev_word_start(text, pos, big),
+                _ => motions::word_end(text, pos, big),
+            };
+        }
+        pos
+    }
+    
+    // Apply the pending operator over words. Like vim's, `dw` on the last word
+    // of a line stops at the end of the line, and `cw` on a word works like `ce`.
+    fn apply_word_motion(&mut self, text: &mut String, key: egui::Key, big: bool) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+        let start = self.cursor_position.min(text.len());
+        let on_word = text[start..].chars().next().is_some_and(|c| !c.is_whitespace());
+        let key = if operation == VimOperation::Change && key == egui::Key::W && on_word {
+            egui::Key::E
+        } else {
+            key
+        };
+        let pos = self.word_target(text, key, big, count);
+        let (from, to) = match key {
+            egui::Key::W => {
+                let line_end = text[start..pos].find('\n').map(|p| start + p).filter(|&end| end > start);
+                (start, line_end.unwrap_or(pos))
+            },
+            egui::Key::B => (pos, start),
+            // e includes the last character of the word
+            _ => (start, pos + text[pos..].chars().next().map_or(0, char::len_utf8)),
+        };
+        self.apply_operator(text, operation, from, to);
+    }
+    
+    // ^ and _: the first non-blank character of the line, or from the cursor
+    // to it after d, c or y
+    fn move_to_first_non_blank(&mut self, text: &mut String) {
+        self.count = None;
+        let pos = self.cursor_position.min(text.len());
+        let target = first_non_blank(text, pos);
+        match std::mem::replace(&mut self.current_operation, VimOperation::None) {
+            VimOperation::None => {
+                self.cursor_position = target;
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            operation => self.apply_operator(text, operation, pos.min(target), pos.max(target)),
+        }
+    }
+    
+    // %: the matching bracket or code fence, or with a count that percentage
+    // of the way down the note (`50%`). After d, c or y both ends are included.
+    fn move_to_matching_pair(&mut self, text: &mut String) {
+        if let Some(percent) = self.count.take() {
+            let lines = text.matches('\n').count() + 1;
+            let line = (percent.min(100) * lines).div_ceil(100);
+            self.current_operation = VimOperation::None;
+            self.go_to_line(text, Some(line));
+            return;
+        }
+        let pos = self.cursor_position.min(text.len());
+        let target = match motions::matching_pair(text, pos) {
+            Some(target) => target,
+            None => {
+                self.current_operation = VimOperation::None;
+                return;
+            },
+        };
+        match std::mem::replace(&mut self.current_operation, VimOperation::None) {
+            VimOperation::None => {
+                self.cursor_position = target;
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            operation => {
+                let (from, last) = (pos.min(target), pos.max(target));
+                let to = last + text[last..].chars().next().map_or(0, char::len_utf8);
+                self.apply_operator(text, operation, from, to);
+            },
+        }
+    }
+    
+    // ( and ): to the start of the sentence (or of the one before), or of the
+    // next one, or the pending operator up to there, like `d)` or `c(`
+    fn move_to_sentence(&mut self, text: &mut String, forward: bool) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let target = motions::sentence_start(text, pos, forward, count);
+        match std::mem::replace(&mut self.current_operation, VimOperation::None) {
+            VimOperation::None => {
+                self.cursor_position = target;
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            operation => self.apply_operator(text, operation, pos.min(target), pos.max(target)),
+        }
+    }
+    
+    // diw, yaw, ciW...: the pending operator over the text object named by
+    // `object` around the cursor, see text_objects::select. Nothing happens
+    // if there's no such object there.
+    fn apply_text_object(&mut self, text: &mut String, object: char) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+        let scope = match self.pending_object.take() {
+            Some(scope) => scope,
+            None => return,
+        };
+        let pos = self.cursor_position.min(text.len());
+        match text_objects::select(text, pos, object, scope, count) {
+            Some((from, to)) if text_objects::is_linewise(object, scope) => self.apply_line_operator(text, operation, from, to),
+            Some((from, to)) => self.apply_operator(text, operation, from, to),
+            None => {},
+        }
+    }
+    
+    // Yank, delete, change or change the case of the whole lines from..to,
+    // `to` being past the newline of the last one or the end of the text. Like
+    // dd, deleting the last lines takes the newline before them; changing
+    // keeps one empty line.
+    fn apply_line_operator(&mut self, text: &mut String, operation: VimOperation, from: usize, to: usize) {
+        self.cursor_position = from;
+        match operation {
+            VimOperation::ShiftRight | VimOperation::ShiftLeft => {
+                self.shift_lines(text, from, to, operation == VimOperation::ShiftRight, 1);
+                return;
+            },
+            VimOperation::Reindent => {
+                self.reindent_lines(text, from, to);
+                return;
+            },
+            VimOperation::Yank => self.yank_to_register(Self::line_register_text(&text[from..to]), RegisterKind::Linewise),
+            VimOperation::Lowercase | VimOperation::Uppercase | VimOperation::ToggleCase => {
+                let lines = change_case(&text[from..to], operation);
+                text.replace_range(from..to, &lines);
+            },
+            VimOperation::Change => {
+                // One line is left to type on, indented like the first one was
+                self.delete_to_register(Self::line_register_text(&text[from..to]), RegisterKind::Linewise);
+                let end = if text[from..to].ends_with('\n') { to - 1 } else { to };
+                let indent_end = from + text[from..end].len() - text[from..end].trim_start_matches([' ', '\t']).len();
+                text.replace_range(indent_end..end, "");
+                self.cursor_position = indent_end;
+                self.vim_mode = VimMode::Insert;
+            },
+            _ => {
+                self.delete_to_register(Self::line_register_text(&text[from..to]), RegisterKind::Linewise);
+                let start = if to == text.len() && !text[from..to].ends_with('\n') && from > 0 { from - 1 } else { from };
+                text.replace_range(start..to, "");
+                // The start of the line that took their place
+                self.cursor_position = if start < from { text[..start].rfind('\n').map_or(0, |p| p + 1) } else { start };
+            },
+        }
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // x, X and s: delete `count` characters from the cursor on, or the ones
+    // before it for X, without going past either end of the line. They go to
+    // the register charwise, so `xp` swaps two characters.
+    fn delete_chars(&mut self, text: &mut String, operation: VimOperation, before: bool) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let (from, to) = if before {
+            let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+            let from = text[line_start..pos].char_indices().rev().take(count).last().map_or(pos, |(i, _)| line_start + i);
+            (from, pos)
+        } else {
+            let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+            let to = text[pos..line_end].char_indices().nth(count).map_or(line_end, |(i, _)| pos + i);
+            (pos, to)
+        };
+        self.apply_operator(text, operation, from, to);
+    }
+    
+    // r{char}: replace `count` characters from the cursor on with `c`, staying
+    // in normal mode on the last of them. Like vim, nothing happens when the
+    // line doesn't have that many left.
+    fn replace_chars(&mut self, text: &mut String, c: char) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+        if text[pos..line_end].chars().count() < count {
+            return;
+        }
+        let to = text[pos..line_end].char_indices().nth(count).map_or(line_end, |(i, _)| pos + i);
+        text.replace_range(pos..to, &c.to_string().repeat(count));
+        self.cursor_position = pos + (count - 1) * c.len_utf8();
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // >> and <<, and > and < over a motion or selection: shift the lines that
+    // from..to touches `times` shiftwidths right or left. A line indented with
+    // a tab shifts by a tab instead, and empty lines aren't indented. The
+    // cursor goes to the first line's first non-blank.
+    fn shift_lines(&mut self, text: &mut String, from: usize, to: usize, right: bool, times: usize) {
+        let last = if to > from { to - char_len_before(text, to) } else { from };
+        let (start, end) = line_bounds(text, from, last);
+        let spaces = " ".repeat(self.shift_width.max(1));
+        let shifted: Vec<String> = text[start..end]
+            .split('\n')
+            .map(|line| {
+                let mut line = line.to_string();
+                for _ in 0..times {
+                    if right && !line.is_empty() {
+                        line.insert_str(0, if line.starts_with('\t') { "\t" } else { &spaces });
+                    } else if !right && line.starts_with('\t') {
+                        line.remove(0);
+                    } else if !right {
+                        let indent = line.len() - line.trim_start_matches(' ').len();
+                        line.replace_range(..indent.min(spaces.len()), "");
+                    }
+                }
+                line
+            })
+            .collect();
+        text.replace_range(start..end, &shifted.join("\n"));
+        self.cursor_position = first_non_blank(text, start);
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // = over a motion or selection, and ==: reindent the lines from..to
+    // touches, see indent::reindent. The cursor goes to the first line's
+    // first non-blank.
+    fn reindent_lines(&mut self, text: &mut String, from: usize, to: usize) {
+        let last = if to > from { to - char_len_before(text, to) } else { from };
+        let first_line = text[..from].matches('\n').count();
+        let last_line = text[..last].matches('\n').count();
+        let reindented = indent::reindent(text, first_line, last_line);
+        if reindented != *text {
+            *text = reindented;
+        }
+        self.cursor_position = first_non_blank(text, line_start_offset(text, first_line).min(text.len()));
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // The indentation of the line `pos` is on, for o, O and Enter to start
+    // the new line with
+    fn line_indent(text: &str, pos: usize) -> String {
+        let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+        text[line_start..first_non_blank(text, pos)].to_string()
+    }
+    
+    // J and gJ: join the line with the next `count - 1` lines (the next one
+    // without a count). J leaves out their indentation and puts a space
+    // between, unless the line already ends with one, the next starts with `)`
+    // or is empty; gJ joins them as they are. The cursor ends where the last
+    // line was joined.
+    fn join_lines(&mut self, text: &mut String, spaces: bool) {
+        let joins = self.count.take().unwrap_or(2).max(2) - 1;
+        let pos = self.cursor_position.min(text.len());
+        let mut join = None;
+        for _ in 0..joins {
+            let newline = match text[join.unwrap_or(pos)..].find('\n') {
+                Some(p) => join.unwrap_or(pos) + p,
+                None => break,
+            };
+            let mut next = newline + 1;
+            let mut separator = "";
+            if spaces {
+                next += text[next..].len() - text[next..].trim_start_matches([' ', '\t']).len();
+                let line_start = text[..newline].rfind('\n').map_or(0, |p| p + 1);
+                let ends_with_space = text[line_start..newline].ends_with([' ', '\t']);
+                let next_is_empty = text[next..].starts_with('\n') || next == text.len();
+                if newline > line_start && !ends_with_space && !next_is_empty && !text[next..].starts_with(')') {
+                    separator = " ";
+                }
+            }
+            text.replace_range(newline..next, separator);
+            join = Some(newline);
+        }
+        if let Some(join) = join {
+            self.cursor_position = join;
+            let line_end = text[join..].find('\n').map_or(text.len(), |p| join + p);
+            if self.cursor_position == line_end && self.cursor_position > 0 && !text[..join].ends_with('\n') {
+                // Nothing was joined on, so on the line's last character
+                self.cursor_position -= char_len_before(text, self.cursor_position);
+            }
+            self.update_cursor_line_column(text);
+            self.desired_column = self.cursor_column;
+        }
+    }
+    
+    // cc and S change the line, keeping its indentation, and guu, gUU and g~~
+    // change its case; with a count, that many lines
+    fn apply_to_lines(&mut self, text: &mut String, operation: VimOperation) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let from = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+        let mut to = pos;
+        for _ in 0..count {
+            to = text[to..].find('\n').map_or(text.len(), |p| to + p + 1);
+            if to == text.len() {
+                break;
+            }
+        }
+        self.apply_line_operator(text, operation, from, to);
+    }
+    
+    // ~: switch the case of `count` characters from the cursor on, and move
+    // past them without leaving the line
+    fn toggle_case(&mut self, text: &mut String) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+        let to = text[pos..line_end].char_indices().nth(count).map_or(line_end, |(i, _)| pos + i);
+        let toggled = change_case(&text[pos..to], VimOperation::ToggleCase);
+        text.replace_range(pos..to, &toggled);
+        let line_end = line_end - (to - pos) + toggled.len();
+        self.cursor_position = pos + toggled.len();
+        if self.cursor_position == line_end && self.cursor_position > pos {
+            // On the line's last character, like x
+            self.cursor_position -= char_len_before(text, self.cursor_position);
+        }
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // Yank, delete, change or change the case of the characters from..to,
+    // leaving the cursor at `from`
+    fn apply_operator(&mut self, text: &mut String, operation: VimOperation, from: usize, to: usize) {
+        if matches!(operation, VimOperation::ShiftRight | VimOperation::ShiftLeft) {
+            // `>w`, `<}`: the lines the motion touches
+            self.shift_lines(text, from, to, operation == VimOperation::ShiftRight, 1);
+            return;
+        }
+        if operation == VimOperation::Reindent {
+            self.reindent_lines(text, from, to);
+            return;
+        }
+        if to > from {
+            let removed = text[from..to].to_string();
+            match operation {
+                VimOperation::Yank => self.yank_to_register(removed, RegisterKind::Charwise),
+                VimOperation::Lowercase | VimOperation::Uppercase | VimOperation::ToggleCase => {
+                    text.replace_range(from..to, &change_case(&removed, operation));
+                },
+                _ => {
+                    self.delete_to_register(removed, RegisterKind::Charwise);
+                    text.replace_range(from..to, "");
+                },
+            }
+        }
+        self.cursor_position = from;
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+        if operation == VimOperation::Change {
+            self.vim_mode = VimMode::Insert;
+        }
+    }
+    
+    // The pending operator up to where a cursor motion goes: h, l, 0 and $
+    // over characters of the line, j and k over whole lines, with a count
+    // for h, l, j and k (`3dl`, `2gUj`). A j or k that can't move does nothing.
+    fn apply_motion_operator(&mut self, text: &mut String, key: egui::Key, modifiers: &egui::Modifiers) {
+        let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+        let count = self.count.take().unwrap_or(1).max(1);
+        let pos = self.cursor_position.min(text.len());
+        let (column, desired_column) = (self.cursor_column, self.desired_column);
+        let repeats = match key {
+            egui::Key::Num0 | egui::Key::Num4 => 1,
+            _ => count,
+        };
+        for _ in 0..repeats {
+            self.handle_motion_key(key, text, modifiers);
+        }
+        let target = self.cursor_position;
+        self.cursor_position = pos;
+        self.cursor_column = column;
+        self.desired_column = desired_column;
+        self.update_cursor_line_column(text);
+        
+        let linewise = matches!(key, egui::Key::J | egui::Key::K | egui::Key::ArrowUp | egui::Key::ArrowDown);
+        if linewise {
+            if line_and_char_column(text, target).0 == self.cursor_line {
+                return;
+            }
+            let (first, last) = (pos.min(target), pos.max(target));
+            let from = text[..first].rfind('\n').map_or(0, |p| p + 1);
+            let to = text[last..].find('\n').map_or(text.len(), |p| last + p + 1);
+            self.apply_line_operator(text, operation, from, to);
+        } else {
+            // h and l stop at the ends of the line, as they do in vim
+            let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+            let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+            let target = target.clamp(line_start, line_end);
+            self.apply_operator(text, operation, pos.min(target), pos.max(target));
+        }
+    }
+    
+    // dG, dgg, yG, gUgg...: the pending operator over the lines from the
+    // cursor's to `line` (1-based; the last line for None), like G and gg move
+    fn apply_line_motion_operator(&mut self, text: &mut String, line: Option<usize>) {
+        let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+        let pos = self.cursor_position.min(text.len());
+        let target = line_start_of(text, line);
+        let (first, last) = (pos.min(target), pos.max(target));
+        let from = text[..first].rfind('\n').map_or(0, |p| p + 1);
+        let to = text[last..].find('\n').map_or(text.len(), |p| last + p + 1);
+        self.apply_line_operator(text, operation, from, to);
+    }
+    
+    // D and C: delete or change from the cursor to the end of the line, like
+    // d$ and c$; with a count, to the end of the line count - 1 lines down
+    fn apply_to_line_end(&mut self, text: &mut String, operation: VimOperation) {
+        let count = self.count.take().unwrap_or(1).max(1);
+        let from = self.cursor_position.min(text.len());
+        let mut to = from;
+        for line in 0..count {
+            if line > 0 {
+                if to == text.len() {
+                    break;
+                }
+                to += 1; // Past the newline
+            }
+            to = text[to..].find('\n').map_or(text.len(), |p| to + p);
+        }
+        self.apply_operator(text, operation, from, to);
+    }
+    
+    // Move to the `count`th `c` on the line (or next to it for t/T), or apply
+    // the pending operator up to it, like `dt.` or `cf)`. Repeating a t/T with
+    // `;` doesn't stop at the character it's already next to.
+    fn find_in_line(&mut self, text: &mut String, find: CharFind, c: char, repeat: bool) -> Option<String> {
+        if !repeat {
+            self.last_find = Some((find, c));
+        }
+        let count = self.count.take().unwrap_or(1).max(1);
+        let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+        let pos = self.cursor_position.min(text.len());
+        let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+        let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+        let skip = usize::from(repeat && find.till);
+        let found = if find.forward {
+            text[pos..line_end]
+                .char_indices()
+                .skip(1 + skip)
+                .filter(|&(_, ch)| ch == c)
+                .nth(count - 1)
+                .map(|(i, _)| pos + i)
+        } else {
+            text[line_start..pos]
+                .char_indices()
+                .rev()
+                .skip(skip)
+                .filter(|&(_, ch)| ch == c)
+                .nth(count - 1)
+                .map(|(i, _)| line_start + i)
+        }?;
+        
+        // What an operator covers: up to and including the character going
+        // forward, and up to the cursor but not under it going back
+        let (start, end) = match (find.forward, find.till) {
+            (true, false) => (pos, found + c.len_utf8()),
+            (true, true) => (pos, found),
+            (false, false) => (found, pos),
+            (false, true) => (found + c.len_utf8(), pos),
+        };
+        match operation {
+            VimOperation::None => {
+                self.cursor_position = match (find.forward, find.till) {
+                    (true, true) => found - char_len_before(text, found),
+                    (false, true) => found + c.len_utf8(),
+                    _ => found,
+                };
+            },
+            operation => self.apply_operator(text, operation, start, end),
+        }
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+        None
+    }
+    
+    // The mark name after `m`, `'` or `` ` `` when it's a letter key. Other
+    // names, like the `'` of `''`, arrive as text, see `handle_command_text`.
+    fn handle_mark_key(&mut self, pending: PendingMark, key: egui::Key, text: &str, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        let name = match key_letter(key) {
+            Some(letter) if modifiers.shift => letter.to_ascii_uppercase(),
+            Some(letter) => letter,
+            None if key == egui::Key::Escape => {
+                self.pending_mark = None;
+                return (true, None);
+            },
+            // Keys like Shift+7 may be followed by their text, so keep waiting
+            None => return (true, None),
+        };
+        self.pending_mark = None;
+        if !Marks::is_valid_name(name) {
+            return (true, Some(format!("status Invalid mark {}", name)));
+        }
+        (true, self.use_mark(pending, name, text))
+    }
+    
+    fn use_mark(&mut self, pending: PendingMark, name: char, text: &str) -> Option<String> {
+        match pending {
+            PendingMark::Set => {
+                self.marks.set(name, self.cursor_position);
+                // The app takes a global mark away from the note that had it
+                Marks::is_global(name).then(|| format!("global_mark {}", name))
+            },
+            PendingMark::Jump { exact } => match self.marks.get(name) {
+                Some(pos) => {
+                    self.jump_to(text, pos, exact);
+                    None
+                },
+                // Set in another note, which the app opens
+                None if Marks::is_global(name) => Some(format!("jump_global_mark {} {}", name, exact)),
+                None => Some(format!("status Mark {} not set", name)),
+            },
+        }
+    }
+    
+    // Move to a position far away, like a mark: its line's first non-blank
+    // character unless `exact`. Where the cursor was is kept for `''`.
+    pub fn jump_to(&mut self, text: &str, pos: usize, exact: bool) {
+        let mut pos = pos.min(text.len());
+        while !text.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        if !exact {
+            pos = first_non_blank(text, pos);
+        }
+        self.marks.set(CONTEXT_MARK, self.cursor_position);
+        self.cursor_position = pos;
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // gg and G: the first non-blank of line `line` (from 1), or the last line
+    // if there's no number or the note is shorter
+    fn go_to_line(&mut self, text: &str, line: Option<usize>) {
+        self.jump_to(text, line_start_of(text, line), false);
+        self.scroll_to_cursor = true;
+    }
+    
+    fn move_cursor(&mut self, text: &str, pos: usize) {
+        self.cursor_position = pos.min(text.len());
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+        self.scroll_to_cursor = true;
+    }
+    
+    // While a search pattern is typed, show its first match from where the
+    // search started, moving the cursor there until the search is done
+    fn update_incsearch(&mut self, text: &str) {
+        let origin = match self.search_origin {
+            Some(origin) => origin,
+            None => return,
+        };
+        let forward = self.command_buffer.starts_with('/');
+        let pattern = self.with_case(&Pattern::new(&self.command_buffer[1..]));
+        let found = search::find(text, &pattern, origin, forward);
+        self.incsearch = found.map(|found| (found.start, found.end));
+        self.move_cursor(text, found.map_or(origin, |found| found.start));
+    }
+    
+    // Enter on a `/` or `?` pattern; an empty pattern searches for the last one
+    fn finish_search(&mut self, text: &str, origin: usize) -> Option<String> {
+        self.incsearch = None;
+        self.cursor_position = origin;
+        let forward = self.command_buffer.starts_with('/');
+        let pattern = match &self.command_buffer[1..] {
+            "" => match &self.last_search {
+                Some((pattern, _)) => pattern.clone(),
+                None => return Some("status No previous search pattern".to_string()),
+            },
+            pattern => Pattern::new(pattern),
+        };
+        self.last_search = Some((pattern.clone(), forward));
+        self.search_for(text, &pattern, origin, forward)
+    }
+    
+    fn search_again(&mut self, text: &str, reverse: bool) -> Option<String> {
+        match self.last_search.clone() {
+            Some((pattern, forward)) => self.search_for(text, &pattern, self.cursor_position, forward != reverse),
+            None => Some("status No previous search pattern".to_string()),
+        }
+    }
+    
+    // `*` and `#` - search for the word under the cursor, or the next word on
+    // the line when it's not on one
+    fn search_word(&mut self, text: &str, forward: bool) -> Option<String> {
+        let line_end = text[self.cursor_position..].find('\n').map_or(text.len(), |p| self.cursor_position + p);
+        let word_pos = text[self.cursor_position..line_end]
+            .char_indices()
+            .find(|&(_, c)| self.is_word_char(c))
+            .map(|(i, _)| self.cursor_position + i);
+        let (start, end) = match word_pos {
+            Some(pos) => self.find_word_boundaries(text, pos),
+            None => return Some("status No string under cursor".to_string()),
+        };
+        let pattern = Pattern::word(&text[start..end]);
+        self.last_search = Some((pattern.clone(), forward));
+        // From the start of the word, so it's not found itself
+        self.search_for(text, &pattern, start, forward)
+    }
+    
+    fn search_for(&mut self, text: &str, pattern: &Pattern, from: usize, forward: bool) -> Option<String> {
+        self.highlight_search = true;
+        let pattern = self.with_case(pattern);
+        if let Err(err) = pattern.regex() {
+            return Some(format!("status Invalid pattern: {}", err));
+        }
+        match search::find(text, &pattern, from, forward) {
+            Some(found) => {
+                self.jump_to(text, found.start, true);
+                self.scroll_to_cursor = true;
+                match (found.wrapped, forward) {
+                    (true, true) => Some("status Search hit BOTTOM, continuing at TOP".to_string()),
+                    (true, false) => Some("status Search hit TOP, continuing at BOTTOM".to_string()),
+                    (false, _) => None,
+                }
+            },
+            None => Some(format!("status Pattern not found: {}", pattern)),
+        }
+    }
+    
+    // A pattern as the `ignorecase` and `smartcase` options say to match it
+    fn with_case(&self, pattern: &Pattern) -> Pattern {
+        pattern.with_case(self.ignore_case, self.smart_case)
+    }
+    
+    // `:s/pattern/replacement/[g]` on the lines of text[start..end]; an empty
+    // pattern is the last search's
+    fn substitute(&mut self, text: &mut String, start: usize, end: usize, substitute: Substitute) -> Option<String> {
+        let pattern = match (substitute.pattern.as_str(), &self.last_search) {
+            ("", Some((pattern, _))) => pattern.clone(),
+            ("", None) => return Some("status No previous search pattern".to_string()),
+            (pattern, _) => Pattern::new(pattern),
+        };
+        let (replaced, count, last) = match search::substitute(text, start, end, &self.with_case(&pattern), &substitute.replacement, substitute.global) {
+            Ok(replaced) => replaced,
+            Err(err) => return Some(format!("status Invalid pattern: {}", err)),
+        };
+        if count == 0 {
+            return Some(format!("status Pattern not found: {}", pattern));
+        }
+        // Like a search, n and N look for the pattern next
+        let forward = self.last_search.as_ref().is_none_or(|(_, forward)| *forward);
+        self.last_search = Some((pattern, forward));
+        *text = replaced;
+        // On the last line that changed, like vim
+        self.jump_to(text, last, false);
+        (count > 1).then(|| format!("status {} substitutions", count))
+    }
+    
+    // Matches of the last search while they're highlighted
+    pub fn search_matches(&self, text: &str) -> Vec<(usize, usize)> {
+        match &self.last_search {
+            Some((pattern, _)) if self.highlight_search => search::find_all(text, &self.with_case(pattern)),
+            _ => Vec::new(),
+        }
+    }
+    
+    // Put yanked or deleted text in the register picked with `"x`, and in the unnamed one
+    fn yank_to_register(&mut self, text: String, kind: RegisterKind) {
+        let name = self.selected_register.take();
+        self.registers.yank(name, text, kind);
+    }
+    
+    fn delete_to_register(&mut self, text: String, kind: RegisterKind) {
+        let name = self.selected_register.take();
+        self.registers.delete(name, text, kind);
+    }
+    
+    // Lines in a register always end in a newline, even the last line of the note
+    fn line_register_text(line: &str) -> String {
+        if line.ends_with('\n') {
+            line.to_string()
+        } else {
+            format!("{}\n", line)
+        }
+    }
+    
+    // p/P: lines go below/above the current line, anything else after/before the cursor
+    fn put_register(&mut self, text: &mut String, before: bool) {
+        let name = self.selected_register.take().unwrap_or(UNNAMED_REGISTER);
+        let register = match self.registers.get(name) {
+            Some(register) if !register.text.is_empty() => register,
+            _ => return,
+        };
+        match (register.kind, before) {
+            (RegisterKind::Linewise, true) => {
+                let line_start = text[..self.cursor_position].rfind('\n')
+                    .map(|pos| pos + 1)
+                    .unwrap_or(0);
+                text.insert_str(line_start, &register.text);
+                self.cursor_position = line_start + register.text.len();
+            },
+            (RegisterKind::Linewise, false) => {
+                match text[self.cursor_position..].find('\n') {
+                    Some(pos) => {
+                        let line_end = self.cursor_position + pos + 1;
+                        text.insert_str(line_end, &register.text);
+                        self.cursor_position = line_end + register.text.len();
+                    },
+                    None => {
+                        // Below the last line, which has no newline to put the lines after
+                        let lines = register.text.trim_end_matches('\n');
+                        text.push('\n');
+                        text.push_str(lines);
+                        self.cursor_position = text.len();
+                    },
+                }
+            },
+            (RegisterKind::Charwise, true) => {
+                text.insert_str(self.cursor_position, &register.text);
+                self.cursor_position += register.text.len();
+            },
+            (RegisterKind::Charwise, false) => {
+                let insert_pos = match self.char_at(text, self.cursor_position) {
+                    Some(c) => self.cursor_position + c.len_utf8(),
+                    None => self.cursor_position,
+                };
+                text.insert_str(insert_pos, &register.text);
+                self.cursor_position = insert_pos + register.text.len();
+            },
+        }
+        self.update_cursor_line_column(text);
+    }
+    
+    // Push an undo step if the buffer changed. Everything typed during one
+    // insert session (including the command that started it, like `o` or `cw`)
+    // is coalesced into a single step that is committed on leaving insert mode.
+    fn record_change(&mut self, before: UndoState, text: &str) {
+        self.marks.adjust(&before.text, text);
+        if before.text != text {
+            // While inserting, the change is the character before the cursor
+            let mut pos = self.cursor_position.min(text.len());
+            if matches!(self.vim_mode, VimMode::Insert | VimMode::Replace) && pos > 0 {
+                pos -= 1;
+                while !text.is_char_boundary(pos) {
+                    pos -= 1;
+                }
+            }
+            self.marks.record_change(text, pos);
+            if let Some(edit) = Change::edit(&before.text, text) {
+                self.changes.publish(edit);
+            }
+        }
+        
+        if matches!(self.vim_mode, VimMode::Insert | VimMode::Replace) {
+            if self.insert_undo_state.is_none() && before.text != text {
+                self.insert_undo_state = Some(before);
+            }
+            return;
+        }
+        
+        if let Some(insert_start) = self.insert_undo_state.take() {
+            if insert_start.text != text {
+                self.history.record(&insert_start.text, text, insert_start.cursor_position);
+            }
+        } else if before.text != text {
+            self.history.record(&before.text, text, before.cursor_position);
+        }
+    }
+    
+    // Start a fresh history for `text`, e.g. when a different note is loaded
+    pub fn reset_history(&mut self, text: &str) {
+        self.history.reset(text);
+        self.insert_undo_state = None;
+    }
+    
+    // Pick up the history saved in an undo file, or start fresh if it doesn't match `text`
+    pub fn restore_history(&mut self, undo_file: &Path, text: &str) {
+        match EditHistory::load(undo_file, text) {
+            Some(history) => {
+                self.history = history;
+                self.insert_undo_state = None;
+            },
+            None => self.reset_history(text),
+        }
+    }
+    
+    // Save the history to an undo file, including any edits not yet recorded
+    // (e.g. an insert that is still in progress)
+    pub fn save_history(&self, undo_file: &Path, text: &str) -> io::Result<()> {
+        let mut history = self.history.clone();
+        history.sync(text, self.cursor_position);
+        history.save(undo_file)
+    }
+    
+    fn undo(&mut self, text: &mut String) -> Option<String> {
+        match self.history.undo() {
+            Some(history_move) => {
+                self.apply_history_move(history_move, text);
+                None
+            },
+            None => Some("status Already at oldest change".to_string()),
+        }
+    }
+    
+    fn redo(&mut self, text: &mut String) -> Option<String> {
+        match self.history.redo() {
+            Some(history_move) => {
+                self.apply_history_move(history_move, text);
+                None
+            },
+            None => Some("status Already at newest change".to_string()),
         }
     }
     
-    pub fn handle_key_press(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
-        match self.vim_mode {
-            VimMode::Normal => self.handle_normal_mode_key(key, text, modifiers),
-            VimMode::Insert => self.handle_insert_mode_key(key, text, modifiers),
-            VimMode::Command => self.handle_command_mode_key(key, text, modifiers),
+    // :earlier / :later, by change count or time (10s, 5m, 1h, 2d)
+    fn time_travel(&mut self, text: &mut String, arg: &str, forward: bool) -> Option<String> {
+        let amount = match TimeTravel::parse(arg) {
+            Some(amount) => amount,
+            None => return Some(format!("status Invalid argument: {}", arg)),
+        };
+        let history_move = if forward {
+            self.history.later(amount)
+        } else {
+            self.history.earlier(amount)
+        };
+        if let Some(history_move) = history_move {
+            self.apply_history_move(history_move, text);
         }
+        Some(format!("status At change {}", self.history.change_number()))
+    }
+    
+    // Swap in the text from the history and put the cursor where it changed
+    fn apply_history_move(&mut self, history_move: HistoryMove, text: &mut String) {
+        self.marks.adjust(text, &history_move.text);
+        if let Some(edit) = Change::edit(text, &history_move.text) {
+            self.changes.publish(edit);
+        }
+        *text = history_move.text;
+        self.cursor_position = history_move.cursor_position;
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
     }
     
     fn handle_normal_mode_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
@@ -43,36 +1492,19 @@ impl SimpleEditor {
         
         // Check if we're in the middle of a operation
         if self.current_operation != VimOperation::None {
-            match (self.current_operation, key) {
-                (VimOperation::Delete, egui::Key::W) if self.register_buffer != "i" => {
-                    // Implement delete word
-                    if self.cursor_position < text.len() {
-                        let start_pos = self.cursor_position;
-                        // Skip current word
-                        let mut end_pos = start_pos;
-                        
-                        // Skip non-whitespace
-                        while end_pos < text.len() && !text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Skip whitespace
-                        while end_pos < text.len() && text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Delete the word
-                        if end_pos > start_pos {
-                            // Store in register buffer before deleting
-                            self.register_buffer = text[start_pos..end_pos].to_string();
-                            text.replace_range(start_pos..end_pos, "");
-                            self.update_cursor_line_column(text);
-                        }
-                    }
-                    // Reset the operation
-                    self.current_operation = VimOperation::None;
+            if std::mem::take(&mut self.pending_g) {
+                // dgg, ygg, gUgg - to the first line, or line {count}; other
+                // g-commands don't go with an operator
+                if key == egui::Key::G && !modifiers.shift {
+                    let line = self.count.take().unwrap_or(1);
+                    self.apply_line_motion_operator(text, Some(line));
                     return (true, None);
-                },
+                }
+                self.current_operation = VimOperation::None;
+                self.pending_object = None;
+                return (true, Some("status Only gg can follow an operator after g".to_string()));
+            }
+            match (self.current_operation, key) {
                 (VimOperation::Delete, egui::Key::D) => {
                     // Implement delete line (dd)
                     // Find line start
@@ -85,59 +1517,29 @@ impl SimpleEditor {
                         .map(|pos| self.cursor_position + pos + 1)
                         .unwrap_or(text.len());
                     
-                    // If this is the last line without a trailing newline, adjust
-                    let adjusted_line_end = if line_end > 0 && line_end < text.len() {
-                        line_end 
-                    } else if line_start > 0 {
-                        // For last line, also remove preceding newline
+                    // The last line has no newline of its own, so take the one before it
+                    let delete_start = if !text[line_start..line_end].ends_with('\n') && line_start > 0 {
                         line_start - 1
                     } else {
-                        line_end
+                        line_start
                     };
                     
-                    // Store in register buffer before deleting
-                    self.register_buffer = text[line_start..line_end].to_string();
+                    // Store in register before deleting
+                    self.delete_to_register(Self::line_register_text(&text[line_start..line_end]), RegisterKind::Linewise);
                     
                     // Delete the line
-                    text.replace_range(line_start..adjusted_line_end, "");
+                    text.replace_range(delete_start..line_end, "");
                     
-                    // Update cursor position
-                    self.cursor_position = line_start;
-                    if self.cursor_position > text.len() {
-                        self.cursor_position = text.len().saturating_sub(1);
-                    }
+                    // Update cursor position to the start of the line that took its place
+                    self.cursor_position = text[..delete_start].rfind('\n')
+                        .filter(|_| delete_start < line_start)
+                        .map_or(delete_start, |pos| pos + 1);
                     self.update_cursor_line_column(text);
                     
                     // Reset the operation
                     self.current_operation = VimOperation::None;
                     return (true, None);
                 },
-                (VimOperation::Yank, egui::Key::W) => {
-                    // Implement yank word
-                    if self.cursor_position < text.len() {
-                        let start_pos = self.cursor_position;
-                        // Skip current word
-                        let mut end_pos = start_pos;
-                        
-                        // Skip non-whitespace
-                        while end_pos < text.len() && !text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Skip whitespace
-                        while end_pos < text.len() && text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Yank the word
-                        if end_pos > start_pos {
-                            self.register_buffer = text[start_pos..end_pos].to_string();
-                        }
-                    }
-                    // Reset the operation
-                    self.current_operation = VimOperation::None;
-                    return (true, None);
-                },
                 (VimOperation::Yank, egui::Key::Y) => {
                     // Implement yank line (yy)
                     // Find line start
@@ -151,136 +1553,170 @@ impl SimpleEditor {
                         .unwrap_or(text.len());
                     
                     // Yank the line
-                    self.register_buffer = text[line_start..line_end].to_string();
+                    self.yank_to_register(Self::line_register_text(&text[line_start..line_end]), RegisterKind::Linewise);
                     
                     // Reset the operation
                     self.current_operation = VimOperation::None;
                     return (true, None);
                 },
-                (VimOperation::Change, egui::Key::W) if self.register_buffer != "i" => {
-                    // Implement change word (similar to delete word but enters insert mode after)
-                    if self.cursor_position < text.len() {
-                        let start_pos = self.cursor_position;
-                        // Skip current word
-                        let mut end_pos = start_pos;
-                        
-                        // Skip non-whitespace
-                        while end_pos < text.len() && !text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Skip whitespace
-                        while end_pos < text.len() && text[end_pos..end_pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                            end_pos += 1;
-                        }
-                        
-                        // Delete the word
-                        if end_pos > start_pos {
-                            // Store in register buffer before deleting
-                            self.register_buffer = text[start_pos..end_pos].to_string();
-                            text.replace_range(start_pos..end_pos, "");
-                            self.update_cursor_line_column(text);
-                        }
-                    }
-                    // Enter insert mode
-                    self.vim_mode = VimMode::Insert;
-                    // Reset the operation
+                (_, egui::Key::I) | (_, egui::Key::A) if self.pending_object.is_none() && !modifiers.shift => {
+                    // di, ca, yi... - the operator waits for the text object
+                    self.pending_object = Some(if key == egui::Key::I { Scope::Inner } else { Scope::Around });
+                    return (true, None);
+                },
+                (_, egui::Key::W) | (_, egui::Key::B) | (_, egui::Key::P)
+                | (_, egui::Key::C) | (_, egui::Key::H) | (_, egui::Key::L) if self.pending_object.is_some() => {
+                    // diw, caW, yab, diB, yap, and the markdown ones: dic, yah, cil...
+                    let object = match (key, modifiers.shift) {
+                        (egui::Key::W, false) => 'w',
+                        (egui::Key::W, true) => 'W',
+                        (egui::Key::P, false) => 'p',
+                        (egui::Key::P, true) => 'P',
+                        (egui::Key::B, false) => 'b',
+                        (egui::Key::B, true) => 'B',
+                        (egui::Key::C, false) => 'c',
+                        (egui::Key::C, true) => 'C',
+                        (egui::Key::H, false) => 'h',
+                        (egui::Key::H, true) => 'H',
+                        (egui::Key::L, false) => 'l',
+                        (_, _) => 'L',
+                    };
+                    self.apply_text_object(text, object);
+                    return (true, None);
+                },
+                (VimOperation::Change, egui::Key::C) => {
+                    // cc - change the whole line
                     self.current_operation = VimOperation::None;
+                    self.apply_to_lines(text, VimOperation::Change);
+                    return (true, None);
+                },
+                (VimOperation::Lowercase, egui::Key::U) | (VimOperation::Uppercase, egui::Key::U) => {
+                    // guu and gUU - the case of the whole line
+                    let operation = std::mem::replace(&mut self.current_operation, VimOperation::None);
+                    self.apply_to_lines(text, operation);
                     return (true, None);
                 },
-                (VimOperation::Delete, egui::Key::I) => {
-                    // Add 'i' to register buffer to track we're building 'di' sequence
-                    self.register_buffer = "i".to_string();
-                    // Don't reset operation - we're waiting for the next key
+                (_, egui::Key::Num5) | (_, egui::Key::Num6) | (_, egui::Key::Minus) | (_, egui::Key::Num9) | (_, egui::Key::Num0) if modifiers.shift => {
+                    // d%, d^, c_ or d) - the operator waits for the `%`, `^`, `_` or `)` text that follows
+                    return (false, None);
+                },
+                (VimOperation::Reindent, egui::Key::PlusEquals) if !modifiers.shift => {
+                    // The second `=` of == comes as text
+                    return (false, None);
+                },
+                (_, egui::Key::W) | (_, egui::Key::B) | (_, egui::Key::E) if self.pending_object.is_none() => {
+                    // dw, cW, yb... - over words, see apply_word_motion
+                    self.apply_word_motion(text, key, modifiers.shift);
                     return (true, None);
                 },
-                (VimOperation::Change, egui::Key::I) => {
-                    // Add 'i' to register buffer to track we're building 'ci' sequence
-                    self.register_buffer = "i".to_string();
-                    // Don't reset operation - we're waiting for the next key
+                (_, egui::Key::F) | (_, egui::Key::T) if self.pending_object.is_none() => {
+                    // dt. or cf) - up to a character on the line, see find_in_line
+                    self.pending_find = Some(CharFind { forward: !modifiers.shift, till: key == egui::Key::T });
                     return (true, None);
                 },
-                (VimOperation::Delete, egui::Key::W) if self.register_buffer == "i" => {
-                    // Handle 'diw' - delete inner word
-                    if !text.is_empty() && self.cursor_position < text.len() {
-                        let (start_pos, end_pos) = self.find_word_boundaries(text, self.cursor_position);
-                        
-                        // Only delete if there's something to delete
-                        if end_pos > start_pos {
-                            // Store in register buffer for paste operations
-                            let content_to_save = text[start_pos..end_pos].to_string();
-                            text.replace_range(start_pos..end_pos, "");
-                            self.register_buffer = content_to_save;
-                            self.cursor_position = start_pos;
-                            self.update_cursor_line_column(text);
-                            self.desired_column = self.cursor_column;
-                        }
-                    }
-                    // Clear the operation
-                    self.current_operation = VimOperation::None;
+                (_, egui::Key::H) | (_, egui::Key::L) | (_, egui::Key::J) | (_, egui::Key::K) | (_, egui::Key::Num0)
+                | (_, egui::Key::ArrowLeft) | (_, egui::Key::ArrowRight) | (_, egui::Key::ArrowUp) | (_, egui::Key::ArrowDown)
+                    if self.pending_object.is_none() && !modifiers.shift && !modifiers.ctrl => {
+                    // dl, gUh, c0, yj, g~k... - see apply_motion_operator
+                    self.apply_motion_operator(text, key, modifiers);
                     return (true, None);
                 },
-                (VimOperation::Change, egui::Key::W) if self.register_buffer == "i" => {
-                    // Handle 'ciw' - change inner word
-                    if !text.is_empty() && self.cursor_position < text.len() {
-                        let (start_pos, end_pos) = self.find_word_boundaries(text, self.cursor_position);
-                        
-                        // Only change if there's something to change
-                        if end_pos > start_pos {
-                            // Store in register buffer for paste operations
-                            let content_to_save = text[start_pos..end_pos].to_string();
-                            text.replace_range(start_pos..end_pos, "");
-                            self.register_buffer = content_to_save;
-                            self.cursor_position = start_pos;
-                            self.update_cursor_line_column(text);
-                            self.desired_column = self.cursor_column;
-                        }
+                (_, egui::Key::G) if self.pending_object.is_none() && !modifiers.ctrl => {
+                    if modifiers.shift {
+                        // dG, yG - to the last line, or line {count}
+                        let line = self.count.take();
+                        self.apply_line_motion_operator(text, line);
+                    } else {
+                        // The g of dgg
+                        self.pending_g = true;
                     }
-                    // Enter insert mode
-                    self.vim_mode = VimMode::Insert;
-                    // Clear the operation
-                    self.current_operation = VimOperation::None;
                     return (true, None);
                 },
-                (VimOperation::Change, egui::Key::C) => {
-                    // Implement change line (similar to dd but enters insert mode after)
-                    // Find line start
-                    let line_start = text[..self.cursor_position].rfind('\n')
-                        .map(|pos| pos + 1)
-                        .unwrap_or(0);
-                    
-                    // Find line end
-                    let line_end = text[self.cursor_position..].find('\n')
-                        .map(|pos| self.cursor_position + pos)
-                        .unwrap_or(text.len());
-                    
-                    // Store in register buffer before deleting
-                    self.register_buffer = text[line_start..line_end].to_string();
-                    
-                    // Delete the line content but keep the line
-                    text.replace_range(line_start..line_end, "");
-                    
-                    // Update cursor position
-                    self.cursor_position = line_start;
-                    self.update_cursor_line_column(text);
-                    
-                    // Enter insert mode
-                    self.vim_mode = VimMode::Insert;
-                    // Reset the operation
-                    self.current_operation = VimOperation::None;
+                (_, egui::Key::Num4) if self.pending_object.is_none() && modifiers.shift => {
+                    // d$, gU$ - to the end of the line
+                    self.apply_motion_operator(text, key, modifiers);
                     return (true, None);
                 },
                 // Add more operation combinations here as needed
                 _ => {
                     // If we don't recognize the combination, reset and fall through to regular handling
                     self.current_operation = VimOperation::None;
+                    self.pending_object = None;
+                }
+            }
+        }
+        
+        // Second key of a g-prefixed command
+        if self.pending_g {
+            self.pending_g = false;
+            match key {
+                egui::Key::G if !modifiers.shift => {
+                    // gg - the first line, or line {count}
+                    self.go_to_line(text, Some(self.count.unwrap_or(1)));
+                    return (true, None);
+                },
+                egui::Key::X => {
+                    // gx - open the URL under the cursor in the system browser
+                    let action = links::url_at(text, self.cursor_position)
+                        .map(|url| format!("open_url {}", url));
+                    return (true, action);
+                },
+                egui::Key::V => {
+                    // gv - select the last visual selection again
+                    if let Some(selection) = self.last_visual {
+                        let clamp = |pos: usize| {
+                            let mut pos = pos.min(text.len());
+                            while !text.is_char_boundary(pos) {
+                                pos -= 1;
+                            }
+                            pos
+                        };
+                        self.vim_mode = selection.mode;
+                        self.visual_anchor = clamp(selection.anchor);
+                        self.cursor_position = clamp(selection.cursor);
+                        self.update_cursor_line_column(text);
+                        self.desired_column = self.cursor_column;
+                    }
+                    return (true, None);
+                },
+                egui::Key::J if modifiers.shift => {
+                    // gJ - join lines without touching their whitespace
+                    self.join_lines(text, false);
+                    return (true, None);
+                },
+                egui::Key::U => {
+                    // gu and gU - lower or upper case over a motion, see apply_operator
+                    self.current_operation = if modifiers.shift { VimOperation::Uppercase } else { VimOperation::Lowercase };
+                    return (true, None);
+                },
+                egui::Key::F => {
+                    // gf - open the page of the person mentioned under the cursor,
+                    // or the file whose path or file:// URL is under it
+                    let action = people::mention_at(text, self.cursor_position)
+                        .map(|name| format!("open_note {}", people::person_note(&name)))
+                        .or_else(|| links::file_path_at(text, self.cursor_position).map(|path| format!("open_file {}", path)));
+                    return (true, action);
+                },
+                _ => {
+                    // Unknown g-command, just cancel it like vim does
+                    return (true, None);
                 }
             }
         }
         
         // Handle operation initiators
         match key {
-            egui::Key::D => {
+            egui::Key::S if modifiers.shift => {
+                // S - the same as cc
+                self.apply_to_lines(text, VimOperation::Change);
+                return (true, None);
+            },
+            egui::Key::D | egui::Key::C if modifiers.shift && !modifiers.ctrl => {
+                let operation = if key == egui::Key::D { VimOperation::Delete } else { VimOperation::Change };
+                self.apply_to_line_end(text, operation);
+                return (true, None);
+            },
+            egui::Key::D if !modifiers.ctrl => {
                 self.current_operation = VimOperation::Delete;
                 return (true, None);
             },
@@ -293,60 +1729,150 @@ impl SimpleEditor {
                 return (true, None);
             },
             egui::Key::P => {
-                // Paste from register buffer
-                if !self.register_buffer.is_empty() {
-                    if modifiers.shift {
-                        // P - Paste before/above current position
-                        if self.register_buffer.contains('\n') {
-                            // For multi-line content, find line start
-                            let line_start = text[..self.cursor_position].rfind('\n')
-                                .map(|pos| pos + 1)
-                                .unwrap_or(0);
-                            
-                            // Insert at line start
-                            text.insert_str(line_start, &self.register_buffer);
-                            self.cursor_position = line_start + self.register_buffer.len();
-                        } else {
-                            // For single-line content, insert at cursor
-                            text.insert_str(self.cursor_position, &self.register_buffer);
-                            self.cursor_position += self.register_buffer.len();
-                        }
-                    } else {
-                        // p - Paste after/below current position
-                        if self.register_buffer.contains('\n') {
-                            // For multi-line content, find line end
-                            let line_end = text[self.cursor_position..].find('\n')
-                                .map(|pos| self.cursor_position + pos + 1)
-                                .unwrap_or(text.len());
-                            
-                            // Insert at line end
-                            text.insert_str(line_end, &self.register_buffer);
-                            self.cursor_position = line_end + self.register_buffer.len();
-                        } else {
-                            // For single-line content, insert after cursor
-                            let insert_pos = if self.cursor_position < text.len() {
-                                self.cursor_position + 1
-                            } else {
-                                self.cursor_position
-                            };
-                            text.insert_str(insert_pos, &self.register_buffer);
-                            self.cursor_position = insert_pos + self.register_buffer.len();
-                        }
-                    }
+                // p - Put after the cursor (below the line for lines), P - before it
+                self.put_register(text, modifiers.shift);
+            },
+            egui::Key::G if !modifiers.shift => {
+                // Start a g-prefixed command
+                self.pending_g = true;
+                return (true, None);
+            },
+            egui::Key::G => {
+                // G - the last line, or line {count}
+                self.go_to_line(text, self.count);
+                return (true, None);
+            },
+            egui::Key::F | egui::Key::T if !modifiers.ctrl && !modifiers.alt => {
+                // f{char} / t{char} - to the next {char} on the line, or right before it; F and T go back
+                self.pending_find = Some(CharFind { forward: !modifiers.shift, till: key == egui::Key::T });
+                return (true, None);
+            },
+            egui::Key::Num6 if modifiers.ctrl => {
+                // Ctrl+^ - back to the note that was open before this one
+                return (true, Some("alternate_note".to_string()));
+            },
+            egui::Key::M if !modifiers.shift && !modifiers.ctrl => {
+                // m{a-zA-Z} - Set a mark at the cursor
+                self.pending_mark = Some(PendingMark::Set);
+                return (true, None);
+            },
+            // Mode switches
+            egui::Key::I => {
+                if modifiers.shift {
+                    // Shift+I - Move to beginning of line and enter insert mode
+                    let line_start = text[..self.cursor_position].rfind('\n')
+                        .map(|pos| pos + 1)
+                        .unwrap_or(0);
+                    self.cursor_position = line_start;
+                    self.update_cursor_line_column(text);
+                }
+                // Enter insert mode
+                self.vim_mode = VimMode::Insert;
+            },
+            egui::Key::A => {
+                if modifiers.shift {
+                    // Shift+A - Move to end of line and enter insert mode
+                    let line_end = text[self.cursor_position..].find('\n')
+                        .map(|pos| self.cursor_position + pos)
+                        .unwrap_or(text.len());
+                    self.cursor_position = line_end;
                     self.update_cursor_line_column(text);
+                } else {
+                    // a - Move cursor forward one character then enter insert mode
+                    if self.cursor_position < text.len() {
+                        self.cursor_position += char_len_at(text, self.cursor_position);
+                        self.update_cursor_line_column(text);
+                    }
+                }
+                self.vim_mode = VimMode::Insert;
+            },
+            // Delete operations
+            egui::Key::X => {
+                // x and X - delete characters under and after the cursor, or before it
+                self.delete_chars(text, VimOperation::Delete, modifiers.shift);
+            },
+            egui::Key::S if !modifiers.shift && !modifiers.ctrl => {
+                // s - delete characters and type in their place
+                self.delete_chars(text, VimOperation::Change, false);
+            },
+            egui::Key::R if !modifiers.shift && !modifiers.ctrl => {
+                // r{char} - replace the character under the cursor, see replace_chars
+                self.pending_replace = true;
+            },
+            egui::Key::R if !modifiers.ctrl => {
+                // R - replace mode, typing over the text
+                self.vim_mode = VimMode::Replace;
+                self.replaced.clear();
+            },
+            egui::Key::J if modifiers.shift && !modifiers.ctrl => {
+                // J - join the next line on, see join_lines
+                self.join_lines(text, true);
+            },
+            egui::Key::O => {
+                // Insert new line before current line and enter insert mode,
+                // indented like the current one
+                let indent = Self::line_indent(text, self.cursor_position);
+                if modifiers.shift {
+                    // Shift+O - Add line above current line
+                    let line_start = text[..self.cursor_position].rfind('\n')
+                        .map(|pos| pos + 1)
+                        .unwrap_or(0);
+                    text.insert_str(line_start, &format!("{}\n", indent));
+                    self.cursor_position = line_start + indent.len();
+                    self.auto_indent = Some(self.cursor_position).filter(|_| !indent.is_empty());
+                } else {
+                    // o - Add line below current line
+                    let line_end = text[self.cursor_position..].find('\n')
+                        .map(|pos| self.cursor_position + pos)
+                        .unwrap_or(text.len());
+                    text.insert_str(line_end, &format!("\n{}", indent));
+                    self.cursor_position = line_end + 1 + indent.len();
+                    self.auto_indent = Some(self.cursor_position).filter(|_| !indent.is_empty());
                 }
+                // Update cursor and enter insert mode
+                self.update_cursor_line_column(text);
+                self.vim_mode = VimMode::Insert;
+            },
+            egui::Key::V if !modifiers.shift && !modifiers.ctrl => {
+                // v - Start a character-wise visual selection at the cursor
+                self.vim_mode = VimMode::Visual;
+                self.visual_anchor = self.cursor_position;
+            },
+            egui::Key::V if modifiers.ctrl => {
+                // Ctrl+V - Start a column selection at the cursor
+                self.vim_mode = VimMode::VisualBlock;
+                self.visual_anchor = self.cursor_position;
             },
+            egui::Key::N if !modifiers.ctrl => {
+                // n - Search again in the same direction, N - the other way
+                return (true, self.search_again(text, modifiers.shift));
+            },
+            _ => {
+                if !self.handle_motion_key(key, text, modifiers) {
+                    // For other keys, update the desired column
+                    self.desired_column = self.cursor_column;
+                    handled = false;
+                }
+            }
+        }
+        
+        (handled, command_action)
+    }
+    
+    // Cursor movements shared by normal and visual mode; returns false for keys that aren't motions
+    fn handle_motion_key(&mut self, key: egui::Key, text: &str, modifiers: &egui::Modifiers) -> bool {
+        match key {
             // Movement keys
             egui::Key::H | egui::Key::ArrowLeft => {
                 if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
+                    self.cursor_position -= char_len_before(text, self.cursor_position);
                     self.update_cursor_line_column(text);
                     self.desired_column = self.cursor_column;
                 }
             },
             egui::Key::L | egui::Key::ArrowRight => {
                 if self.cursor_position < text.len() {
-                    self.cursor_position += 1;
+                    self.cursor_position += char_len_at(text, self.cursor_position);
                     self.update_cursor_line_column(text);
                     self.desired_column = self.cursor_column;
                 }
@@ -375,143 +1901,342 @@ impl SimpleEditor {
                     self.desired_column = current_desired;
                 }
             },
-            // Word movement
-            egui::Key::W => {
-                // Jump to start of next word
-                if self.cursor_position < text.len() {
-                    // Skip current word if we're in the middle of one
-                    let mut pos = self.cursor_position;
-                    
-                    // Skip non-whitespace
-                    while pos < text.len() && !text[pos..pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                        pos += 1;
-                    }
-                    
-                    // Skip whitespace
-                    while pos < text.len() && text[pos..pos+1].chars().next().unwrap_or(' ').is_whitespace() {
-                        pos += 1;
-                    }
-                    
-                    if pos > self.cursor_position && pos <= text.len() {
-                        self.cursor_position = pos;
-                        self.update_cursor_line_column(text);
-                        self.desired_column = self.cursor_column;
-                    }
-                }
+            egui::Key::D | egui::Key::U if modifiers.ctrl => {
+                self.scroll_half_page(text, key == egui::Key::D);
+            },
+            egui::Key::F | egui::Key::B if modifiers.ctrl => {
+                self.scroll_page(text, key == egui::Key::F);
+            },
+            // Word movement: w b e, and W B E over WORDs
+            egui::Key::W | egui::Key::B | egui::Key::E if !modifiers.ctrl && !modifiers.alt => {
+                let count = self.count.take().unwrap_or(1).max(1);
+                let pos = self.word_target(text, key, modifiers.shift, count);
+                if pos != self.cursor_position {
+                    self.cursor_position = pos;
+                    self.update_cursor_line_column(text);
+                    self.desired_column = self.cursor_column;
+                }
+            },
+            // Line navigation
+            egui::Key::Num0 if !modifiers.shift => {
+                // Move to beginning of line
+                let line_start = text[..self.cursor_position].rfind('\n')
+                    .map(|pos| pos + 1)
+                    .unwrap_or(0);
+                self.cursor_position = line_start;
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            egui::Key::Num4 => {
+                // Move to end of line ($ in vim)
+                let line_end = text[self.cursor_position..].find('\n')
+                    .map(|pos| self.cursor_position + pos)
+                    .unwrap_or(text.len());
+                self.cursor_position = line_end;
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            _ => return false,
+        }
+        true
+    }
+    
+    fn handle_visual_mode_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        if std::mem::take(&mut self.pending_g) {
+            match key {
+                egui::Key::G if !modifiers.shift => self.go_to_line(text, Some(1)),
+                egui::Key::U => {
+                    // gu and gU, the same as u and U
+                    let operation = if modifiers.shift { VimOperation::Uppercase } else { VimOperation::Lowercase };
+                    self.change_case_selection(text, operation);
+                },
+                _ => {},
+            }
+            return (true, None);
+        }
+        match key {
+            egui::Key::Escape => {
+                self.vim_mode = VimMode::Normal;
+            },
+            egui::Key::V if !modifiers.shift && !modifiers.ctrl => {
+                // v again ends the selection
+                self.vim_mode = VimMode::Normal;
+            },
+            egui::Key::V if modifiers.ctrl => {
+                self.vim_mode = VimMode::VisualBlock;
+            },
+            egui::Key::D | egui::Key::X if !modifiers.ctrl => {
+                self.delete_selection(text);
+                self.vim_mode = VimMode::Normal;
+            },
+            egui::Key::U if !modifiers.ctrl => {
+                // u and U - lower or upper case the selection; ~ switches it, see handle_command_text
+                let operation = if modifiers.shift { VimOperation::Uppercase } else { VimOperation::Lowercase };
+                self.change_case_selection(text, operation);
+            },
+            egui::Key::Y => {
+                if let Some((start, end)) = self.visual_range(text) {
+                    self.yank_to_register(text[start..end].to_string(), RegisterKind::Charwise);
+                    self.cursor_position = start;
+                    self.update_cursor_line_column(text);
+                    self.desired_column = self.cursor_column;
+                }
+                self.vim_mode = VimMode::Normal;
+            },
+            egui::Key::C | egui::Key::S => {
+                self.delete_selection(text);
+                self.vim_mode = VimMode::Insert;
+            },
+            egui::Key::G if !modifiers.shift => {
+                self.pending_g = true;
+            },
+            egui::Key::G => {
+                self.go_to_line(text, None);
+            },
+            egui::Key::F | egui::Key::T if !modifiers.ctrl && !modifiers.alt => {
+                self.pending_find = Some(CharFind { forward: !modifiers.shift, till: key == egui::Key::T });
+            },
+            egui::Key::O => {
+                // Jump to the other end of the selection
+                std::mem::swap(&mut self.visual_anchor, &mut self.cursor_position);
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            },
+            _ => return (self.handle_motion_key(key, text, modifiers), None),
+        }
+        (true, None)
+    }
+    
+    fn current_visual(&self) -> Option<VisualSelection> {
+        match self.vim_mode {
+            VimMode::Visual | VimMode::VisualBlock => Some(VisualSelection {
+                mode: self.vim_mode,
+                anchor: self.visual_anchor,
+                cursor: self.cursor_position,
+            }),
+            _ => None,
+        }
+    }
+    
+    // Byte range of the visual selection. Both ends are inclusive in vim, so the
+    // character under whichever end comes last is part of it.
+    pub fn visual_range(&self, text: &str) -> Option<(usize, usize)> {
+        if self.vim_mode != VimMode::Visual {
+            return None;
+        }
+        Some(self.selection_range(text, self.visual_anchor, self.cursor_position))
+    }
+    
+    // Byte ranges to highlight for the current selection (one per line in block mode)
+    pub fn selection_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        match self.vim_mode {
+            VimMode::Visual => self.visual_range(text).into_iter().collect(),
+            VimMode::VisualBlock => self.block_ranges(text),
+            _ => Vec::new(),
+        }
+    }
+    
+    fn selection_range(&self, text: &str, anchor: usize, cursor: usize) -> (usize, usize) {
+        let start = anchor.min(cursor).min(text.len());
+        let last = anchor.max(cursor).min(text.len());
+        let end = last + self.char_at(text, last).map_or(0, |c| c.len_utf8());
+        (start, end)
+    }
+    
+    // Cut the visual selection into the register
+    fn delete_selection(&mut self, text: &mut String) {
+        if let Some((start, end)) = self.visual_range(text) {
+            self.delete_to_register(text[start..end].to_string(), RegisterKind::Charwise);
+            text.replace_range(start..end, "");
+            self.cursor_position = start;
+            self.update_cursor_line_column(text);
+            self.desired_column = self.cursor_column;
+        }
+    }
+    
+    // u, U and ~ in visual mode: the case of the selection, then back to normal mode
+    fn change_case_selection(&mut self, text: &mut String, operation: VimOperation) {
+        let ranges = self.selection_ranges(text);
+        // From the last one, as changing case may change a range's length
+        for &(start, end) in ranges.iter().rev() {
+            let changed = change_case(&text[start..end], operation);
+            text.replace_range(start..end, &changed);
+        }
+        if let Some(&(start, _)) = ranges.first() {
+            self.cursor_position = start;
+        }
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+        self.vim_mode = VimMode::Normal;
+    }
+    
+    fn handle_visual_block_mode_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        match key {
+            egui::Key::Escape => {
+                self.vim_mode = VimMode::Normal;
+            },
+            egui::Key::V if modifiers.ctrl => {
+                // Ctrl+V again ends the selection
+                self.vim_mode = VimMode::Normal;
             },
-            egui::Key::B => {
-                // Jump to start of previous word
-                if self.cursor_position > 0 {
-                    let mut pos = self.cursor_position;
-                    
-                    // Skip whitespace backwards
-                    while pos > 0 && text[pos-1..pos].chars().next().unwrap_or(' ').is_whitespace() {
-                        pos -= 1;
-                    }
-                    
-                    // Skip non-whitespace backwards
-                    while pos > 0 && !text[pos-1..pos].chars().next().unwrap_or(' ').is_whitespace() {
-                        pos -= 1;
-                    }
-                    
-                    if pos < self.cursor_position {
-                        self.cursor_position = pos;
-                        self.update_cursor_line_column(text);
-                        self.desired_column = self.cursor_column;
-                    }
-                }
+            egui::Key::V if !modifiers.shift => {
+                self.vim_mode = VimMode::Visual;
             },
-            // Line navigation
-            egui::Key::Num0 => {
-                // Move to beginning of line
-                let line_start = text[..self.cursor_position].rfind('\n')
-                    .map(|pos| pos + 1)
-                    .unwrap_or(0);
-                self.cursor_position = line_start;
-                self.update_cursor_line_column(text);
-                self.desired_column = self.cursor_column;
+            egui::Key::D | egui::Key::X if !modifiers.ctrl => {
+                self.delete_block(text);
+                self.vim_mode = VimMode::Normal;
             },
-            egui::Key::Num4 => {
-                // Move to end of line ($ in vim)
-                let line_end = text[self.cursor_position..].find('\n')
-                    .map(|pos| self.cursor_position + pos)
-                    .unwrap_or(text.len());
-                self.cursor_position = line_end;
+            egui::Key::U if !modifiers.ctrl => {
+                let operation = if modifiers.shift { VimOperation::Uppercase } else { VimOperation::Lowercase };
+                self.change_case_selection(text, operation);
+            },
+            egui::Key::Y => {
+                let ranges = self.block_ranges(text);
+                let block = ranges
+                    .iter()
+                    .map(|&(start, end)| &text[start..end])
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.yank_to_register(block, RegisterKind::Charwise);
+                self.cursor_position = ranges.first().map_or(self.cursor_position, |&(start, _)| start);
                 self.update_cursor_line_column(text);
                 self.desired_column = self.cursor_column;
+                self.vim_mode = VimMode::Normal;
             },
-            // Mode switches
-            egui::Key::I => {
-                if modifiers.shift {
-                    // Shift+I - Move to beginning of line and enter insert mode
-                    let line_start = text[..self.cursor_position].rfind('\n')
-                        .map(|pos| pos + 1)
-                        .unwrap_or(0);
-                    self.cursor_position = line_start;
-                    self.update_cursor_line_column(text);
-                }
-                // Enter insert mode
-                self.vim_mode = VimMode::Insert;
-            },
-            egui::Key::A => {
-                if modifiers.shift {
-                    // Shift+A - Move to end of line and enter insert mode
-                    let line_end = text[self.cursor_position..].find('\n')
-                        .map(|pos| self.cursor_position + pos)
-                        .unwrap_or(text.len());
-                    self.cursor_position = line_end;
-                    self.update_cursor_line_column(text);
-                } else {
-                    // a - Move cursor forward one character then enter insert mode
-                    if self.cursor_position < text.len() {
-                        self.cursor_position += 1;
-                        self.update_cursor_line_column(text);
-                    }
-                }
-                self.vim_mode = VimMode::Insert;
+            egui::Key::C | egui::Key::S => {
+                // Change: delete the block, then type the replacement on every line
+                let (first_line, last_line, left, _) = self.block_bounds(text);
+                self.delete_block(text);
+                self.start_block_insert(text, first_line, last_line, left, false);
             },
-            // Command mode - use : shortcut
-            egui::Key::Num9 if modifiers.shift => {
-                // Using shift+9 as : to enter command mode
-                self.vim_mode = VimMode::Command;
-                self.command_buffer = ":".to_string();
+            egui::Key::I if modifiers.shift => {
+                let (first_line, last_line, left, _) = self.block_bounds(text);
+                self.start_block_insert(text, first_line, last_line, left, false);
             },
-            // Delete operations
-            egui::Key::X => {
-                if self.cursor_position < text.len() {
-                    text.remove(self.cursor_position);
-                    self.update_cursor_line_column(text);
-                }
+            egui::Key::A if modifiers.shift => {
+                let (first_line, last_line, _, right) = self.block_bounds(text);
+                self.start_block_insert(text, first_line, last_line, right + 1, true);
             },
             egui::Key::O => {
-                // Insert new line before current line and enter insert mode
-                if modifiers.shift {
-                    // Shift+O - Add line above current line
-                    let line_start = text[..self.cursor_position].rfind('\n')
-                        .map(|pos| pos + 1)
-                        .unwrap_or(0);
-                    text.insert(line_start, '\n');
-                    self.cursor_position = line_start;
-                } else {
-                    // o - Add line below current line
-                    let line_end = text[self.cursor_position..].find('\n')
-                        .map(|pos| self.cursor_position + pos)
-                        .unwrap_or(text.len());
-                    text.insert(line_end, '\n');
-                    self.cursor_position = line_end + 1;
-                }
-                // Update cursor and enter insert mode
+                std::mem::swap(&mut self.visual_anchor, &mut self.cursor_position);
                 self.update_cursor_line_column(text);
-                self.vim_mode = VimMode::Insert;
-            },
-            _ => {
-                // For other keys, update the desired column
                 self.desired_column = self.cursor_column;
-                handled = false;
+            },
+            _ => return (self.handle_motion_key(key, text, modifiers), None),
+        }
+        (true, None)
+    }
+    
+    // Lines and inclusive character columns covered by the block selection
+    fn block_bounds(&self, text: &str) -> (usize, usize, usize, usize) {
+        let (anchor_line, anchor_column) = line_and_char_column(text, self.visual_anchor.min(text.len()));
+        let (cursor_line, cursor_column) = line_and_char_column(text, self.cursor_position.min(text.len()));
+        (
+            anchor_line.min(cursor_line),
+            anchor_line.max(cursor_line),
+            anchor_column.min(cursor_column),
+            anchor_column.max(cursor_column),
+        )
+    }
+    
+    // Byte range of the block on each of its lines; empty on lines too short to reach it
+    fn block_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        let (first_line, last_line, left, right) = self.block_bounds(text);
+        let mut ranges = Vec::new();
+        let mut line_start = 0;
+        for (index, line) in text.split('\n').enumerate() {
+            if index > last_line {
+                break;
             }
+            if index >= first_line {
+                let start = char_column_offset(line, left).unwrap_or(line.len());
+                let end = char_column_offset(line, right + 1).unwrap_or(line.len());
+                ranges.push((line_start + start, line_start + end));
+            }
+            line_start += line.len() + 1;
+        }
+        ranges
+    }
+    
+    // Cut the block into the register, one line of it per line
+    fn delete_block(&mut self, text: &mut String) {
+        let ranges = self.block_ranges(text);
+        let block = ranges
+            .iter()
+            .map(|&(start, end)| &text[start..end])
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.delete_to_register(block, RegisterKind::Charwise);
+        // Bottom up so the earlier ranges stay valid
+        for &(start, end) in ranges.iter().rev() {
+            text.replace_range(start..end, "");
+        }
+        self.cursor_position = ranges.first().map_or(self.cursor_position, |&(start, _)| start);
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    fn start_block_insert(&mut self, text: &mut String, first_line: usize, last_line: usize, column: usize, append: bool) {
+        let line_start = line_start_offset(text, first_line);
+        let line_end = text[line_start..].find('\n').map_or(text.len(), |pos| line_start + pos);
+        self.cursor_position = match char_column_offset(&text[line_start..line_end], column) {
+            Some(offset) => line_start + offset,
+            None if append => {
+                // Pad the first line out to the column as well
+                let padding = column - text[line_start..line_end].chars().count();
+                text.insert_str(line_end, &" ".repeat(padding));
+                line_end + padding
+            },
+            None => line_end,
+        };
+        self.update_cursor_line_column(text);
+        self.block_insert = Some(BlockInsert {
+            start: self.cursor_position,
+            first_line,
+            last_line,
+            column,
+            append,
+        });
+        self.vim_mode = VimMode::Insert;
+    }
+    
+    // Leave insert mode (Escape). Finishes a block insert by repeating the
+    // typed text on the rest of the block's lines.
+    pub fn finish_insert(&mut self, text: &mut String) {
+        if let Some(block) = self.block_insert.take() {
+            self.repeat_block_insert(text, block);
+        }
+        self.vim_mode = VimMode::Normal;
+        // In vim, Escape in insert mode moves cursor back one char, staying on the line
+        if self.cursor_position > 0 && !text[..self.cursor_position].ends_with('\n') {
+            self.cursor_position -= char_len_before(text, self.cursor_position);
+            self.update_cursor_line_column(text);
         }
+    }
+    
+    fn repeat_block_insert(&mut self, text: &mut String, block: BlockInsert) {
+        // Like vim, give up if the cursor left the line or typing went past a line break
+        let inserted = match text.get(block.start..self.cursor_position) {
+            Some(inserted) if !inserted.is_empty() && !inserted.contains('\n') => inserted.to_string(),
+            _ => return,
+        };
         
-        (handled, command_action)
+        for line in block.first_line + 1..=block.last_line {
+            let line_start = line_start_offset(text, line);
+            if line_start > text.len() {
+                break;
+            }
+            let line_end = text[line_start..].find('\n').map_or(text.len(), |pos| line_start + pos);
+            let line_text = &text[line_start..line_end];
+            match char_column_offset(line_text, block.column) {
+                Some(offset) => text.insert_str(line_start + offset, &inserted),
+                None if block.append => {
+                    let padding = " ".repeat(block.column - line_text.chars().count());
+                    text.insert_str(line_end, &format!("{}{}", padding, inserted));
+                },
+                None => {}, // Short lines are skipped by `I`
+            }
+        }
     }
     
     fn handle_insert_mode_key(&mut self, key: egui::Key, text: &mut String, _modifiers: &egui::Modifiers) -> (bool, Option<String>) {
@@ -520,24 +2245,40 @@ impl SimpleEditor {
         
         match key {
             egui::Key::Escape => {
-                self.vim_mode = VimMode::Normal;
-                // In vim, Escape in insert mode moves cursor back one char
-                if self.cursor_position > 0 && !text.is_empty() {
-                    self.cursor_position -= 1;
-                    self.update_cursor_line_column(text);
+                // An indent that nothing was typed after isn't kept
+                if let Some(end) = self.auto_indent.take().filter(|&end| end == self.cursor_position) {
+                    let line_start = text[..end].rfind('\n').map_or(0, |p| p + 1);
+                    let at_line_end = text[end..].starts_with('\n') || end == text.len();
+                    if at_line_end && text[line_start..end].trim_start_matches([' ', '\t']).is_empty() {
+                        text.replace_range(line_start..end, "");
+                        self.cursor_position = line_start;
+                    }
                 }
+                self.finish_insert(text);
             },
             egui::Key::Enter => {
                 if self.cursor_position <= text.len() {
-                    text.insert(self.cursor_position, '\n');
-                    self.cursor_position += 1;
+                    // The new line starts at the current one's indentation instead
+                    // of the blanks after the cursor, and what's left of the line
+                    // is left empty if it's only blanks
+                    let indent = Self::line_indent(text, self.cursor_position);
+                    let blanks = text[self.cursor_position..].len() - text[self.cursor_position..].trim_start_matches([' ', '\t']).len();
+                    text.replace_range(self.cursor_position..self.cursor_position + blanks, "");
+                    let line_start = text[..self.cursor_position].rfind('\n').map_or(0, |p| p + 1);
+                    if text[line_start..self.cursor_position].trim_start_matches([' ', '\t']).is_empty() {
+                        text.replace_range(line_start..self.cursor_position, "");
+                        self.cursor_position = line_start;
+                    }
+                    text.insert_str(self.cursor_position, &format!("\n{}", indent));
+                    self.cursor_position += 1 + indent.len();
+                    self.auto_indent = Some(self.cursor_position).filter(|_| !indent.is_empty());
                     self.update_cursor_line_column(text);
                 }
             },
             egui::Key::Backspace => {
                 if self.cursor_position > 0 {
-                    text.remove(self.cursor_position - 1);
-                    self.cursor_position -= 1;
+                    self.cursor_position -= char_len_before(text, self.cursor_position);
+                    text.remove(self.cursor_position);
                     self.update_cursor_line_column(text);
                 }
             },
@@ -550,14 +2291,14 @@ impl SimpleEditor {
             },
             egui::Key::ArrowLeft => {
                 if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
+                    self.cursor_position -= char_len_before(text, self.cursor_position);
                     self.update_cursor_line_column(text);
                     self.desired_column = self.cursor_column;
                 }
             },
             egui::Key::ArrowRight => {
                 if self.cursor_position < text.len() {
-                    self.cursor_position += 1;
+                    self.cursor_position += char_len_at(text, self.cursor_position);
                     self.update_cursor_line_column(text);
                     self.desired_column = self.cursor_column;
                 }
@@ -612,24 +2353,75 @@ impl SimpleEditor {
         (handled, command_action)
     }
     
+    // Replace mode is insert mode where typing goes over the text, see
+    // replace_char; Backspace puts back what was there
+    fn handle_replace_mode_key(&mut self, key: egui::Key, text: &mut String, modifiers: &egui::Modifiers) -> (bool, Option<String>) {
+        match key {
+            egui::Key::Escape => {
+                self.replaced.clear();
+                self.finish_insert(text);
+            },
+            egui::Key::Enter => {
+                // A line break is added, not typed over anything
+                let pos = self.cursor_position.min(text.len());
+                text.insert(pos, '\n');
+                self.replaced.push(None);
+                self.cursor_position = pos + 1;
+                self.update_cursor_line_column(text);
+            },
+            egui::Key::Backspace => {
+                if self.cursor_position > 0 {
+                    self.cursor_position -= char_len_before(text, self.cursor_position);
+                    let pos = self.cursor_position;
+                    match self.replaced.pop() {
+                        Some(Some(original)) => text.replace_range(pos..pos + char_len_at(text, pos), &original.to_string()),
+                        Some(None) => {
+                            text.remove(pos);
+                        },
+                        None => {}, // Before where replacing started it only moves back
+                    }
+                    self.update_cursor_line_column(text);
+                }
+            },
+            _ => {
+                let result = self.handle_insert_mode_key(key, text, modifiers);
+                if result.0 {
+                    // Moving around (or Delete) starts over from the new place
+                    self.replaced.clear();
+                }
+                return result;
+            },
+        }
+        (true, None)
+    }
+    
     fn handle_command_mode_key(&mut self, key: egui::Key, text: &mut String, _modifiers: &egui::Modifiers) -> (bool, Option<String>) {
         let mut handled = true;
         let mut command_action = None;
         
         match key {
             egui::Key::Escape => {
+                if let Some(origin) = self.search_origin.take() {
+                    // Back to where the search started
+                    self.move_cursor(text, origin);
+                    self.incsearch = None;
+                }
                 self.vim_mode = VimMode::Normal;
                 self.command_buffer.clear();
             },
             egui::Key::Enter => {
                 // Process command and get action
-                command_action = self.execute_command(text);
+                command_action = match self.search_origin.take() {
+                    Some(origin) => self.finish_search(text, origin),
+                    None => self.execute_command(text),
+                };
                 self.vim_mode = VimMode::Normal;
                 self.command_buffer.clear();
             },
             egui::Key::Backspace => {
-                if self.command_buffer.len() > 1 { // Keep the initial ':'
+                if self.command_buffer.len() > 1 { // Keep the initial ':' or '/'
                     self.command_buffer.pop();
+                    self.update_incsearch(text);
                 }
             },
             _ => {
@@ -640,9 +2432,13 @@ impl SimpleEditor {
         (handled, command_action)
     }
     
-    fn execute_command(&mut self, _text: &mut String) -> Option<String> {
+    fn execute_command(&mut self, text: &mut String) -> Option<String> {
         // Basic command processing that returns an action for the app to handle
-        match self.command_buffer.as_str() {
+        let command = self.command_buffer.clone();
+        if let Some(range_command) = command.strip_prefix(":'<,'>") {
+            return self.execute_selection_command(text, range_command.trim());
+        }
+        match command.as_str() {
             ":w" => {
                 println!("Save command received");
                 Some("save".to_string())
@@ -655,6 +2451,122 @@ impl SimpleEditor {
                 println!("Save and quit command received");
                 Some("save_quit".to_string())
             },
+            ":reveal" => Some("reveal".to_string()),
+            ":copypath" => Some("copy_path".to_string()),
+            ":copyrelpath" => Some("copy_relative_path".to_string()),
+            cmd if cmd == ":earlier" || cmd.starts_with(":earlier ") => {
+                self.time_travel(text, &cmd[":earlier".len()..], false)
+            },
+            cmd if cmd == ":later" || cmd.starts_with(":later ") => {
+                self.time_travel(text, &cmd[":later".len()..], true)
+            },
+            // :{number} - go to that line, :$ to the last
+            cmd if cmd.len() > 1 && cmd[1..].chars().all(|c| c.is_ascii_digit()) => {
+                self.go_to_line(text, Some(cmd[1..].parse().unwrap_or(usize::MAX)));
+                None
+            },
+            ":e #" | ":edit #" => Some("alternate_note".to_string()),
+            ":$" => {
+                self.go_to_line(text, None);
+                None
+            },
+            ":noh" | ":nohlsearch" => {
+                // Until the next search or n/N
+                self.highlight_search = false;
+                None
+            },
+            ":bookmarks" => Some("bookmarks".to_string()),
+            cmd if cmd.starts_with(":bookmark ") => {
+                Some(format!("bookmark {}", cmd[":bookmark ".len()..].trim()))
+            },
+            cmd if matches!(cmd.split(' ').next(), Some(":reg") | Some(":registers")) => {
+                // Optional argument: the registers to show, e.g. `:reg a0"`
+                let names = cmd.split_once(' ').map_or("", |(_, names)| names.trim());
+                Some(format!("registers {}", names).trim_end().to_string())
+            },
+            cmd if cmd == ":marks" || cmd.starts_with(":marks ") => {
+                // Optional argument: the marks to show, e.g. `:marks aB`
+                let names = cmd[":marks".len()..].trim();
+                Some(format!("marks {}", names).trim_end().to_string())
+            },
+            ":feeds" => Some("feeds".to_string()),
+            ":tasks" => Some("tasks".to_string()),
+            ":tags" => Some("tags".to_string()),
+            ":projects" => Some("projects".to_string()),
+            ":properties" | ":props" => Some("properties".to_string()),
+            cmd if cmd == ":layout" || cmd.starts_with(":layout ") => {
+                // Optional arguments: `save {name}`, `delete {name}` or the name to restore
+                Some(format!("layout {}", cmd[":layout".len()..].trim()).trim_end().to_string())
+            },
+            cmd if cmd.starts_with(":mail ") => {
+                // A .eml or mbox file, then optionally text to pick messages by subject or sender
+                Some(format!("mail {}", cmd[":mail ".len()..].trim()))
+            },
+            cmd if cmd.starts_with(":attach ") => Some(format!("attach {}", cmd[":attach ".len()..].trim())),
+            ":pasteimage" => Some("paste_image".to_string()),
+            cmd if cmd == ":export html" || cmd.starts_with(":export html ") => {
+                // Optional argument: the file to write
+                let path = cmd[":export html".len()..].trim();
+                Some(format!("export_html {}", path).trim_end().to_string())
+            },
+            cmd if cmd == ":export site" || cmd.starts_with(":export site ") => {
+                // Optional arguments: the folder to export (all notes by default), then the output folder
+                let args = cmd[":export site".len()..].trim();
+                Some(format!("export_site {}", args).trim_end().to_string())
+            },
+            ":maintenance" => Some("maintenance".to_string()),
+            ":merge" => Some("merge".to_string()),
+            ":undo-vault" => Some("undo_vault".to_string()),
+            ":tutor" => Some("tutor".to_string()),
+            cmd if cmd == ":pomodoro" || cmd.starts_with(":pomodoro ") => {
+                // Optional argument: minutes, or `stop`
+                let args = cmd[":pomodoro".len()..].trim();
+                Some(format!("pomodoro {}", args).trim_end().to_string())
+            },
+            cmd if cmd == ":lock" || cmd.starts_with(":lock ") => {
+                // Optional argument: `set` or `off`
+                let args = cmd[":lock".len()..].trim();
+                Some(format!("lock {}", args).trim_end().to_string())
+            },
+            ":tutor reset" => Some("tutor reset".to_string()),
+            ":convert-utf8" => Some("convert_utf8".to_string()),
+            cmd if cmd.starts_with(":new ") && !cmd[":new ".len()..].trim().is_empty() => {
+                Some(format!("new_note {}", cmd[":new ".len()..].trim()))
+            },
+            cmd if cmd.starts_with(":settings ") => Some(format!("settings {}", cmd[":settings ".len()..].trim())),
+            ":index status" | ":index" => Some("index status".to_string()),
+            ":index rebuild" => Some("index rebuild".to_string()),
+            ":cnext" | ":cn" => Some("cnext".to_string()),
+            ":cprev" | ":cprevious" | ":cp" | ":cN" => Some("cprev".to_string()),
+            cmd if cmd == ":search" || cmd.starts_with(":search ") => {
+                // Optional argument: the query to start with, filters included
+                let query = cmd[":search".len()..].trim();
+                Some(format!("search {}", query).trim_end().to_string())
+            },
+            cmd if cmd == ":meetings" || cmd.starts_with(":meetings ") => {
+                // Optional argument: an .ics path or URL overriding the configured calendar
+                let source = cmd[":meetings".len()..].trim();
+                Some(format!("meetings {}", source).trim_end().to_string())
+            },
+            cmd if cmd.starts_with(":highlight") || cmd.starts_with(":annotate") => {
+                Some("status Select the text to annotate with v first".to_string())
+            },
+            ":unannotate" => Some(format!("unannotate {}", self.cursor_position)),
+            cmd if cmd.starts_with(":set ") => Some(format!("set {}", cmd[":set ".len()..].trim())),
+            cmd if cmd.starts_with(":%s") || (cmd.starts_with(":s") && Substitute::parse(&cmd[":s".len()..]).is_some()) => {
+                // :s on the cursor's line, :%s on every line
+                let (start, end, args) = match cmd.strip_prefix(":%s") {
+                    Some(args) => (0, text.len(), args),
+                    None => {
+                        let (start, end) = line_bounds(text, self.cursor_position, self.cursor_position);
+                        (start, end, &cmd[":s".len()..])
+                    },
+                };
+                match Substitute::parse(args) {
+                    Some(substitute) => self.substitute(text, start, end, substitute),
+                    None => Some("status Usage: :s/pattern/replacement/[g]".to_string()),
+                }
+            },
             _ => {
                 // Other commands not yet implemented
                 None
@@ -662,35 +2574,140 @@ impl SimpleEditor {
         }
     }
     
+    // Commands entered with `:` from visual mode, applied to that selection
+    fn execute_selection_command(&mut self, text: &mut String, command: &str) -> Option<String> {
+        let selection = self.last_visual?;
+        let (start, end) = self.selection_range(text, selection.anchor, selection.cursor);
+        if let Some(substitute) = command.strip_prefix('s').and_then(Substitute::parse) {
+            // On every line of the selection
+            let last = (end - char_len_before(text, end)).max(start);
+            let (start, end) = line_bounds(text, start, last);
+            return self.substitute(text, start, end, substitute);
+        }
+        let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "highlight" | "hi" => {
+                let color = if arg.trim().is_empty() { "yellow" } else { arg.trim() };
+                Some(format!("highlight {} {} {}", start, end, color))
+            },
+            "annotate" => Some(format!("annotate {} {} {}", start, end, arg.trim())),
+            _ => Some(format!("status Not a selection command: {}", name)),
+        }
+    }
+    
     pub fn handle_text_input(&mut self, c: char, text: &mut String) {
+        if matches!(self.vim_mode, VimMode::Insert | VimMode::Replace | VimMode::Command) {
+            self.macros.record_text(c);
+        }
         match self.vim_mode {
             VimMode::Insert => {
-                if c >= ' ' || c == '\n' || c == '\t' {
-                    if self.cursor_position <= text.len() {
-                        // Insert the character at cursor
-                        text.insert(self.cursor_position, c);
-                        self.cursor_position += 1;
-                        self.update_cursor_line_column(text);
+                let before = UndoState::capture(text, self.cursor_position);
+                if (c >= ' ' || c == '\n' || c == '\t') && self.cursor_position <= text.len() {
+                    // Insert the character at cursor
+                    text.insert(self.cursor_position, c);
+                    self.cursor_position += c.len_utf8();
+                    self.update_cursor_line_column(text);
+                    
+                    if !c.is_whitespace() {
+                        self.wrap_at_text_width(text);
                     }
                 }
+                self.record_change(before, text);
             },
-            VimMode::Command => {
-                if c >= ' ' {
-                    // Add to command buffer
-                    self.command_buffer.push(c);
+            VimMode::Replace => {
+                let before = UndoState::capture(text, self.cursor_position);
+                if c >= ' ' || c == '\t' {
+                    self.replace_char(c, text);
                 }
+                self.record_change(before, text);
+            },
+            VimMode::Command if c >= ' ' => {
+                // Add to command buffer
+                self.command_buffer.push(c);
+                self.update_incsearch(text);
             },
             _ => {},
         }
     }
     
+    // Type `c` over the character under the cursor, or add it at the end of
+    // the line, remembering what was there
+    fn replace_char(&mut self, c: char, text: &mut String) {
+        let pos = self.cursor_position.min(text.len());
+        let original = match self.char_at(text, pos) {
+            Some(original) if original != '\n' => {
+                text.replace_range(pos..pos + original.len_utf8(), &c.to_string());
+                Some(original)
+            },
+            _ => {
+                text.insert(pos, c);
+                None
+            },
+        };
+        self.replaced.push(original);
+        self.cursor_position = pos + c.len_utf8();
+        self.update_cursor_line_column(text);
+    }
+    
+    // Replace the text between `start` and the cursor, e.g. to accept a completion.
+    // Counts as typing, so it joins the current insert session's undo step.
+    pub fn complete_before_cursor(&mut self, text: &mut String, start: usize, replacement: &str) {
+        let before = UndoState::capture(text, self.cursor_position);
+        text.replace_range(start..self.cursor_position, replacement);
+        self.cursor_position = start + replacement.len();
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+        self.record_change(before, text);
+    }
+    
+    // Put the cursor at the start of a line (0-based), or the last line if there are fewer
+    pub fn move_to_line(&mut self, text: &str, line: usize) {
+        self.cursor_position = text
+            .match_indices('\n')
+            .take(line)
+            .last()
+            .map_or(0, |(i, _)| i + 1);
+        if self.cursor_position > text.len() {
+            self.cursor_position = text.len();
+        }
+        self.update_cursor_line_column(text);
+        self.desired_column = self.cursor_column;
+    }
+    
+    // Break the current line at the last space before the cursor once it grows past textwidth
+    fn wrap_at_text_width(&mut self, text: &mut String) {
+        let text_width = match self.text_width {
+            Some(tw) => tw,
+            None => return,
+        };
+        if self.cursor_column <= text_width {
+            return;
+        }
+        
+        let line_start = text[..self.cursor_position].rfind('\n')
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        
+        // Only break at a space that leaves some text on the first line
+        if let Some(space_offset) = text[line_start..self.cursor_position].rfind(' ') {
+            if space_offset > 0 {
+                let space_pos = line_start + space_offset;
+                text.replace_range(space_pos..space_pos + 1, "\n");
+                self.update_cursor_line_column(text);
+                self.desired_column = self.cursor_column;
+            }
+        }
+    }
+    
     pub fn update_cursor_line_column(&mut self, text: &str) {
+        // Keep the cursor on a character boundary within the text, whatever moved it
+        self.cursor_position = self.cursor_position.min(text.len());
+        while !text.is_char_boundary(self.cursor_position) {
+            self.cursor_position -= 1;
+        }
+        
         // Calculate line and column based on cursor position
-        let text_before_cursor = if self.cursor_position <= text.len() {
-            &text[..self.cursor_position]
-        } else {
-            text // Safety check
-        };
+        let text_before_cursor = &text[..self.cursor_position];
         
         self.cursor_line = text_before_cursor.matches('\n').count();
         self.cursor_column = self.cursor_position - text_before_cursor.rfind('\n').map_or(0, |pos| pos + 1);
@@ -801,21 +2818,53 @@ impl SimpleEditor {
     }
     
     pub fn get_mode_display(&self) -> String {
-        match self.vim_mode {
+        let mode = match self.vim_mode {
             VimMode::Normal => {
-                if self.current_operation == VimOperation::None {
+                if let Some(pending) = self.pending_macro {
+                    match pending {
+                        PendingMacro::Record => "NORMAL (q)".to_string(),
+                        PendingMacro::Play => format!("NORMAL ({}@)", self.count.map_or(String::new(), |count| count.to_string())),
+                    }
+                } else if let Some(pending) = self.pending_mark {
+                    match pending {
+                        PendingMark::Set => "NORMAL (m)".to_string(),
+                        PendingMark::Jump { exact: false } => "NORMAL (')".to_string(),
+                        PendingMark::Jump { exact: true } => "NORMAL (`)".to_string(),
+                    }
+                } else if let Some(count) = self.count {
+                    format!("NORMAL ({})", count)
+                } else if self.pending_register_name {
+                    "NORMAL (\")".to_string()
+                } else if let Some(name) = self.selected_register {
+                    format!("NORMAL (\"{})", name)
+                } else if self.pending_g {
+                    "NORMAL (g)".to_string()
+                } else if self.current_operation == VimOperation::None {
                     "NORMAL".to_string()
                 } else {
                     match self.current_operation {
                         VimOperation::Delete => "NORMAL (d)".to_string(),
                         VimOperation::Yank => "NORMAL (y)".to_string(),
                         VimOperation::Change => "NORMAL (c)".to_string(),
+                        VimOperation::Lowercase => "NORMAL (gu)".to_string(),
+                        VimOperation::Uppercase => "NORMAL (gU)".to_string(),
+                        VimOperation::ToggleCase => "NORMAL (g~)".to_string(),
+                        VimOperation::ShiftRight => "NORMAL (>)".to_string(),
+                        VimOperation::ShiftLeft => "NORMAL (<)".to_string(),
+                        VimOperation::Reindent => "NORMAL (=)".to_string(),
                         _ => "NORMAL".to_string(),
                     }
                 }
             },
             VimMode::Insert => "INSERT".to_string(),
+            VimMode::Replace => "REPLACE".to_string(),
             VimMode::Command => self.command_buffer.clone(),
+            VimMode::Visual => "VISUAL".to_string(),
+            VimMode::VisualBlock => "VISUAL BLOCK".to_string(),
+        };
+        match self.macros.recording {
+            Some(name) => format!("{}  recording @{}", mode, name),
+            None => mode,
         }
     }
 
@@ -848,38 +2897,107 @@ impl SimpleEditor {
         
         // Find start of word by going backward
         let mut start = pos;
-        while start > 0 {
-            let prev_pos = start - 1;
-            // Move backward by UTF-8 character, not just bytes
-            let prev_char_pos = text[..prev_pos].char_indices()
-                .map(|(i, _)| i)
-                .rev()
-                .next()
-                .unwrap_or(0);
-                
-            if let Some(prev_char) = text[prev_char_pos..].chars().next() {
-                if !self.is_word_char(prev_char) {
-                    break;
-                }
-                start = prev_char_pos;
-            } else {
-                break;
-            }
+        while let Some(c) = text[..start].chars().next_back().filter(|&c| self.is_word_char(c)) {
+            start -= c.len_utf8();
         }
         
         // Find end of word by going forward
         let mut end = pos;
-        while end < text.len() {
-            if let Some(c) = text[end..].chars().next() {
-                if !self.is_word_char(c) {
-                    break;
-                }
-                end += c.len_utf8();
-            } else {
-                break;
-            }
+        while let Some(c) = text[end..].chars().next().filter(|&c| self.is_word_char(c)) {
+            end += c.len_utf8();
         }
         
         (start, end)
     }
-} 
\ No newline at end of file
+} 
+
+impl Default for SimpleEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Start of line `line` (1-based, past the end is the last line), or of the
+// last line for None
+fn line_start_of(text: &str, line: Option<usize>) -> usize {
+    let last_line = text.matches('\n').count();
+    match line.map_or(last_line, |line| line.saturating_sub(1).min(last_line)) {
+        0 => 0,
+        line => text.match_indices('\n').nth(line - 1).map_or(text.len(), |(pos, _)| pos + 1),
+    }
+}
+
+// Line number and character column of a byte offset
+fn line_and_char_column(text: &str, pos: usize) -> (usize, usize) {
+    let before = &text[..pos];
+    let line_start = before.rfind('\n').map_or(0, |p| p + 1);
+    (before.matches('\n').count(), before[line_start..].chars().count())
+}
+
+// The first character of `pos`'s line that isn't a space or tab
+fn first_non_blank(text: &str, pos: usize) -> usize {
+    let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    line_start + (line.len() - line.trim_start_matches([' ', '\t']).len())
+}
+
+// Start of the line `start` is on and end of the line `end` is on
+fn line_bounds(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = text[..start].rfind('\n').map_or(0, |p| p + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |p| end + p);
+    (line_start, line_end)
+}
+
+// Byte offset where a line starts (text.len() + 1 past the last line)
+fn line_start_offset(text: &str, line: usize) -> usize {
+    if line == 0 {
+        return 0;
+    }
+    text.match_indices('\n')
+        .nth(line - 1)
+        .map_or(text.len() + 1, |(i, _)| i + 1)
+}
+
+// Byte offset of a character column within a line; None if the line is shorter
+fn char_column_offset(line: &str, column: usize) -> Option<usize> {
+    if column == line.chars().count() {
+        return Some(line.len());
+    }
+    line.char_indices().nth(column).map(|(i, _)| i)
+}
+
+// `text` in lower or upper case, or with each letter's case switched, for gu, gU and g~
+fn change_case(text: &str, operation: VimOperation) -> String {
+    match operation {
+        VimOperation::Lowercase => text.to_lowercase(),
+        VimOperation::Uppercase => text.to_uppercase(),
+        _ => text
+            .chars()
+            .map(|c| if c.is_uppercase() { c.to_lowercase().to_string() } else { c.to_uppercase().to_string() })
+            .collect(),
+    }
+}
+
+// The letter on a letter key
+// Byte length of the character at `pos`, or 0 at the end of the text
+fn char_len_at(text: &str, pos: usize) -> usize {
+    text[pos..].chars().next().map_or(0, char::len_utf8)
+}
+
+// Byte length of the character before `pos`, or 0 at the start of the text
+fn char_len_before(text: &str, pos: usize) -> usize {
+    text[..pos].chars().next_back().map_or(0, char::len_utf8)
+}
+
+pub(super) fn key_letter(key: egui::Key) -> Option<char> {
+    use egui::Key::*;
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    letters.iter().position(|&k| k == key).map(|i| (b'a' + i as u8) as char)
+}
+
+pub(super) fn key_digit(key: egui::Key) -> Option<char> {
+    use egui::Key::*;
+    let digits = [Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9];
+    digits.iter().position(|&k| k == key).map(|i| (b'0' + i as u8) as char)
+}
@@ -0,0 +1,396 @@
+// Text objects, the `iw` of `diw` or the `aw` of `yaw`: a span of text around
+// the cursor for an operator to work on, whether or not the cursor is at its
+// start. `i` takes the object itself, `a` takes it with the blanks around it,
+// and with its quotes or brackets for a quoted string or a bracketed one.
+// A paragraph is taken in whole lines, and so are the markdown objects: a
+// fenced code block, a heading's section and a list item.
+
+use super::motions::{class, skip_back, skip_forward, Class};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Inner,  // i
+    Around, // a
+}
+
+// The span of `count` of the object named by `object`, the character typed
+// after `i` or `a`, around `pos`. None if there's no such object there.
+pub fn select(text: &str, pos: usize, object: char, scope: Scope, count: usize) -> Option<(usize, usize)> {
+    match object {
+        'w' => word(text, pos, scope, false, count),
+        'W' => word(text, pos, scope, true, count),
+        '"' | '\'' | '`' => quoted(text, pos, object, scope),
+        '(' | ')' | 'b' => bracketed(text, pos, '(', ')', scope, count),
+        '[' | ']' => bracketed(text, pos, '[', ']', scope, count),
+        '{' | '}' | 'B' => bracketed(text, pos, '{', '}', scope, count),
+        '<' | '>' => bracketed(text, pos, '<', '>', scope, count),
+        'p' => paragraph(text, pos, scope, count),
+        'c' => code_block(text, pos, scope),
+        'h' => section(text, pos, scope, count),
+        'l' => list_item(text, pos, scope, count),
+        _ => None,
+    }
+}
+
+// Whether the object is whole lines, to yank and put as lines
+pub fn is_linewise(object: char, scope: Scope) -> bool {
+    match object {
+        'p' | 'c' | 'h' => true,
+        'l' => scope == Scope::Around,
+        _ => false,
+    }
+}
+
+// iw / aw, and iW / aW over WORDs: `count` words from the one at `pos`. For
+// iw the blanks between words are words of their own. aw takes a word with
+// the blanks after it, or the ones before it if none follow, and on blanks
+// it takes them with the word after them. Neither goes past the line's end.
+fn word(text: &str, pos: usize, scope: Scope, big: bool, count: usize) -> Option<(usize, usize)> {
+    let class_at = |p: usize| text[p..].chars().next().filter(|&c| c != '\n').map(|c| class(c, big));
+    let run = |k: Class| move |c: char| c != '\n' && class(c, big) == k;
+    let first = class_at(pos)?;
+    let mut start = skip_back(text, pos, run(first));
+    let mut end = pos;
+    for _ in 0..count {
+        let k = match class_at(end) {
+            Some(k) => k,
+            None => break,
+        };
+        end = skip_forward(text, end, run(k));
+        if scope == Scope::Inner {
+            continue;
+        }
+        match class_at(end) {
+            Some(Class::Blank) if first != Class::Blank => end = skip_forward(text, end, run(Class::Blank)),
+            Some(k) if first == Class::Blank && k != Class::Blank => end = skip_forward(text, end, run(k)),
+            _ => {},
+        }
+    }
+    let trailing_blank = text[..end].chars().next_back().is_some_and(|c| c != '\n' && c.is_whitespace());
+    if scope == Scope::Around && first != Class::Blank && !trailing_blank {
+        start = skip_back(text, start, run(Class::Blank));
+    }
+    Some((start, end))
+}
+
+// i" / a", i' / a' and i` / a`: the quoted string the cursor is in or on, or
+// else the next one on the line. Quotes pair up from the start of the line,
+// skipping ones escaped with a backslash. a" takes the quotes too, and the
+// blanks after them, or the ones before them if none follow.
+fn quoted(text: &str, pos: usize, quote: char, scope: Scope) -> Option<(usize, usize)> {
+    let line_start = text[..pos].rfind('\n').map_or(0, |p| p + 1);
+    let line_end = text[pos..].find('\n').map_or(text.len(), |p| pos + p);
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, c) in text[line_start..line_end].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            quotes.push(line_start + i);
+        }
+    }
+    let (open, close) = quotes.chunks_exact(2).map(|pair| (pair[0], pair[1])).find(|&(_, close)| close >= pos)?;
+    let quote_len = quote.len_utf8();
+    match scope {
+        Scope::Inner => Some((open + quote_len, close)),
+        Scope::Around => Some(with_blanks(text, open, close + quote_len)),
+    }
+}
+
+// start..end with the blanks after it on its line, or if there are none,
+// the ones before it
+fn with_blanks(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let blank = |c: char| c != '\n' && c.is_whitespace();
+    let after = skip_forward(text, end, blank);
+    if after > end {
+        (start, after)
+    } else {
+        (skip_back(text, start, blank), end)
+    }
+}
+
+// i( / a( (or ib / ab), i[ / a[, i{ / a{ (or iB / aB) and i< / a<: the
+// `count`th pair of brackets out from `pos`, skipping the pairs nested in it.
+// The cursor can be on either bracket. i( takes what's between the brackets,
+// or just the lines between them when they end and start their lines, like
+// the braces of a block; a( takes the brackets too.
+fn bracketed(text: &str, pos: usize, open: char, close: char, scope: Scope, count: usize) -> Option<(usize, usize)> {
+    // On the opening bracket, that's the first pair
+    let search_end = match text[pos..].chars().next() {
+        Some(c) if c == open => pos + c.len_utf8(),
+        _ => pos,
+    };
+    let mut depth = 0usize;
+    let mut levels = count;
+    let mut start = None;
+    for (i, c) in text[..search_end].char_indices().rev() {
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            if depth > 0 {
+                depth -= 1;
+            } else {
+                levels -= 1;
+                if levels == 0 {
+                    start = Some(i);
+                    break;
+                }
+            }
+        }
+    }
+    let start = start?;
+
+    let inner_start = start + open.len_utf8();
+    let mut depth = 0usize;
+    let mut end = None;
+    for (i, c) in text[inner_start..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                end = Some(inner_start + i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let end = end?;
+
+    match scope {
+        Scope::Around => Some((start, end + close.len_utf8())),
+        Scope::Inner => {
+            // The lines between brackets that are on lines of their own
+            let from = if text[inner_start..end].starts_with('\n') { inner_start + 1 } else { inner_start };
+            let to = match text[from..end].rfind('\n') {
+                Some(newline) if text[from + newline + 1..end].trim().is_empty() => from + newline + 1,
+                _ => end,
+            };
+            Some((from, to))
+        },
+    }
+}
+
+// ip / ap: the paragraph at `pos`, a run of lines between blank ones (lines
+// of only whitespace count as blank), from the start of its first line to
+// past the newline of its last. On blank lines ip takes those instead, and a
+// count takes that many runs of either. ap takes a paragraph and the blank
+// lines after it, or the ones before it if there are none after; on blank
+// lines, those and the paragraph after them.
+fn paragraph(text: &str, pos: usize, scope: Scope, count: usize) -> Option<(usize, usize)> {
+    let lines = line_spans(text);
+    let blank = |line: usize| text[lines[line].0..lines[line].1].trim().is_empty();
+    // Past the end of the run of lines like `line`
+    let run_end = |line: usize| (line..lines.len()).find(|&next| blank(next) != blank(line)).unwrap_or(lines.len());
+
+    let current = line_at(&lines, pos);
+    let mut first = (0..current).rev().find(|&prev| blank(prev) != blank(current)).map_or(0, |prev| prev + 1);
+    let mut last = first; // Past the last line taken
+    for _ in 0..count {
+        if last == lines.len() {
+            break;
+        }
+        last = run_end(last);
+        if scope == Scope::Around && last < lines.len() {
+            last = run_end(last);
+        }
+    }
+    // ap of the last paragraph takes the blank lines before it instead
+    if scope == Scope::Around && !blank(current) && !blank(last - 1) {
+        first = (0..first).rev().find(|&prev| !blank(prev)).map_or(0, |prev| prev + 1);
+    }
+    Some((lines[first].0, lines[last - 1].1))
+}
+
+// Start and end, past the newline, of each line
+fn line_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        lines.push((start, start + line.len()));
+        start += line.len();
+    }
+    if lines.is_empty() {
+        lines.push((0, 0));
+    }
+    lines
+}
+
+// The line `pos` is on
+fn line_at(lines: &[(usize, usize)], pos: usize) -> usize {
+    lines.iter().position(|&(_, end)| pos < end).unwrap_or(lines.len() - 1)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+// The fenced code blocks, as the line of the opening fence and the line of
+// the closing one, or lines.len() for a block that's never closed
+fn code_blocks(text: &str, lines: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+    for (i, &(start, end)) in lines.iter().enumerate() {
+        let trimmed = text[start..end].trim_start();
+        let fence = ["```", "~~~"].into_iter().find(|fence| trimmed.starts_with(fence));
+        match (open, fence) {
+            (None, Some(fence)) => open = Some((i, fence)),
+            (Some((first, opening)), Some(fence)) if fence == opening => {
+                blocks.push((first, i));
+                open = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some((first, _)) = open {
+        blocks.push((first, lines.len()));
+    }
+    blocks
+}
+
+// ic / ac: the fenced code block the cursor is in or on a fence of. ic takes
+// the lines between the fences, ac the fences too.
+fn code_block(text: &str, pos: usize, scope: Scope) -> Option<(usize, usize)> {
+    let lines = line_spans(text);
+    let current = line_at(&lines, pos);
+    let (open, close) = code_blocks(text, &lines).into_iter().find(|&(open, close)| open <= current && current <= close)?;
+    // Past the last line taken
+    let (first, last) = match scope {
+        Scope::Inner => (open + 1, close),
+        Scope::Around => (open, (close + 1).min(lines.len())),
+    };
+    if first >= last {
+        return None;
+    }
+    Some((lines[first].0, lines[last - 1].1))
+}
+
+// The level of a `# heading` line, 1 to 6
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(char::is_whitespace))).then_some(level)
+}
+
+// ih / ah: the section of the heading the cursor is under, up to the next
+// heading of the same level or a higher one. ah takes the heading line too,
+// ih just what's under it. A count goes out to the heading's parents, so
+// `2dah` deletes the section a subsection is in. `#` lines in code blocks
+// aren't headings.
+fn section(text: &str, pos: usize, scope: Scope, count: usize) -> Option<(usize, usize)> {
+    let lines = line_spans(text);
+    let mut in_code = vec![false; lines.len()];
+    for (open, close) in code_blocks(text, &lines) {
+        in_code[open..(close + 1).min(lines.len())].fill(true);
+    }
+    let level = |line: usize| if in_code[line] { None } else { heading_level(&text[lines[line].0..lines[line].1]) };
+
+    let current = line_at(&lines, pos);
+    let mut heading = (0..=current).rev().find(|&line| level(line).is_some())?;
+    for _ in 1..count {
+        let inner = level(heading)?;
+        match (0..heading).rev().find(|&line| level(line).is_some_and(|outer| outer < inner)) {
+            Some(parent) => heading = parent,
+            None => break,
+        }
+    }
+    let heading_level = level(heading)?;
+    let end = (heading + 1..lines.len()).find(|&line| level(line).is_some_and(|next| next <= heading_level)).unwrap_or(lines.len());
+    let first = if scope == Scope::Inner { heading + 1 } else { heading };
+    if first >= end {
+        return None;
+    }
+    Some((lines[first].0, lines[end - 1].1))
+}
+
+// Where the text of a list item starts on its line, after the `-`, `*`,
+// `+`, `1.` or `1)` marker and a `[ ]` or `[x]` task box, if the line is one
+fn item_text_start(line: &str) -> Option<usize> {
+    let marker_start = indent(line);
+    let rest = &line[marker_start..];
+    let marker = if rest.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 || !rest[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        digits + 1
+    };
+    let after = &rest[marker..];
+    if !after.starts_with([' ', '\t']) {
+        return None;
+    }
+    let mut start = line.len() - after.trim_start_matches([' ', '\t']).len();
+    if ["[ ] ", "[x] ", "[X] "].iter().any(|task| line[start..].starts_with(task)) {
+        start += 4;
+    }
+    Some(start)
+}
+
+// il / al: the list item the cursor is on, or the one whose more indented
+// lines it's on. al takes the item's lines with the items nested under it;
+// il takes the item's text after its marker, up to its first nested item,
+// leaving the marker for `cil` to write a new item on. A count goes out to
+// the item's parents.
+fn list_item(text: &str, pos: usize, scope: Scope, count: usize) -> Option<(usize, usize)> {
+    let lines = line_spans(text);
+    let line = |i: usize| &text[lines[i].0..lines[i].1];
+    let blank = |i: usize| line(i).trim().is_empty();
+    let is_item = |i: usize| item_text_start(line(i)).is_some();
+    // The nearest item above `i` that's less indented, not looking past a
+    // paragraph that isn't in a list
+    let enclosing = |i: usize| {
+        for above in (0..i).rev() {
+            if is_item(above) && indent(line(above)) < indent(line(i)) {
+                return Some(above);
+            }
+            if !blank(above) && !is_item(above) && indent(line(above)) == 0 {
+                return None;
+            }
+        }
+        None
+    };
+
+    let current = line_at(&lines, pos);
+    if blank(current) {
+        return None;
+    }
+    let mut item = if is_item(current) { current } else { enclosing(current)? };
+    for _ in 1..count {
+        match enclosing(item) {
+            Some(parent) => item = parent,
+            None => break,
+        }
+    }
+
+    // The item's lines go on while they're more indented than its marker;
+    // blank lines only belong to it if more of it follows
+    let item_indent = indent(line(item));
+    let mut last = item; // Its last line
+    let mut own_last = item; // Its last line before a nested item
+    let mut nested = false;
+    for next in item + 1..lines.len() {
+        if blank(next) {
+            continue;
+        }
+        if indent(line(next)) <= item_indent {
+            break;
+        }
+        nested = nested || is_item(next);
+        if !nested {
+            own_last = next;
+        }
+        last = next;
+    }
+
+    match scope {
+        Scope::Around => Some((lines[item].0, lines[last].1)),
+        Scope::Inner => {
+            let from = lines[item].0 + item_text_start(line(item))?;
+            let to = lines[own_last].0 + line(own_last).trim_end_matches(['\n', '\r']).len();
+            Some((from, to.max(from)))
+        },
+    }
+}
@@ -0,0 +1,76 @@
+// Notes that aren't UTF-8, like ones written by older editors on Windows.
+// They're read as UTF-16 when they start with its byte order mark, as UTF-8
+// with the broken bytes replaced when most of them is UTF-8, and as
+// Windows-1252 (Latin-1 with curly quotes and the like) otherwise. The note is
+// opened read-only, so nothing is written back until it's converted to UTF-8.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf16Le,
+    Utf16Be,
+    BrokenUtf8, // UTF-8 with bytes that aren't
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::BrokenUtf8 => "UTF-8 with invalid bytes",
+            Encoding::Windows1252 => "Windows-1252",
+        }
+    }
+}
+
+// The text of a file that isn't valid UTF-8, and what it was read as
+pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return (decode_utf16(rest, u16::from_le_bytes), Encoding::Utf16Le);
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return (decode_utf16(rest, u16::from_be_bytes), Encoding::Utf16Be);
+    }
+    // Characters that are valid UTF-8 and not ASCII mean the rest is broken
+    // UTF-8 rather than another encoding
+    let has_utf8 = bytes.utf8_chunks().any(|chunk| !chunk.valid().is_ascii());
+    if has_utf8 {
+        return (String::from_utf8_lossy(bytes).into_owned(), Encoding::BrokenUtf8);
+    }
+    (bytes.iter().map(|&byte| windows_1252(byte)).collect(), Encoding::Windows1252)
+}
+
+// Save a note's text, unless it was read as `encoding`: the decoded text
+// would replace the bytes it came from, which is what converting does, with
+// a backup, so this refuses whatever the note's options say
+pub fn write_note(path: &Path, text: &str, encoding: Option<Encoding>) -> io::Result<()> {
+    if let Some(encoding) = encoding {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("it was read as {}; :convert-utf8 to save it as UTF-8", encoding.name()),
+        ));
+    }
+    fs::write(path, text)
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+// Windows-1252 is Latin-1 except for 0x80-0x9F; the five bytes it leaves
+// undefined are kept as the control characters Latin-1 has there
+fn windows_1252(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+        '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
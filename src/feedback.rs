@@ -0,0 +1,88 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::platform;
+
+// Optional feedback when something happens that's easy to miss: a sound, a
+// flash of the window, or both. Each event is set in config.toml's
+// [feedback] as "sound", "flash" or "flash sound", and is off when unset.
+
+const FLASH: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedbackConfig {
+    pub save_failed: Option<String>,
+    pub insert_mode: Option<String>, // Entering and leaving insert mode
+    pub pomodoro: Option<String>,    // A `:pomodoro` timer running out
+    // Command line that plays the sound; the system's alert sound when unset
+    pub sound_command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    SaveFailed,
+    InsertMode,
+    PomodoroEnded,
+}
+
+#[derive(Debug, Default)]
+pub struct Feedback {
+    flash_until: Option<Instant>,
+}
+
+impl Feedback {
+    // Give the feedback configured for `event`, if any
+    pub fn notify(&mut self, config: &FeedbackConfig, event: Event) {
+        let kinds = match event {
+            Event::SaveFailed => &config.save_failed,
+            Event::InsertMode => &config.insert_mode,
+            Event::PomodoroEnded => &config.pomodoro,
+        };
+        for kind in kinds.as_deref().unwrap_or("").split_whitespace() {
+            match kind {
+                "flash" => self.flash_until = Some(Instant::now() + FLASH),
+                "sound" => {
+                    if let Err(err) = play_sound(config.sound_command.as_deref()) {
+                        println!("Could not play a sound: {}", err);
+                    }
+                },
+                other => println!("Unknown feedback \"{}\" in config, expected flash or sound", other),
+            }
+        }
+    }
+
+    // Draw the flash over the whole window while it lasts
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let until = match self.flash_until {
+            Some(until) if Instant::now() < until => until,
+            _ => {
+                self.flash_until = None;
+                return;
+            },
+        };
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("feedback_flash")));
+        let color = if ctx.style().visuals.dark_mode {
+            egui::Color32::from_white_alpha(40)
+        } else {
+            egui::Color32::from_black_alpha(30)
+        };
+        painter.rect_filled(ctx.screen_rect(), 0.0, color);
+        ctx.request_repaint_after(until.saturating_duration_since(Instant::now()));
+    }
+}
+
+// In the background, so a slow player doesn't hold up the frame
+fn play_sound(command: Option<&str>) -> std::io::Result<()> {
+    let command = match command {
+        Some(command) => command,
+        None => return platform::play_alert_sound(),
+    };
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        Some(program) => Command::new(program).args(parts).spawn().map(|_| ()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,193 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::importers::feed::{self, FeedItem};
+use crate::platform;
+use crate::templates;
+
+// Background reader for the RSS/Atom feeds listed in the config. New items
+// become notes in feeds/, or entries in a daily digest note, so they can be
+// read and processed like any other note.
+
+pub const FEEDS_DIR: &str = "feeds";
+
+// Item ids remembered per feed; anything older has dropped out of the feed by then
+const SEEN_ITEMS_PER_FEED: usize = 500;
+
+pub enum FeedEvent {
+    NewItems { feed: String, items: Vec<FeedItem> },
+    Failed(String),
+}
+
+pub struct FeedReader {
+    events: Receiver<FeedEvent>,
+    refresh: Sender<()>,
+}
+
+impl FeedReader {
+    // Fetch the feeds now and then every `interval` on a background thread
+    pub fn start(urls: Vec<String>, interval: Duration, seen_file: PathBuf, ctx: egui::Context) -> Self {
+        let (sender, events) = mpsc::channel();
+        let (refresh, refresh_requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut seen = SeenItems::load(&seen_file);
+            loop {
+                for url in &urls {
+                    let event = match fetch_new_items(url, &mut seen) {
+                        Ok(Some(event)) => event,
+                        Ok(None) => continue,
+                        Err(err) => FeedEvent::Failed(format!("Could not fetch feed {}: {}", url, err)),
+                    };
+                    if sender.send(event).is_err() {
+                        return; // The app is gone
+                    }
+                    ctx.request_repaint();
+                }
+                if let Err(err) = seen.save(&seen_file) {
+                    println!("Could not write {}: {}", seen_file.display(), err);
+                }
+
+                match refresh_requests.recv_timeout(interval) {
+                    Ok(()) | Err(RecvTimeoutError::Timeout) => {
+                        // Several `:feeds` in a row only need one fetch
+                        while refresh_requests.try_recv().is_ok() {}
+                    },
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+
+        Self { events, refresh }
+    }
+
+    // Fetch now instead of waiting for the next interval
+    pub fn refresh(&self) {
+        let _ = self.refresh.send(());
+    }
+
+    // Events received since the last call
+    pub fn poll(&self) -> Vec<FeedEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+// Ids of the items already turned into notes, per feed URL
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct SeenItems {
+    feeds: HashMap<String, Vec<String>>,
+}
+
+impl SeenItems {
+    fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid feeds file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+// The items of a feed we haven't seen before, or None if there are none
+fn fetch_new_items(url: &str, seen: &mut SeenItems) -> io::Result<Option<FeedEvent>> {
+    let xml = platform::fetch_url(url)?;
+    if !feed::is_feed(&xml) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an RSS or Atom feed"));
+    }
+    let parsed = feed::parse_feed(&xml);
+
+    let seen_ids = seen.feeds.entry(url.to_string()).or_default();
+    let items: Vec<FeedItem> = parsed.items.into_iter().filter(|item| !seen_ids.contains(&item.id)).collect();
+    seen_ids.extend(items.iter().map(|item| item.id.clone()));
+    let excess = seen_ids.len().saturating_sub(SEEN_ITEMS_PER_FEED);
+    seen_ids.drain(..excess);
+
+    if items.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(FeedEvent::NewItems {
+        feed: parsed.title.unwrap_or_else(|| url.to_string()),
+        items,
+    }))
+}
+
+// Save new items under feeds/ and return the notes that were created or changed.
+// Each item gets its own note unless `digest` is set, in which case they are
+// appended to today's feeds/digest-<date>.md.
+pub fn write_items(notes_dir: &Path, feed: &str, items: &[FeedItem], digest: bool) -> io::Result<Vec<String>> {
+    fs::create_dir_all(notes_dir.join(FEEDS_DIR))?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+    if digest {
+        let note = format!("{}/digest-{}.md", FEEDS_DIR, today);
+        let path = notes_dir.join(&note);
+        let mut content = fs::read_to_string(&path).unwrap_or_else(|_| format!("# Feeds {}\n", today));
+        for item in items {
+            content.push_str(&format!("\n## {}\n\n{}\n", item.title, item_byline(feed, item)));
+            if !item.markdown.is_empty() {
+                content.push_str(&format!("\n{}\n", item.markdown));
+            }
+        }
+        fs::write(&path, content)?;
+        return Ok(vec![note]);
+    }
+
+    let mut notes = Vec::new();
+    // Oldest first so the notes are created in the order the items were published
+    for item in items.iter().rev() {
+        let date = item.published.map_or(today.clone(), |published| published.format("%Y-%m-%d").to_string());
+        let mut slug: String = templates::slugify(&item.title).chars().take(60).collect();
+        if slug.is_empty() {
+            slug = "item".to_string();
+        }
+        let base_name = format!("{}/{}-{}", FEEDS_DIR, date, slug.trim_end_matches('-'));
+        let mut note = format!("{}.md", base_name);
+        let mut counter = 2;
+        while notes_dir.join(&note).exists() {
+            note = format!("{}-{}.md", base_name, counter);
+            counter += 1;
+        }
+
+        let mut content = format!("---\ntitle: {}\nfeed: {}\n", templates::yaml_string(&item.title), templates::yaml_string(feed));
+        if let Some(link) = &item.link {
+            content.push_str(&format!("source: {}\n", templates::yaml_string(link)));
+        }
+        if let Some(published) = item.published {
+            content.push_str(&format!("published: {}\n", published.format("%Y-%m-%d %H:%M")));
+        }
+        content.push_str(&format!("---\n\n# {}\n\n{}\n", item.title, item.markdown));
+        fs::write(notes_dir.join(&note), content)?;
+        notes.push(note);
+    }
+    Ok(notes)
+}
+
+// "*Feed* · 2024-05-01 · [link](...)" line under a digest entry's heading
+fn item_byline(feed: &str, item: &FeedItem) -> String {
+    let mut parts = vec![format!("*{}*", feed)];
+    if let Some(published) = item.published {
+        parts.push(published.format("%Y-%m-%d %H:%M").to_string());
+    }
+    if let Some(link) = &item.link {
+        parts.push(format!("[link]({})", link));
+    }
+    parts.join(" · ")
+}
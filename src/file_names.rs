@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::links;
+
+// How the names of new and renamed notes are written, from the
+// `[file_names]` table of config.toml: `lowercase`, `dashes` for spaces and
+// underscores, and `strip_emoji`. All off by default, so names stay as typed.
+// Only the note's own name changes, not the folders it's in.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileNamesConfig {
+    pub lowercase: bool,
+    pub dashes: bool,
+    pub strip_emoji: bool,
+}
+
+impl FileNamesConfig {
+    // `name` with the options applied, keeping its folders and extension.
+    // A name they'd leave empty is kept as it is.
+    pub fn apply(&self, name: &str) -> String {
+        let (dir, file) = match name.rsplit_once('/') {
+            Some((dir, file)) => (format!("{}/", dir), file),
+            None => (String::new(), name),
+        };
+        let (stem, extension) = split_extension(file);
+
+        let mut slug: String = stem.chars().filter(|c| !(self.strip_emoji && is_emoji(*c))).collect();
+        if self.lowercase {
+            slug = slug.to_lowercase();
+        }
+        if self.dashes {
+            slug = slug
+                .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("-");
+        } else {
+            slug = slug.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        if slug.is_empty() {
+            return name.to_string();
+        }
+        format!("{}{}{}", dir, slug, extension)
+    }
+}
+
+// The stem of a note's file name and its .md or .txt extension, if it has one
+fn split_extension(file: &str) -> (&str, &str) {
+    [".md", ".txt"]
+        .into_iter()
+        .find(|extension| file.len() > extension.len() && file.ends_with(extension))
+        .map_or((file, ""), |extension| file.split_at(file.len() - extension.len()))
+}
+
+// Emoji and the joiners and selectors that build them up
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF // Pictographs, emoticons, transport, flags...
+            | 0x2600..=0x27BF // Miscellaneous symbols and dingbats
+            | 0x2B00..=0x2BFF // Stars, arrows and squares like ⭐ and ⬛
+            | 0x231A..=0x231B | 0x23E9..=0x23FA // ⌚ ⌛ ⏩ ⏰...
+            | 0x200D | 0xFE0E | 0xFE0F | 0x20E3 // Zero-width joiner, variation selectors, keycap
+            | 0xE0020..=0xE007F // Tags of subdivision flags
+    )
+}
+
+// A note name with an extension, .md if it has none of the note ones
+pub fn with_extension(name: &str) -> String {
+    if name.ends_with(".md") || name.ends_with(".txt") {
+        name.to_string()
+    } else {
+        format!("{}.md", name)
+    }
+}
+
+// The note that a note named `name` would clash with: one of `notes` with
+// the same name but for case, which macOS and Windows take for the same
+// file, or a file that's there but not listed
+pub fn collision(notes_dir: &Path, notes: &[String], name: &str) -> Option<String> {
+    notes
+        .iter()
+        .find(|note| links::same_name(note, name))
+        .cloned()
+        .or_else(|| notes_dir.join(name).exists().then(|| name.to_string()))
+}
+
+// Pairs of notes whose names differ only in case; one of each would be lost
+// on macOS or Windows
+pub fn case_clashes(notes: &[String]) -> Vec<(&String, &String)> {
+    let mut by_name: Vec<(String, &String)> = notes.iter().map(|note| (note.to_lowercase(), note)).collect();
+    by_name.sort();
+    by_name.windows(2).filter(|pair| pair[0].0 == pair[1].0).map(|pair| (pair[0].1, pair[1].1)).collect()
+}
+
+// `name`, or the first of name-2, name-3... that doesn't clash with a note
+pub fn numbered(notes_dir: &Path, notes: &[String], name: &str) -> String {
+    let (stem, extension) = split_extension(name);
+    let mut numbered = name.to_string();
+    let mut counter = 2;
+    while collision(notes_dir, notes, &numbered).is_some() {
+        numbered = format!("{}-{}{}", stem, counter, extension);
+        counter += 1;
+    }
+    numbered
+}
@@ -0,0 +1,114 @@
+use eframe::egui;
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+// Global search backed by ripgrep, for vaults too big to read into memory
+// every time the search screen opens. rg runs on a background thread and its
+// JSON output becomes a list of hits, which the search screen filters like
+// its own results and which `:cnext` / `:cprev` step through afterwards.
+
+// A matching line, as in vim's quickfix list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub note: String, // Path relative to the notes folder
+    pub line: usize,  // From 0
+    pub text: String,
+}
+
+struct GrepResult {
+    generation: u64,
+    hits: io::Result<Vec<Hit>>,
+}
+
+pub struct Grep {
+    command: String,
+    sender: Sender<GrepResult>,
+    results: Receiver<GrepResult>,
+    generation: u64, // Of the latest search; older ones' results are dropped
+    pub running: bool,
+}
+
+impl Grep {
+    pub fn new(command: &str) -> Self {
+        let (sender, results) = mpsc::channel();
+        Self { command: command.to_string(), sender, results, generation: 0, running: false }
+    }
+
+    // Whether the command runs at all
+    pub fn is_available(&self) -> bool {
+        Command::new(&self.command)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    // Search the notes for lines with any of `terms`, ignoring case. Results
+    // of searches started before this one are thrown away.
+    pub fn spawn(&mut self, notes_dir: &Path, terms: &[String], trash_dir: &str, ctx: &egui::Context) {
+        self.generation += 1;
+        self.running = true;
+        let generation = self.generation;
+        let command = self.command.clone();
+        let notes_dir = notes_dir.to_path_buf();
+        let terms = terms.to_vec();
+        let trash_dir = trash_dir.to_string();
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let hits = run_ripgrep(&command, &notes_dir, &terms, &trash_dir);
+            let _ = sender.send(GrepResult { generation, hits });
+            ctx.request_repaint();
+        });
+    }
+
+    // The latest search's hits, once it's done
+    pub fn poll(&mut self) -> Option<io::Result<Vec<Hit>>> {
+        let latest = self.results.try_iter().filter(|result| result.generation == self.generation).last()?;
+        self.running = false;
+        Some(latest.hits)
+    }
+}
+
+fn run_ripgrep(command: &str, notes_dir: &Path, terms: &[String], trash_dir: &str) -> io::Result<Vec<Hit>> {
+    let mut rg = Command::new(command);
+    rg.current_dir(notes_dir)
+        .args(["--json", "--ignore-case", "--fixed-strings", "--no-ignore", "--follow"])
+        .args(["--glob", "*.md", "--glob", "*.txt"]);
+    for term in terms {
+        rg.arg("-e").arg(term);
+    }
+    // Hidden folders are skipped like the notes list does, except the trash
+    rg.arg(".");
+    if notes_dir.join(trash_dir).is_dir() {
+        rg.arg(trash_dir);
+    }
+    let output = rg.output()?;
+    // 1 means nothing matched
+    if !output.status.success() && output.status.code() != Some(1) {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(parse_match).collect())
+}
+
+// A `{"type": "match", ...}` line of rg's JSON output. Paths and lines that
+// aren't UTF-8 come base64 encoded and are skipped.
+fn parse_match(line: &str) -> Option<Hit> {
+    let message: Value = serde_json::from_str(line).ok()?;
+    if message["type"] != "match" {
+        return None;
+    }
+    let data = &message["data"];
+    let path = data["path"]["text"].as_str()?.replace('\\', "/");
+    let text = data["lines"]["text"].as_str()?;
+    Some(Hit {
+        note: path.strip_prefix("./").unwrap_or(&path).to_string(),
+        line: (data["line_number"].as_u64()? as usize).saturating_sub(1),
+        text: text.trim().to_string(),
+    })
+}
@@ -0,0 +1,373 @@
+use chrono::{DateTime, FixedOffset};
+use std::io;
+use std::path::Path;
+
+use super::html;
+use crate::attachments;
+use crate::links;
+use crate::templates;
+
+// Import of .eml files and mbox mailboxes. Messages become notes with the
+// sender and date in the frontmatter; attachments are written to assets/.
+
+pub struct EmailMessage {
+    pub subject: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub message_id: Option<String>,
+    pub body: String, // Markdown; HTML-only messages are converted
+    pub attachments: Vec<Attachment>,
+}
+
+pub struct Attachment {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+// Messages in a file, which is either a single .eml message or an mbox
+pub fn parse_file(data: &[u8]) -> Vec<EmailMessage> {
+    if data.starts_with(b"From ") {
+        split_mbox(data).into_iter().map(parse_message).collect()
+    } else {
+        vec![parse_message(data)]
+    }
+}
+
+// An mbox is messages concatenated after "From " separator lines
+fn split_mbox(data: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut start = None;
+    let mut line_start = 0;
+    while line_start < data.len() {
+        let line_end = data[line_start..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| line_start + i + 1);
+        if data[line_start..].starts_with(b"From ") {
+            if let Some(start) = start {
+                messages.push(&data[start..line_start]);
+            }
+            start = Some(line_end);
+        }
+        line_start = line_end;
+    }
+    if let Some(start) = start {
+        messages.push(&data[start..]);
+    }
+    messages
+}
+
+pub fn parse_message(raw: &[u8]) -> EmailMessage {
+    let (head, _) = split_head_body(raw);
+    let headers = parse_headers(&head);
+    let header = |name: &str| header_value(&headers, name).map(decode_header).filter(|v| !v.is_empty());
+
+    let mut text = None;
+    let mut html_body = None;
+    let mut attachments = Vec::new();
+    collect_parts(raw, &mut text, &mut html_body, &mut attachments);
+
+    let body = match (text, html_body) {
+        (Some(text), _) => text.replace("\r\n", "\n").trim().to_string(),
+        (None, Some(html_body)) => html::to_markdown(&html_body, None),
+        (None, None) => String::new(),
+    };
+
+    EmailMessage {
+        subject: header("subject").unwrap_or_else(|| "(no subject)".to_string()),
+        from: header("from").unwrap_or_default(),
+        to: header("to"),
+        date: header("date").and_then(|date| parse_date(&date)),
+        message_id: header("message-id"),
+        body,
+        attachments,
+    }
+}
+
+impl EmailMessage {
+    // Note contents, linking to the attachments saved at `attachment_paths`
+    pub fn to_note(&self, attachment_paths: &[String]) -> String {
+        let mut note = format!("---\ntitle: {}\nfrom: {}\n", templates::yaml_string(&self.subject), templates::yaml_string(&self.from));
+        if let Some(to) = &self.to {
+            note.push_str(&format!("to: {}\n", templates::yaml_string(to)));
+        }
+        if let Some(date) = self.date {
+            note.push_str(&format!("date: {}\n", date.format("%Y-%m-%d %H:%M")));
+        }
+        if let Some(message_id) = &self.message_id {
+            note.push_str(&format!("message-id: {}\n", templates::yaml_string(message_id)));
+        }
+        note.push_str(&format!("---\n\n# {}\n\n{}\n", self.subject, self.body));
+
+        if !attachment_paths.is_empty() {
+            note.push_str("\n## Attachments\n\n");
+            for path in attachment_paths {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                note.push_str(&format!("- [{}]({})\n", name, path.replace(' ', "%20")));
+            }
+        }
+        note
+    }
+
+    // Write the attachments to assets/ and return their paths relative to the
+    // notes folder. A file that's already there with the same contents is reused.
+    pub fn save_attachments(&self, notes_dir: &Path) -> io::Result<Vec<String>> {
+        self.attachments
+            .iter()
+            .map(|attachment| attachments::save(notes_dir, &attachment.file_name, &attachment.data))
+            .collect()
+    }
+}
+
+fn split_head_body(raw: &[u8]) -> (String, &[u8]) {
+    // A part may have no headers at all
+    if raw.starts_with(b"\r\n") || raw.starts_with(b"\n") {
+        return (String::new(), &raw[if raw[0] == b'\r' { 2 } else { 1 }..]);
+    }
+    let (head_end, body_start) = find_bytes(raw, b"\r\n\r\n")
+        .map(|i| (i, i + 4))
+        .into_iter()
+        .chain(find_bytes(raw, b"\n\n").map(|i| (i, i + 2)))
+        .min()
+        .unwrap_or((raw.len(), raw.len()));
+    // Headers are ASCII, apart from the odd raw UTF-8 subject
+    let head = String::from_utf8_lossy(&raw[..head_end]).into_owned();
+    (head, &raw[body_start..])
+}
+
+// Header names (lowercased) and values, with folded lines joined
+fn parse_headers(head: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+// Split `text/plain; charset="utf-8"` into the lowercased value and its parameters
+fn parse_parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let main = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+    let parameters = parts
+        .filter_map(|part| part.split_once('='))
+        .map(|(name, value)| {
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            match name.strip_suffix('*') {
+                // RFC 2231: filename*=UTF-8''na%C3%AFve.pdf
+                Some(name) => {
+                    let value = value.splitn(3, '\'').nth(2).unwrap_or(value);
+                    (name.to_string(), links::percent_decode(value))
+                },
+                None => (name, decode_header(value)),
+            }
+        })
+        .collect();
+    (main, parameters)
+}
+
+fn parameter<'a>(parameters: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    parameters.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+// Walk the MIME tree, keeping the first text/plain and text/html bodies and
+// collecting everything else that has a file name as an attachment
+fn collect_parts(raw: &[u8], text: &mut Option<String>, html_body: &mut Option<String>, attachments: &mut Vec<Attachment>) {
+    let (head, body) = split_head_body(raw);
+    let headers = parse_headers(&head);
+    let (content_type, type_parameters) = parse_parameters(header_value(&headers, "content-type").unwrap_or("text/plain"));
+    let (disposition, disposition_parameters) = parse_parameters(header_value(&headers, "content-disposition").unwrap_or(""));
+
+    if content_type.starts_with("multipart/") {
+        if let Some(boundary) = parameter(&type_parameters, "boundary") {
+            for part in split_multipart(body, boundary) {
+                collect_parts(part, text, html_body, attachments);
+            }
+        }
+        return;
+    }
+
+    let encoding = header_value(&headers, "content-transfer-encoding").unwrap_or("").to_ascii_lowercase();
+    let data = match encoding.as_str() {
+        "base64" => decode_base64(body),
+        "quoted-printable" => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    };
+    let file_name = parameter(&disposition_parameters, "filename")
+        .or_else(|| parameter(&type_parameters, "name"))
+        .map(sanitize_file_name);
+    let charset = parameter(&type_parameters, "charset").unwrap_or("utf-8");
+
+    match (content_type.as_str(), file_name) {
+        ("text/plain", None) if disposition != "attachment" && text.is_none() => {
+            *text = Some(decode_charset(&data, charset));
+        },
+        ("text/html", None) if disposition != "attachment" && html_body.is_none() => {
+            *html_body = Some(decode_charset(&data, charset));
+        },
+        (_, Some(file_name)) => attachments.push(Attachment { file_name, data }),
+        _ if disposition == "attachment" => attachments.push(Attachment {
+            file_name: format!("attachment-{}", attachments.len() + 1),
+            data,
+        }),
+        _ => {},
+    }
+}
+
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..].iter().position(|&b| b == b'\n').map_or(body.len(), |i| line_start + i + 1);
+        let line = &body[line_start..line_end];
+        if line.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = part_start {
+                // The line break before the delimiter belongs to the delimiter
+                let mut end = line_start;
+                if end > start && body[end - 1] == b'\n' {
+                    end -= 1;
+                }
+                if end > start && body[end - 1] == b'\r' {
+                    end -= 1;
+                }
+                parts.push(&body[start..end]);
+            }
+            if line[delimiter.len()..].starts_with(b"--") {
+                break; // Closing delimiter
+            }
+            part_start = Some(line_end);
+        }
+        line_start = line_end;
+    }
+    parts
+}
+
+// Decode RFC 2047 encoded words like =?UTF-8?B?SGVsbG8=?= in a header value
+fn decode_header(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].splitn(4, '?').collect::<Vec<_>>();
+        let word = match decoded[..] {
+            [charset, encoding, encoded, tail] if tail.starts_with('=') => {
+                let data = match encoding.to_ascii_uppercase().as_str() {
+                    "B" => Some(decode_base64(encoded.as_bytes())),
+                    "Q" => Some(decode_quoted_printable(encoded.replace('_', " ").as_bytes())),
+                    _ => None,
+                };
+                data.map(|data| (decode_charset(&data, charset), start + 2 + charset.len() + encoding.len() + encoded.len() + 4))
+            },
+            _ => None,
+        };
+        match word {
+            Some((word, len)) => {
+                // Whitespace between two encoded words is not part of the text
+                let between = &rest[..start];
+                if !(after_word && between.trim().is_empty()) {
+                    out.push_str(between);
+                }
+                out.push_str(&word);
+                rest = &rest[len..];
+                after_word = true;
+            },
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_charset(data: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        // Close enough for windows-1252 too, apart from a few punctuation marks
+        "iso-8859-1" | "latin1" | "latin-1" | "windows-1252" | "cp1252" => data.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+fn decode_base64(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => continue, // Line breaks and padding
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    out
+}
+
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'=' {
+            let rest = &data[i + 1..];
+            if rest.starts_with(b"\r\n") {
+                i += 3; // Soft line break
+                continue;
+            }
+            if rest.starts_with(b"\n") {
+                i += 2;
+                continue;
+            }
+            let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = hex {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(data[i]);
+        i += 1;
+    }
+    out
+}
+
+// Attachment names come from the sender, so keep them to a plain file name
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() { "attachment".to_string() } else { name.to_string() }
+}
+
+// Dates often end in a comment like "(CEST)", which chrono doesn't accept
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.split('(').next().unwrap_or(date).trim();
+    DateTime::parse_from_rfc2822(date).ok()
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
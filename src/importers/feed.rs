@@ -0,0 +1,152 @@
+use chrono::{DateTime, FixedOffset};
+
+use super::html;
+
+// Parsing of RSS (0.9x, 1.0, 2.0) and Atom feeds. The formats are close enough
+// that we just look for <item>/<entry> elements and read whichever of the
+// usual child elements they have.
+
+pub struct Feed {
+    pub title: Option<String>,
+    pub items: Vec<FeedItem>,
+}
+
+pub struct FeedItem {
+    pub id: String, // guid/id, falling back to the link
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+    pub markdown: String, // The item's content or summary
+}
+
+pub fn parse_feed(xml: &str) -> Feed {
+    let mut items = Vec::new();
+    let mut first_item = None;
+    let mut pos = 0;
+
+    while let Some((start, name)) = find_element(xml, pos, &["item", "entry"]) {
+        first_item.get_or_insert(start);
+        let close = format!("</{}>", name);
+        let end = xml[start..].find(&close).map_or(xml.len(), |end| start + end);
+        if let Some(item) = parse_item(&xml[start..end]) {
+            items.push(item);
+        }
+        pos = (end + close.len()).min(xml.len());
+    }
+
+    // The feed's own title comes before the first item
+    let header = &xml[..first_item.unwrap_or(xml.len())];
+    Feed {
+        title: child_text(header, "title").map(|t| collapse_whitespace(&t)).filter(|t| !t.is_empty()),
+        items,
+    }
+}
+
+// Whether this looks like a feed at all, to tell an empty feed from an HTML error page
+pub fn is_feed(xml: &str) -> bool {
+    find_element(xml, 0, &["rss", "feed", "rdf:RDF"]).is_some()
+}
+
+fn parse_item(block: &str) -> Option<FeedItem> {
+    let link = atom_link(block).or_else(|| child_text(block, "link")).filter(|l| !l.is_empty());
+    let title = child_text(block, "title")
+        .map(|t| collapse_whitespace(&t))
+        .filter(|t| !t.is_empty())
+        .or_else(|| link.clone())?;
+    let id = child_text(block, "guid")
+        .or_else(|| child_text(block, "id"))
+        .filter(|id| !id.is_empty())
+        .or_else(|| link.clone())
+        .unwrap_or_else(|| title.clone());
+    let published = ["pubDate", "published", "updated", "dc:date"]
+        .iter()
+        .filter_map(|name| child_text(block, name))
+        .find_map(|date| parse_date(&date));
+    // Full content if the feed has it, otherwise the summary
+    let content = ["content:encoded", "content", "description", "summary"]
+        .iter()
+        .filter_map(|name| child_text(block, name))
+        .find(|content| !content.is_empty())
+        .unwrap_or_default();
+
+    Some(FeedItem {
+        markdown: html::to_markdown(&content, link.as_deref()),
+        id,
+        title,
+        link,
+        published,
+    })
+}
+
+// Atom puts the page URL in <link rel="alternate" href="..."/>, where rel defaults to alternate
+fn atom_link(block: &str) -> Option<String> {
+    let mut pos = 0;
+    while let Some((start, _)) = find_element(block, pos, &["link"]) {
+        let end = start + block[start..].find('>')?;
+        let attributes = &block[start + "<link".len()..end];
+        let rel = html::attribute_value(attributes, "rel");
+        if rel.is_none_or(|rel| rel == "alternate") {
+            if let Some(href) = html::attribute_value(attributes, "href") {
+                return Some(href);
+            }
+        }
+        pos = end;
+    }
+    None
+}
+
+// Position and name of the first of these elements at or after `from`
+fn find_element<'a>(xml: &str, from: usize, names: &[&'a str]) -> Option<(usize, &'a str)> {
+    let mut pos = from;
+    while let Some(found) = xml[pos..].find('<') {
+        let start = pos + found;
+        let rest = &xml[start + 1..];
+        for name in names {
+            let matches = rest.starts_with(name)
+                && rest[name.len()..].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace());
+            if matches {
+                return Some((start, *name));
+            }
+        }
+        pos = start + 1;
+    }
+    None
+}
+
+// Text of the first child element with this name
+fn child_text(block: &str, name: &str) -> Option<String> {
+    let (start, _) = find_element(block, 0, &[name])?;
+    let open_end = start + block[start..].find('>')?;
+    if block[..open_end].ends_with('/') {
+        return Some(String::new());
+    }
+    let content_start = open_end + 1;
+    let content_end = content_start + block[content_start..].find(&format!("</{}>", name))?;
+    Some(unescape(&block[content_start..content_end]).trim().to_string())
+}
+
+// CDATA sections are taken as they are, everything else has its entities decoded
+fn unescape(raw: &str) -> String {
+    let mut out = String::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find("<![CDATA[") {
+        out.push_str(&html::decode_entities(&rest[..start]));
+        let cdata = &rest[start + "<![CDATA[".len()..];
+        let end = cdata.find("]]>").unwrap_or(cdata.len());
+        out.push_str(&cdata[..end]);
+        rest = &cdata[(end + "]]>".len()).min(cdata.len())..];
+    }
+    out.push_str(&html::decode_entities(rest));
+    out
+}
+
+// RSS uses RFC 2822 dates, Atom RFC 3339
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .ok()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,389 @@
+// Turn a web page into markdown. The main content is picked the way a very
+// light readability pass would: the <article> if there is one, else <main>,
+// else the whole <body>, with page chrome (navigation, scripts, forms, ...)
+// dropped before converting the remaining HTML.
+
+pub struct Article {
+    pub title: Option<String>,
+    pub markdown: String,
+}
+
+// Elements whose content is never part of the article
+const SKIPPED_ELEMENTS: [&str; 11] = [
+    "script", "style", "nav", "header", "footer", "aside", "form", "noscript", "svg", "iframe", "button",
+];
+
+pub fn extract_article(html: &str, page_url: Option<&str>) -> Article {
+    let title = meta_content(html, "og:title")
+        .or_else(|| element_inner(html, "title").map(decode_entities))
+        .map(|t| collapse_whitespace(&t))
+        .filter(|t| !t.is_empty());
+    let content = element_inner(html, "article")
+        .or_else(|| element_inner(html, "main"))
+        .or_else(|| element_inner(html, "body"))
+        .unwrap_or(html);
+
+    Article {
+        title,
+        markdown: to_markdown(content, page_url),
+    }
+}
+
+pub fn to_markdown(html: &str, page_url: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut skip_depth: usize = 0;
+    let mut in_pre = false;
+    let mut lists: Vec<Option<usize>> = Vec::new(); // None for <ul>, the next number for <ol>
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut quotes: Vec<usize> = Vec::new(); // Where each open blockquote started in `out`
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let rest = &html[pos..];
+        if rest.starts_with("<!--") {
+            pos += rest.find("-->").map_or(rest.len(), |end| end + 3);
+            continue;
+        }
+        if rest.starts_with('<') {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let tag = Tag::parse(&rest[..end]);
+            pos += end;
+
+            if SKIPPED_ELEMENTS.contains(&tag.name.as_str()) {
+                if tag.closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else if !tag.self_closing {
+                    skip_depth += 1;
+                }
+                continue;
+            }
+            if skip_depth > 0 {
+                continue;
+            }
+
+            match (tag.name.as_str(), tag.closing) {
+                ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                    block_break(&mut out);
+                    let level = tag.name[1..].parse().unwrap_or(1);
+                    out.push_str(&"#".repeat(level));
+                    out.push(' ');
+                },
+                // Paragraphs inside list items stay on the item's line
+                ("p" | "div" | "section" | "article" | "main" | "table" | "tr" | "dl" | "figure" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6", _) if lists.is_empty() => {
+                    block_break(&mut out);
+                },
+                ("br", _) => {
+                    trim_trailing_spaces(&mut out);
+                    out.push('\n');
+                },
+                ("hr", _) => {
+                    block_break(&mut out);
+                    out.push_str("---");
+                    block_break(&mut out);
+                },
+                ("strong" | "b", _) => out.push_str("**"),
+                ("em" | "i", _) => out.push('*'),
+                ("code", _) if !in_pre => out.push('`'),
+                ("pre", false) => {
+                    block_break(&mut out);
+                    out.push_str("```\n");
+                    in_pre = true;
+                },
+                ("pre", true) => {
+                    if !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str("```");
+                    block_break(&mut out);
+                    in_pre = false;
+                },
+                ("blockquote", false) => {
+                    block_break(&mut out);
+                    quotes.push(out.len());
+                },
+                ("blockquote", true) => {
+                    // Quote everything converted since the blockquote started
+                    if let Some(start) = quotes.pop() {
+                        let quoted: Vec<String> = out[start..]
+                            .trim()
+                            .lines()
+                            .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                            .collect();
+                        out.truncate(start);
+                        out.push_str(&quoted.join("\n"));
+                    }
+                    block_break(&mut out);
+                },
+                ("ul" | "ol", false) => {
+                    // Nested lists continue right below their item
+                    if lists.is_empty() {
+                        block_break(&mut out);
+                    }
+                    lists.push(if tag.name == "ol" { Some(1) } else { None });
+                },
+                ("ul" | "ol", true) => {
+                    lists.pop();
+                    if lists.is_empty() {
+                        block_break(&mut out);
+                    }
+                },
+                ("li", false) => {
+                    trim_trailing_spaces(&mut out);
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                    match lists.last_mut() {
+                        Some(Some(number)) => {
+                            out.push_str(&format!("{}. ", number));
+                            *number += 1;
+                        },
+                        _ => out.push_str("- "),
+                    }
+                },
+                ("a", false) => {
+                    let href = tag.attribute("href")
+                        .filter(|href| !href.starts_with('#') && !href.starts_with("javascript:"))
+                        .map(|href| resolve_url(&href, page_url));
+                    if href.is_some() {
+                        out.push('[');
+                    }
+                    links.push(href);
+                },
+                ("a", true) => {
+                    if let Some(Some(href)) = links.pop() {
+                        out.push_str(&format!("]({})", href));
+                    }
+                },
+                ("img", false) => {
+                    if let Some(src) = tag.attribute("src") {
+                        let alt = tag.attribute("alt").unwrap_or_default();
+                        out.push_str(&format!("![{}]({})", alt, resolve_url(&src, page_url)));
+                    }
+                },
+                ("td" | "th", false) => out.push(' '),
+                _ => {},
+            }
+            continue;
+        }
+
+        let end = rest.find('<').unwrap_or(rest.len());
+        if skip_depth == 0 {
+            let text = decode_entities(&rest[..end]);
+            if in_pre {
+                out.push_str(&text);
+            } else {
+                push_collapsed(&mut out, &text);
+            }
+        }
+        pos += end;
+    }
+
+    tidy(&out)
+}
+
+// A parsed start or end tag: name, whether it's an end tag, and its raw attributes
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attributes: String,
+}
+
+impl Tag {
+    fn parse(raw: &str) -> Self {
+        let inner = raw.trim_start_matches('<').trim_end_matches('>');
+        let self_closing = inner.ends_with('/');
+        let inner = inner.trim_end_matches('/').trim();
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, inner),
+        };
+        let name_end = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+        Self {
+            name: inner[..name_end].to_ascii_lowercase(),
+            closing,
+            self_closing,
+            attributes: inner[name_end..].to_string(),
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        attribute_value(&self.attributes, name)
+    }
+}
+
+// Value of `name="..."` (or single-quoted / unquoted) in a tag's attribute text
+pub fn attribute_value(attributes: &str, name: &str) -> Option<String> {
+    let lower = attributes.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(found) = lower[search_from..].find(name) {
+        let start = search_from + found;
+        search_from = start + name.len();
+        let at_word_start = start == 0 || lower[..start].ends_with(char::is_whitespace);
+        let after = attributes[start + name.len()..].trim_start();
+        if !at_word_start || !after.starts_with('=') {
+            continue;
+        }
+        let value = after[1..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(char::is_whitespace).next().unwrap_or(""),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+// Inner HTML of the first element with this tag name (up to its last closing tag)
+fn element_inner<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", tag);
+    let mut search_from = 0;
+    let start = loop {
+        let found = search_from + lower[search_from..].find(&open)?;
+        let next = lower[found + open.len()..].chars().next();
+        if matches!(next, Some('>') | Some(' ') | Some('\t') | Some('\n') | Some('\r')) {
+            break found;
+        }
+        search_from = found + open.len();
+    };
+    let content_start = start + lower[start..].find('>')? + 1;
+    let content_end = lower.rfind(&format!("</{}", tag)).filter(|&end| end >= content_start)?;
+    Some(&html[content_start..content_end])
+}
+
+fn meta_content(html: &str, property: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    lower.match_indices("<meta").find_map(|(start, _)| {
+        let end = start + lower[start..].find('>')?;
+        let attributes = &html[start + "<meta".len()..end];
+        let matches = attribute_value(attributes, "property").or_else(|| attribute_value(attributes, "name"))
+            .is_some_and(|p| p.eq_ignore_ascii_case(property));
+        if matches { attribute_value(attributes, "content") } else { None }
+    })
+}
+
+// Make links and image sources absolute so they still work from the note
+fn resolve_url(href: &str, page_url: Option<&str>) -> String {
+    let page_url = match page_url {
+        Some(url) if !href.contains("://") && !href.starts_with("mailto:") && !href.starts_with("data:") => url,
+        _ => return href.to_string(),
+    };
+    let scheme_end = page_url.find("://").map_or(0, |i| i + 3);
+    if let Some(rest) = href.strip_prefix("//") {
+        return format!("{}{}", &page_url[..scheme_end], rest);
+    }
+    let origin_end = page_url[scheme_end..].find('/').map_or(page_url.len(), |i| scheme_end + i);
+    if href.starts_with('/') {
+        format!("{}{}", &page_url[..origin_end], href)
+    } else {
+        // Relative to the page's folder
+        let base_end = page_url[origin_end..].rfind('/').map_or(page_url.len(), |i| origin_end + i);
+        format!("{}/{}", &page_url[..base_end], href)
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end + 1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                "rsquo" => Some('’'),
+                "lsquo" => Some('‘'),
+                "rdquo" => Some('”'),
+                "ldquo" => Some('“'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            },
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Append text the way a browser shows it: runs of whitespace become one space
+fn push_collapsed(out: &mut String, text: &str) {
+    for (i, word) in text.split_whitespace().enumerate() {
+        let needs_space = i > 0 || text.starts_with(char::is_whitespace);
+        if needs_space && !out.is_empty() && !out.ends_with(char::is_whitespace) && !out.ends_with('[') {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    // Also keeps the space in `<b>a</b> <i>b</i>`, where it's all the text there is
+    if text.ends_with(char::is_whitespace) && !out.is_empty() && !out.ends_with(char::is_whitespace) && !out.ends_with('[') {
+        out.push(' ');
+    }
+}
+
+fn trim_trailing_spaces(out: &mut String) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+}
+
+// End the current block with a blank line
+fn block_break(out: &mut String) {
+    trim_trailing_spaces(out);
+    if out.is_empty() || out.ends_with("\n\n") {
+        return;
+    }
+    out.push_str(if out.ends_with('\n') { "\n" } else { "\n\n" });
+}
+
+// Strip trailing spaces from lines and squeeze runs of blank lines
+fn tidy(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut blank_lines = 0;
+    for line in markdown.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+// A single VEVENT from an iCalendar file, with times in local time
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    pub summary: String,
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+    pub all_day: bool,
+    pub location: Option<String>,
+    pub attendees: Vec<String>,
+}
+
+// Parse the events of an .ics file. Recurrence rules are not expanded,
+// so only the first occurrence of a repeating event is returned.
+pub fn parse_events(ics: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    let mut current: Option<CalendarEvent> = None;
+
+    for line in unfold_lines(ics) {
+        let (name, params, value) = match split_property(&line) {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VEVENT") => {
+                current = Some(CalendarEvent {
+                    summary: String::new(),
+                    start: NaiveDateTime::default(),
+                    end: None,
+                    all_day: false,
+                    location: None,
+                    attendees: Vec::new(),
+                });
+            },
+            ("END", "VEVENT") => {
+                if let Some(event) = current.take() {
+                    if event.start != NaiveDateTime::default() {
+                        events.push(event);
+                    }
+                }
+            },
+            _ => {
+                let event = match current.as_mut() {
+                    Some(event) => event,
+                    None => continue,
+                };
+                match name.as_str() {
+                    "SUMMARY" => event.summary = unescape_text(&value),
+                    "LOCATION" => event.location = Some(unescape_text(&value)).filter(|l| !l.is_empty()),
+                    "DTSTART" => {
+                        if let Some((start, all_day)) = parse_date_time(&value) {
+                            event.start = start;
+                            event.all_day = all_day;
+                        }
+                    },
+                    "DTEND" => event.end = parse_date_time(&value).map(|(end, _)| end),
+                    "ATTENDEE" => {
+                        // Prefer the display name, fall back to the address
+                        let name = params.iter()
+                            .find(|(key, _)| key == "CN")
+                            .map(|(_, cn)| cn.trim_matches('"').to_string())
+                            .unwrap_or_else(|| value.trim_start_matches("mailto:").trim_start_matches("MAILTO:").to_string());
+                        if !name.is_empty() {
+                            event.attendees.push(name);
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    events.sort_by_key(|event| event.start);
+    events
+}
+
+pub fn events_on(events: &[CalendarEvent], date: NaiveDate) -> Vec<&CalendarEvent> {
+    events.iter().filter(|event| event.start.date() == date).collect()
+}
+
+// Long lines are folded onto continuation lines that start with a space or tab
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in ics.lines() {
+        let raw = raw.trim_end_matches('\r');
+        if let Some(continuation) = raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(continuation);
+                continue;
+            }
+        }
+        lines.push(raw.to_string());
+    }
+    lines
+}
+
+// A property's name, its parameters as (KEY, value) and its value
+type Property = (String, Vec<(String, String)>, String);
+
+// Split `NAME;PARAM=x;PARAM2=y:value` into its parts
+fn split_property(line: &str) -> Option<Property> {
+    // The value starts at the first ':' that isn't inside a quoted parameter
+    let mut in_quotes = false;
+    let colon = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        c == ':' && !in_quotes
+    })?.0;
+
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.to_ascii_uppercase();
+    let params = head
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_ascii_uppercase(), value.to_string()))
+        .collect();
+    Some((name, params, line[colon + 1..].to_string()))
+}
+
+// Returns the local date-time and whether it was a date-only (all-day) value
+fn parse_date_time(value: &str) -> Option<(NaiveDateTime, bool)> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local: DateTime<Local> = Utc.from_utc_datetime(&naive).with_timezone(&Local);
+        return Some((local.naive_local(), false));
+    }
+    if let Ok(naive) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        // Floating or TZID times are treated as local time
+        return Some((naive, false));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    Some((date.and_hms_opt(0, 0, 0)?, true))
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
@@ -0,0 +1,4 @@
+pub mod email;
+pub mod feed;
+pub mod html;
+pub mod ics;
@@ -0,0 +1,90 @@
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Instant;
+
+use crate::background::Cancel;
+use crate::links::LinkIndex;
+
+// `:index status` and `:index rebuild` for the link index that backlinks come
+// from. The rebuild reads every note again on a background thread, reporting
+// how far it has got, and the app swaps the new index in when it's done.
+// Cancelling it keeps the old index.
+
+enum RebuildEvent {
+    Progress { done: usize, total: usize },
+    Done(LinkIndex),
+}
+
+pub struct IndexRebuild {
+    events: Receiver<RebuildEvent>,
+    pub done: usize,
+    pub total: usize,
+    pub started: Instant,
+    pub cancel: Cancel,
+}
+
+impl IndexRebuild {
+    pub fn start(notes_dir: &Path, notes_files: Vec<String>, max_size: u64, ctx: &egui::Context) -> Self {
+        let (sender, events) = mpsc::channel();
+        let notes_dir: PathBuf = notes_dir.to_path_buf();
+        let total = notes_files.len();
+        let ctx = ctx.clone();
+        let cancel = Cancel::default();
+        let cancelled = cancel.clone();
+
+        thread::spawn(move || {
+            let mut last_reported = 0;
+            let index = LinkIndex::build_with_progress(&notes_dir, &notes_files, max_size, |done, total| {
+                // Every percent is plenty for the status line
+                if done - last_reported >= (total / 100).max(1) {
+                    last_reported = done;
+                    let _ = sender.send(RebuildEvent::Progress { done, total });
+                    ctx.request_repaint();
+                }
+                !cancelled.is_cancelled()
+            });
+            if let Some(index) = index {
+                let _ = sender.send(RebuildEvent::Done(index));
+                ctx.request_repaint();
+            }
+        });
+        Self { events, done: 0, total, started: Instant::now(), cancel }
+    }
+
+    // The new index once the rebuild is done
+    pub fn poll(&mut self) -> Option<LinkIndex> {
+        for event in self.events.try_iter() {
+            match event {
+                RebuildEvent::Progress { done, total } => {
+                    self.done = done;
+                    self.total = total;
+                },
+                RebuildEvent::Done(index) => return Some(index),
+            }
+        }
+        None
+    }
+}
+
+// One line for the status line, e.g. "Link index: 512 notes, 1830 links, 96 KB,
+// built 09:12:03, updated 09:40:11, up to date"
+pub fn status_text(index: &LinkIndex, pending: &[String]) -> String {
+    let time = |time: Option<chrono::DateTime<chrono::Local>>| time.map_or("never".to_string(), |time| time.format("%H:%M:%S").to_string());
+    let pending_text = match pending {
+        [] => "up to date".to_string(),
+        [one] => format!("1 pending ({})", one),
+        [first, second] => format!("2 pending ({}, {})", first, second),
+        [first, second, ..] => format!("{} pending ({}, {}, ...)", pending.len(), first, second),
+    };
+    format!(
+        "Link index: {} notes, {} links, {} KB, built {}, updated {}, {}",
+        index.note_count(),
+        index.link_count(),
+        index.size().div_ceil(1024),
+        time(index.built_at),
+        time(index.updated_at),
+        pending_text
+    )
+}
@@ -0,0 +1,172 @@
+use eframe::egui;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::background::Cancel;
+
+// External commands run on attachments in the background, like OCR of pasted
+// screenshots or transcription of voice memos. Their output is added to the note below the attachment's link,
+// folded away in a <details> block, so it shows up in searches.
+
+// How often a running command is checked on, to kill it when it's cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobKind {
+    Ocr,
+    Transcription,
+}
+
+impl JobKind {
+    // Shown in the status line while the job runs
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobKind::Ocr => "OCR",
+            JobKind::Transcription => "Transcription",
+        }
+    }
+
+    // Summary line of the block the output goes in
+    fn summary(&self) -> &'static str {
+        match self {
+            JobKind::Ocr => "Text in image",
+            JobKind::Transcription => "Transcript",
+        }
+    }
+}
+
+pub struct Job {
+    pub kind: JobKind,
+    pub note: String,  // Note the attachment is linked from
+    pub asset: String, // Attachment path relative to the notes folder
+}
+
+pub struct JobResult {
+    pub job: Job,
+    pub output: io::Result<String>,
+}
+
+// A job that hasn't finished yet, for the status line
+pub struct RunningJob {
+    pub kind: JobKind,
+    pub asset: String,
+    pub started: Instant,
+    pub cancel: Cancel,
+}
+
+pub struct Jobs {
+    sender: Sender<JobResult>,
+    results: Receiver<JobResult>,
+    pub running: Vec<RunningJob>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        let (sender, results) = mpsc::channel();
+        Self { sender, results, running: Vec::new() }
+    }
+}
+
+impl Jobs {
+    // Run `command` on `file` on a background thread. `{file}` in the command
+    // is replaced by the file's path; without it the path is the last argument.
+    pub fn spawn(&mut self, command: &str, file: PathBuf, job: Job, ctx: &egui::Context) {
+        let command = command.to_string();
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        let cancel = Cancel::default();
+        self.running.push(RunningJob { kind: job.kind, asset: job.asset.clone(), started: Instant::now(), cancel: cancel.clone() });
+
+        thread::spawn(move || {
+            let output = run_command(&command, &file, &cancel);
+            let _ = sender.send(JobResult { job, output });
+            ctx.request_repaint();
+        });
+    }
+
+    // Jobs finished since the last call
+    pub fn poll(&mut self) -> Vec<JobResult> {
+        let results: Vec<JobResult> = self.results.try_iter().collect();
+        for result in &results {
+            if let Some(index) = self.running.iter().position(|running| running.kind == result.job.kind && running.asset == result.job.asset) {
+                self.running.remove(index);
+            }
+        }
+        results
+    }
+}
+
+// The command's output, or an Interrupted error if it was cancelled and killed
+fn run_command(command: &str, file: &Path, cancel: &Cancel) -> io::Result<String> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut args: Vec<std::ffi::OsString> = parts
+        .map(|arg| if arg == "{file}" { file.as_os_str().to_owned() } else { arg.into() })
+        .collect();
+    if !command.contains("{file}") {
+        args.push(file.as_os_str().to_owned());
+    }
+
+    let mut child = Command::new(program).args(&args).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    // Read the output as it comes, so a long transcript can't fill up the
+    // pipe while the command is waited on
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        thread::sleep(CANCEL_CHECK_INTERVAL);
+    };
+
+    let read = |output: thread::JoinHandle<Vec<u8>>| String::from_utf8_lossy(&output.join().unwrap_or_default()).trim().to_string();
+    if !status.success() {
+        return Err(io::Error::other(read(stderr)));
+    }
+    Ok(read(stdout))
+}
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+// Put the output of a job in a collapsed block below the line linking to its
+// attachment. Returns where the block was inserted and its length, or None if
+// the link is no longer in the note.
+pub fn insert_output(content: &mut String, job: &Job, output: &str) -> Option<(usize, usize)> {
+    let link_target = format!("]({})", job.asset.replace(' ', "%20"));
+    let link_pos = content.find(&link_target)?;
+    let line_end = content[link_pos..].find('\n').map(|i| link_pos + i + 1);
+
+    let mut block = format!(
+        "\n<details>\n<summary>{}</summary>\n\n{}\n\n</details>\n",
+        job.kind.summary(),
+        output
+    );
+    let offset = match line_end {
+        Some(offset) => offset,
+        None => {
+            block.insert(0, '\n');
+            content.len()
+        }
+    };
+    content.insert_str(offset, &block);
+    Some((offset, block.len()))
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Note operations that `:undo-vault` can take back, newest last: notes moved
+// to the trash, renamed or moved to another folder (archiving is a move into
+// archive/), and tags merged across notes. Each keeps what's needed to put
+// things back, and the journal is kept in .vimnote/journal.json.
+
+pub const JOURNAL_FILE: &str = "journal.json";
+// Older operations are forgotten
+const MAX_OPERATIONS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Trashed { note: String, trashed_as: String }, // Where it went in the trash
+    Renamed { from: String, to: String },
+    // With each changed note as it was before
+    TagMerged { from: String, into: String, notes: Vec<(String, String)> },
+}
+
+impl Operation {
+    pub fn description(&self) -> String {
+        match self {
+            Operation::Trashed { note, .. } => format!("delete {}", note),
+            Operation::Renamed { from, to } => format!("rename {} to {}", from, to),
+            Operation::TagMerged { from, into, .. } => format!("merge #{} into #{}", from, into),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Journal {
+    pub operations: Vec<Operation>,
+}
+
+impl Journal {
+    // A missing or unreadable file means nothing to undo
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid journal file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        if self.operations.len() > MAX_OPERATIONS {
+            self.operations.remove(0);
+        }
+    }
+}
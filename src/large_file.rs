@@ -0,0 +1,109 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// Notes bigger than the `large_file_mb` setting (a stray log file, say) open
+// in a read-only viewer that reads the file a piece at a time instead of
+// loading all of it into the editor. They're also left out of the link index
+// and only their first lines are searched. "Open fully" loads one anyway.
+
+// Bytes read at a time; whole lines, unless a line is longer than this
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+pub fn is_large(path: &Path, max_size: u64) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() > max_size)
+}
+
+pub struct LargeFile {
+    pub file_name: String,
+    path: PathBuf,
+    pub size: u64,
+    pub offset: u64, // Where the chunk shown starts in the file
+    pub chunk: String,
+    chunk_len: u64, // Bytes of the file the chunk covers
+}
+
+impl LargeFile {
+    pub fn open(notes_dir: &Path, file_name: &str) -> io::Result<Self> {
+        let path = notes_dir.join(file_name);
+        let size = fs::metadata(&path)?.len();
+        let mut file = Self { file_name: file_name.to_string(), path, size, offset: 0, chunk: String::new(), chunk_len: 0 };
+        file.read_at(0, false)?;
+        Ok(file)
+    }
+
+    // Read the chunk at `offset`, or at the first line that starts after it
+    fn read_at(&mut self, offset: u64, line_start: bool) -> io::Result<()> {
+        let mut file = File::open(&self.path)?;
+        self.size = file.metadata()?.len();
+        let offset = offset.min(self.size);
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        file.take(CHUNK_SIZE).read_to_end(&mut bytes)?;
+
+        let at_end = offset + bytes.len() as u64 >= self.size;
+        let start = match bytes.iter().position(|&b| b == b'\n') {
+            Some(newline) if line_start && offset > 0 => newline + 1,
+            _ => 0,
+        };
+        let end = match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(newline) if !at_end && newline + 1 > start => newline + 1,
+            _ => bytes.len(),
+        };
+        self.offset = offset + start as u64;
+        self.chunk_len = (end - start) as u64;
+        // Not all logs are UTF-8, and a long line may be cut in the middle of a character
+        self.chunk = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+        Ok(())
+    }
+
+    pub fn at_start(&self) -> bool {
+        self.offset == 0
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.offset + self.chunk_len >= self.size
+    }
+
+    pub fn next_part(&mut self) -> io::Result<()> {
+        if self.at_end() {
+            return Ok(());
+        }
+        // Chunks end at a line start, or mid-line when it's too long for one
+        self.read_at(self.offset + self.chunk_len, false)
+    }
+
+    pub fn previous_part(&mut self) -> io::Result<()> {
+        if self.at_start() {
+            return Ok(());
+        }
+        self.read_at(self.offset.saturating_sub(CHUNK_SIZE), true)
+    }
+
+    pub fn first_part(&mut self) -> io::Result<()> {
+        self.read_at(0, false)
+    }
+
+    pub fn last_part(&mut self) -> io::Result<()> {
+        self.read_at(self.size.saturating_sub(CHUNK_SIZE), true)
+    }
+
+    // Which part of the file is shown, e.g. "1.5 MB - 1.8 MB of 2.1 GB"
+    pub fn position_text(&self) -> String {
+        format!("{} - {} of {}", format_size(self.offset), format_size(self.offset + self.chunk_len), format_size(self.size))
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A named workspace setup: which side panels are open, the note being edited
+// and the theme. Saved per vault with `:layout save {name}` and restored with
+// `:layout {name}`. Only one note is open at a time, so that's the only buffer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub name: String,
+    pub panels: Vec<String>, // Open side panels, see PANELS
+    pub note: Option<String>,
+    pub line: usize, // Cursor line in the note, 0-based
+    pub dark_mode: bool,
+}
+
+// Side panels a layout can open
+pub const PANELS: [&str; 4] = ["bookmarks", "registers", "marks", "tags"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Layouts {
+    pub items: Vec<Layout>,
+}
+
+impl Layouts {
+    // Load saved layouts; a missing or unreadable file means none
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid layouts file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    // Add a layout, replacing any existing one with the same name
+    pub fn add(&mut self, layout: Layout) {
+        self.items.retain(|l| l.name != layout.name);
+        self.items.push(layout);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.items.len();
+        self.items.retain(|l| l.name != name);
+        self.items.len() != count
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Layout> {
+        self.items.iter().find(|l| l.name == name)
+    }
+
+    // Keep layouts pointing at a note that was renamed
+    pub fn rename_note(&mut self, old_name: &str, new_name: &str) {
+        for layout in self.items.iter_mut().filter(|l| l.note.as_deref() == Some(old_name)) {
+            layout.note = Some(new_name.to_string());
+        }
+    }
+}
@@ -0,0 +1,11 @@
+// The vim editing engine, without the app around it, so benches/ and tests/
+// can drive it directly. main.rs uses these modules from here too.
+pub mod editor;
+pub mod modes;
+pub mod operations;
+pub mod links;
+pub mod large_file;
+pub mod people;
+pub mod platform;
+pub mod encoding;
+pub mod conflicts;
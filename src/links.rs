@@ -0,0 +1,446 @@
+use chrono::{DateTime, Local};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+use crate::large_file;
+
+// Helpers for finding links in note text
+
+// Find the URL at a byte position, either inside a markdown link `[text](url)`,
+// an autolink `<url>`, or a bare `https://...` / `www....` URL
+pub fn url_at(text: &str, pos: usize) -> Option<String> {
+    if pos > text.len() || !text.is_char_boundary(pos) {
+        return None;
+    }
+
+    let line_start = text[..pos].rfind('\n').map(|p| p + 1).unwrap_or(0);
+    let line_end = text[pos..].find('\n').map(|p| pos + p).unwrap_or(text.len());
+    let line = &text[line_start..line_end];
+    let col = pos - line_start;
+
+    markdown_link_target_at(line, col)
+        .filter(|target| looks_like_url(target))
+        .map(|target| with_scheme(&target))
+        .or_else(|| bare_url_at(line, col))
+}
+
+// The `(target)` of a markdown link when `col` is anywhere within `[text](target)`
+pub fn markdown_link_target_at(line: &str, col: usize) -> Option<String> {
+    let mut search_from = 0;
+    while let Some(open_offset) = line[search_from..].find('[') {
+        let open = search_from + open_offset;
+        let close = match line[open..].find("](") {
+            Some(offset) => open + offset,
+            None => break,
+        };
+        let target_start = close + 2;
+        let target_end = match line[target_start..].find(')') {
+            Some(offset) => target_start + offset,
+            None => break,
+        };
+
+        if col >= open && col <= target_end {
+            // Drop an optional link title: [text](url "title")
+            let target = line[target_start..target_end].split_whitespace().next().unwrap_or("");
+            return Some(target.trim_matches(|c| c == '<' || c == '>').to_string());
+        }
+        search_from = target_end + 1;
+    }
+    None
+}
+
+fn bare_url_at(line: &str, col: usize) -> Option<String> {
+    // URLs can't contain whitespace, so take the whitespace-delimited token
+    let token_start = line[..col].rfind(char::is_whitespace).map(|p| p + 1).unwrap_or(0);
+    let token_end = line[col..].find(char::is_whitespace).map(|p| col + p).unwrap_or(line.len());
+    let token = &line[token_start..token_end];
+
+    let url_start = ["https://", "http://", "file://", "mailto:", "www."]
+        .iter()
+        .filter_map(|scheme| token.find(scheme))
+        .min()?;
+    let url = &token[url_start..];
+
+    // Strip wrapping punctuation that is almost never part of the URL itself
+    let url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', '>', '*', '_']);
+    let url = if url.ends_with(')') && !url.contains('(') {
+        url.trim_end_matches(')')
+    } else {
+        url
+    };
+
+    // The cursor has to be on the URL, not on leading punctuation like `(` or `<`
+    if col < token_start + url_start || col > token_start + url_start + url.len() {
+        return None;
+    }
+
+    Some(with_scheme(url))
+}
+
+// Browsers need a scheme, so `www.example.com` becomes `https://www.example.com`
+fn with_scheme(url: &str) -> String {
+    if url.starts_with("www.") {
+        format!("https://{}", url)
+    } else {
+        url.to_string()
+    }
+}
+
+fn looks_like_url(target: &str) -> bool {
+    target.contains("://") || target.starts_with("mailto:") || target.starts_with("www.")
+}
+
+// The file path at a byte position, for `gf`: the target of a markdown link
+// that isn't a web URL (`[Budget](assets/budget.xlsx)`), a `file://` URL, or
+// a relative path like `./q3.pdf`, `../shared/plan.ods` or `assets/a.pdf`.
+// Relative paths are returned as written, for the caller to resolve.
+pub fn file_path_at(text: &str, pos: usize) -> Option<String> {
+    if pos > text.len() || !text.is_char_boundary(pos) {
+        return None;
+    }
+
+    let line_start = text[..pos].rfind('\n').map(|p| p + 1).unwrap_or(0);
+    let line_end = text[pos..].find('\n').map(|p| pos + p).unwrap_or(text.len());
+    let line = &text[line_start..line_end];
+    let col = pos - line_start;
+
+    if let Some(target) = markdown_link_target_at(line, col) {
+        return match target.strip_prefix("file://") {
+            Some(_) => file_url_path(&target),
+            None if looks_like_url(&target) || target.starts_with('#') || target.is_empty() => None,
+            // `notes.md#section` is the note
+            None => Some(target.split('#').next().unwrap_or(&target).replace("%20", " ")),
+        };
+    }
+    if let Some(url) = bare_url_at(line, col) {
+        return url.starts_with("file://").then(|| file_url_path(&url)).flatten();
+    }
+
+    let token_start = line[..col].rfind(char::is_whitespace).map(|p| p + 1).unwrap_or(0);
+    let token_end = line[col..].find(char::is_whitespace).map(|p| col + p).unwrap_or(line.len());
+    let token = line[token_start..token_end]
+        .trim_start_matches(['(', '<', '"', '\'', '`', '*', '_'])
+        .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'', '`', '*', '_']);
+    if token.contains("://") || token.starts_with('/') || token.starts_with("[[") {
+        return None;
+    }
+    // A path needs a folder and a file name with an extension, so that `and/or` isn't one
+    let (folder, file) = token.rsplit_once('/')?;
+    let has_extension = file.rsplit_once('.').is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty());
+    if folder.is_empty() || !has_extension {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+// The path of a `file://` URL: `file:///home/me/a%20b.pdf` is `/home/me/a b.pdf`,
+// and `file:///C:/Users/a.pdf` is `C:/Users/a.pdf`
+fn file_url_path(url: &str) -> Option<String> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let path = percent_decode(path);
+    let is_drive = |path: &str| path.as_bytes().get(2) == Some(&b':') && path.as_bytes()[1].is_ascii_alphabetic();
+    let path = match path.strip_prefix('/') {
+        Some(rest) if is_drive(&path) => rest.to_string(),
+        _ => path,
+    };
+    if path.is_empty() { None } else { Some(path) }
+}
+
+// `%20` and the like back into the characters they stand for; a `%` that isn't
+// followed by two hex digits stays as it is
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit)).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Heading of the generated backlinks section at the bottom of a note
+pub const BACKLINKS_HEADING: &str = "## Backlinks";
+
+// Lines around the generated section. Only what's between them is rewritten,
+// so a `## Backlinks` section the user wrote themselves is left alone.
+pub const BACKLINKS_START: &str = "<!-- backlinks -->";
+pub const BACKLINKS_END: &str = "<!-- /backlinks -->";
+
+// Names of the notes a note links to, taken from `[[wiki links]]` and
+// markdown links to local `.md`/`.txt` files. The generated backlinks
+// section is skipped so backlinks don't turn into links of their own.
+pub fn note_links(text: &str) -> Vec<String> {
+    let body = strip_backlinks_section(text);
+    let mut targets = Vec::new();
+
+    // [[Name]] and [[Name|alias]]
+    let mut rest = body.as_str();
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        match after.find("]]") {
+            Some(end) => {
+                let inner = &after[..end];
+                let name = inner.split('|').next().unwrap_or("").trim();
+                if !name.is_empty() && !name.contains('\n') {
+                    targets.push(name.to_string());
+                }
+                rest = &after[end + 2..];
+            },
+            None => break,
+        }
+    }
+
+    // [text](other.md)
+    for line in body.lines() {
+        let mut col = 0;
+        while let Some(open) = line[col..].find('[') {
+            let open = col + open;
+            match markdown_link_target_at(line, open) {
+                Some(target) => {
+                    if !looks_like_url(&target) && (target.ends_with(".md") || target.ends_with(".txt")) {
+                        targets.push(target.replace("%20", " "));
+                    }
+                    // Continue after this link's closing parenthesis
+                    col = line[open..].find(')').map(|p| open + p + 1).unwrap_or(line.len());
+                },
+                None => col = open + 1,
+            }
+        }
+    }
+
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+// The note without its generated backlinks section
+pub fn strip_backlinks_section(text: &str) -> String {
+    match find_backlinks_section(text) {
+        Some((start, end)) => format!("{}{}", &text[..start], &text[end..]),
+        None => text.to_string(),
+    }
+}
+
+// Byte range of the generated section, end marker and its newline included.
+// A section missing its end marker runs to the end of the note.
+fn find_backlinks_section(text: &str) -> Option<(usize, usize)> {
+    let start = line_starting_with(text, 0, BACKLINKS_START);
+    if let Some(start) = start {
+        let end = line_starting_with(text, start + BACKLINKS_START.len(), BACKLINKS_END)
+            .map(|pos| text[pos..].find('\n').map_or(text.len(), |newline| pos + newline + 1))
+            .unwrap_or(text.len());
+        return Some((start, end));
+    }
+    // Notes from before the markers: a trailing section that's nothing but
+    // the generated list is taken for the generated one
+    let pos = find_backlinks_heading(text)?;
+    let generated = text[pos + BACKLINKS_HEADING.len()..]
+        .lines()
+        .all(|line| line.trim().is_empty() || (line.starts_with("- [[") && line.trim_end().ends_with("]]")));
+    generated.then_some((pos, text.len()))
+}
+
+// Position of the first line from `from` on that starts with `prefix`
+fn line_starting_with(text: &str, from: usize, prefix: &str) -> Option<usize> {
+    let mut pos = from;
+    while pos < text.len() {
+        if (pos == 0 || text.as_bytes()[pos - 1] == b'\n') && text[pos..].starts_with(prefix) {
+            return Some(pos);
+        }
+        pos = text[pos..].find('\n').map(|newline| pos + newline + 1)?;
+    }
+    None
+}
+
+fn find_backlinks_heading(text: &str) -> Option<usize> {
+    if text.starts_with(BACKLINKS_HEADING) {
+        return Some(0);
+    }
+    text.match_indices(&format!("\n{}", BACKLINKS_HEADING))
+        .map(|(pos, _)| pos + 1)
+        // The section has to be the last one in the note
+        .filter(|&pos| !text[pos + BACKLINKS_HEADING.len()..].contains("\n#"))
+        .last()
+}
+
+// Replace (or remove, when there are none) the generated backlinks section.
+// A new one goes at the bottom of the note.
+pub fn with_backlinks_section(text: &str, backlinks: &[String]) -> String {
+    let mut section = String::new();
+    if !backlinks.is_empty() {
+        section.push_str(BACKLINKS_START);
+        section.push('\n');
+        section.push_str(BACKLINKS_HEADING);
+        section.push_str("\n\n");
+        for name in backlinks {
+            section.push_str(&format!("- [[{}]]\n", name));
+        }
+        section.push_str(BACKLINKS_END);
+        section.push('\n');
+    }
+
+    match find_backlinks_section(text) {
+        // Somewhere in the middle of the note it's swapped in place
+        Some((start, end)) if end < text.len() => format!("{}{}{}", &text[..start], section, &text[end..]),
+        Some((start, _)) => with_section_at_end(&text[..start], &section),
+        None if section.is_empty() => text.to_string(),
+        None => with_section_at_end(text, &section),
+    }
+}
+
+fn with_section_at_end(body: &str, section: &str) -> String {
+    let body = body.trim_end_matches('\n');
+    match (body.is_empty(), section.is_empty()) {
+        (true, _) => section.to_string(),
+        (false, true) => format!("{}\n", body),
+        (false, false) => format!("{}\n\n{}", body, section),
+    }
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Display name used when linking to a note: the file name without extension
+pub fn note_stem(file_name: &str) -> &str {
+    file_name
+        .strip_suffix(".md")
+        .or_else(|| file_name.strip_suffix(".txt"))
+        .unwrap_or(file_name)
+}
+
+// Whether two note names or links are the same but for case. macOS and
+// Windows take them for the same file, so links match notes this way to work
+// the same everywhere.
+pub fn same_name(a: &str, b: &str) -> bool {
+    a == b || a.to_lowercase() == b.to_lowercase()
+}
+
+// Outgoing links of every note, used to answer "what links here" queries
+#[derive(Debug, Default)]
+pub struct LinkIndex {
+    notes: HashMap<String, IndexedNote>,
+    pub built_at: Option<DateTime<Local>>,
+    pub updated_at: Option<DateTime<Local>>, // Last time a note was indexed again, e.g. on save
+}
+
+#[derive(Debug)]
+struct IndexedNote {
+    links: Vec<String>,
+    hash: u64,             // Of the content indexed, to tell whether the file has changed since
+    indexed_at: SystemTime,
+}
+
+impl LinkIndex {
+    // Notes bigger than `max_size` bytes are left out
+    pub fn build(notes_dir: &Path, notes_files: &[String], max_size: u64) -> Self {
+        Self::build_with_progress(notes_dir, notes_files, max_size, |_, _| true).unwrap_or_default()
+    }
+
+    // Like `build`, calling `progress` with how many notes are done out of how
+    // many. The build stops when `progress` returns false, and gives None.
+    pub fn build_with_progress(notes_dir: &Path, notes_files: &[String], max_size: u64, mut progress: impl FnMut(usize, usize) -> bool) -> Option<Self> {
+        let start = Instant::now();
+        let mut index = Self::default();
+        for (done, file_name) in notes_files.iter().enumerate() {
+            let path = notes_dir.join(file_name);
+            if !large_file::is_large(&path, max_size) {
+                if let Ok(content) = fs::read_to_string(&path) {
+                    index.update(file_name, &content);
+                }
+            }
+            if !progress(done + 1, notes_files.len()) {
+                return None;
+            }
+        }
+        index.built_at = Some(Local::now());
+        index.updated_at = None;
+        println!("Built link index in {:?}", start.elapsed());
+        Some(index)
+    }
+
+    pub fn update(&mut self, file_name: &str, content: &str) {
+        self.notes.insert(file_name.to_string(), IndexedNote {
+            links: note_links(content),
+            hash: content_hash(content),
+            indexed_at: SystemTime::now(),
+        });
+        self.updated_at = Some(Local::now());
+    }
+
+    // What `file_name` linked to when it was last indexed
+    pub fn links(&self, file_name: &str) -> &[String] {
+        self.notes.get(file_name).map_or(&[], |note| &note.links)
+    }
+
+    pub fn note_count(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn link_count(&self) -> usize {
+        self.notes.values().map(|note| note.links.len()).sum()
+    }
+
+    // Rough memory used by the names and links, in bytes
+    pub fn size(&self) -> usize {
+        self.notes
+            .iter()
+            .map(|(name, note)| name.len() + note.links.iter().map(String::len).sum::<usize>() + std::mem::size_of::<IndexedNote>())
+            .sum()
+    }
+
+    // Notes whose file has changed since they were indexed, new notes, and
+    // notes that are gone but still indexed. Only files modified after they
+    // were indexed are read, and notes bigger than `max_size` aren't indexed.
+    pub fn pending(&self, notes_dir: &Path, notes_files: &[String], max_size: u64) -> Vec<String> {
+        let mut pending: Vec<String> = notes_files
+            .iter()
+            .filter(|file_name| !large_file::is_large(&notes_dir.join(file_name), max_size))
+            .filter(|file_name| match self.notes.get(*file_name) {
+                None => true,
+                Some(note) => {
+                    let path = notes_dir.join(file_name);
+                    let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+                    match modified {
+                        Ok(modified) if modified <= note.indexed_at => false,
+                        _ => fs::read_to_string(&path).map_or(true, |content| content_hash(&content) != note.hash),
+                    }
+                },
+            })
+            .cloned()
+            .collect();
+        pending.extend(self.notes.keys().filter(|name| !notes_files.contains(name)).cloned());
+        pending.sort();
+        pending
+    }
+
+    // Notes linking to `file_name`, sorted by name
+    pub fn backlinks(&self, file_name: &str) -> Vec<String> {
+        let stem = note_stem(file_name);
+        let mut sources: Vec<String> = self.notes
+            .iter()
+            .filter(|(source, _)| source.as_str() != file_name)
+            .filter(|(_, note)| note.links.iter().any(|t| same_name(t, file_name) || same_name(t, stem)))
+            .map(|(source, _)| note_stem(source).to_string())
+            .collect();
+        sources.sort();
+        sources
+    }
+}
@@ -0,0 +1,270 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use eframe::egui;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::background::Cancel;
+use crate::importers::html;
+use crate::templates;
+
+// A small HTTP API on 127.0.0.1 for browser extensions and scripts. It is
+// off unless `api_port` is set in the config, and every request must send
+// `api_token` in an `X-VimNote-Token` header; one is made up and saved in the
+// config the first time the API starts without it. Any web page can send
+// requests to 127.0.0.1, so a request from a browser page is refused unless
+// its `Origin` is one of `api_origins` (the clipper extension's), and only
+// those origins get CORS headers.
+//
+//   POST /clip  {"url": "...", "html": "...", "title": "..."}
+//       Save the page's main content as markdown in clippings/
+
+pub const CLIPPINGS_DIR: &str = "clippings";
+
+// Pages are big, but not this big
+const MAX_BODY_BYTES: usize = 20 * 1024 * 1024;
+
+// Something the app should tell the user about
+pub enum ApiEvent {
+    Clipped(String), // Note path relative to the notes folder
+    Failed(String),
+}
+
+pub struct LocalApi {
+    events: Receiver<ApiEvent>,
+    port: u16,
+    stop: Cancel,
+}
+
+#[derive(Deserialize)]
+struct ClipRequest {
+    url: String,
+    html: String,
+    title: Option<String>,
+}
+
+impl LocalApi {
+    // Start listening on a background thread; `ctx` is used to wake the UI when something arrives
+    pub fn start(port: u16, token: String, origins: Vec<String>, notes_dir: PathBuf, ctx: egui::Context) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (sender, events) = mpsc::channel();
+        let stop = Cancel::default();
+
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if stopped.is_cancelled() {
+                    break;
+                }
+                if let Some(event) = handle_connection(stream, &notes_dir, &token, &origins) {
+                    if sender.send(event).is_err() {
+                        break; // The app is gone
+                    }
+                    ctx.request_repaint();
+                }
+            }
+        });
+
+        println!("Local API listening on 127.0.0.1:{}", port);
+        Ok(Self { events, port, stop })
+    }
+
+    // Events received since the last call
+    pub fn poll(&self) -> Vec<ApiEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+// Stop listening, e.g. for `:set offline`. The thread is waiting for a
+// connection, so make one for it to see that it should stop.
+impl Drop for LocalApi {
+    fn drop(&mut self) {
+        self.stop.cancel();
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        println!("Local API on 127.0.0.1:{} stopped", self.port);
+    }
+}
+
+// A new `api_token`: 32 random bytes as hex
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    origin: Option<String>, // Sent by browsers, not by scripts
+    body: Vec<u8>,
+}
+
+fn handle_connection(mut stream: TcpStream, notes_dir: &Path, token: &str, origins: &[String]) -> Option<ApiEvent> {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let request = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(err) => {
+            respond(&mut stream, "400 Bad Request", None, &error_json(&err.to_string()));
+            return None;
+        }
+    };
+
+    // A page on some web site, or an extension that isn't allowed
+    let origin = match &request.origin {
+        Some(origin) if origins.contains(origin) => Some(origin.as_str()),
+        Some(_) => {
+            respond(&mut stream, "403 Forbidden", None, &error_json("origin not in api_origins"));
+            return None;
+        },
+        None => None,
+    };
+    if request.method == "OPTIONS" {
+        // CORS preflight from the clipper extension
+        respond(&mut stream, "204 No Content", origin, "");
+        return None;
+    }
+    if request.token.as_deref() != Some(token) {
+        respond(&mut stream, "401 Unauthorized", origin, &error_json("missing or wrong X-VimNote-Token"));
+        return None;
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/clip") => {
+            let clip: ClipRequest = match serde_json::from_slice(&request.body) {
+                Ok(clip) => clip,
+                Err(err) => {
+                    respond(&mut stream, "400 Bad Request", origin, &error_json(&err.to_string()));
+                    return None;
+                }
+            };
+            if clip.url.chars().any(char::is_control) {
+                respond(&mut stream, "400 Bad Request", origin, &error_json("url contains control characters"));
+                return None;
+            }
+            match save_clipping(notes_dir, &clip) {
+                Ok(note) => {
+                    respond(&mut stream, "201 Created", origin, &serde_json::json!({ "note": note }).to_string());
+                    Some(ApiEvent::Clipped(note))
+                },
+                Err(err) => {
+                    respond(&mut stream, "500 Internal Server Error", origin, &error_json(&err.to_string()));
+                    Some(ApiEvent::Failed(format!("Could not save clipping from {}: {}", clip.url, err)))
+                }
+            }
+        },
+        _ => {
+            respond(&mut stream, "404 Not Found", origin, &error_json("unknown endpoint"));
+            None
+        }
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line")),
+    };
+
+    let mut content_length = 0;
+    let mut token = None;
+    let mut origin = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                "x-vimnote-token" => token = Some(value.trim().to_string()),
+                "origin" => origin = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { method, path, token, origin, body })
+}
+
+// `origin`: an allowed origin the request came from, which gets CORS headers
+fn respond(stream: &mut TcpStream, status: &str, origin: Option<&str>, body: &str) {
+    let cors = origin
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\n\
+                 Vary: Origin\r\n\
+                 Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+                 Access-Control-Allow-Headers: Content-Type, X-VimNote-Token\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         {}\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        cors,
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+// Write the clipped page to clippings/<date>-<slug>.md, never overwriting an
+// existing clipping. The note is marked read-only so it can be annotated.
+fn save_clipping(notes_dir: &Path, clip: &ClipRequest) -> io::Result<String> {
+    let article = html::extract_article(&clip.html, Some(&clip.url));
+    let title = clip.title.clone()
+        .filter(|t| !t.trim().is_empty())
+        .or(article.title)
+        .unwrap_or_else(|| clip.url.clone());
+    // A newline in the title would end its frontmatter line or the heading
+    let title = title.split(char::is_control).filter(|part| !part.is_empty()).collect::<Vec<_>>().join(" ");
+
+    let now = chrono::Local::now();
+    let mut slug: String = templates::slugify(&title).chars().take(60).collect();
+    if slug.is_empty() {
+        slug = "clipping".to_string();
+    }
+    fs::create_dir_all(notes_dir.join(CLIPPINGS_DIR))?;
+    let base_name = format!("{}/{}-{}", CLIPPINGS_DIR, now.format("%Y-%m-%d"), slug.trim_end_matches('-'));
+    let mut note = format!("{}.md", base_name);
+    let mut counter = 2;
+    while notes_dir.join(&note).exists() {
+        note = format!("{}-{}.md", base_name, counter);
+        counter += 1;
+    }
+
+    let content = format!(
+        "---\ntitle: {}\nsource: {}\nclipped: {}\nreadonly: true\n---\n\n# {}\n\n{}\n",
+        templates::yaml_string(&title),
+        templates::yaml_string(&clip.url),
+        now.format("%Y-%m-%d %H:%M"),
+        title,
+        article.markdown
+    );
+    fs::write(notes_dir.join(&note), content)?;
+    Ok(note)
+}
@@ -0,0 +1,178 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// A lock screen for shared machines, so nobody reads the notes over your
+// shoulder while you're away. It isn't encryption: the notes stay as they
+// are on disk. The vault's passphrase, set with `:lock set`, is kept as an
+// argon2 hash in .vimnote/lock.json. `:lock` locks right away, and with
+// `lock_after_minutes` in config.toml the app locks after that long without
+// input; either way the window is blank until the passphrase is typed.
+
+pub const LOCK_FILE: &str = "lock.json";
+
+#[derive(Serialize, Deserialize)]
+struct LockFile {
+    hash: String, // PHC string, "$argon2id$v=19$..."
+}
+
+// The vault's passphrase hash, if it has one
+pub fn load(data_dir: &Path) -> Option<String> {
+    let json = fs::read_to_string(data_dir.join(LOCK_FILE)).ok()?;
+    match serde_json::from_str::<LockFile>(&json) {
+        Ok(lock) => Some(lock.hash),
+        Err(err) => {
+            println!("Invalid lock file: {}", err);
+            None
+        },
+    }
+}
+
+// Hash `passphrase` with a new salt and keep it as the vault's
+pub fn set_passphrase(data_dir: &Path, passphrase: &str) -> io::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(passphrase.as_bytes(), &salt)
+        .map_err(|err| io::Error::other(err.to_string()))?
+        .to_string();
+    fs::create_dir_all(data_dir)?;
+    let json = serde_json::to_string_pretty(&LockFile { hash: hash.clone() }).map_err(io::Error::other)?;
+    fs::write(data_dir.join(LOCK_FILE), json)?;
+    Ok(hash)
+}
+
+pub fn remove(data_dir: &Path) -> io::Result<()> {
+    match fs::remove_file(data_dir.join(LOCK_FILE)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+pub fn verify(hash: &str, passphrase: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(passphrase.as_bytes(), &hash).is_ok())
+}
+
+// Locks after `after` without input
+pub struct IdleTimer {
+    after: Option<Duration>,
+    last_input: Instant,
+}
+
+impl IdleTimer {
+    pub fn new(minutes: Option<u64>) -> Self {
+        Self { after: minutes.filter(|&minutes| minutes > 0).map(|minutes| Duration::from_secs(minutes * 60)), last_input: Instant::now() }
+    }
+
+    // Start counting again, e.g. after unlocking
+    pub fn reset(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    // Call every frame; true once it's been idle long enough
+    pub fn check(&mut self, ctx: &egui::Context) -> bool {
+        let after = match self.after {
+            Some(after) => after,
+            None => return false,
+        };
+        if ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving()) {
+            self.last_input = Instant::now();
+        }
+        let idle = self.last_input.elapsed();
+        if idle >= after {
+            return true;
+        }
+        // Wake up to lock even if nothing else happens
+        ctx.request_repaint_after(after - idle);
+        false
+    }
+}
+
+// The blank window with a passphrase field, while locked
+#[derive(Default)]
+pub struct LockScreen {
+    passphrase: String,
+    wrong: bool,
+}
+
+impl LockScreen {
+    // True once the right passphrase is typed
+    pub fn show(&mut self, ctx: &egui::Context, hash: &str) -> bool {
+        let mut unlocked = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 3.0);
+                ui.heading("Locked");
+                ui.add_space(8.0);
+                let field = ui.add(egui::TextEdit::singleline(&mut self.passphrase).password(true).hint_text("Passphrase"));
+                field.request_focus();
+                if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    unlocked = verify(hash, &self.passphrase);
+                    self.wrong = !unlocked;
+                    self.passphrase.clear();
+                }
+                if self.wrong {
+                    ui.colored_label(ui.visuals().error_fg_color, "Wrong passphrase");
+                }
+            });
+        });
+        unlocked
+    }
+}
+
+// `:lock set`: the new passphrase, typed twice
+#[derive(Default)]
+pub struct PassphraseDialog {
+    passphrase: String,
+    again: String,
+    mismatch: bool,
+}
+
+pub enum DialogResult {
+    Open,
+    Cancelled,
+    Set(String),
+}
+
+impl PassphraseDialog {
+    pub fn show(&mut self, ctx: &egui::Context) -> DialogResult {
+        let mut result = DialogResult::Open;
+        let mut open = true;
+        egui::Window::new("Lock passphrase")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("The passphrase that unlocks this vault after :lock or being idle");
+                let first = ui.add(egui::TextEdit::singleline(&mut self.passphrase).password(true).hint_text("Passphrase"));
+                if self.passphrase.is_empty() && self.again.is_empty() {
+                    first.request_focus();
+                }
+                let second = ui.add(egui::TextEdit::singleline(&mut self.again).password(true).hint_text("Again"));
+                if self.mismatch {
+                    ui.colored_label(ui.visuals().error_fg_color, "The two don't match");
+                }
+                let entered = second.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                ui.horizontal(|ui| {
+                    if (ui.button("Set").clicked() || entered) && !self.passphrase.is_empty() {
+                        if self.passphrase == self.again {
+                            result = DialogResult::Set(std::mem::take(&mut self.passphrase));
+                        } else {
+                            self.mismatch = true;
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        result = DialogResult::Cancelled;
+                    }
+                });
+            });
+        if !open {
+            result = DialogResult::Cancelled;
+        }
+        result
+    }
+}
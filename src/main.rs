@@ -1,16 +1,71 @@
-mod modes;
-mod operations;
-mod editor;
 mod app;
+mod note_options;
+mod config;
+mod templates;
+mod importers;
+mod bookmarks;
+mod annotations;
+mod local_api;
+mod feeds;
+mod attachments;
+mod jobs;
+mod markdown;
+mod blocks;
+mod views;
+mod diagrams;
+mod tasks;
+mod site;
+mod trash;
+mod maintenance;
+mod settings;
+mod backups;
+mod tutor;
+mod onboarding;
+mod feedback;
+mod pomodoro;
+mod journal;
+mod search;
+mod grep;
+mod index;
+mod tags;
+mod layouts;
+mod startup;
+mod file_names;
+mod vault;
+mod background;
+mod recovery;
+mod portable;
+mod lock;
+mod redaction;
+mod access_log;
+mod projects;
+mod recurring;
+mod citations;
+
+// The editing engine lives in the library so benches/ and tests/ can use it
+use minimalist_notes::{conflicts, editor, encoding, large_file, links, modes, operations, people, platform};
 
 use app::NotesApp;
+use config::Config;
+use onboarding::Onboarding;
+use startup::StartupProfile;
 use std::path::PathBuf;
 use eframe::egui;
 
 fn main() -> Result<(), eframe::Error> {
+    // `--profile-startup` prints how long each part of starting up took
+    let mut profile = std::env::args().any(|arg| arg == "--profile-startup").then(StartupProfile::new);
+    
     // Set up logging
     env_logger::init();
     
+    // `--portable`, or a portable.toml next to the executable, keeps
+    // everything next to it; decided before the config is loaded
+    portable::init(std::env::args().any(|arg| arg == "--portable"));
+    
+    // Unsaved edits are written out if the app panics
+    recovery::install_panic_hook();
+    
     // Application options
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1024.0, 768.0)),
@@ -19,10 +74,11 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
     
-    // Create application with notes directory in user's documents folder
-    let notes_dir = dirs::document_dir()
-        .unwrap_or_else(|| std::env::current_dir().unwrap())
-        .join("vimnote");
+    // Notes go where config.toml says, or in the user's documents folder
+    // (next to the executable when portable)
+    let notes_dir = Config::load().notes_dir.map(portable::resolve).unwrap_or_else(portable::default_notes_dir);
+    // Without a config.toml yet, ask how to set things up first
+    let onboarding = Config::is_missing().then(|| Onboarding::new(&notes_dir));
     
     eframe::run_native(
         "VimNote",
@@ -37,7 +93,16 @@ fn main() -> Result<(), eframe::Error> {
             // Enable global dark mode by default
             cc.egui_ctx.set_visuals(egui::Visuals::dark());
             
-            Box::new(NotesApp::new(notes_dir))
+            if let Some(profile) = &mut profile {
+                profile.record("window");
+            }
+            let mut app = NotesApp::new(notes_dir);
+            app.onboarding = onboarding;
+            if let Some(profile) = &mut profile {
+                profile.record("app state");
+            }
+            app.startup_profile = profile;
+            Box::new(app)
         }),
     )
 }
\ No newline at end of file
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::large_file;
+
+// Keeps what's stored next to the notes in .vimnote/ from growing without
+// bound: trashed notes expire after a while, and the trash, the undo
+// histories and the backups can be capped in size, the oldest files going
+// first. Runs when the notes are loaded if any limit is set, and with
+// `:maintenance`.
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub trash_age: Option<Duration>,
+    pub trash_size: Option<u64>,   // Bytes
+    pub history_size: Option<u64>, // Bytes
+    pub backup_size: Option<u64>,  // Bytes
+}
+
+impl Limits {
+    pub fn any(&self) -> bool {
+        self.trash_age.is_some() || self.trash_size.is_some() || self.history_size.is_some() || self.backup_size.is_some()
+    }
+}
+
+// What a run removed
+#[derive(Debug, Default)]
+pub struct Report {
+    pub trashed_notes: usize,
+    pub histories: usize,
+    pub backups: usize,
+    pub bytes: u64,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.trashed_notes == 0 && self.histories == 0 && self.backups == 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Nothing to clean up");
+        }
+        write!(
+            f,
+            "Reclaimed {}: {} trashed note(s), {} undo history file(s), {} backup(s)",
+            large_file::format_size(self.bytes),
+            self.trashed_notes,
+            self.histories,
+            self.backups
+        )
+    }
+}
+
+struct StoredFile {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+pub fn run(trash_dir: &Path, history_dir: &Path, backups_dir: &Path, limits: &Limits) -> Report {
+    let now = SystemTime::now();
+    let expired = |file: &StoredFile| {
+        limits
+            .trash_age
+            .is_some_and(|age| now.duration_since(file.modified).is_ok_and(|elapsed| elapsed > age))
+    };
+    let (trashed_notes, trash_bytes) = remove_oldest(trash_dir, limits.trash_size, expired);
+    remove_empty_folders(trash_dir);
+    let (histories, history_bytes) = remove_oldest(history_dir, limits.history_size, |_| false);
+    let (backups, backup_bytes) = remove_oldest(backups_dir, limits.backup_size, |_| false);
+    remove_empty_folders(backups_dir);
+
+    Report {
+        trashed_notes,
+        histories,
+        backups,
+        bytes: trash_bytes + history_bytes + backup_bytes,
+    }
+}
+
+// Remove the files in `dir`, oldest first, that are expired or that don't
+// fit in `max_size` together. Returns how many were removed and their size.
+fn remove_oldest(dir: &Path, max_size: Option<u64>, expired: impl Fn(&StoredFile) -> bool) -> (usize, u64) {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files);
+    files.sort_by_key(|file| file.modified);
+
+    let mut total: u64 = files.iter().map(|file| file.len).sum();
+    let mut removed = (0, 0);
+    for file in files {
+        let over_size = max_size.is_some_and(|max| total > max);
+        if !over_size && !expired(&file) {
+            continue;
+        }
+        match fs::remove_file(&file.path) {
+            Ok(()) => {
+                total -= file.len;
+                removed.0 += 1;
+                removed.1 += file.len;
+            },
+            Err(err) => println!("Could not remove {}: {}", file.path.display(), err),
+        }
+    }
+    removed
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<StoredFile>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if let (Ok(file_type), Ok(metadata)) = (entry.file_type(), entry.metadata()) {
+                if file_type.is_dir() {
+                    collect_files(&entry.path(), files);
+                } else if file_type.is_file() {
+                    files.push(StoredFile {
+                        path: entry.path(),
+                        len: metadata.len(),
+                        modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Folders of trashed notes or backups that are empty now; `dir` itself is kept
+fn remove_empty_folders(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                remove_empty_folders(&entry.path());
+                // Fails if something is still in it
+                let _ = fs::remove_dir(entry.path());
+            }
+        }
+    }
+}
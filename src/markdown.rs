@@ -0,0 +1,617 @@
+use crate::citations;
+use crate::tasks::{self, SectionProgress};
+use crate::templates;
+
+// Markdown to HTML for exports. Covers what notes are written with: headings,
+// paragraphs, lists with task boxes, blockquotes, fenced code, tables, rules,
+// inline HTML (like the <details> blocks OCR adds) and inline emphasis, code,
+// links, images, [[wiki links]] and `@citekey` citations.
+//
+// How an export wants links and code blocks rendered
+pub struct Hooks<'a> {
+    // Href for a link or image pointing inside the notes folder, or None for
+    // a link to a note that isn't there (rendered as plain text). URLs are
+    // kept as they are.
+    pub resolve: &'a dyn Fn(&str) -> Option<String>,
+    // HTML for a fenced code block given its language and contents, or None
+    // to show it as code
+    pub code_block: &'a dyn Fn(&str, &str) -> Option<String>,
+    // The entry an `@citekey` cites, or None if it isn't in the bibliography
+    // (rendered as text, like an @mention)
+    pub cite: &'a dyn Fn(&str) -> Option<Cited>,
+}
+
+// An entry of the bibliography as it's cited in the text
+pub struct Cited {
+    pub key: String, // As the bibliography has it, for the link to its reference
+    pub authors: String,
+    pub year: String,
+}
+
+impl Cited {
+    fn link(&self, text: &str) -> String {
+        format!("<a class=\"citation\" href=\"#ref-{}\">{}</a>", escape_html(&self.key), escape_html(text))
+    }
+}
+
+pub fn to_html(markdown: &str, hooks: &Hooks) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut html = String::new();
+    // Headings get the progress of the tasks in their section
+    let sections = tasks::section_progress(markdown);
+    render_blocks(&lines, hooks, &sections, &mut html);
+    html
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The note without its leading `---` frontmatter block
+pub fn strip_frontmatter(content: &str) -> &str {
+    if !content.starts_with("---") {
+        return content;
+    }
+    let mut offset = content.find('\n').map_or(content.len(), |pos| pos + 1);
+    while offset < content.len() {
+        let line_end = content[offset..].find('\n').map_or(content.len(), |pos| offset + pos + 1);
+        let line = content[offset..line_end].trim();
+        if line == "---" || line == "..." {
+            return &content[line_end..];
+        }
+        offset = line_end;
+    }
+    content
+}
+
+// The text of the first `# heading`, if the note has one
+pub fn first_heading(markdown: &str) -> Option<&str> {
+    markdown
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(str::trim)
+        .filter(|heading| !heading.is_empty())
+}
+
+// `sections` are the task counts of headings by line; nested blocks have none
+fn render_blocks(lines: &[&str], hooks: &Hooks, sections: &[SectionProgress], html: &mut String) {
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+
+        if trimmed.is_empty() || is_comment(trimmed) {
+            i += 1;
+        } else if let Some(fence) = code_fence(trimmed) {
+            // Everything up to the closing fence, verbatim
+            let language = trimmed[fence.len()..].trim();
+            let end = lines[i + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with(fence))
+                .map_or(lines.len(), |pos| i + 1 + pos);
+            let code = lines[i + 1..end].join("\n");
+            match (hooks.code_block)(language, &code) {
+                Some(block) => html.push_str(&block),
+                None => {
+                    if language.is_empty() {
+                        html.push_str("<pre><code>");
+                    } else {
+                        html.push_str(&format!("<pre><code class=\"language-{}\">", escape_html(language)));
+                    }
+                    html.push_str(&escape_html(&code));
+                    if !code.is_empty() {
+                        html.push('\n');
+                    }
+                    html.push_str("</code></pre>\n");
+                },
+            }
+            i = end + 1;
+        } else if let Some((level, heading)) = heading(trimmed) {
+            let progress = sections
+                .iter()
+                .find(|section| section.line == i)
+                .map(|section| format!(" <span class=\"progress\">{}</span>", section.label()))
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<h{level} id=\"{}\">{}{}</h{level}>\n",
+                templates::slugify(heading),
+                render_inline(heading, hooks),
+                progress
+            ));
+            i += 1;
+        } else if is_rule(trimmed) {
+            html.push_str("<hr>\n");
+            i += 1;
+        } else if trimmed.starts_with('>') {
+            let end = block_end(lines, i, |l| l.trim_start().starts_with('>'));
+            let quoted: Vec<&str> = lines[i..end]
+                .iter()
+                .map(|l| {
+                    let l = l.trim_start().trim_start_matches('>');
+                    l.strip_prefix(' ').unwrap_or(l)
+                })
+                .collect();
+            html.push_str("<blockquote>\n");
+            render_blocks(&quoted, hooks, &[], html);
+            html.push_str("</blockquote>\n");
+            i = end;
+        } else if list_marker(line).is_some() {
+            i = render_list(lines, i, hooks, html);
+        } else if i + 1 < lines.len() && trimmed.contains('|') && is_table_separator(lines[i + 1]) {
+            let end = block_end(lines, i + 2, |l| l.contains('|'));
+            render_table(&lines[i..end], hooks, html);
+            i = end;
+        } else if is_html_block(trimmed) {
+            // Passed through until the next blank line
+            let end = block_end(lines, i, |l| !l.trim().is_empty());
+            html.push_str(&lines[i..end].join("\n"));
+            html.push('\n');
+            i = end;
+        } else {
+            let end = paragraph_end(lines, i);
+            html.push_str("<p>");
+            html.push_str(&render_paragraph(&lines[i..end], hooks));
+            html.push_str("</p>\n");
+            i = end;
+        }
+    }
+}
+
+// Index of the first line from `start` on that doesn't match
+fn block_end(lines: &[&str], start: usize, matches: impl Fn(&str) -> bool) -> usize {
+    lines[start..].iter().position(|l| !matches(l)).map_or(lines.len(), |pos| start + pos)
+}
+
+fn paragraph_end(lines: &[&str], start: usize) -> usize {
+    let mut end = start + 1;
+    while end < lines.len() {
+        let trimmed = lines[end].trim_start();
+        let starts_block = trimmed.is_empty()
+            || code_fence(trimmed).is_some()
+            || heading(trimmed).is_some()
+            || trimmed.starts_with('>')
+            || list_marker(lines[end]).is_some()
+            || is_html_block(trimmed)
+            || is_comment(trimmed);
+        if starts_block {
+            break;
+        }
+        end += 1;
+    }
+    end
+}
+
+// Lines joined back together; two trailing spaces or a backslash break the line
+fn render_paragraph(lines: &[&str], hooks: &Hooks) -> String {
+    let mut html = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        let hard_break = line.ends_with("  ") || line.ends_with('\\');
+        let text = line.trim().trim_end_matches('\\');
+        html.push_str(&render_inline(text, hooks));
+        if index + 1 < lines.len() {
+            html.push_str(if hard_break { "<br>\n" } else { "\n" });
+        }
+    }
+    html
+}
+
+pub fn code_fence(line: &str) -> Option<&'static str> {
+    if line.starts_with("```") {
+        Some("```")
+    } else if line.starts_with("~~~") {
+        Some("~~~")
+    } else {
+        None
+    }
+}
+
+// Level and text of a `# heading` line
+pub fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None; // #tag, not a heading
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|&rule| chars.iter().all(|&c| c == rule))
+}
+
+fn is_html_block(line: &str) -> bool {
+    let tag: String = line
+        .strip_prefix('<')
+        .map(|rest| rest.trim_start_matches('/').chars().take_while(|c| c.is_ascii_alphanumeric()).collect())
+        .unwrap_or_default();
+    const BLOCK_TAGS: [&str; 12] = ["details", "summary", "div", "table", "figure", "p", "section", "aside", "pre", "ul", "ol", "img"];
+    BLOCK_TAGS.contains(&tag.to_ascii_lowercase().as_str())
+}
+
+// A one-line `<!-- comment -->`, like the ones around generated backlinks.
+// It's left out of the page.
+fn is_comment(line: &str) -> bool {
+    line.starts_with("<!--") && line.trim_end().ends_with("-->")
+}
+
+// (indent, ordered, width of the marker including the space after it)
+fn list_marker(line: &str) -> Option<(usize, bool, usize)> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    if let Some(after) = rest.strip_prefix("- ").or(rest.strip_prefix("* ")).or(rest.strip_prefix("+ ")) {
+        // `* * *` is a rule, not a list
+        if is_rule(rest) {
+            return None;
+        }
+        return Some((indent, false, rest.len() - after.len()));
+    }
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && digits < 10 {
+        let after = &rest[digits..];
+        if after.starts_with(". ") || after.starts_with(") ") {
+            return Some((indent, true, digits + 2));
+        }
+    }
+    None
+}
+
+// Render the list starting at `start` and return the index after it
+fn render_list(lines: &[&str], start: usize, hooks: &Hooks, html: &mut String) -> usize {
+    let (indent, ordered, _) = list_marker(lines[start]).unwrap_or((0, false, 2));
+    let mut items: Vec<Vec<String>> = Vec::new();
+    let mut loose = false;
+    let mut i = start;
+
+    while i < lines.len() {
+        let line = lines[i];
+        match list_marker(line) {
+            Some((item_indent, item_ordered, width)) if item_indent == indent && item_ordered == ordered => {
+                items.push(vec![line[item_indent + width..].to_string()]);
+                i += 1;
+            },
+            _ if line.trim().is_empty() => {
+                // A blank line either separates items of a loose list or ends the list
+                let next = lines[i + 1..].iter().find(|l| !l.trim().is_empty());
+                let continues = next.is_some_and(|next| {
+                    let next_indent = next.len() - next.trim_start().len();
+                    next_indent > indent || list_marker(next).is_some_and(|(n, o, _)| n == indent && o == ordered)
+                });
+                if !continues {
+                    break;
+                }
+                loose = true;
+                if let Some(item) = items.last_mut() {
+                    item.push(String::new());
+                }
+                i += 1;
+            },
+            _ => {
+                let line_indent = line.len() - line.trim_start().len();
+                let continuation = line_indent > indent || list_marker(line).is_none() && !lines[i - 1].trim().is_empty() && !is_comment(line);
+                if !continuation || items.is_empty() {
+                    break;
+                }
+                // Nested content is dedented so it parses as blocks of its own
+                let dedent = line_indent.min(indent + 2);
+                if let Some(item) = items.last_mut() {
+                    item.push(line[dedent..].to_string());
+                }
+                i += 1;
+            },
+        }
+    }
+
+    let tag = if ordered { "ol" } else { "ul" };
+    let first_number: usize = lines[start].trim_start().chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap_or(1);
+    if ordered && first_number != 1 {
+        html.push_str(&format!("<ol start=\"{}\">\n", first_number));
+    } else {
+        html.push_str(&format!("<{}>\n", tag));
+    }
+    for item in items {
+        let mut first = item[0].as_str();
+        let checkbox = if let Some(rest) = first.strip_prefix("[ ] ") {
+            first = rest;
+            Some(false)
+        } else if let Some(rest) = first.strip_prefix("[x] ").or(first.strip_prefix("[X] ")) {
+            first = rest;
+            Some(true)
+        } else {
+            None
+        };
+        match checkbox {
+            Some(done) => html.push_str(&format!(
+                "<li class=\"task\"><input type=\"checkbox\" disabled{}> ",
+                if done { " checked" } else { "" }
+            )),
+            None => html.push_str("<li>"),
+        }
+
+        let lines: Vec<&str> = std::iter::once(first).chain(item[1..].iter().map(String::as_str)).collect();
+        let nested = lines[1..].iter().any(|l| l.trim().is_empty() || list_marker(l).is_some() || code_fence(l.trim_start()).is_some());
+        if loose || nested {
+            // Tight items keep their first paragraph inline
+            let first_end = paragraph_end(&lines, 0);
+            if loose {
+                html.push_str("<p>");
+            }
+            html.push_str(&render_paragraph(&lines[..first_end], hooks));
+            if loose {
+                html.push_str("</p>");
+            }
+            html.push('\n');
+            render_blocks(&lines[first_end..], hooks, &[], html);
+        } else {
+            html.push_str(&render_paragraph(&lines, hooks));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str(&format!("</{}>\n", tag));
+    i
+}
+
+fn is_table_separator(line: &str) -> bool {
+    let cells = table_cells(line);
+    !cells.is_empty()
+        && cells.iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn table_cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+fn render_table(lines: &[&str], hooks: &Hooks, html: &mut String) {
+    let alignments: Vec<&str> = table_cells(lines[1])
+        .iter()
+        .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => " style=\"text-align: center\"",
+            (false, true) => " style=\"text-align: right\"",
+            _ => "",
+        })
+        .collect();
+    let row = |line: &str, cell_tag: &str| {
+        let mut row = String::from("<tr>");
+        for (index, cell) in table_cells(line).iter().enumerate() {
+            let align = alignments.get(index).copied().unwrap_or("");
+            row.push_str(&format!("<{cell_tag}{align}>{}</{cell_tag}>", render_inline(cell, hooks)));
+        }
+        row.push_str("</tr>\n");
+        row
+    };
+
+    html.push_str("<table>\n<thead>\n");
+    html.push_str(&row(lines[0], "th"));
+    html.push_str("</thead>\n<tbody>\n");
+    for line in &lines[2..] {
+        html.push_str(&row(line, "td"));
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+fn is_url(target: &str) -> bool {
+    target.contains("://") || target.starts_with("mailto:") || target.starts_with('#')
+}
+
+fn link_href(target: &str, hooks: &Hooks) -> Option<String> {
+    if is_url(target) {
+        Some(target.to_string())
+    } else {
+        let (path, anchor) = match target.split_once('#') {
+            Some((path, anchor)) => (path, format!("#{}", anchor)),
+            None => (target, String::new()),
+        };
+        (hooks.resolve)(&path.replace("%20", " ")).map(|href| format!("{}{}", href, anchor))
+    }
+}
+
+// `[@key]`, `[@key, p. 3]` or `[@one; -@two]` at the start of `text` as
+// "(Smith 2020, p. 3)", with its length. `-@` leaves out the authors. None
+// unless every key is in the bibliography.
+fn bracketed_citation(text: &str, hooks: &Hooks) -> Option<(String, usize)> {
+    let end = text.find(']')?;
+    let inner = &text[1..end];
+    if inner.contains(['[', '\n']) {
+        return None;
+    }
+    let mut cites = Vec::new();
+    for part in inner.split(';') {
+        let part = part.trim();
+        let (suppress_author, after) = match part.strip_prefix("-@") {
+            Some(after) => (true, after),
+            None => (false, part.strip_prefix('@')?),
+        };
+        let key = citations::key_at_start(after);
+        let cited = (hooks.cite)(key).filter(|_| !key.is_empty())?;
+        let label = if suppress_author { cited.year.clone() } else { format!("{} {}", cited.authors, cited.year) };
+        let locator = after[key.len()..].trim_start_matches([',', ' ']);
+        let mut cite = cited.link(&label);
+        if !locator.is_empty() {
+            cite.push_str(&format!(", {}", escape_html(locator)));
+        }
+        cites.push(cite);
+    }
+    Some((format!("({})", cites.join("; ")), end + 1))
+}
+
+// `[text](target)` or `[text](target "title")` at the start of `text`:
+// (text, target, length of the whole link)
+fn parse_link(text: &str) -> Option<(&str, &str, usize)> {
+    let mut depth = 0;
+    let mut label_end = None;
+    for (index, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    label_end = Some(index);
+                    break;
+                }
+            },
+            _ => {},
+        }
+    }
+    let label_end = label_end?;
+    let after = text[label_end + 1..].strip_prefix('(')?;
+    let close = after.find(')')?;
+    let target = after[..close].split(" \"").next().unwrap_or("").trim();
+    let target = target.strip_prefix('<').and_then(|t| t.strip_suffix('>')).unwrap_or(target);
+    Some((&text[1..label_end], target, label_end + 2 + close + 1))
+}
+
+fn render_inline(text: &str, hooks: &Hooks) -> String {
+    let mut html = String::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap_or(' ');
+
+        if c == '\\' && rest[1..].starts_with(|next: char| next.is_ascii_punctuation()) {
+            html.push_str(&escape_html(&rest[1..2]));
+            i += 2;
+            continue;
+        }
+        if c == '`' {
+            let ticks = rest.chars().take_while(|&c| c == '`').count();
+            if let Some(end) = rest[ticks..].find(&"`".repeat(ticks)) {
+                html.push_str(&format!("<code>{}</code>", escape_html(rest[ticks..ticks + end].trim())));
+                i += ticks * 2 + end;
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix("[[") {
+            if let Some(end) = after.find("]]").filter(|&end| !after[..end].contains('\n')) {
+                let inner = &after[..end];
+                let (name, label) = inner.split_once('|').unwrap_or((inner, inner));
+                match (hooks.resolve)(name.trim()) {
+                    Some(href) => html.push_str(&format!("<a href=\"{}\">{}</a>", escape_html(&href), escape_html(label.trim()))),
+                    None => html.push_str(&format!("<span class=\"missing-link\">{}</span>", escape_html(label.trim()))),
+                }
+                i += end + 4;
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix('!') {
+            if let Some((alt, target, len)) = after.starts_with('[').then(|| parse_link(after)).flatten() {
+                let src = link_href(target, hooks).unwrap_or_else(|| target.to_string());
+                html.push_str(&format!("<img src=\"{}\" alt=\"{}\">", escape_html(&src), escape_html(alt)));
+                i += len + 1;
+                continue;
+            }
+        }
+        if c == '[' {
+            if let Some((citation, len)) = bracketed_citation(rest, hooks) {
+                html.push_str(&citation);
+                i += len;
+                continue;
+            }
+            if let Some((label, target, len)) = parse_link(rest) {
+                let label_html = render_inline(label, hooks);
+                match link_href(target, hooks) {
+                    Some(href) => html.push_str(&format!("<a href=\"{}\">{}</a>", escape_html(&href), label_html)),
+                    None => html.push_str(&format!("<span class=\"missing-link\">{}</span>", label_html)),
+                }
+                i += len;
+                continue;
+            }
+        }
+        if c == '<' {
+            // <https://autolinks> and inline tags like <kbd> pass through
+            if let Some(end) = rest.find('>') {
+                let inner = &rest[1..end];
+                if is_url(inner) && !inner.contains(' ') {
+                    html.push_str(&format!("<a href=\"{0}\">{0}</a>", escape_html(inner)));
+                    i += end + 1;
+                    continue;
+                }
+                if inner.trim_start_matches('/').starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    html.push_str(&rest[..=end]);
+                    i += end + 1;
+                    continue;
+                }
+            }
+        }
+        if c == '@' && citations::is_citation_start(text, i) {
+            let key = citations::key_at_start(&rest[1..]);
+            if let Some(cited) = (!key.is_empty()).then(|| (hooks.cite)(key)).flatten() {
+                html.push_str(&cited.link(&format!("{} ({})", cited.authors, cited.year)));
+                i += key.len() + 1;
+                continue;
+            }
+        }
+        if rest.starts_with("https://") || rest.starts_with("http://") {
+            let end = rest.find(|c: char| c.is_whitespace() || c == '<').unwrap_or(rest.len());
+            let url = rest[..end].trim_end_matches(['.', ',', ')', ';', ':', '!', '?']);
+            html.push_str(&format!("<a href=\"{0}\">{0}</a>", escape_html(url)));
+            i += url.len();
+            continue;
+        }
+        if let Some((tag, delimiter)) = [("strong", "**"), ("strong", "__"), ("del", "~~"), ("em", "*"), ("em", "_")]
+            .into_iter()
+            .find(|(_, delimiter)| rest.starts_with(delimiter))
+        {
+            if let Some(len) = emphasis(text, i, delimiter) {
+                let inner = &rest[delimiter.len()..len - delimiter.len()];
+                html.push_str(&format!("<{tag}>{}</{tag}>", render_inline(inner, hooks)));
+                i += len;
+                continue;
+            }
+            // Not emphasis; keep the delimiter as text
+            html.push_str(delimiter);
+            i += delimiter.len();
+            continue;
+        }
+
+        html.push_str(&escape_html(&rest[..c.len_utf8()]));
+        i += c.len_utf8();
+    }
+    html
+}
+
+// Length of the emphasis starting at `start` if it has a closing delimiter.
+// Like markdown, the text must not start or end with a space, and `_` only
+// works at word boundaries so snake_case stays as it is.
+fn emphasis(text: &str, start: usize, delimiter: &str) -> Option<usize> {
+    let inner_start = start + delimiter.len();
+    let inner = &text[inner_start..];
+    if inner.starts_with(char::is_whitespace) || inner.is_empty() {
+        return None;
+    }
+    if delimiter.starts_with('_') && text[..start].ends_with(|c: char| c.is_alphanumeric()) {
+        return None;
+    }
+    let mut search = 0;
+    while let Some(pos) = inner[search..].find(delimiter) {
+        let close = search + pos;
+        let after = &inner[close + delimiter.len()..];
+        let not_closing = inner[..close].ends_with(char::is_whitespace)
+            // `**` inside `*...*` isn't its closing `*`
+            || delimiter.len() == 1 && after.starts_with(delimiter)
+            || delimiter.starts_with('_') && after.starts_with(|c: char| c.is_alphanumeric());
+        if close > 0 && !not_closing {
+            return Some(delimiter.len() * 2 + close);
+        }
+        search = close + delimiter.len();
+    }
+    None
+}
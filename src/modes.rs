@@ -3,11 +3,17 @@ pub enum AppMode {
     List,   // Navigating the notes list
     Editor, // Editing a note
     Rename, // Renaming a note
+    TemplatePicker, // Choosing a template for a new note
+    Search, // Searching all notes
+    Projects, // The projects board
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VimMode {
     Normal,
     Insert,
+    Replace, // Typing over the text, started with R
     Command,
+    Visual, // Character-wise selection started with v
+    VisualBlock, // Column selection started with Ctrl+V
 } 
\ No newline at end of file
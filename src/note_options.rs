@@ -0,0 +1,168 @@
+// Per-note editor options that are read from the note itself, either from
+// YAML-style frontmatter keys or from a vim-style modeline near the top/bottom.
+// They're resolved in this order, later ones winning: built-in defaults, the
+// config's `[options]`, its `[folders.<name>]` from the outermost folder in,
+// frontmatter, modelines, and `:set` while the note is open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteOptions {
+    pub lang: Option<String>,
+    pub spell: bool,
+    pub textwidth: Option<usize>,
+    pub shiftwidth: usize, // Spaces for >> and <<
+    pub wrap: bool,
+    pub readonly: bool, // Text can't be edited, only annotated
+    pub ignorecase: bool, // Searches ignore case
+    pub smartcase: bool, // ...unless the pattern has capitals
+}
+
+impl Default for NoteOptions {
+    fn default() -> Self {
+        Self {
+            lang: None,
+            spell: false,
+            textwidth: None,
+            shiftwidth: 2,
+            wrap: true,
+            readonly: false,
+            ignorecase: false,
+            smartcase: false,
+        }
+    }
+}
+
+// Vim only looks at this many lines at the start and end of a file for modelines
+const MODELINE_SCAN_LINES: usize = 5;
+
+impl NoteOptions {
+    // The note's own options, on top of the defaults from config for its folder
+    pub fn from_content(defaults: Self, content: &str) -> Self {
+        let mut options = defaults;
+        options.apply_frontmatter(content);
+        // Modelines win over frontmatter, same as vim applies them last
+        options.apply_modelines(content);
+        options
+    }
+
+    // Parse `key: value` pairs between the leading `---` fences
+    fn apply_frontmatter(&mut self, content: &str) {
+        let mut lines = content.lines();
+        if lines.next().map(|l| l.trim_end()) != Some("---") {
+            return;
+        }
+
+        for line in lines {
+            let line = line.trim();
+            if line == "---" || line == "..." {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim().trim_matches('"').trim_matches('\'');
+                self.set_option(key.trim(), Some(value));
+            }
+        }
+    }
+
+    fn apply_modelines(&mut self, content: &str) {
+        let lines: Vec<&str> = content.lines().collect();
+        let head = lines.len().min(MODELINE_SCAN_LINES);
+        let tail_start = lines.len().saturating_sub(MODELINE_SCAN_LINES).max(head);
+
+        for line in lines[..head].iter().chain(lines[tail_start..].iter()) {
+            if let Some(settings) = Self::modeline_settings(line) {
+                for setting in settings {
+                    match setting.split_once('=') {
+                        Some((name, value)) => self.set_option(name, Some(value)),
+                        None => self.set_option(setting, None),
+                    };
+                }
+            }
+        }
+    }
+
+    // Extract the option list from a modeline in either of vim's two forms:
+    //   vim: tw=72 nowrap
+    //   vim: set tw=72 nowrap :
+    fn modeline_settings(line: &str) -> Option<Vec<&str>> {
+        let start = ["vim:", "vi:", "ex:"]
+            .iter()
+            .filter_map(|marker| {
+                line.find(marker).and_then(|pos| {
+                    // The marker must be at the start or preceded by whitespace
+                    let at_word_start = pos == 0 || line[..pos].ends_with(char::is_whitespace);
+                    if at_word_start { Some(pos + marker.len()) } else { None }
+                })
+            })
+            .min()?;
+
+        let rest = line[start..].trim_start();
+        if let Some(set_args) = rest.strip_prefix("set ").or_else(|| rest.strip_prefix("se ")) {
+            // The second form ends at the next ':'
+            let set_args = set_args.split(':').next().unwrap_or("");
+            Some(set_args.split_whitespace().collect())
+        } else {
+            Some(rest.split(|c: char| c == ':' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect())
+        }
+    }
+
+    // Apply a single option, accepting both the frontmatter and vim spellings.
+    // Returns false for options we don't know about.
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> bool {
+        match name {
+            "lang" | "language" | "spelllang" | "spl" => {
+                match value {
+                    Some(v) if !v.is_empty() => self.lang = Some(v.to_string()),
+                    _ => self.lang = None,
+                }
+            },
+            "textwidth" | "tw" => {
+                self.textwidth = value
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .filter(|tw| *tw > 0);
+            },
+            "shiftwidth" | "sw" => {
+                if let Some(sw) = value.and_then(|v| v.parse::<usize>().ok()).filter(|sw| *sw > 0) {
+                    self.shiftwidth = sw;
+                }
+            },
+            "wrap" => self.wrap = value.is_none_or(Self::parse_bool),
+            "nowrap" => self.wrap = false,
+            "spell" => self.spell = value.is_none_or(Self::parse_bool),
+            "nospell" => self.spell = false,
+            "readonly" | "ro" => self.readonly = value.is_none_or(Self::parse_bool),
+            "noreadonly" | "noro" => self.readonly = false,
+            "ignorecase" | "ic" => self.ignorecase = value.is_none_or(Self::parse_bool),
+            "noignorecase" | "noic" => self.ignorecase = false,
+            "smartcase" | "scs" => self.smartcase = value.is_none_or(Self::parse_bool),
+            "nosmartcase" | "noscs" => self.smartcase = false,
+            _ => return false,
+        }
+        true
+    }
+
+    fn parse_bool(value: &str) -> bool {
+        !matches!(value.to_ascii_lowercase().as_str(), "false" | "no" | "off" | "0")
+    }
+
+    // Short summary for the status line, empty when nothing is set
+    pub fn status_text(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(lang) = &self.lang {
+            parts.push(format!("lang: {}", lang));
+        }
+        if self.spell {
+            parts.push("spell".to_string());
+        }
+        if let Some(tw) = self.textwidth {
+            parts.push(format!("tw={}", tw));
+        }
+        if !self.wrap {
+            parts.push("nowrap".to_string());
+        }
+        if self.readonly {
+            parts.push("[RO]".to_string());
+        }
+        parts.join("  ")
+    }
+}
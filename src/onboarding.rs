@@ -0,0 +1,143 @@
+use eframe::egui;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::portable;
+use crate::tutor;
+
+// The first-run setup, shown instead of the notes when there's no config.toml
+// yet: where the notes go, light or dark, autosave and backups, and a
+// tutorial note of the vim basics to start from. Finishing writes config.toml,
+// so it's only shown once.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Vault,
+    Theme,
+    Saving,
+    Tutorial,
+}
+
+pub struct Onboarding {
+    step: Step,
+    notes_dir: String,
+    pub dark_mode: bool,
+    autosave: bool,
+    backups: bool,
+    tutorial: bool,
+    error: Option<String>,
+}
+
+// What was chosen, once the setup is done
+pub struct Setup {
+    pub notes_dir: PathBuf,
+    pub tutorial: Option<String>, // The tutorial note to open, if one was made
+}
+
+impl Onboarding {
+    pub fn new(default_dir: &Path) -> Self {
+        Self {
+            step: Step::Vault,
+            notes_dir: default_dir.display().to_string(),
+            dark_mode: false,
+            autosave: true,
+            backups: false,
+            tutorial: true,
+            error: None,
+        }
+    }
+
+    // The setup screen; returns what was chosen when it's finished
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Setup> {
+        let mut finished = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(40.0);
+                ui.heading("Welcome to VimNote");
+                ui.add_space(20.0);
+            });
+
+            match self.step {
+                Step::Vault => {
+                    ui.strong("Where should your notes go?");
+                    ui.label("A folder of plain Markdown files. An existing folder of notes works too.");
+                    ui.text_edit_singleline(&mut self.notes_dir);
+                },
+                Step::Theme => {
+                    ui.strong("Light or dark?");
+                    ui.radio_value(&mut self.dark_mode, false, "Light");
+                    ui.radio_value(&mut self.dark_mode, true, "Dark");
+                    ui.weak("Alt+T switches between them any time.");
+                },
+                Step::Saving => {
+                    ui.strong("Saving");
+                    ui.checkbox(&mut self.autosave, "Autosave a few seconds after you stop typing");
+                    ui.checkbox(&mut self.backups, "Keep a daily copy of each note you change, in .vimnote/backups/");
+                    ui.weak("Deleted notes always go to .vimnote/trash/ first, and undo history is kept between sessions.");
+                },
+                Step::Tutorial => {
+                    ui.strong("New to vim?");
+                    ui.checkbox(&mut self.tutorial, format!("Add a two-minute tutorial note, \"{}\"", tutor::BASICS_NOTE));
+                    ui.weak("VimNote is edited with vim keys: Esc for commands, i to type.");
+                },
+            }
+
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.add_space(20.0);
+            ui.horizontal(|ui| {
+                if self.step != Step::Vault && ui.button("Back").clicked() {
+                    self.step = match self.step {
+                        Step::Tutorial => Step::Saving,
+                        Step::Saving => Step::Theme,
+                        _ => Step::Vault,
+                    };
+                }
+                let last = self.step == Step::Tutorial;
+                if ui.button(if last { "Start" } else { "Next" }).clicked() {
+                    match self.step {
+                        Step::Vault => self.step = Step::Theme,
+                        Step::Theme => self.step = Step::Saving,
+                        Step::Saving => self.step = Step::Tutorial,
+                        Step::Tutorial => match self.finish() {
+                            Ok(setup) => finished = Some(setup),
+                            Err(err) => self.error = Some(format!("Could not set up VimNote: {}", err)),
+                        },
+                    }
+                }
+            });
+        });
+        finished
+    }
+
+    // Create the notes folder and the tutorial note, and write config.toml
+    fn finish(&self) -> io::Result<Setup> {
+        if self.notes_dir.trim().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no notes folder"));
+        }
+        let notes_dir = portable::resolve(PathBuf::from(self.notes_dir.trim()));
+        fs::create_dir_all(&notes_dir)?;
+
+        let mut tutorial = None;
+        if self.tutorial {
+            let path = notes_dir.join(tutor::BASICS_NOTE);
+            if !path.exists() {
+                fs::write(&path, tutor::BASICS)?;
+            }
+            tutorial = Some(tutor::BASICS_NOTE.to_string());
+        }
+
+        let config = Config {
+            notes_dir: Some(portable::relative(&notes_dir)),
+            dark_mode: self.dark_mode,
+            autosave: self.autosave,
+            backups: self.backups,
+            ..Config::default()
+        };
+        config.save()?;
+        Ok(Setup { notes_dir, tutorial })
+    }
+}
@@ -4,4 +4,10 @@ pub enum VimOperation {
     Delete,
     Yank,
     Change,
+    Lowercase,  // gu
+    Uppercase,  // gU
+    ToggleCase, // g~
+    ShiftRight, // >
+    ShiftLeft,  // <
+    Reindent,   // =
 } 
\ No newline at end of file
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::Path;
+
+// Person pages live in this folder; `@Name` refers to `people/Name.md`
+pub const PEOPLE_DIR: &str = "people";
+
+// Names of everyone with a page in the people folder, sorted
+pub fn load_people(notes_dir: &Path) -> Vec<String> {
+    let mut people: Vec<String> = fs::read_dir(notes_dir.join(PEOPLE_DIR))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let path = entry.path();
+                    if path.extension().and_then(|e| e.to_str()) != Some("md") {
+                        return None;
+                    }
+                    path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    people.sort_by_key(|name| name.to_lowercase());
+    people
+}
+
+// Note path (relative to the notes folder) of a person's page
+pub fn person_note(name: &str) -> String {
+    format!("{}/{}.md", PEOPLE_DIR, name)
+}
+
+fn is_mention_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+// An `@` only starts a mention at the beginning of a word, so emails don't count
+fn is_mention_start(text: &str, at_pos: usize) -> bool {
+    text[..at_pos].chars().next_back().is_none_or(|c| !is_mention_char(c) && c != '@')
+}
+
+// The mention being typed right before the cursor: byte offset where the
+// name starts (just after the `@`) and the name typed so far
+pub fn mention_prefix(text: &str, cursor: usize) -> Option<(usize, &str)> {
+    let before = text.get(..cursor)?;
+    let name_start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_mention_char(c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(cursor);
+    if name_start == 0 || !before[..name_start].ends_with('@') || !is_mention_start(text, name_start - 1) {
+        return None;
+    }
+    Some((name_start, &before[name_start..]))
+}
+
+// People whose name starts with the prefix, then those merely containing it
+pub fn matching_people<'a>(people: &'a [String], prefix: &str) -> Vec<&'a String> {
+    let prefix = prefix.to_lowercase();
+    let (mut matches, contains): (Vec<&String>, Vec<&String>) = people
+        .iter()
+        .filter(|name| name.to_lowercase().contains(&prefix))
+        .partition(|name| name.to_lowercase().starts_with(&prefix));
+    matches.extend(contains);
+    matches
+}
+
+// The person mentioned at a byte position, if the cursor is on an `@Name`
+pub fn mention_at(text: &str, pos: usize) -> Option<String> {
+    if pos >= text.len() || !text.is_char_boundary(pos) {
+        return None;
+    }
+
+    // Walk back over the name to the `@`
+    let word_start = text[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_mention_char(c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(pos);
+    let at_pos = if text[pos..].starts_with('@') {
+        pos
+    } else if word_start > 0 && text[..word_start].ends_with('@') {
+        word_start - 1
+    } else {
+        return None;
+    };
+    if !is_mention_start(text, at_pos) {
+        return None;
+    }
+
+    let name: String = text[at_pos + 1..].chars().take_while(|&c| is_mention_char(c)).collect();
+    // A sentence-ending period isn't part of the name
+    let name = name.trim_end_matches('.');
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+thread_local! {
+    // Kept for the whole session: on X11 copied text is only available while the clipboard object lives
+    static CLIPBOARD: RefCell<Option<arboard::Clipboard>> = const { RefCell::new(None) };
+}
+
+// Show a file selected in the OS file manager (Finder, Explorer, or the default
+// Linux file manager, which can only open the containing folder)
+pub fn reveal_in_file_manager(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        Command::new("open").arg("-R").arg(path).spawn()?;
+    } else if cfg!(target_os = "windows") {
+        let mut select_arg = std::ffi::OsString::from("/select,");
+        select_arg.push(path.as_os_str());
+        Command::new("explorer").arg(select_arg).spawn()?;
+    } else {
+        let folder = path.parent().unwrap_or(path);
+        Command::new("xdg-open").arg(folder).spawn()?;
+    }
+    Ok(())
+}
+
+// Extensions of programs, scripts and installers, which the OS would run
+// rather than show
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "app", "apk", "appimage", "bat", "bin", "cmd", "com", "command", "cpl", "csh", "deb", "desktop", "dmg", "exe",
+    "gadget", "hta", "jar", "js", "jse", "ksh", "lnk", "msc", "msi", "msp", "pif", "pkg", "pl", "ps1", "psm1", "py",
+    "rb", "reg", "rpm", "run", "scr", "sh", "url", "vb", "vbe", "vbs", "workflow", "ws", "wsf", "zsh",
+];
+
+// Whether opening `path` with the default app could run it: a program or
+// script by its extension, or on Unix a file without one that has an
+// executable bit set. Files with other extensions are opened by what they
+// are, even on drives where every file looks executable.
+pub fn is_executable(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => EXECUTABLE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()),
+        None => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                if let Ok(metadata) = std::fs::metadata(path) {
+                    return metadata.is_file() && metadata.permissions().mode() & 0o111 != 0;
+                }
+            }
+            false
+        },
+    }
+}
+
+// Open a URL or file with whatever the OS has registered for it
+pub fn open_with_default_app(target: &str) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        Command::new("open").arg(target).spawn()?;
+    } else if cfg!(target_os = "windows") {
+        // Not `cmd /C start`: cmd would run whatever follows a `&` or `|` in the target
+        Command::new("rundll32").arg("url.dll,FileProtocolHandler").arg(target).spawn()?;
+    } else {
+        Command::new("xdg-open").arg(target).spawn()?;
+    }
+    Ok(())
+}
+
+// Play the system's alert sound, without waiting for it to finish
+pub fn play_alert_sound() -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        Command::new("afplay").arg("/System/Library/Sounds/Tink.aiff").spawn()?;
+    } else if cfg!(target_os = "windows") {
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command", "[System.Media.SystemSounds]::Beep.Play()"])
+            .spawn()?;
+    } else {
+        Command::new("canberra-gtk-play").args(["--id", "bell"]).spawn()?;
+    }
+    Ok(())
+}
+
+// Download a URL as text. Uses the system curl so we don't need an HTTP stack of our own.
+pub fn fetch_url(url: &str) -> io::Result<String> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--location", "--max-time", "20"])
+        .arg(url)
+        .output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Run `f` with the system clipboard, opening it on first use
+fn with_clipboard<T>(f: impl FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>) -> io::Result<T> {
+    let to_io_error = |err: arboard::Error| io::Error::other(err.to_string());
+    CLIPBOARD.with(|cell| {
+        let mut clipboard = cell.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = Some(arboard::Clipboard::new().map_err(to_io_error)?);
+        }
+        f(clipboard.as_mut().unwrap()).map_err(to_io_error)
+    })
+}
+
+// Text on the system clipboard. `primary` reads the primary selection on
+// Linux (vim's "* register); elsewhere there is only the one clipboard.
+pub fn clipboard_text(primary: bool) -> io::Result<String> {
+    with_clipboard(|clipboard| {
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+        if primary {
+            use arboard::{GetExtLinux, LinuxClipboardKind};
+            return clipboard.get().clipboard(LinuxClipboardKind::Primary).text();
+        }
+        let _ = primary;
+        clipboard.get_text()
+    })
+}
+
+pub fn set_clipboard_text(text: &str, primary: bool) -> io::Result<()> {
+    with_clipboard(|clipboard| {
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+        if primary {
+            use arboard::{LinuxClipboardKind, SetExtLinux};
+            return clipboard.set().clipboard(LinuxClipboardKind::Primary).text(text.to_string());
+        }
+        let _ = primary;
+        clipboard.set_text(text.to_string())
+    })
+}
+
+// Image on the system clipboard as (width, height, RGBA pixels)
+pub fn clipboard_image() -> io::Result<(usize, usize, Vec<u8>)> {
+    with_clipboard(|clipboard| clipboard.get_image())
+        .map(|image| (image.width, image.height, image.bytes.into_owned()))
+}
@@ -0,0 +1,29 @@
+use std::time::{Duration, Instant};
+
+// A focus timer: `:pomodoro [minutes]` counts down in the status line, 25
+// minutes by default, and `:pomodoro stop` stops it. When it runs out the
+// status line says so and the `pomodoro` feedback from config.toml is given.
+
+pub const DEFAULT_MINUTES: u64 = 25;
+
+#[derive(Debug)]
+pub struct Pomodoro {
+    pub minutes: u64,
+    ends: Instant,
+}
+
+impl Pomodoro {
+    pub fn start(minutes: u64) -> Self {
+        Self { minutes, ends: Instant::now() + Duration::from_secs(minutes * 60) }
+    }
+
+    pub fn is_over(&self) -> bool {
+        Instant::now() >= self.ends
+    }
+
+    // Time left, like "🍅 24:59"
+    pub fn label(&self) -> String {
+        let left = self.ends.saturating_duration_since(Instant::now()).as_secs();
+        format!("🍅 {:02}:{:02}", left / 60, left % 60)
+    }
+}
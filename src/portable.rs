@@ -0,0 +1,73 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Portable mode, for running VimNote off a USB stick on a machine where it
+// can't write to the usual places. With `--portable`, or a portable.toml next
+// to the executable (it can be empty), config.toml and style.css live in
+// config/ next to the executable instead of the OS config directory, and the
+// notes default to notes/ there, with their .vimnote (undo histories, marks,
+// layouts...) inside as always. A notes_dir that's inside the executable's
+// folder is written to config.toml relative to it, so the stick still works
+// when it gets another drive letter.
+
+pub const PORTABLE_FILE: &str = "portable.toml";
+const CONFIG_DIR: &str = "config";
+const NOTES_DIR: &str = "notes";
+
+// The executable's folder when running portable
+static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+// Decide once at startup, before the config is loaded, whether this run is portable
+pub fn init(flag: bool) {
+    let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let root = exe_dir.filter(|dir| flag || dir.join(PORTABLE_FILE).exists());
+    match &root {
+        Some(root) => println!("Portable mode: config and notes in {}", root.display()),
+        None if flag => println!("--portable: could not find the executable's folder; using the usual ones"),
+        None => {},
+    }
+    let _ = ROOT.set(root);
+}
+
+pub fn root() -> Option<&'static Path> {
+    ROOT.get().and_then(|root| root.as_deref())
+}
+
+// Where config.toml and style.css are
+pub fn config_dir() -> Option<PathBuf> {
+    match root() {
+        Some(root) => Some(root.join(CONFIG_DIR)),
+        None => dirs::config_dir().map(|dir| dir.join("vimnote")),
+    }
+}
+
+// The notes folder when config.toml doesn't say: Documents/vimnote, or
+// notes/ next to the executable
+pub fn default_notes_dir() -> PathBuf {
+    match root() {
+        Some(root) => root.join(NOTES_DIR),
+        None => dirs::document_dir()
+            .unwrap_or_else(|| env::current_dir().unwrap())
+            .join("vimnote"),
+    }
+}
+
+// A notes folder from config.toml or the setup; when portable, a relative
+// one is in the executable's folder
+pub fn resolve(path: PathBuf) -> PathBuf {
+    match root() {
+        Some(root) if path.is_relative() => root.join(path),
+        _ => path,
+    }
+}
+
+// A notes folder to write to config.toml: relative to the executable's
+// folder when it's in there and this run is portable
+pub fn relative(path: &Path) -> PathBuf {
+    match root().and_then(|root| path.strip_prefix(root).ok()) {
+        Some(inside) if inside.as_os_str().is_empty() => PathBuf::from("."),
+        Some(inside) => inside.to_path_buf(),
+        None => path.to_path_buf(),
+    }
+}
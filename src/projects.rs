@@ -0,0 +1,126 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::links;
+use crate::markdown;
+use crate::note_options::NoteOptions;
+use crate::search::{Location, NoteEntry};
+
+// The projects board, `:projects`: notes with a `status:` in their
+// frontmatter, in a column per status. idea, active and done are always
+// there, in that order, and any other status gets a column after them.
+// Moving a note to another column rewrites its `status:` line, so the board
+// stays a view of plain files.
+
+pub const STATUSES: [&str; 3] = ["idea", "active", "done"];
+
+pub struct Card {
+    pub note: String,
+    pub title: String, // The first heading, or the note's name
+}
+
+pub struct Column {
+    pub status: String,
+    pub cards: Vec<Card>, // Most recently modified first
+}
+
+pub struct Board {
+    pub columns: Vec<Column>,
+    pub column: usize, // The selected card's column
+    pub card: usize,
+}
+
+impl Board {
+    pub fn collect(notes: &[NoteEntry]) -> Self {
+        let mut columns: Vec<Column> = STATUSES
+            .iter()
+            .map(|status| Column { status: status.to_string(), cards: Vec::new() })
+            .collect();
+        let mut notes: Vec<&NoteEntry> = notes.iter().filter(|note| note.location == Location::Notes).collect();
+        notes.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+
+        for note in notes {
+            let status = match status(&note.content) {
+                Some(status) => status,
+                None => continue,
+            };
+            let title = markdown::first_heading(markdown::strip_frontmatter(&note.content))
+                .unwrap_or_else(|| links::note_stem(note.name.rsplit('/').next().unwrap_or(&note.name)))
+                .to_string();
+            let card = Card { note: note.name.clone(), title };
+            match columns.iter_mut().find(|column| column.status == status) {
+                Some(column) => column.cards.push(card),
+                None => columns.push(Column { status, cards: vec![card] }),
+            }
+        }
+        columns[STATUSES.len()..].sort_by(|a, b| a.status.cmp(&b.status));
+        Self { columns, column: 0, card: 0 }
+    }
+
+    pub fn selected(&self) -> Option<&Card> {
+        self.columns.get(self.column)?.cards.get(self.card)
+    }
+
+    // h/l and j/k, staying on the board
+    pub fn move_selection(&mut self, columns: isize, cards: isize) {
+        let last_column = self.columns.len().saturating_sub(1);
+        self.column = self.column.saturating_add_signed(columns).min(last_column);
+        let last_card = self.columns.get(self.column).map_or(0, |column| column.cards.len().saturating_sub(1));
+        self.card = self.card.saturating_add_signed(cards).min(last_card);
+    }
+
+    // After the note's status was written: the card goes to the top of the
+    // `to` column and stays selected
+    pub fn move_card(&mut self, column: usize, card: usize, to: usize) {
+        if column == to || to >= self.columns.len() || card >= self.columns[column].cards.len() {
+            return;
+        }
+        let moved = self.columns[column].cards.remove(card);
+        self.columns[to].cards.insert(0, moved);
+        self.column = to;
+        self.card = 0;
+    }
+}
+
+// The `status:` in a note's frontmatter, in lower case
+pub fn status(content: &str) -> Option<String> {
+    let body = markdown::strip_frontmatter(content);
+    let frontmatter = &content[..content.len() - body.len()];
+    frontmatter
+        .lines()
+        .find_map(|line| line.strip_prefix("status:"))
+        .map(|value| value.trim().trim_matches(['"', '\'']).trim().to_lowercase())
+        .filter(|status| !status.is_empty())
+}
+
+// The note with its `status:` set, adding the line, or the frontmatter, if
+// it has none
+pub fn set_status(content: &str, status: &str) -> String {
+    let body = markdown::strip_frontmatter(content);
+    let frontmatter = &content[..content.len() - body.len()];
+    if frontmatter.is_empty() {
+        return format!("---\nstatus: {}\n---\n{}", status, content);
+    }
+    let mut lines: Vec<String> = frontmatter.split_inclusive('\n').map(str::to_string).collect();
+    match lines.iter().position(|line| line.starts_with("status:")) {
+        Some(index) => {
+            let line_break = &lines[index][lines[index].trim_end_matches(['\n', '\r']).len()..];
+            lines[index] = format!("status: {}{}", status, line_break);
+        },
+        // Before the closing `---`
+        None => lines.insert(lines.len() - 1, format!("status: {}\n", status)),
+    }
+    lines.concat() + body
+}
+
+// Write the note's new status, leaving read-only notes alone
+pub fn move_note(notes_dir: &Path, config: &Config, note: &str, status: &str) -> io::Result<()> {
+    let path = notes_dir.join(note);
+    let content = fs::read_to_string(&path)?;
+    if NoteOptions::from_content(config.note_defaults(note), &content).readonly {
+        return Err(io::Error::other(format!("{} is read-only", note)));
+    }
+    fs::write(&path, set_status(&content, status))
+}
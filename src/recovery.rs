@@ -0,0 +1,99 @@
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, TryLockError};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+// If the app panics while the open note has unsaved edits, a panic hook
+// writes the note's text to .vimnote/recovery.json on the way down, and the
+// next start offers to put it back. The hook can't reach into the app, which
+// may be half way through changing something, so the app keeps a copy of
+// the text here for it whenever there are edits that aren't saved yet.
+
+pub const RECOVERY_FILE: &str = "recovery.json";
+
+// What the hook writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recovery {
+    pub note: String,
+    pub content: String,
+    pub crashed_at: String, // Local time, "2024-05-12 09:30"
+}
+
+struct Unsaved {
+    path: PathBuf, // The recovery file to write
+    note: String,
+    content: String,
+}
+
+static UNSAVED: Mutex<Option<Unsaved>> = Mutex::new(None);
+
+// Keep `content` to write out for `note` if the app panics before it's saved
+pub fn track(data_dir: &Path, note: &str, content: &str) {
+    let mut unsaved = UNSAVED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *unsaved = Some(Unsaved { path: data_dir.join(RECOVERY_FILE), note: note.to_string(), content: content.to_string() });
+}
+
+// The note was saved, or another one opened; nothing to write
+pub fn clear() {
+    *UNSAVED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+// Write the unsaved text, if any, when a panic is about to take the app
+// down: any panic in release builds, which abort, or else one on the UI
+// thread, since the background threads of jobs, indexing and the like can
+// unwind without it.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if cfg!(panic = "abort") || thread::current().name() == Some("main") {
+            match write_unsaved() {
+                Ok(Some(path)) => eprintln!("Unsaved changes were written to {}", path.display()),
+                Ok(None) => {},
+                Err(err) => eprintln!("Could not write the unsaved changes: {}", err),
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_unsaved() -> io::Result<Option<PathBuf>> {
+    // The panic may have come while the lock was held; waiting would hang
+    let unsaved = match UNSAVED.try_lock() {
+        Ok(unsaved) => unsaved,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return Ok(None),
+    };
+    let unsaved = match unsaved.as_ref() {
+        Some(unsaved) => unsaved,
+        None => return Ok(None),
+    };
+    let recovery = Recovery {
+        note: unsaved.note.clone(),
+        content: unsaved.content.clone(),
+        crashed_at: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+    };
+    if let Some(dir) = unsaved.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string(&recovery).map_err(io::Error::other)?;
+    fs::write(&unsaved.path, json)?;
+    Ok(Some(unsaved.path.clone()))
+}
+
+// What the last run left behind when it crashed
+pub fn load(data_dir: &Path) -> Option<Recovery> {
+    let json = fs::read_to_string(data_dir.join(RECOVERY_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+// Restored or not wanted
+pub fn discard(data_dir: &Path) -> io::Result<()> {
+    match fs::remove_file(data_dir.join(RECOVERY_FILE)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
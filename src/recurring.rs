@@ -0,0 +1,165 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::file_names;
+use crate::links;
+use crate::templates::{self, TemplateVars};
+
+// Recurring notes, `[[recurring]]` in config.toml: a note made from a
+// template every day, on a weekday or on a day of the month. When the app is
+// opened, each one's latest date that has come is looked at, and its note is
+// created if it wasn't yet, so a weekly review skipped on Monday still shows
+// up on Tuesday. Which dates have had their note is kept in
+// .vimnote/recurring.json, so a note that was deleted or renamed isn't made
+// again, and existing notes are never overwritten.
+
+pub const RECURRING_FILE: &str = "recurring.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recurring {
+    // The note's name; `{{date}}` is the date it's for, like "reviews/weekly {{date}}"
+    pub name: String,
+    // Template that the note starts from, by name, like `template` of a folder
+    pub template: String,
+    // "day", a weekday like "monday", "month" for the 1st or "month 15"
+    pub every: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Every {
+    Day,
+    Week(Weekday),
+    Month(u32), // Day of the month, the last day in months that are shorter
+}
+
+impl Every {
+    pub fn parse(every: &str) -> Result<Self, String> {
+        let every = every.trim().to_lowercase();
+        let mut words = every.split_whitespace();
+        let parsed = match (words.next(), words.next(), words.next()) {
+            (Some("day" | "daily"), None, _) => Some(Every::Day),
+            (Some("month" | "monthly"), None, _) => Some(Every::Month(1)),
+            (Some("month" | "monthly"), Some(day), None) => day.parse().ok().filter(|day| (1..=31).contains(day)).map(Every::Month),
+            (Some(weekday), None, _) => weekday.parse().ok().map(Every::Week),
+            _ => None,
+        };
+        parsed.ok_or_else(|| format!("`every = \"{}\"` isn't \"day\", a weekday, \"month\" or \"month <day>\"", every))
+    }
+
+    // The latest date on or before `today` that a note is for
+    pub fn latest(&self, today: NaiveDate) -> NaiveDate {
+        match *self {
+            Every::Day => today,
+            Every::Week(weekday) => {
+                let days = (7 + today.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+                today - Duration::days(days as i64)
+            },
+            Every::Month(day) => {
+                let this_month = day_of_month(today.year(), today.month(), day);
+                if this_month <= today {
+                    return this_month;
+                }
+                match today.month() {
+                    1 => day_of_month(today.year() - 1, 12, day),
+                    month => day_of_month(today.year(), month - 1, day),
+                }
+            },
+        }
+    }
+}
+
+// `day` of the month, or its last day if it's shorter
+fn day_of_month(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or_default()
+}
+
+// The last date each recurring note was made for, as YYYY-MM-DD, by its `name`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Made {
+    pub dates: HashMap<String, String>,
+}
+
+impl Made {
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid recurring notes file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Created {
+    pub created: Vec<String>,
+    pub due_today: Vec<String>, // Notes for today, or made just now for a day that was missed
+    pub errors: Vec<String>,
+}
+
+// The note name of `recurring` for the YYYY-MM-DD `day`
+pub fn note_name(config: &Config, recurring: &Recurring, day: &str) -> String {
+    let name = recurring.name.replace("{{date}}", day);
+    file_names::with_extension(&config.file_names.apply(&name))
+}
+
+// Create the recurring notes whose date has come, remembering them in `made`
+pub fn create_due(notes_dir: &Path, config: &Config, made: &mut Made, today: NaiveDate) -> Created {
+    let mut result = Created::default();
+
+    for recurring in &config.recurring {
+        let every = match Every::parse(&recurring.every) {
+            Ok(every) => every,
+            Err(err) => {
+                result.errors.push(format!("{}: {}", recurring.name, err));
+                continue;
+            }
+        };
+        let date = every.latest(today);
+        let day = date.format("%Y-%m-%d").to_string();
+        let note = note_name(config, recurring, &day);
+        if made.dates.get(&recurring.name).is_some_and(|made| *made >= day) {
+            if date == today && notes_dir.join(&note).exists() {
+                result.due_today.push(note);
+            }
+            continue;
+        }
+
+        let path = notes_dir.join(&note);
+        if !path.exists() {
+            let template = templates::template_or_default(notes_dir, &recurring.template, "# {{title}}\n");
+            let title = links::note_stem(note.rsplit('/').next().unwrap_or(&note));
+            let vars = TemplateVars {
+                date: day.clone(),
+                ..TemplateVars::now(title)
+            };
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, templates::render(&template, &vars)));
+            if let Err(err) = written {
+                result.errors.push(format!("Could not create {}: {}", note, err));
+                continue;
+            }
+            result.created.push(note.clone());
+        }
+        made.dates.insert(recurring.name.clone(), day);
+        result.due_today.push(note);
+    }
+    result
+}
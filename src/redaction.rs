@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use crate::markdown;
+
+// Secrets that stay out of HTML exports (`:export html`, which is also how a
+// note gets printed, and `:export site`) while the note keeps them, shown as
+// usual in the editor. A `::secret::` marker hides the rest of its line, or
+// the text up to a second marker:
+//
+//   Wifi password: ::secret:: hunter2
+//   The door code is ::secret::4711::secret:: until Friday
+//
+// and fields named under `secret:` in the frontmatter (`secret: [pin, iban]`)
+// hide the value of `pin: 1234` lines in the note, list items and bold names
+// too. The frontmatter itself is never exported.
+
+pub const MARKER: &str = "::secret::";
+const MASK: &str = "••••••";
+
+// What exports do with secrets, `redaction` in config.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Redaction {
+    #[default]
+    Mask,  // ••••••, the same length whatever the secret's
+    Strip, // Left out, along with the whole line of a secret field
+}
+
+// The note as it should be exported
+pub fn redact(content: &str, redaction: Redaction) -> String {
+    let body = markdown::strip_frontmatter(content);
+    let frontmatter = &content[..content.len() - body.len()];
+    let fields = secret_fields(frontmatter);
+    if fields.is_empty() && !body.contains(MARKER) {
+        return content.to_string();
+    }
+    let mut redacted = String::with_capacity(content.len());
+    redacted.push_str(frontmatter);
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        let line_break = &line[text.len()..];
+        let text = redact_markers(text, redaction);
+        match (field_value(&text, &fields), redaction) {
+            (Some(value), Redaction::Mask) => {
+                redacted.push_str(text[..value].trim_end());
+                redacted.push(' ');
+                redacted.push_str(MASK);
+            },
+            (Some(_), Redaction::Strip) => continue,
+            (None, _) => redacted.push_str(&text),
+        }
+        redacted.push_str(line_break);
+    }
+    redacted
+}
+
+// The names of a `secret: [a, b]`, `secret: a, b` or `secret:` followed by `- a` lines
+fn secret_fields(frontmatter: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut in_list = false;
+    for line in frontmatter.lines() {
+        let items = if let Some(value) = line.strip_prefix("secret:") {
+            in_list = value.trim().is_empty();
+            value
+        } else if in_list && line.trim_start().starts_with('-') {
+            line
+        } else {
+            if !line.starts_with(char::is_whitespace) {
+                in_list = false;
+            }
+            continue;
+        };
+        let padding = |c: char| c.is_whitespace() || "[]\"'-".contains(c);
+        fields.extend(
+            items
+                .split(',')
+                .map(|item| item.trim_matches(padding))
+                .filter(|item| !item.is_empty())
+                .map(str::to_string),
+        );
+    }
+    fields
+}
+
+// The line with the text after each `::secret::` masked or left out, up to
+// the next marker or the end of the line
+fn redact_markers(line: &str, redaction: Redaction) -> String {
+    let mut redacted = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(MARKER) {
+        redacted.push_str(&rest[..start]);
+        let secret = &rest[start + MARKER.len()..];
+        let (secret, after) = match secret.find(MARKER) {
+            Some(end) => (&secret[..end], &secret[end + MARKER.len()..]),
+            None => (secret, ""),
+        };
+        rest = match redaction {
+            Redaction::Mask if !secret.trim().is_empty() => {
+                // Keep the spaces around it, as in `code: ::secret:: 1234`
+                redacted.push_str(&secret[..secret.len() - secret.trim_start().len()]);
+                redacted.push_str(MASK);
+                redacted.push_str(&secret[secret.trim_end().len()..]);
+                after
+            },
+            Redaction::Mask => after,
+            // `is ::secret::4711::secret:: until` shouldn't end up with two spaces
+            Redaction::Strip if redacted.ends_with(' ') => after.trim_start_matches(' '),
+            Redaction::Strip => after,
+        };
+    }
+    redacted.push_str(rest);
+    if redaction == Redaction::Strip && rest.is_empty() && redacted.len() < line.len() {
+        // Nothing left after a secret at the end of the line
+        redacted.truncate(redacted.trim_end().len());
+    }
+    redacted
+}
+
+// Where the value starts in a `pin: 1234`, `- pin: 1234` or `**PIN:** 1234`
+// line, if `pin` is a secret field and the value isn't empty
+fn field_value(line: &str, fields: &[String]) -> Option<usize> {
+    if fields.is_empty() {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let item = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))
+        .unwrap_or(trimmed);
+    let colon = item.find(':')?;
+    let name = item[..colon].trim().trim_matches('*').trim();
+    if !fields.iter().any(|field| field.to_lowercase() == name.to_lowercase()) {
+        return None;
+    }
+    let after = &item[colon + 1..];
+    let value = after.trim_start_matches('*');
+    if value.trim().is_empty() {
+        return None;
+    }
+    Some(line.len() - value.len())
+}
@@ -0,0 +1,511 @@
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::grep::Hit;
+use crate::config::Config;
+use crate::markdown;
+use crate::note_options::NoteOptions;
+
+// Search across all notes, on the search screen (`:search` or Alt+F).
+//
+// A query is words and "quoted phrases" that must all appear in a note
+// (ignoring case), plus filters:
+//   in:archive, in:trash     also search notes in archive/ or the trash
+//   modified:RANGE           last changed in RANGE
+//   created:RANGE            created in RANGE (a `created:` or `date:`
+//                            frontmatter key, or else the file's dates)
+// where RANGE is a day, month or year (2024-05-01, 2024-05, 2024), today,
+// yesterday, the last few days or weeks (7d, 2w), a comparison (>2024-01-01,
+// <=2024-06) or a span (2024-01..2024-03).
+
+pub const ARCHIVE_DIR: &str = "archive";
+
+// Matching lines shown per note
+const MAX_LINES_PER_NOTE: usize = 5;
+
+// Lines above and below a hit shown when it's expanded
+const CONTEXT_LINES: usize = 3;
+
+// Filters that have a chip on the search screen: label and query token
+pub const FILTER_CHIPS: [(&str, &str); 5] = [
+    ("Archived", "in:archive"),
+    ("Trash", "in:trash"),
+    ("Today", "modified:today"),
+    ("Last 7 days", "modified:7d"),
+    ("Last 30 days", "modified:30d"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Notes,
+    Archive,
+    Trash,
+}
+
+impl Location {
+    pub fn of(file_name: &str, trash_prefix: &str) -> Self {
+        if file_name.starts_with(trash_prefix) {
+            Location::Trash
+        } else if file_name.starts_with(&format!("{}/", ARCHIVE_DIR)) {
+            Location::Archive
+        } else {
+            Location::Notes
+        }
+    }
+}
+
+// Days a date filter accepts, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub terms: Vec<String>, // Lowercase
+    pub archive: bool,
+    pub trash: bool,
+    pub modified: Option<DateRange>,
+    pub created: Option<DateRange>,
+    pub errors: Vec<String>, // Filters that couldn't be read, shown below the query
+}
+
+impl Query {
+    pub fn parse(query: &str, today: NaiveDate) -> Self {
+        let mut parsed = Self::default();
+        for token in tokens(query) {
+            let (key, value) = match token.split_once(':') {
+                Some((key, value)) if !token.starts_with('"') => (key, value),
+                _ => {
+                    parsed.terms.push(token.trim_matches('"').to_lowercase());
+                    continue;
+                }
+            };
+            match (key, value) {
+                ("in", "archive") => parsed.archive = true,
+                ("in", "trash") => parsed.trash = true,
+                ("modified", range) | ("created", range) => match parse_range(range, today) {
+                    Some(range) if key == "modified" => parsed.modified = Some(range),
+                    Some(range) => parsed.created = Some(range),
+                    None => parsed.errors.push(format!("Unknown date range {}", token)),
+                },
+                ("in", _) => parsed.errors.push(format!("Unknown folder {}", token)),
+                // Not a filter, like a URL or a time: search for it
+                _ => parsed.terms.push(token.to_lowercase()),
+            }
+        }
+        parsed.terms.retain(|term| !term.is_empty());
+        parsed
+    }
+
+    fn has_filters(&self) -> bool {
+        self.modified.is_some() || self.created.is_some()
+    }
+
+    // Whether the folder and date filters let a note through
+    fn accepts(&self, note: &NoteEntry) -> bool {
+        let in_folder = match note.location {
+            Location::Notes => true,
+            Location::Archive => self.archive,
+            Location::Trash => self.trash,
+        };
+        in_folder
+            && self.modified.is_none_or(|range| range.contains(note.modified))
+            && self.created.is_none_or(|range| range.contains(note.created))
+    }
+}
+
+// Words and "quoted phrases"; a quote may start inside a token, as in title:"a b"
+fn tokens(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            },
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_range(range: &str, today: NaiveDate) -> Option<DateRange> {
+    if let Some((from, to)) = range.split_once("..") {
+        let from = if from.is_empty() { None } else { Some(date_span(from, today)?.0) };
+        let to = if to.is_empty() { None } else { Some(date_span(to, today)?.1) };
+        return Some(DateRange { from, to });
+    }
+    if let Some(date) = range.strip_prefix(">=") {
+        return Some(DateRange { from: Some(date_span(date, today)?.0), to: None });
+    }
+    if let Some(date) = range.strip_prefix("<=") {
+        return Some(DateRange { from: None, to: Some(date_span(date, today)?.1) });
+    }
+    if let Some(date) = range.strip_prefix('>') {
+        return Some(DateRange { from: Some(date_span(date, today)?.1 + Duration::days(1)), to: None });
+    }
+    if let Some(date) = range.strip_prefix('<') {
+        return Some(DateRange { from: None, to: Some(date_span(date, today)?.0 - Duration::days(1)) });
+    }
+    let (from, to) = date_span(range, today)?;
+    Some(DateRange { from: Some(from), to: Some(to) })
+}
+
+// First and last day a date like 2024, 2024-05, 2024-05-01, today or 7d stands for
+fn date_span(date: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    match date {
+        "today" => return Some((today, today)),
+        "yesterday" => {
+            let yesterday = today - Duration::days(1);
+            return Some((yesterday, yesterday));
+        },
+        _ => {},
+    }
+    // The last few days or weeks, today included
+    if let Some(days) = date.strip_suffix('d').and_then(|n| n.parse::<i64>().ok()) {
+        return Some((today - Duration::days(days.max(1) - 1), today));
+    }
+    if let Some(weeks) = date.strip_suffix('w').and_then(|n| n.parse::<i64>().ok()) {
+        return Some((today - Duration::days(weeks.max(1) * 7 - 1), today));
+    }
+
+    let parts: Vec<&str> = date.split('-').collect();
+    let numbers: Vec<u32> = parts.iter().map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [year] => Some((
+            NaiveDate::from_ymd_opt(year as i32, 1, 1)?,
+            NaiveDate::from_ymd_opt(year as i32, 12, 31)?,
+        )),
+        [year, month] => {
+            let first = NaiveDate::from_ymd_opt(year as i32, month, 1)?;
+            let next = if month == 12 {
+                NaiveDate::from_ymd_opt(year as i32 + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year as i32, month + 1, 1)?
+            };
+            Some((first, next - Duration::days(1)))
+        },
+        [year, month, day] => {
+            let date = NaiveDate::from_ymd_opt(year as i32, month, day)?;
+            Some((date, date))
+        },
+        _ => None,
+    }
+}
+
+// A note's contents and dates, read once when the search screen opens
+pub struct NoteEntry {
+    pub name: String,
+    pub location: Location,
+    pub content: String, // Empty when ripgrep does the searching
+    pub modified: NaiveDate,
+    pub created: NaiveDate,
+}
+
+// With `read_content` false only the dates are read, and only the first lines
+// of each note for its frontmatter; the same goes for notes bigger than `max_size`
+pub fn load_notes(notes_dir: &Path, names: &[String], trash_prefix: &str, read_content: bool, max_size: u64) -> Vec<NoteEntry> {
+    let mut notes = Vec::new();
+    for name in names {
+        let path = notes_dir.join(name);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let read_content = read_content && metadata.len() <= max_size;
+        let text = if read_content { fs::read_to_string(&path) } else { read_head(&path) };
+        let text = match text {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let file_date = |time: std::io::Result<std::time::SystemTime>| time.ok().map(|time| DateTime::<Local>::from(time).date_naive());
+        let modified = file_date(metadata.modified()).unwrap_or_else(|| Local::now().date_naive());
+        let created = frontmatter_date(&text)
+            .or_else(|| file_date(metadata.created()))
+            .unwrap_or(modified);
+        let content = if read_content { text } else { String::new() };
+        notes.push(NoteEntry { name: name.clone(), location: Location::of(name, trash_prefix), content, modified, created });
+    }
+    notes
+}
+
+// The start of a note, enough for its frontmatter
+fn read_head(path: &Path) -> io::Result<String> {
+    use std::io::Read;
+    let mut head = Vec::new();
+    fs::File::open(path)?.take(4096).read_to_end(&mut head)?;
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+// The date in a `created:` or `date:` frontmatter key, like templates write
+fn frontmatter_date(content: &str) -> Option<NaiveDate> {
+    let frontmatter = &content[..content.len() - markdown::strip_frontmatter(content).len()];
+    frontmatter.lines().find_map(|line| {
+        let value = line.strip_prefix("created:").or_else(|| line.strip_prefix("date:"))?;
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+    })
+}
+
+pub struct SearchResult {
+    pub note: String,
+    pub location: Location,
+    pub lines: Vec<(usize, String)>, // Matching lines, by line number
+}
+
+// Notes matching the query, most recently changed first
+pub fn search(notes: &[NoteEntry], query: &Query) -> Vec<SearchResult> {
+    if query.terms.is_empty() && !query.has_filters() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<&NoteEntry> = notes
+        .iter()
+        .filter(|note| query.accepts(note))
+        .filter(|note| {
+            let content = note.content.to_lowercase();
+            let name = note.name.to_lowercase();
+            query.terms.iter().all(|term| content.contains(term) || name.contains(term))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+
+    matches
+        .into_iter()
+        .map(|note| {
+            let lines = note
+                .content
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| {
+                    let line = line.to_lowercase();
+                    query.terms.iter().any(|term| line.contains(term))
+                })
+                .take(MAX_LINES_PER_NOTE)
+                .map(|(number, line)| (number, line.trim().to_string()))
+                .collect();
+            SearchResult { note: note.name.clone(), location: note.location, lines }
+        })
+        .collect()
+}
+
+// Like `search`, from the lines ripgrep found with any of the terms: a note
+// matches if every term is in one of its lines or its name
+pub fn search_hits(notes: &[NoteEntry], query: &Query, hits: &[Hit]) -> Vec<SearchResult> {
+    let mut matches: Vec<(&NoteEntry, Vec<&Hit>)> = notes
+        .iter()
+        .filter(|note| query.accepts(note))
+        .filter_map(|note| {
+            // Hits for earlier terms may still be here while ripgrep runs
+            let note_hits: Vec<&Hit> = hits
+                .iter()
+                .filter(|hit| hit.note == note.name)
+                .filter(|hit| {
+                    let text = hit.text.to_lowercase();
+                    query.terms.iter().any(|term| text.contains(term))
+                })
+                .collect();
+            let name = note.name.to_lowercase();
+            let found = query.terms.iter().all(|term| {
+                name.contains(term) || note_hits.iter().any(|hit| hit.text.to_lowercase().contains(term))
+            });
+            (found && !note_hits.is_empty()).then_some((note, note_hits))
+        })
+        .collect();
+    matches.sort_by(|(a, _), (b, _)| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+
+    matches
+        .into_iter()
+        .map(|(note, note_hits)| SearchResult {
+            note: note.name.clone(),
+            location: note.location,
+            lines: note_hits.iter().take(MAX_LINES_PER_NOTE).map(|hit| (hit.line, hit.text.clone())).collect(),
+        })
+        .collect()
+}
+
+// A hit expanded in place with the lines around it, which can be edited
+// and written back to the note without opening it
+pub struct Expanded {
+    pub note: String,
+    pub line: usize,       // The matching line
+    pub first_line: usize, // First line of the context
+    pub original: String,  // The context as it was read, to notice changes on disk
+    pub text: String,      // The context being edited
+    pub focused: bool,     // The context's text box has the keyboard
+}
+
+impl Expanded {
+    pub fn is_changed(&self) -> bool {
+        self.text != self.original
+    }
+}
+
+// State of the search screen
+pub struct SearchScreen {
+    pub query: String,
+    pub notes: Vec<NoteEntry>,
+    pub parsed: Query,
+    pub results: Vec<SearchResult>,
+    pub selected: usize,
+    pub expanded: Option<Expanded>,
+    pub hits: Option<Vec<Hit>>, // What ripgrep found for the terms, when it does the searching
+    pub hits_for: Vec<String>,  // The terms ripgrep was last asked for
+}
+
+impl SearchScreen {
+    pub fn new(notes: Vec<NoteEntry>, query: &str) -> Self {
+        let mut screen = Self {
+            query: query.to_string(),
+            notes,
+            parsed: Query::default(),
+            results: Vec::new(),
+            selected: 0,
+            expanded: None,
+            hits: None,
+            hits_for: Vec::new(),
+        };
+        screen.run();
+        screen
+    }
+
+    // Search again after the query changed
+    pub fn run(&mut self) {
+        self.parsed = Query::parse(&self.query, Local::now().date_naive());
+        self.results = match &self.hits {
+            Some(hits) if !self.parsed.terms.is_empty() => search_hits(&self.notes, &self.parsed, hits),
+            _ => search(&self.notes, &self.parsed),
+        };
+        self.selected = self.selected.min(self.results.len().saturating_sub(1));
+    }
+
+    pub fn has_filter(&self, token: &str) -> bool {
+        tokens(&self.query).iter().any(|t| t == token)
+    }
+
+    // Add a filter to the query, or take it out if it's there. Only one
+    // modified: filter applies, so turning one on replaces the others.
+    pub fn toggle_filter(&mut self, token: &str) {
+        let key = token.split(':').next().unwrap_or(token);
+        let active = self.has_filter(token);
+        let mut kept: Vec<String> = tokens(&self.query)
+            .into_iter()
+            .filter(|t| t != token && !(key != "in" && t.starts_with(&format!("{}:", key))))
+            .collect();
+        if !active {
+            kept.push(token.to_string());
+        }
+        self.query = kept.join(" ");
+        if !self.query.is_empty() {
+            self.query.push(' ');
+        }
+        self.run();
+    }
+
+    // ripgrep's hits for the current terms have come in
+    pub fn set_hits(&mut self, hits: Vec<Hit>) {
+        self.hits = Some(hits);
+        self.run();
+    }
+
+    // The listed hits, for `:cnext` and `:cprev` once the screen closes
+    pub fn quickfix(&self) -> Vec<Hit> {
+        self.results
+            .iter()
+            .flat_map(|result| {
+                result.lines.iter().map(|(line, text)| Hit { note: result.note.clone(), line: *line, text: text.clone() })
+            })
+            .collect()
+    }
+
+    // Show the lines around a hit, or hide them if they're showing
+    pub fn toggle_expanded(&mut self, notes_dir: &Path, note: &str, line: usize) {
+        if self.expanded.as_ref().is_some_and(|expanded| expanded.note == note && expanded.line == line) {
+            self.expanded = None;
+            return;
+        }
+        let content = match fs::read_to_string(notes_dir.join(note)) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let lines: Vec<&str> = content.split('\n').collect();
+        let first_line = line.saturating_sub(CONTEXT_LINES);
+        let end = (line + CONTEXT_LINES + 1).min(lines.len());
+        let original = lines[first_line.min(end)..end].join("\n");
+        self.expanded = Some(Expanded {
+            note: note.to_string(),
+            line,
+            first_line,
+            text: original.clone(),
+            original,
+            focused: false,
+        });
+    }
+
+    // Write the expanded context back into its note. The note is read again
+    // first, and left alone if those lines changed since the search opened.
+    // Returns the note's name.
+    pub fn write_back(&mut self, notes_dir: &Path, config: &Config) -> io::Result<String> {
+        let expanded = match &self.expanded {
+            Some(expanded) => expanded,
+            None => return Err(io::Error::other("Nothing to save")),
+        };
+        let path = notes_dir.join(&expanded.note);
+        let content = fs::read_to_string(&path)?;
+        if NoteOptions::from_content(config.note_defaults(&expanded.note), &content).readonly {
+            return Err(io::Error::other(format!("{} is read-only", expanded.note)));
+        }
+
+        let lines: Vec<&str> = content.split('\n').collect();
+        let count = expanded.original.split('\n').count();
+        let end = expanded.first_line + count;
+        if end > lines.len() || lines[expanded.first_line..end].join("\n") != expanded.original {
+            return Err(io::Error::other(format!("{} has changed, search again", expanded.note)));
+        }
+        let mut updated: Vec<&str> = lines[..expanded.first_line].to_vec();
+        updated.push(&expanded.text);
+        updated.extend_from_slice(&lines[end..]);
+        let updated = updated.join("\n");
+        fs::write(&path, &updated)?;
+
+        let note = expanded.note.clone();
+        let read_content = self.hits.is_none();
+        if let Some(entry) = self.notes.iter_mut().find(|entry| entry.name == note) {
+            if read_content {
+                entry.content = updated;
+            }
+            entry.modified = Local::now().date_naive();
+        }
+        self.expanded = None;
+        self.run();
+        Ok(note)
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.results.is_empty() {
+            return;
+        }
+        let selected = self.selected as isize + delta;
+        self.selected = selected.clamp(0, self.results.len() as isize - 1) as usize;
+    }
+}
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::templates::TEMPLATES_DIR;
+
+// A whole setup in one file that can be taken to another machine:
+// config.toml, the stylesheet for HTML exports, and the vault's templates and
+// window layouts. `:settings export` writes it and `:settings import` puts
+// everything back where it goes. Files are kept as they are, comments and all.
+
+pub const DEFAULT_FILE: &str = "vimnote-settings.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub config: Option<String>,
+    pub stylesheet: Option<String>,
+    pub layouts: Option<String>,
+    // By file name in templates/, like "daily.md"
+    pub templates: BTreeMap<String, String>,
+}
+
+impl Settings {
+    // What's set up now; `layouts_path` is the vault's layouts.json
+    pub fn collect(notes_dir: &Path, layouts_path: &Path) -> Self {
+        let read = |path: Option<PathBuf>| path.and_then(|path| fs::read_to_string(path).ok());
+        let mut templates = BTreeMap::new();
+        if let Ok(entries) = fs::read_dir(notes_dir.join(TEMPLATES_DIR)) {
+            for entry in entries.flatten() {
+                if let (Some(name), Ok(content)) = (entry.file_name().to_str(), fs::read_to_string(entry.path())) {
+                    templates.insert(name.to_string(), content);
+                }
+            }
+        }
+        Self {
+            config: read(Config::path()),
+            stylesheet: read(Config::stylesheet_path()),
+            layouts: read(Some(layouts_path.to_path_buf())),
+            templates,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    // Write everything in the file where it goes. A config.toml that's there
+    // already is kept as config.toml.bak. Returns what was put back.
+    pub fn install(&self, notes_dir: &Path, layouts_path: &Path) -> io::Result<Vec<String>> {
+        let mut installed = Vec::new();
+        if let (Some(config), Some(path)) = (&self.config, Config::path()) {
+            // Check it before it replaces a working one
+            toml::from_str::<Config>(config).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            if path.exists() {
+                fs::copy(&path, path.with_extension("toml.bak"))?;
+            }
+            write_file(&path, config)?;
+            installed.push("config".to_string());
+        }
+        if let (Some(stylesheet), Some(path)) = (&self.stylesheet, Config::stylesheet_path()) {
+            write_file(&path, stylesheet)?;
+            installed.push("stylesheet".to_string());
+        }
+        if let Some(layouts) = &self.layouts {
+            write_file(layouts_path, layouts)?;
+            installed.push("layouts".to_string());
+        }
+        let mut templates = 0;
+        for (name, content) in &self.templates {
+            // Only file names; nothing outside templates/
+            if name.contains(['/', '\\']) || name.starts_with('.') {
+                continue;
+            }
+            write_file(&notes_dir.join(TEMPLATES_DIR).join(name), content)?;
+            templates += 1;
+        }
+        if templates > 0 {
+            installed.push(format!("{} templates", templates));
+        }
+        Ok(installed)
+    }
+}
+
+fn write_file(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)
+}
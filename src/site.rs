@@ -0,0 +1,435 @@
+use eframe::egui;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Instant;
+
+use crate::background::Cancel;
+use crate::citations::{self, Bibliography, Entry};
+use crate::config::Config;
+use crate::diagrams;
+use crate::links;
+use crate::markdown;
+use crate::redaction::{self, Redaction};
+use crate::templates;
+
+// Static HTML export of a folder of notes, for publishing as a digital garden.
+// Every note becomes a page next to an index listing them all; links between
+// exported notes point at their pages, and attachments they use are copied
+// along. Links to notes outside the folder become plain text, so private
+// notes don't leak into the site through a link.
+//
+// A site is exported on a background thread, see SiteExport.
+//
+// A single note can also be exported as one self-contained HTML page. Both use
+// the same theme, which a style.css next to config.toml can restyle, and draw
+// the diagrams in notes with `diagram_commands` (see diagrams.rs), leave
+// out the secrets marked in them (see redaction.rs), and cite from the
+// bibliography with a References list under each note that cites (see
+// citations.rs).
+
+const STYLESHEET: &str = "style.css";
+
+const DEFAULT_CSS: &str = "\
+:root { --bg: #fdfdfc; --fg: #2b2b2b; --muted: #777; --accent: #2f6f8f; --code-bg: #f1f1ee; --border: #ddd; }
+@media (prefers-color-scheme: dark) {
+  :root { --bg: #1e1e1e; --fg: #d8d8d8; --muted: #8a8a8a; --accent: #7fb8d6; --code-bg: #2a2a2a; --border: #3a3a3a; }
+}
+body { background: var(--bg); color: var(--fg); font: 17px/1.6 Georgia, serif; max-width: 42em; margin: 0 auto; padding: 1.5em; }
+header { font-family: sans-serif; font-size: 0.9em; margin-bottom: 2em; }
+header a { color: var(--muted); }
+a { color: var(--accent); }
+.missing-link { color: var(--muted); }
+h1, h2, h3, h4, h5, h6 { font-family: sans-serif; line-height: 1.25; }
+code, pre { font-family: Menlo, Consolas, monospace; font-size: 0.88em; background: var(--code-bg); }
+code { padding: 0.1em 0.3em; border-radius: 3px; }
+pre { padding: 0.8em 1em; overflow-x: auto; border-radius: 4px; }
+pre code { padding: 0; background: none; }
+blockquote { margin: 0; padding-left: 1em; border-left: 3px solid var(--border); color: var(--muted); }
+img { max-width: 100%; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid var(--border); padding: 0.3em 0.6em; }
+li.task { list-style: none; margin-left: -1.3em; }
+hr { border: none; border-top: 1px solid var(--border); }
+.notes { padding-left: 1.2em; }
+.folder { color: var(--muted); }
+.progress { font: 0.6em sans-serif; color: var(--muted); vertical-align: middle; margin-left: 0.5em; }
+figure.diagram { margin: 1em 0; text-align: center; }
+figure.diagram svg { max-width: 100%; height: auto; }
+.references ul { list-style: none; padding-left: 0; }
+.references li { padding-left: 2em; text-indent: -2em; margin-bottom: 0.4em; }
+";
+
+// The default theme followed by the user's stylesheet, so its rules win
+pub fn stylesheet() -> String {
+    let mut css = DEFAULT_CSS.to_string();
+    if let Some(path) = Config::stylesheet_path() {
+        if let Ok(custom) = fs::read_to_string(&path) {
+            css.push_str(&format!("\n/* {} */\n", path.display()));
+            css.push_str(&custom);
+        }
+    }
+    css
+}
+
+// What exported notes' diagrams, secrets and citations become
+#[derive(Clone)]
+pub struct Rendering {
+    pub diagram_commands: HashMap<String, String>,
+    pub redaction: Redaction,
+    pub bibliography: Bibliography,
+}
+
+impl Rendering {
+    // A note's HTML, with its links made by `resolve`
+    fn body(&self, content: &str, resolve: &dyn Fn(&str) -> Option<String>) -> String {
+        let code_block = |language: &str, code: &str| diagrams::render(language, code, &self.diagram_commands);
+        let cite = |key: &str| self.bibliography.get(key).map(Entry::cited);
+        let hooks = markdown::Hooks { resolve, code_block: &code_block, cite: &cite };
+        let content = markdown::strip_frontmatter(content);
+        markdown::to_html(content, &hooks) + &citations::references_html(&self.bibliography, content)
+    }
+}
+
+pub struct Export {
+    pub pages: usize,
+    pub assets: usize,
+}
+
+// Export the notes under `folder` (relative to the notes folder, empty for all
+// notes) to `out_dir`, calling `progress` with how many notes are done out of
+// how many. It stops with an Interrupted error when `progress` returns false.
+pub fn export(
+    notes_dir: &Path,
+    notes_files: &[String],
+    folder: &str,
+    out_dir: &Path,
+    rendering: &Rendering,
+    mut progress: impl FnMut(usize, usize) -> bool,
+) -> io::Result<Export> {
+    let folder = folder.trim_matches('/');
+    let prefix = if folder.is_empty() { String::new() } else { format!("{}/", folder) };
+    let notes: Vec<&String> = notes_files.iter().filter(|name| name.starts_with(&prefix)).collect();
+    if notes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no notes in {}", folder)));
+    }
+
+    // Page of every exported note, by its path and by its path without extension
+    let mut pages: HashMap<String, String> = HashMap::new();
+    for name in &notes {
+        let page = format!("{}.html", links::note_stem(&name[prefix.len()..]));
+        pages.insert(name.to_string(), page.clone());
+        pages.insert(links::note_stem(name).to_string(), page);
+    }
+    // A folder's own index note is the front page, above the list of notes
+    let index_note = ["index.md", "index.txt"]
+        .iter()
+        .map(|name| format!("{}{}", prefix, name))
+        .find(|name| notes.contains(&name));
+
+    fs::create_dir_all(out_dir)?;
+    fs::write(out_dir.join(STYLESHEET), stylesheet())?;
+
+    let mut copied_assets = Vec::new();
+    let mut titles = Vec::new();
+    for name in &notes {
+        let content = redaction::redact(&fs::read_to_string(notes_dir.join(name))?, rendering.redaction);
+        let page = &pages[name.as_str()];
+        let title = note_title(&content, name);
+        let site = SitePaths { notes_dir, out_dir, prefix: &prefix, pages: &pages };
+        let body = export_body(&site, name, page, &content, rendering, &mut copied_assets)?;
+        if Some(name.as_str()) != index_note.as_deref() {
+            write_page(out_dir, page, &title, &body)?;
+        }
+        titles.push((name.as_str(), title, body));
+        if !progress(titles.len(), notes.len()) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+    }
+
+    // Index: the index note if there is one, then every page by folder
+    let mut index_body = String::new();
+    let site_title = match titles.iter().find(|(name, _, _)| Some(*name) == index_note.as_deref()) {
+        Some((_, title, body)) => {
+            index_body.push_str(body);
+            index_body.push_str("<h2>All notes</h2>\n");
+            title.clone()
+        },
+        None => {
+            let title = if folder.is_empty() { "Notes" } else { folder.rsplit('/').next().unwrap_or(folder) };
+            index_body.push_str(&format!("<h1>{}</h1>\n", markdown::escape_html(title)));
+            title.to_string()
+        },
+    };
+    index_body.push_str("<ul class=\"notes\">\n");
+    for (name, title, _) in &titles {
+        if Some(*name) == index_note.as_deref() {
+            continue;
+        }
+        let relative = &name[prefix.len()..];
+        let folder_label = relative
+            .rsplit_once('/')
+            .map(|(dir, _)| format!(" <span class=\"folder\">{}/</span>", markdown::escape_html(dir)))
+            .unwrap_or_default();
+        index_body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>{}</li>\n",
+            href_path(&pages[*name]),
+            markdown::escape_html(title),
+            folder_label
+        ));
+    }
+    index_body.push_str("</ul>\n");
+    write_page(out_dir, "index.html", &site_title, &index_body)?;
+
+    println!("Exported {} notes from {:?} to {:?}", titles.len(), folder, out_dir);
+    Ok(Export { pages: titles.len(), assets: copied_assets.len() })
+}
+
+enum ExportEvent {
+    Progress { done: usize, total: usize },
+    Done(io::Result<Export>),
+}
+
+// `:export site` running on a background thread
+pub struct SiteExport {
+    events: Receiver<ExportEvent>,
+    pub out_dir: PathBuf,
+    pub done: usize,
+    pub total: usize,
+    pub started: Instant,
+    pub cancel: Cancel,
+}
+
+impl SiteExport {
+    pub fn start(
+        notes_dir: &Path,
+        notes_files: Vec<String>,
+        folder: &str,
+        out_dir: PathBuf,
+        rendering: Rendering,
+        ctx: &egui::Context,
+    ) -> Self {
+        let (sender, events) = mpsc::channel();
+        let notes_dir = notes_dir.to_path_buf();
+        let folder = folder.to_string();
+        let ctx = ctx.clone();
+        let cancel = Cancel::default();
+        let cancelled = cancel.clone();
+        let site_dir = out_dir.clone();
+
+        thread::spawn(move || {
+            let result = export(&notes_dir, &notes_files, &folder, &site_dir, &rendering, |done, total| {
+                let _ = sender.send(ExportEvent::Progress { done, total });
+                ctx.request_repaint();
+                !cancelled.is_cancelled()
+            });
+            let _ = sender.send(ExportEvent::Done(result));
+            ctx.request_repaint();
+        });
+        Self { events, out_dir, done: 0, total: 0, started: Instant::now(), cancel }
+    }
+
+    // How the export went, once it's over
+    pub fn poll(&mut self) -> Option<io::Result<Export>> {
+        for event in self.events.try_iter() {
+            match event {
+                ExportEvent::Progress { done, total } => {
+                    self.done = done;
+                    self.total = total;
+                },
+                ExportEvent::Done(result) => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+// Title from frontmatter, then the first heading, then the file name
+fn note_title(content: &str, name: &str) -> String {
+    let frontmatter = &content[..content.len() - markdown::strip_frontmatter(content).len()];
+    let from_frontmatter = frontmatter.lines().find_map(|line| {
+        line.strip_prefix("title:").map(templates::yaml_unquote)
+    });
+    from_frontmatter
+        .filter(|title| !title.is_empty())
+        .or_else(|| markdown::first_heading(markdown::strip_frontmatter(content)).map(str::to_string))
+        .unwrap_or_else(|| links::note_stem(name.rsplit('/').next().unwrap_or(name)).to_string())
+}
+
+// Where an exported site's notes come from and go to
+struct SitePaths<'a> {
+    notes_dir: &'a Path,
+    out_dir: &'a Path,
+    prefix: &'a str,                    // Exported folder, with a trailing slash
+    pages: &'a HashMap<String, String>, // Page of each note, see `export`
+}
+
+// HTML of a note, with its links pointing into the site
+fn export_body(
+    site: &SitePaths,
+    name: &str,
+    page: &str,
+    content: &str,
+    rendering: &Rendering,
+    copied_assets: &mut Vec<String>,
+) -> io::Result<String> {
+    let SitePaths { notes_dir, out_dir, prefix, pages } = *site;
+    let note_dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+    // The renderer only reads through `resolve`, so what it copies is noted in cells
+    let copied_assets = RefCell::new(copied_assets);
+    let copy_error = RefCell::new(None);
+    let resolve = |target: &str| -> Option<String> {
+        // Note links: relative to the note, from the notes folder, or by note name
+        let candidates = [join_path(note_dir, target), normalize(target)];
+        for candidate in candidates.iter().flatten() {
+            if let Some(target_page) = pages.get(candidate.as_str()) {
+                return Some(relative_href(page, target_page));
+            }
+        }
+        // The same but for case, as the link would work on macOS and Windows
+        for candidate in candidates.iter().flatten() {
+            if let Some((_, target_page)) = pages.iter().find(|(note, _)| links::same_name(note, candidate)) {
+                return Some(relative_href(page, target_page));
+            }
+        }
+        if target.ends_with(".md") || target.ends_with(".txt") || !target.contains('.') {
+            let stem = links::note_stem(target);
+            return pages
+                .iter()
+                .find(|(note, _)| note.rsplit('/').next().is_some_and(|name| links::same_name(name, stem)))
+                .map(|(_, target_page)| relative_href(page, target_page));
+        }
+
+        // Attachments are copied under the same path; ones from outside the
+        // exported folder keep their path from the notes folder
+        let asset = candidates.iter().flatten().find(|path| notes_dir.join(path).is_file())?.clone();
+        let site_path = asset.strip_prefix(prefix).unwrap_or(&asset).to_string();
+        let mut copied_assets = copied_assets.borrow_mut();
+        if !copied_assets.contains(&site_path) {
+            let destination = out_dir.join(&site_path);
+            let copied = destination
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::copy(notes_dir.join(&asset), &destination));
+            if let Err(e) = copied {
+                copy_error.borrow_mut().get_or_insert(e);
+                return None;
+            }
+            copied_assets.push(site_path.clone());
+        }
+        Some(relative_href(page, &site_path))
+    };
+    let html = rendering.body(content, &resolve);
+    match copy_error.into_inner() {
+        Some(e) => Err(e),
+        None => Ok(html),
+    }
+}
+
+fn write_page(out_dir: &Path, page: &str, title: &str, body: &str) -> io::Result<()> {
+    let root = "../".repeat(page.matches('/').count());
+    let head = format!("<link rel=\"stylesheet\" href=\"{}{}\">", root, STYLESHEET);
+    let header = format!("<header><a href=\"{}index.html\">Index</a></header>\n", root);
+    let path = out_dir.join(page);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, html_page(title, &head, &header, body))
+}
+
+fn html_page(title: &str, head: &str, header: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n{}\n{}</head>\n<body>\n{}<main>\n{}</main>\n</body>\n</html>\n",
+        markdown::escape_html(title),
+        head,
+        diagrams::page_script(body).map(|script| format!("{}\n", script)).unwrap_or_default(),
+        header,
+        body
+    )
+}
+
+// Export one note as a page with the theme inlined. Images and other
+// attachments are linked by their full path, and links to other notes
+// become plain text.
+pub fn export_note(
+    notes_dir: &Path,
+    name: &str,
+    content: &str,
+    out_path: &Path,
+    rendering: &Rendering,
+) -> io::Result<()> {
+    let content = &redaction::redact(content, rendering.redaction);
+    let note_dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let resolve = |target: &str| -> Option<String> {
+        let is_note = target.ends_with(".md") || target.ends_with(".txt") || !target.contains('.');
+        if is_note {
+            return None;
+        }
+        [join_path(note_dir, target), normalize(target)]
+            .into_iter()
+            .flatten()
+            .map(|path| notes_dir.join(path))
+            .find(|path| path.is_file())
+            .map(|path| {
+                let path = path.to_string_lossy().replace('\\', "/");
+                // Windows paths need a slash before the drive letter
+                let slash = if path.starts_with('/') { "" } else { "/" };
+                format!("file://{}{}", slash, href_path(&path))
+            })
+    };
+    let body = rendering.body(content, &resolve);
+    let head = format!("<style>\n{}</style>", stylesheet());
+
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out_path, html_page(&note_title(content, name), &head, "", &body))
+}
+
+// `target` resolved against a folder, without `.` and `..` parts. None if it
+// leads out of the notes folder.
+fn join_path(dir: &str, target: &str) -> Option<String> {
+    if dir.is_empty() {
+        normalize(target)
+    } else {
+        normalize(&format!("{}/{}", dir, target))
+    }
+}
+
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => {
+                parts.pop()?;
+            },
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// Link from one page of the site to another path in it
+fn relative_href(from_page: &str, to: &str) -> String {
+    let from_dirs: Vec<&str> = from_page.split('/').collect();
+    let from_dirs = &from_dirs[..from_dirs.len() - 1];
+    let to_parts: Vec<&str> = to.split('/').collect();
+    let common = from_dirs
+        .iter()
+        .zip(&to_parts[..to_parts.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let relative = format!("{}{}", "../".repeat(from_dirs.len() - common), to_parts[common..].join("/"));
+    href_path(&relative)
+}
+
+fn href_path(path: &str) -> String {
+    path.replace('%', "%25").replace(' ', "%20").replace('#', "%23").replace('?', "%3F")
+}
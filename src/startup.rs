@@ -0,0 +1,144 @@
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::grep::Grep;
+use crate::links::LinkIndex;
+use crate::people;
+
+// Scanning the notes folder, building the link index and looking for ripgrep
+// happen on a background thread started after the first frame, so the window
+// shows up right away; the sidebar says "Loading notes..." until they're done.
+// Notes show up in the sidebar as the scan finds them.
+
+// How often the notes found so far are sent to the sidebar
+const FOUND_INTERVAL: Duration = Duration::from_millis(100);
+
+// Timings of each startup phase, printed once everything is loaded when
+// VimNote is started with --profile-startup
+pub struct StartupProfile {
+    start: Instant,
+    last: Instant,
+    phases: Vec<(String, Duration)>,
+}
+
+impl StartupProfile {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self { start: now, last: now, phases: Vec::new() }
+    }
+
+    // A phase that ended now, having started when the last one ended
+    pub fn record(&mut self, phase: &str) {
+        let now = Instant::now();
+        self.phases.push((phase.to_string(), now - self.last));
+        self.last = now;
+    }
+
+    // A phase timed elsewhere, like on the loading thread
+    pub fn add(&mut self, phase: &str, took: Duration) {
+        self.phases.push((phase.to_string(), took));
+    }
+
+    pub fn report(&self) {
+        println!("Startup profile:");
+        for (phase, took) in &self.phases {
+            println!("  {:<24} {:>10.1?}", phase, took);
+        }
+        println!("  {:<24} {:>10.1?}", "total", self.start.elapsed());
+    }
+}
+
+pub struct Loaded {
+    pub notes_files: Vec<String>,
+    pub link_index: LinkIndex,
+    pub people: Vec<String>,
+    pub grep: Option<Grep>, // When ripgrep is configured and found
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+enum LoadEvent {
+    Found(Vec<String>),
+    Done(Loaded),
+}
+
+pub struct NotesLoader {
+    events: Receiver<LoadEvent>,
+    pub found: usize, // Notes found so far
+    new_found: Vec<String>, // Found since take_found
+}
+
+impl NotesLoader {
+    // `ripgrep_command` is checked for when the config turns ripgrep on; notes
+    // bigger than `max_size` aren't indexed
+    pub fn start(notes_dir: &Path, ripgrep_command: Option<String>, max_size: u64, ctx: &egui::Context) -> Self {
+        let (sender, events) = mpsc::channel();
+        let notes_dir: PathBuf = notes_dir.to_path_buf();
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let mut timings = Vec::new();
+            let mut timed = |phase, start: Instant| timings.push((phase, start.elapsed()));
+
+            let start = Instant::now();
+            let mut batch = Vec::new();
+            let mut last_sent = Instant::now();
+            let notes_files = crate::app::NotesApp::scan_notes_dir_with(&notes_dir, |file_name| {
+                batch.push(file_name.to_string());
+                if last_sent.elapsed() >= FOUND_INTERVAL {
+                    let _ = sender.send(LoadEvent::Found(std::mem::take(&mut batch)));
+                    ctx.request_repaint();
+                    last_sent = Instant::now();
+                }
+            });
+            if !batch.is_empty() {
+                let _ = sender.send(LoadEvent::Found(batch));
+            }
+            timed("scan notes folder", start);
+
+            let start = Instant::now();
+            let link_index = LinkIndex::build(&notes_dir, &notes_files, max_size);
+            timed("link index", start);
+
+            let start = Instant::now();
+            let people = people::load_people(&notes_dir);
+            timed("people", start);
+
+            let grep = ripgrep_command.and_then(|command| {
+                let start = Instant::now();
+                let grep = Grep::new(&command);
+                let available = grep.is_available();
+                timed("ripgrep check", start);
+                if !available {
+                    println!("{} not found, using the built-in search", command);
+                }
+                available.then_some(grep)
+            });
+
+            let _ = sender.send(LoadEvent::Done(Loaded { notes_files, link_index, people, grep, timings }));
+            ctx.request_repaint();
+        });
+        Self { events, found: 0, new_found: Vec::new() }
+    }
+
+    // Everything once it's loaded
+    pub fn poll(&mut self) -> Option<Loaded> {
+        for event in self.events.try_iter() {
+            match event {
+                LoadEvent::Found(files) => {
+                    self.found += files.len();
+                    self.new_found.extend(files);
+                },
+                LoadEvent::Done(loaded) => return Some(loaded),
+            }
+        }
+        None
+    }
+
+    // Notes found since the last call, for the sidebar
+    pub fn take_found(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.new_found)
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{Datelike, NaiveDate};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::markdown;
+use crate::note_options::NoteOptions;
+use crate::search::NoteEntry;
+
+// Tags are `#words` in a note's text, outside code, or the items of a `tags:`
+// frontmatter key. `:tags` shows how much each one is used, month by month,
+// and which look like duplicates of each other so they can be merged.
+
+// Months of history shown per tag, this one included
+pub const MONTHS: usize = 12;
+
+// Byte ranges of the tag names in `content`, without the `#`
+pub fn tag_spans(content: &str) -> Vec<Range<usize>> {
+    let body = markdown::strip_frontmatter(content);
+    let body_start = content.len() - body.len();
+    let mut spans = frontmatter_spans(&content[..body_start]);
+
+    let mut fence: Option<&str> = None;
+    let mut offset = body_start;
+    for line in body.split('\n') {
+        let trimmed = line.trim_start();
+        if let Some(current) = fence {
+            if trimmed.starts_with(current) {
+                fence = None;
+            }
+        } else if let Some(opening) = markdown::code_fence(trimmed) {
+            fence = Some(opening);
+        } else {
+            spans.extend(line_spans(line).into_iter().map(|span| span.start + offset..span.end + offset));
+        }
+        offset += line.len() + 1;
+    }
+    spans
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '/')
+}
+
+// `#tag`s in a line of text, skipping `code` and `#`s inside words and links
+fn line_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut in_code = false;
+    let mut previous: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if c == '#' && !in_code && previous.is_none_or(|p| p.is_whitespace() || "([{,;".contains(p)) {
+            let name_len: usize = line[i + 1..].chars().take_while(|&c| is_tag_char(c)).map(char::len_utf8).sum();
+            let name = line[i + 1..i + 1 + name_len].trim_end_matches(['-', '/']);
+            // `#1` is an issue number, not a tag
+            if name.chars().any(|c| !c.is_numeric()) {
+                spans.push(i + 1..i + 1 + name.len());
+            }
+        }
+        previous = Some(c);
+    }
+    spans
+}
+
+// Items of a `tags: [a, b]`, `tags: a, b` or `tags:` followed by `- a` lines
+fn frontmatter_spans(frontmatter: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut in_list = false;
+    let mut offset = 0;
+    for line in frontmatter.split('\n') {
+        if let Some(value) = line.strip_prefix("tags:") {
+            in_list = value.trim().is_empty();
+            let mut start = offset + "tags:".len();
+            for item in value.split(',') {
+                spans.extend(item_span(frontmatter, start..start + item.len()));
+                start += item.len() + 1;
+            }
+        } else if in_list && line.trim_start().starts_with('-') {
+            spans.extend(item_span(frontmatter, offset..offset + line.len()));
+        } else if !line.starts_with(char::is_whitespace) {
+            in_list = false;
+        }
+        offset += line.len() + 1;
+    }
+    spans
+}
+
+// `range` without the brackets, quotes, list dash and `#` around the name
+fn item_span(text: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let item = &text[range.clone()];
+    let padding = |c: char| c.is_whitespace() || "[]\"'#-".contains(c);
+    let start = range.start + (item.len() - item.trim_start_matches(padding).len());
+    let end = range.end - (item.len() - item.trim_end_matches(padding).len());
+    (start < end).then_some(start..end)
+}
+
+// A note's tags, each once
+pub fn note_tags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for span in tag_spans(content) {
+        let tag = &content[span];
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+pub struct TagStat {
+    pub tag: String,
+    pub notes: usize,
+    pub by_month: Vec<usize>, // Notes created each month, oldest first
+}
+
+pub struct TagStats {
+    pub tags: Vec<TagStat>, // Most used first
+    pub months: Vec<NaiveDate>, // First day of each month in `by_month`
+    pub note_count: usize,
+    pub untagged: usize,
+    pub duplicates: Vec<(String, String)>, // Less used tag, the tag to merge it into
+}
+
+impl TagStats {
+    pub fn collect(notes: &[NoteEntry], today: NaiveDate) -> Self {
+        let months: Vec<NaiveDate> = (0..MONTHS)
+            .rev()
+            .filter_map(|back| months_back(today, back))
+            .collect();
+        let this_month = month_number(today);
+
+        let mut by_tag: HashMap<String, TagStat> = HashMap::new();
+        let mut untagged = 0;
+        for note in notes {
+            let tags = note_tags(&note.content);
+            if tags.is_empty() {
+                untagged += 1;
+            }
+            let age = this_month - month_number(note.created);
+            for tag in tags {
+                let stat = by_tag.entry(tag.clone()).or_insert_with(|| TagStat { tag, notes: 0, by_month: vec![0; months.len()] });
+                stat.notes += 1;
+                if (0..months.len() as i32).contains(&age) {
+                    stat.by_month[months.len() - 1 - age as usize] += 1;
+                }
+            }
+        }
+
+        let mut tags: Vec<TagStat> = by_tag.into_values().collect();
+        tags.sort_by(|a, b| b.notes.cmp(&a.notes).then_with(|| a.tag.cmp(&b.tag)));
+        let duplicates = near_duplicates(&tags);
+        Self { tags, months, note_count: notes.len(), untagged, duplicates }
+    }
+}
+
+fn month_number(date: NaiveDate) -> i32 {
+    date.year() * 12 + date.month0() as i32
+}
+
+fn months_back(today: NaiveDate, back: usize) -> Option<NaiveDate> {
+    let month = month_number(today) - back as i32;
+    NaiveDate::from_ymd_opt(month.div_euclid(12), month.rem_euclid(12) as u32 + 1, 1)
+}
+
+// `#todo` and `#todos`, `#Work` and `#work`, `#to-read` and `#to_read`, and
+// one-letter typos of longer tags. `tags` is sorted most used first, so each
+// pair merges into the tag that's used more.
+fn near_duplicates(tags: &[TagStat]) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut merged: HashSet<&str> = HashSet::new();
+    for (i, into) in tags.iter().enumerate() {
+        if merged.contains(into.tag.as_str()) {
+            continue;
+        }
+        for from in &tags[i + 1..] {
+            if !merged.contains(from.tag.as_str()) && looks_same(&into.tag, &from.tag) {
+                merged.insert(&from.tag);
+                pairs.push((from.tag.clone(), into.tag.clone()));
+            }
+        }
+    }
+    pairs
+}
+
+fn looks_same(a: &str, b: &str) -> bool {
+    let key = |tag: &str| -> String { tag.to_lowercase().chars().filter(|c| !matches!(c, '-' | '_')).collect() };
+    let (a, b) = (key(a), key(b));
+    let plural = |one: &str, other: &str| other.strip_prefix(one).is_some_and(|end| end == "s" || end == "es");
+    if a == b || plural(&a, &b) || plural(&b, &a) {
+        return true;
+    }
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    a.len().min(b.len()) >= 5 && within_one_edit(&a, &b)
+}
+
+fn within_one_edit(a: &[char], b: &[char]) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+    let prefix = short.iter().zip(long).take_while(|(x, y)| x == y).count();
+    if short.len() == long.len() {
+        // One letter swapped for another
+        short.get(prefix + 1..) == long.get(prefix + 1..)
+    } else {
+        // One letter added
+        short[prefix..] == long[prefix + 1..]
+    }
+}
+
+// `content` with every `from` tag renamed to `into`, or None if it has none
+pub fn rename_tag(content: &str, from: &str, into: &str) -> Option<String> {
+    let spans: Vec<Range<usize>> = tag_spans(content).into_iter().filter(|span| &content[span.clone()] == from).collect();
+    if spans.is_empty() {
+        return None;
+    }
+    let mut renamed = String::with_capacity(content.len());
+    let mut last = 0;
+    for span in spans {
+        renamed.push_str(&content[last..span.start]);
+        renamed.push_str(into);
+        last = span.end;
+    }
+    renamed.push_str(&content[last..]);
+    Some(renamed)
+}
+
+// Rename the `from` tag to `into` in every note that has it, leaving read-only
+// notes alone. Returns the notes that changed, with what they were before.
+pub fn merge(notes_dir: &Path, config: &Config, names: &[String], from: &str, into: &str) -> io::Result<Vec<(String, String)>> {
+    let mut changed = Vec::new();
+    for name in names {
+        let path = notes_dir.join(name);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        if NoteOptions::from_content(config.note_defaults(name), &content).readonly {
+            continue;
+        }
+        if let Some(renamed) = rename_tag(&content, from, into) {
+            fs::write(&path, renamed)?;
+            changed.push((name.clone(), content));
+        }
+    }
+    Ok(changed)
+}
+
+// Undo a merge: put back the notes as they were before it, as (name,
+// content). Notes edited since are left alone. Returns the ones put back.
+pub fn unmerge(notes_dir: &Path, notes: &[(String, String)], from: &str, into: &str) -> io::Result<Vec<String>> {
+    let mut restored = Vec::new();
+    for (name, before) in notes {
+        let path = notes_dir.join(name);
+        let unchanged = fs::read_to_string(&path).is_ok_and(|content| rename_tag(before, from, into).as_deref() == Some(content.as_str()));
+        if unchanged {
+            fs::write(&path, before)?;
+            restored.push(name.clone());
+        }
+    }
+    Ok(restored)
+}
+
+// Counts as a row of bars, e.g. "▁▃█▂"
+pub fn sparkline(counts: &[usize]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts
+        .iter()
+        .map(|&count| if count == 0 { ' ' } else { BARS[(count * 8 - 1) / max] })
+        .collect()
+}
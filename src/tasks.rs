@@ -0,0 +1,78 @@
+use crate::markdown;
+
+// Checkbox tasks in notes: list items starting with `[ ]` or `[x]`
+
+// How many tasks under a heading are done. A heading's section runs to the
+// next heading of the same or a higher level, so it includes its subsections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionProgress {
+    pub line: usize,   // Line of the heading
+    pub offset: usize, // Byte offset of that line
+    pub done: usize,
+    pub total: usize,
+}
+
+impl SectionProgress {
+    pub fn label(&self) -> String {
+        format!("{}/{} done", self.done, self.total)
+    }
+}
+
+// Some(done) if the line is a task
+pub fn task_state(line: &str) -> Option<bool> {
+    let item = line.trim_start();
+    let item = match item.strip_prefix("- ").or(item.strip_prefix("* ")).or(item.strip_prefix("+ ")) {
+        Some(item) => item,
+        None => {
+            // Ordered lists: `1. [ ]` or `1) [ ]`
+            let digits = item.chars().take_while(|c| c.is_ascii_digit()).count();
+            let after = &item[digits..];
+            if digits == 0 {
+                return None;
+            }
+            after.strip_prefix(". ").or(after.strip_prefix(") "))?
+        }
+    };
+    match item.get(..3) {
+        Some("[ ]") => Some(false),
+        Some("[x]") | Some("[X]") => Some(true),
+        _ => None,
+    }
+    .filter(|_| item.len() == 3 || item[3..].starts_with(' '))
+}
+
+// Progress of every heading whose section has tasks, in the order of the note
+pub fn section_progress(text: &str) -> Vec<SectionProgress> {
+    let mut sections = Vec::new();
+    let mut open: Vec<(usize, SectionProgress)> = Vec::new(); // With the heading level
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+
+    for (line_number, line) in text.split('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(current) = fence {
+            // Nothing inside a code block counts
+            if trimmed.starts_with(current) {
+                fence = None;
+            }
+        } else if let Some(opening) = markdown::code_fence(trimmed) {
+            fence = Some(opening);
+        } else if let Some((level, _)) = markdown::heading(trimmed) {
+            while open.last().is_some_and(|(open_level, _)| *open_level >= level) {
+                sections.extend(open.pop().map(|(_, section)| section));
+            }
+            open.push((level, SectionProgress { line: line_number, offset, done: 0, total: 0 }));
+        } else if let Some(done) = task_state(line) {
+            for (_, section) in open.iter_mut() {
+                section.total += 1;
+                section.done += done as usize;
+            }
+        }
+        offset += line.len() + 1;
+    }
+    sections.extend(open.into_iter().map(|(_, section)| section));
+
+    sections.retain(|section| section.total > 0);
+    sections.sort_by_key(|section| section.line);
+    sections
+}
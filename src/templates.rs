@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::Path;
+
+// Templates are plain notes in this folder inside the notes directory
+pub const TEMPLATES_DIR: &str = "templates";
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    pub content: String,
+}
+
+// Values substituted for `{{title}}`, `{{date}}`, `{{time}}` and `{{datetime}}`,
+// plus any extra `{{name}}` values an importer provides
+#[derive(Debug, Clone)]
+pub struct TemplateVars {
+    pub title: String,
+    pub date: String,
+    pub time: String,
+    pub extra: Vec<(String, String)>,
+}
+
+impl TemplateVars {
+    pub fn now(title: &str) -> Self {
+        let now = chrono::Local::now();
+        Self {
+            title: title.to_string(),
+            date: now.format("%Y-%m-%d").to_string(),
+            time: now.format("%H:%M").to_string(),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.extra.push((name.to_string(), value.into()));
+        self
+    }
+
+    // Placeholder values used when previewing a template
+    pub fn sample() -> Self {
+        Self::now("Sample note")
+    }
+}
+
+pub fn load_templates(notes_dir: &Path) -> Vec<Template> {
+    let mut templates = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(notes_dir.join(TEMPLATES_DIR)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_note = matches!(path.extension().and_then(|e| e.to_str()), Some("md") | Some("txt"));
+            if !path.is_file() || !is_note {
+                continue;
+            }
+            if let (Some(name), Ok(content)) = (path.file_stem().and_then(|s| s.to_str()), fs::read_to_string(&path)) {
+                templates.push(Template {
+                    name: name.to_string(),
+                    content,
+                });
+            }
+        }
+    }
+
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+// Fill in template variables; unknown `{{names}}` are left untouched
+pub fn render(content: &str, vars: &TemplateVars) -> String {
+    let mut rendered = content
+        .replace("{{title}}", &vars.title)
+        .replace("{{date}}", &vars.date)
+        .replace("{{time}}", &vars.time)
+        .replace("{{datetime}}", &format!("{} {}", vars.date, vars.time));
+    for (name, value) in &vars.extra {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    rendered
+}
+
+// Load a template by name, or fall back to the given built-in content
+pub fn template_or_default(notes_dir: &Path, name: &str, default: &str) -> String {
+    ["md", "txt"]
+        .iter()
+        .find_map(|ext| fs::read_to_string(notes_dir.join(TEMPLATES_DIR).join(format!("{}.{}", name, ext))).ok())
+        .unwrap_or_else(|| default.to_string())
+}
+
+// Lowercase file-name friendly version of a title
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// State of the template picker: the loaded templates, a filter and the selection
+pub struct TemplatePicker {
+    pub templates: Vec<Template>,
+    pub filter: String,
+    pub editing_filter: bool,
+    pub selected: usize,
+}
+
+impl TemplatePicker {
+    pub fn new(templates: Vec<Template>) -> Self {
+        Self {
+            templates,
+            filter: String::new(),
+            editing_filter: false,
+            selected: 0,
+        }
+    }
+
+    // Templates whose name contains the filter text (case-insensitive)
+    pub fn filtered(&self) -> Vec<&Template> {
+        let filter = self.filter.to_lowercase();
+        self.templates
+            .iter()
+            .filter(|t| t.name.to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let count = self.filtered().len();
+        if count == 0 {
+            self.selected = 0;
+            return;
+        }
+        let selected = self.selected as isize + delta;
+        self.selected = selected.clamp(0, count as isize - 1) as usize;
+    }
+
+    pub fn selected_template(&self) -> Option<&Template> {
+        self.filtered().get(self.selected).copied()
+    }
+}
+
+// A double-quoted YAML scalar, for frontmatter values that come from outside
+// the app, like a clipped page's title or an email's sender
+pub fn yaml_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// The text of a `yaml_string`; other values are only trimmed of their quotes
+pub fn yaml_unquote(value: &str) -> String {
+    let value = value.trim();
+    let inner = match value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return value.trim_matches('\'').to_string(),
+    };
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                text.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+            },
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Deleted notes are moved to .vimnote/trash/ instead of being removed, so
+// they can still be found with `in:trash` searches and opened again.
+
+pub const TRASH_DIR: &str = "trash";
+
+// Move a note into `trash_dir`, keeping its folders. A note trashed earlier
+// under the same name is kept; the new one gets a -2, -3... suffix. It's
+// touched on the way, so `trash_days` counts from when it was deleted.
+// Returns where it went.
+pub fn move_to_trash(notes_dir: &Path, trash_dir: &Path, file_name: &str) -> io::Result<PathBuf> {
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (file_name, String::new()),
+    };
+    let mut target = trash_dir.join(file_name);
+    let mut counter = 2;
+    while target.exists() {
+        target = trash_dir.join(format!("{}-{}{}", stem, counter, extension));
+        counter += 1;
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(notes_dir.join(file_name), &target)?;
+    fs::File::options().write(true).open(&target)?.set_modified(SystemTime::now())?;
+    Ok(target)
+}
+
+// `path` inside `trash_dir`, with forward slashes like note names
+pub fn relative_path(trash_dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(trash_dir).unwrap_or(path);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+// Put `trashed_as` (a path inside `trash_dir`) back as the note `file_name`,
+// unless a note of that name was made since
+pub fn restore(notes_dir: &Path, trash_dir: &Path, trashed_as: &str, file_name: &str) -> io::Result<()> {
+    let target = notes_dir.join(file_name);
+    if target.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", file_name)));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(trash_dir.join(trashed_as), target)
+}
@@ -0,0 +1,199 @@
+// Tutorials as notes in the vault that are edited to learn. The vim basics
+// note from the first-run setup is read and tried out; the tutor note from
+// `:tutor` has lessons like vimtutor's, each with `→` lines to fix, and the
+// app checks the note as it's edited to tell when a lesson is done.
+
+pub const BASICS_NOTE: &str = "Vim basics.md";
+
+pub const BASICS: &str = "# Vim basics
+
+Two minutes of the keys you'll use all the time. This is a normal note: try
+everything right here, and delete it when you're done.
+
+VimNote starts in normal mode, where keys are commands instead of text. The
+mode is shown at the bottom. Esc always brings you back to normal mode.
+
+## 1. Moving around
+
+h j k l move left, down, up and right. w jumps to the next word and b back,
+0 and $ go to the start and end of the line, gg and G to the top and bottom.
+
+Move to the x at the end of this line: ..........................x
+
+## 2. Typing
+
+i starts typing before the cursor and a after it; A types at the end of the
+line and o on a new line below. Esc stops typing.
+
+Finish this line with a period, using A
+
+## 3. Deleting
+
+x deletes the character under the cursor, dw a word and dd the whole line.
+
+Delete the doubled word: the the quick brown fox
+Delete this whole line with dd
+
+## 4. Undo
+
+u undoes the last change and Ctrl+R redoes it. Delete a word above, then
+bring it back with u.
+
+## 5. Copy and paste
+
+yy copies a line and p pastes it below; dd then p moves a line.
+
+Copy this line and paste it under itself
+
+## 6. Searching and commands
+
+/ searches: type a word, Enter, then n for the next match and N for the one
+before. : starts a command, like :w to save (with autosave on, notes also
+save a few seconds after you stop typing) or :q to go back to the notes list.
+
+Search for the word fox
+
+That's it. Alt+N starts a new note of your own, and :tutor has lessons
+that check themselves as you do them.
+";
+
+pub const TUTOR_NOTE: &str = "Vim tutor.md";
+
+struct Lesson {
+    title: &'static str,
+    text: &'static str,
+    // Whether the note shows the lesson was done
+    done: fn(&str) -> bool,
+}
+
+const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Deleting characters",
+        text: "Put the cursor on an extra letter and press x to delete it. Fix the line:
+
+→ Thhe ccow jumpedd ovver the moon.",
+        done: |note| note.contains("→ The cow jumped over the moon."),
+    },
+    Lesson {
+        title: "Inserting text",
+        text: "Move to where text is missing, press i, type it, and press Esc. Make the
+line read \"There is some text missing from this line.\"
+
+→ There is text misng this .",
+        done: |note| note.contains("→ There is some text missing from this line."),
+    },
+    Lesson {
+        title: "Appending",
+        text: "A types at the end of the line, wherever the cursor is. End the line with
+a period:
+
+→ This line needs a period at the end",
+        done: |note| note.contains("→ This line needs a period at the end."),
+    },
+    Lesson {
+        title: "Deleting words",
+        text: "With the cursor at the start of a word, dw deletes it. Delete the words
+that don't belong:
+
+→ There are a some words fun that don't belong paper in this sentence.",
+        done: |note| note.contains("→ There are some words that don't belong in this sentence."),
+    },
+    Lesson {
+        title: "Deleting lines",
+        text: "dd deletes the whole line. Delete the line that doesn't belong:
+
+→ Roses are red,
+→ Mud is fun,
+→ Violets are blue,",
+        done: |note| note.contains("→ Roses are red,\n→ Violets are blue,") && !note.contains("→ Mud is fun,"),
+    },
+    Lesson {
+        title: "Putting",
+        text: "A deleted line can be put back with p, below the cursor. Put the lines
+in order: dd the first one, move to the last, and p.
+
+→ c) The third line
+→ a) The first line
+→ b) The second line",
+        done: |note| note.contains("→ a) The first line\n→ b) The second line\n→ c) The third line"),
+    },
+    Lesson {
+        title: "Changing words",
+        text: "cw deletes the rest of the word and starts typing in its place. Fix the
+misspelled words:
+
+→ This lubw has a few wptfd that mrrf changing.",
+        done: |note| note.contains("→ This line has a few words that need changing."),
+    },
+    Lesson {
+        title: "Opening lines",
+        text: "o opens a new line below the cursor and starts typing on it. Add a line
+with anything on it under this one:
+
+→ Open a line under me.",
+        done: |note| {
+            let mut lines = note.lines().skip_while(|line| *line != "→ Open a line under me.");
+            lines.next().is_some() && lines.next().is_some_and(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        },
+    },
+    Lesson {
+        title: "Substituting",
+        text: "On the line, type :s/thee/the/g and Enter to replace every thee with the.
+
+→ thee best time to see thee flowers is in thee spring.",
+        done: |note| note.contains("→ the best time to see the flowers is in the spring."),
+    },
+];
+
+// The tutor note as it starts out
+pub fn tutor_note() -> String {
+    let mut note = String::from(
+        "# Vim tutor
+
+Lessons on editing with vim keys, right in this note. Each has lines marked
+→ to fix; the status line says when a lesson is done. Esc gets you back to
+normal mode, u undoes a mistake, and :tutor reset starts over.
+",
+    );
+    for (i, lesson) in LESSONS.iter().enumerate() {
+        note.push_str(&format!("\n## Lesson {}: {}\n\n{}\n", i + 1, lesson.title, lesson.text));
+    }
+    note
+}
+
+// Which lessons of the tutor note are done, as it's edited
+#[derive(Debug)]
+pub struct Progress {
+    done: Vec<bool>,
+}
+
+impl Progress {
+    pub fn new(note: &str) -> Self {
+        Self { done: LESSONS.iter().map(|lesson| (lesson.done)(note)).collect() }
+    }
+
+    // Check the note again; returns the lessons done since the last check
+    pub fn update(&mut self, note: &str) -> Vec<usize> {
+        let mut finished = Vec::new();
+        for (i, lesson) in LESSONS.iter().enumerate() {
+            let done = (lesson.done)(note);
+            if done && !self.done[i] {
+                finished.push(i);
+            }
+            self.done[i] = done;
+        }
+        finished
+    }
+
+    pub fn done_count(&self) -> usize {
+        self.done.iter().filter(|done| **done).count()
+    }
+
+    pub fn total(&self) -> usize {
+        LESSONS.len()
+    }
+
+    pub fn title(&self, lesson: usize) -> &'static str {
+        LESSONS[lesson].title
+    }
+}
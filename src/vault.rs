@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Whether the notes folder is there to save to. A vault on a network share
+// or an external drive can go away while the app runs: then the folder can't
+// be read, or it's the empty folder the share was mounted on, where a save
+// would quietly make a new note. Saving waits while it's gone.
+
+// How often the folder is looked at
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// `has_notes`: whether notes were found in the folder, so an empty one means
+// it's not mounted
+pub fn is_available(notes_dir: &Path, has_notes: bool) -> bool {
+    match fs::read_dir(notes_dir) {
+        Ok(mut entries) => !has_notes || entries.next().is_some(),
+        Err(_) => false,
+    }
+}
+
+pub struct VaultWatch {
+    available: bool,
+    last_check: Instant,
+}
+
+impl VaultWatch {
+    pub fn new() -> Self {
+        Self { available: true, last_check: Instant::now() }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.available
+    }
+
+    // Look at the folder again if it's been CHECK_INTERVAL, or right away
+    // with `now`. Returns whether it's available when that changed.
+    pub fn check(&mut self, notes_dir: &Path, has_notes: bool, now: bool) -> Option<bool> {
+        if !now && self.last_check.elapsed() < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        let available = is_available(notes_dir, has_notes);
+        if available == self.available {
+            return None;
+        }
+        self.available = available;
+        Some(available)
+    }
+}
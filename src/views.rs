@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Where you were in a note and the marks a-z you set in it, kept in a sidecar
+// file so they're still there after a restart, like vim's views. The
+// `viewoptions` setting picks which of them are kept. Bookmarks don't need
+// this: they're always saved, in bookmarks.json. The global marks A-Z are
+// kept together in one file, like vim keeps them in its viminfo. There are no
+// folds to keep, since the editor doesn't fold.
+
+// What's kept of a note's view: `:set viewoptions=cursor,marks`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewOptions {
+    pub cursor: bool,
+    pub marks: bool,
+}
+
+impl ViewOptions {
+    // A comma-separated list like vim's; empty keeps nothing
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut options = Self { cursor: false, marks: false };
+        for item in value.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match item {
+                "cursor" => options.cursor = true,
+                "marks" => options.marks = true,
+                _ => return Err(format!("Invalid argument: viewoptions={}", value)),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn any(&self) -> bool {
+        self.cursor || self.marks
+    }
+}
+
+impl fmt::Display for ViewOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let items: Vec<&str> = [(self.cursor, "cursor"), (self.marks, "marks")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteView {
+    pub cursor: Option<usize>,    // Byte offset in the note
+    pub marks: Vec<(char, usize)>, // Local marks a-z and their byte offsets
+}
+
+impl NoteView {
+    // The view saved for a note; nothing if there's none or it can't be read
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Invalid view file {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+// Where a global mark A-Z is: its note, and the byte offset in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalMark {
+    pub note: String,
+    pub pos: usize,
+}
+
+pub fn load_global_marks(path: &Path) -> BTreeMap<char, GlobalMark> {
+    match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+            println!("Invalid global marks file {}: {}", path.display(), err);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+pub fn save_global_marks(path: &Path, marks: &BTreeMap<char, GlobalMark>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(marks).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
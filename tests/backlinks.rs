@@ -0,0 +1,53 @@
+use minimalist_notes::links::{note_links, with_backlinks_section};
+
+// The generated backlinks section sits between marker comments, and saving
+// only rewrites what's between them.
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn generated_section_is_added_replaced_and_removed() {
+    let added = with_backlinks_section("# Plan\ntext\n", &names(&["a"]));
+    assert_eq!(added, "# Plan\ntext\n\n<!-- backlinks -->\n## Backlinks\n\n- [[a]]\n<!-- /backlinks -->\n");
+    assert_eq!(with_backlinks_section(&added, &names(&["a"])), added);
+
+    let replaced = with_backlinks_section(&added, &names(&["a", "b"]));
+    assert_eq!(replaced, "# Plan\ntext\n\n<!-- backlinks -->\n## Backlinks\n\n- [[a]]\n- [[b]]\n<!-- /backlinks -->\n");
+    // Backlinks aren't links of the note itself
+    assert!(note_links(&replaced).is_empty());
+
+    assert_eq!(with_backlinks_section(&replaced, &[]), "# Plan\ntext\n");
+}
+
+#[test]
+fn section_written_by_hand_is_left_alone() {
+    let note = "# Plan\n\n## Backlinks\n\nWhy [[b]] points here.\n";
+    assert_eq!(with_backlinks_section(note, &[]), note);
+    assert_eq!(
+        with_backlinks_section(note, &names(&["a"])),
+        format!("{}\n<!-- backlinks -->\n## Backlinks\n\n- [[a]]\n<!-- /backlinks -->\n", note)
+    );
+    assert_eq!(note_links(note), names(&["b"]));
+}
+
+#[test]
+fn only_text_between_the_markers_is_rewritten() {
+    let note = "# Plan\n<!-- backlinks -->\n## Backlinks\n\n- [[a]]\n<!-- /backlinks -->\n\n## Later\nmore\n";
+    assert_eq!(
+        with_backlinks_section(note, &names(&["c"])),
+        "# Plan\n<!-- backlinks -->\n## Backlinks\n\n- [[c]]\n<!-- /backlinks -->\n\n## Later\nmore\n"
+    );
+    assert_eq!(with_backlinks_section(note, &[]), "# Plan\n\n## Later\nmore\n");
+}
+
+#[test]
+fn section_from_before_the_markers_gets_them() {
+    let note = "# Plan\n\n## Backlinks\n\n- [[a]]\n";
+    assert_eq!(
+        with_backlinks_section(note, &names(&["a"])),
+        "# Plan\n\n<!-- backlinks -->\n## Backlinks\n\n- [[a]]\n<!-- /backlinks -->\n"
+    );
+    assert_eq!(with_backlinks_section(note, &[]), "# Plan\n");
+}
@@ -0,0 +1,23 @@
+use minimalist_notes::conflicts::{Hunk, Merge, Pick};
+
+// Applying a merge writes the merged text over the note, so it must only be
+// applied to the texts it was made from; the app makes it again otherwise.
+
+#[test]
+fn merge_knows_the_texts_it_was_made_from() {
+    let ours = "# Plan\none\ntwo\n";
+    let theirs = "# Plan\none\n2\n";
+    let mut merge = Merge::new("plan.md".to_string(), "plan.sync-conflict-1.md".to_string(), ours, theirs);
+    assert_eq!(merge.changed_count(), 1);
+    assert!(merge.is_of(ours, theirs));
+
+    // Picking sides doesn't change what it was made from
+    let changed = merge.hunks.iter().position(|hunk| matches!(hunk, Hunk::Changed { .. })).unwrap();
+    merge.picks[changed] = Pick::Both;
+    assert_eq!(merge.merged(), "# Plan\none\ntwo\n2\n");
+    assert!(merge.is_of(ours, theirs));
+
+    // An edit to the open note after the merge was made
+    assert!(!merge.is_of("# Plan\none\ntwo\nthree\n", theirs));
+    assert!(!merge.is_of(ours, "# Plan\n"));
+}
@@ -0,0 +1,161 @@
+use eframe::egui;
+use minimalist_notes::editor::{Change, SimpleEditor};
+use minimalist_notes::modes::VimMode;
+use proptest::prelude::*;
+
+// Random key sequences fed to the editor the way the app routes them: no
+// panics, and the cursor always stays on a character boundary within the text.
+// A new command's keys, and the characters its motions or text objects look
+// for in a note, go in KEYS/CHARS and NOTE so that it gets fuzzed too.
+
+// What generated notes are made of: words, indentation, brackets and quotes
+const NOTE: &str = "[a-zäö #\\-\n\t()\\[\\]{}<>\"'.,`]{0,200}";
+
+// Keys and the text egui sends after them, if any
+const KEYS: &[(egui::Key, Option<char>)] = &[
+    (egui::Key::A, Some('a')), (egui::Key::B, Some('b')), (egui::Key::C, Some('c')), (egui::Key::D, Some('d')),
+    (egui::Key::E, Some('e')), (egui::Key::F, Some('f')), (egui::Key::G, Some('g')), (egui::Key::H, Some('h')),
+    (egui::Key::I, Some('i')), (egui::Key::J, Some('j')), (egui::Key::K, Some('k')), (egui::Key::L, Some('l')),
+    (egui::Key::M, Some('m')), (egui::Key::N, Some('n')), (egui::Key::O, Some('o')), (egui::Key::P, Some('p')),
+    (egui::Key::Q, Some('q')), (egui::Key::R, Some('r')), (egui::Key::S, Some('s')), (egui::Key::T, Some('t')),
+    (egui::Key::U, Some('u')), (egui::Key::V, Some('v')), (egui::Key::W, Some('w')), (egui::Key::X, Some('x')),
+    (egui::Key::Y, Some('y')), (egui::Key::Z, Some('z')),
+    (egui::Key::Num0, Some('0')), (egui::Key::Num1, Some('1')), (egui::Key::Num2, Some('2')), (egui::Key::Num3, Some('3')),
+    (egui::Key::Num4, Some('4')), (egui::Key::Num5, Some('5')), (egui::Key::Num6, Some('6')), (egui::Key::Num7, Some('7')),
+    (egui::Key::Num8, Some('8')), (egui::Key::Num9, Some('9')), (egui::Key::Space, Some(' ')), (egui::Key::Minus, Some('-')),
+    (egui::Key::PlusEquals, Some('=')),
+    (egui::Key::Escape, None), (egui::Key::Enter, None), (egui::Key::Backspace, None), (egui::Key::Delete, None),
+    (egui::Key::ArrowLeft, None), (egui::Key::ArrowRight, None), (egui::Key::ArrowUp, None), (egui::Key::ArrowDown, None),
+    (egui::Key::Home, None), (egui::Key::End, None), (egui::Key::Tab, None),
+];
+
+// Text that has no key of its own. `+` and `*` are left out: they're the
+// system clipboard registers.
+const CHARS: &[char] = &[
+    '/', '?', ';', ',', '.', '\'', '`', '"', '@', ':', '$', '#', '=', '%', '^', '_', '~', '(', ')', '<', '>', '[', ']',
+    '{', '}', 'ä', '😀', '\n', '\t',
+];
+
+const MAX_MACRO_EVENTS: usize = 1000;
+
+#[derive(Debug, Clone)]
+enum Input {
+    Key { key: egui::Key, text: Option<char>, shift: bool, ctrl: bool },
+    Text(char),
+}
+
+fn input() -> impl Strategy<Value = Input> {
+    prop_oneof![
+        4 => (prop::sample::select(KEYS), any::<bool>(), prop::bool::weighted(0.1)).prop_map(|((key, text), shift, ctrl)| {
+            let text = text.map(|c| if shift { c.to_ascii_uppercase() } else { c });
+            Input::Key { key, text, shift, ctrl }
+        }),
+        1 => prop::sample::select(CHARS).prop_map(Input::Text),
+    ]
+}
+
+// One frame's worth of input, routed like NotesApp::update does
+fn apply(editor: &mut SimpleEditor, text: &mut String, input: &Input) {
+    let normal_text = matches!(editor.vim_mode, VimMode::Normal | VimMode::Visual | VimMode::VisualBlock);
+    let mut typed = None;
+    match *input {
+        Input::Key { key, text: key_text, shift, ctrl } => {
+            let modifiers = egui::Modifiers { shift, ctrl, command: ctrl, ..Default::default() };
+            let was_insert = editor.vim_mode == VimMode::Insert;
+            editor.handle_key_press(key, text, &modifiers);
+            // The app skips the text of the key that entered insert mode
+            if !ctrl && (editor.vim_mode != VimMode::Insert || was_insert) {
+                typed = key_text;
+            }
+        },
+        Input::Text(c) => typed = Some(c),
+    }
+    if let Some(c) = typed {
+        if normal_text {
+            editor.handle_normal_text(c, text);
+        } else if matches!(editor.vim_mode, VimMode::Insert | VimMode::Replace | VimMode::Command) {
+            editor.handle_text_input(c, text);
+        }
+    }
+}
+
+fn check_invariants(editor: &SimpleEditor, text: &str) -> Result<(), TestCaseError> {
+    prop_assert!(editor.cursor_position <= text.len(), "cursor {} past the end ({})", editor.cursor_position, text.len());
+    prop_assert!(text.is_char_boundary(editor.cursor_position), "cursor {} inside a character", editor.cursor_position);
+    prop_assert!(editor.cursor_line <= text.matches('\n').count(), "cursor line {} past the last line", editor.cursor_line);
+    if let Some((start, end)) = editor.visual_range(text) {
+        prop_assert!(start <= end && end <= text.len(), "selection {}..{} out of bounds", start, end);
+    }
+    Ok(())
+}
+
+// Feed the inputs to the editor, checking `check` after each one
+fn run(
+    editor: &mut SimpleEditor,
+    text: &mut String,
+    inputs: &[Input],
+    mut check: impl FnMut(&SimpleEditor, &str) -> Result<(), TestCaseError>,
+) -> Result<(), TestCaseError> {
+    for input in inputs {
+        apply(editor, text, input);
+        check(editor, text)?;
+
+        // Macros played with @ come back as events, like in the app
+        let mut played = 0;
+        while let Some(event) = editor.next_macro_event() {
+            played += 1;
+            if played > MAX_MACRO_EVENTS {
+                break;
+            }
+            let input = match event {
+                egui::Event::Key { key, modifiers, .. } => Input::Key { key, text: None, shift: modifiers.shift, ctrl: modifiers.ctrl },
+                egui::Event::Text(typed) => match typed.chars().next() {
+                    Some(c) => Input::Text(c),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            apply(editor, text, &input);
+            check(editor, text)?;
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(500))]
+
+    #[test]
+    fn random_keys_keep_the_cursor_in_bounds(
+        note in NOTE,
+        inputs in prop::collection::vec(input(), 0..200),
+    ) {
+        let mut editor = SimpleEditor::new();
+        let mut text = note;
+        editor.reset_history(&text);
+        run(&mut editor, &mut text, &inputs, check_invariants)?;
+    }
+
+    // Applying the edits the editor reports to a copy of the note gives the same text
+    #[test]
+    fn published_edits_follow_the_text(
+        note in NOTE,
+        inputs in prop::collection::vec(input(), 0..200),
+    ) {
+        let mut editor = SimpleEditor::new();
+        let changes = editor.changes.subscribe();
+        let mut text = note.clone();
+        let mut copy = note;
+        editor.reset_history(&text);
+        run(&mut editor, &mut text, &inputs, |_, text| {
+            for change in changes.try_iter() {
+                if let Change::Edited { start, old_end, new_end } = change {
+                    prop_assert!(old_end <= copy.len() && new_end <= text.len(), "edit {}..{} -> {} out of bounds", start, old_end, new_end);
+                    copy.replace_range(start..old_end, &text[start..new_end]);
+                }
+            }
+            prop_assert_eq!(&copy, text);
+            Ok(())
+        })?;
+    }
+}
@@ -0,0 +1,222 @@
+use minimalist_notes::editor::Script;
+use minimalist_notes::modes::VimMode;
+
+// Keys typed into the editor and the note they leave, with a `|` at the
+// cursor. The keys are in vim's key notation, see editor::Script. A new vim
+// command gets a few lines in the table it belongs to.
+
+// Each case: the note, the keys, and the note after them
+fn check(cases: &[(&str, &str, &str)]) {
+    for (text, keys, expected) in cases {
+        let mut script = Script::new(text);
+        script.keys(keys);
+        assert_eq!(script.state(), *expected, "typing {:?} on {:?}", keys, text);
+    }
+}
+
+#[test]
+fn motions() {
+    check(&[
+        ("a\n  b\nc\n  d", "G", "a\n  b\nc\n  |d"),
+        ("  a\nb\nc", "jjlgg", "  |a\nb\nc"),
+        ("a\n  b\nc", "2G", "a\n  |b\nc"),
+        ("a\nb", "9G", "a\n|b"),
+        ("abcxdx", "fx", "abc|xdx"),
+        ("abcxdx", "2fx", "abcxd|x"),
+        ("abcxdx", "fx;,", "abc|xdx"),
+        ("abcxdx", "tx", "ab|cxdx"),
+        ("xaxbc", "$Fx", "xa|xbc"),
+        ("a$b", "f$", "a|$b"),
+        ("foo.bar baz", "w", "foo|.bar baz"),
+        ("foo.bar baz", "W", "foo.bar |baz"),
+        ("a b c", "2w", "a b |c"),
+        ("foo.bar baz", "Wb", "foo.|bar baz"),
+        ("foo.bar baz", "e", "fo|o.bar baz"),
+        ("foo.bar baz", "E", "foo.ba|r baz"),
+        ("ab. cd", "$0", "|ab. cd"),
+        ("a. b. c. d", "3)", "a. b. c. |d"),
+        ("a\nb\n  c", "<C-f>", "a\nb\n  |c"),
+        ("ab\ncd\nef", "l<C-d>", "ab\ncd\ne|f"),
+    ]);
+}
+
+#[test]
+fn operators() {
+    check(&[
+        ("one\ntwo\nthree", "jdd", "one\n|three"),
+        ("a b c d", "2dw", "|c d"),
+        ("abc.d", "dt.", "|.d"),
+        ("abc.d", "df.", "|d"),
+        ("f(a) b", "lcf)X<Esc>", "f|X b"),
+        ("xabc", "llldFx", "|c"),
+        ("one two", "cwxx<Esc>", "x|x two"),
+        ("abcdef", "lvlld", "a|ef"),
+        ("abc\ndef", "lvjd", "a|f"),
+        ("abcdef", "vllcX<Esc>", "|Xdef"),
+        ("ab\ncd\nef", "l<C-v>jjIX<Esc>", "a|Xb\ncXd\neXf"),
+        ("abc\ndef", "l<C-v>jld", "a|\nd"),
+        ("ab cd\nx", "wDjp", "ab \nxcd|"),
+        ("ab cd\nef\ngh", "w2D", "ab |\ngh"),
+        ("ab cd\nx", "wCxy<Esc>", "ab x|y\nx"),
+        ("a\n  two x\nb", "jwccnew<Esc>", "a\n  ne|w\nb"),
+        ("a\nb\nc\nd", "j2ccz<Esc>", "a\n|z\nd"),
+        ("  ab\nc", "lSz<Esc>", "  |z\nc"),
+        ("abcde", "l3x", "a|e"),
+        ("abc\nd", "l9x", "a|\nd"),
+        ("abc", "xp", "ba|c"),
+        ("abcd", "$h2X", "a|d"),
+        ("abcd", "2sX<Esc>", "|Xcd"),
+        ("abcde", "l3rx", "axx|xe"),
+        ("abc", "lr.", "a|.c"),
+        ("abc\nd", "l5rx", "a|bc\nd"),
+        ("abcd", "lRxy<Esc>", "ax|yd"),
+        ("ab\ncd", "lRxyz<Esc>", "axy|z\ncd"),
+        ("abcd", "lRxy<BS><BS>z<Esc>", "a|zcd"),
+        ("abcd", "3~", "ABC|d"),
+        ("a foo b", "wgUiw", "a |FOO b"),
+        ("Foo bar", "g~w", "|fOO bar"),
+        ("AB\nCD", "guu", "|ab\nCD"),
+        ("abc", "lgUl", "a|Bc"),
+        ("abc", "llgUh", "a|Bc"),
+        ("ab\ncd", "l3gUl", "a|B\ncd"),
+        ("abc def", "wgU0", "|ABC def"),
+        ("abc def", "lgU$", "a|BC DEF"),
+        ("ab\ncd\nef", "lgUj", "|AB\nCD\nef"),
+        ("ab\ncd\nef", "jjguk", "ab\n|cd\nef"),
+        ("Ab\ncD", "jg~k", "|aB\nCd"),
+        ("abc", "g~l", "|Abc"),
+        ("ab\ncd", "jdj", "ab\n|cd"),
+        ("ab\ncd\nef", "dj", "|ef"),
+        ("abc\nd", "ldl", "a|c\nd"),
+        ("ab\ncd", "ldl", "a|\ncd"),
+        ("abc", "lly0P", "ab|abc"),
+        ("a\nb\nc\nd", "jdG", "|a"),
+        ("a\nb\nc\nd", "jjdgg", "|d"),
+        ("a\nb\nc", "jjygg", "|a\nb\nc"),
+        ("a\nb\nc", "jyggGp", "a\nb\nc\na\nb|"),
+        ("a\nb\nc\nd", "2dG", "|c\nd"),
+        ("a\nb\nc", "jgUG", "a\n|B\nC"),
+        ("a\nb", "jdgx", "a\n|b"),
+        ("abc", "vlU", "|ABc"),
+        ("ab\n  cd", "J", "ab| cd"),
+        ("a\nb\nc\nd", "3J", "a b| c\nd"),
+        ("f(a\n)", "J", "f(a|)"),
+        ("ab\n  cd", "gJ", "ab|  cd"),
+        ("- a\n- b", "j>>", "- a\n  |- b"),
+        ("    - a", "<lt><lt>", "  |- a"),
+        ("a\nb\nc", "2>>", "  |a\n  b\nc"),
+        ("a\nb\n\nc", ">ip", "  |a\n  b\n\nc"),
+        ("a\nb\nc", "vj>", "  |a\n  b\nc"),
+        ("- a\n   - b", "j==", "- a\n  |- b"),
+        ("1. a\n  - b\n   c", "=ip", "|1. a\n   - b\n     c"),
+        ("# h\n  text", "vj=", "|# h\ntext"),
+        ("  - a", "ob<Esc>", "  - a\n  |b"),
+        ("  ab", "fbi<CR><Esc>", "  a\n | b"),
+    ]);
+}
+
+#[test]
+fn text_objects() {
+    check(&[
+        ("one two three", "wldiw", "one | three"),
+        ("one   two", "elldiw", "one|two"),
+        ("one two three", "wldaw", "one |three"),
+        ("one two three", "wwdaw", "one two|"),
+        ("one two", "cawxx <Esc>", "xx| two"),
+        ("a b c d", "2daw", "|c d"),
+        ("a b c d", "3diw", "| c d"),
+        ("x a.b/c y", "wldiW", "x | y"),
+        ("say \"hi there\" ok", "fhci\"x<Esc>", "say \"|x\" ok"),
+        ("a \"b\" c", "di\"", "a \"|\" c"),
+        ("x 'a b' y", "fada'", "x |y"),
+        ("\"a\\\"b\" c", "ldi\"", "\"|\" c"),
+        ("f(a, b) x", "fbdi(", "f(|) x"),
+        ("f(a, b) x", "fbda(", "f| x"),
+        ("f(a(b)c)", "fcci(x<Esc>", "f(|x)"),
+        ("f(a(b)c)", "fb2di(", "f(|)"),
+        ("x[1, [2]]", "f1di]", "x[|]"),
+        ("if x {\n    a\n    b\n}\nz", "jdi{", "if x {\n|}\nz"),
+        ("a\nb\n\nc\n\nd", "jdap", "|c\n\nd"),
+        ("a\nb\n\nc", "jdip", "|\nc"),
+        ("a\n\nb\nc", "Gdap", "|a"),
+        ("a\n\nb", "yapGP", "a\n\na\n\n|b"),
+        ("x\n\na\nb\n\ny", "jjcipz<Esc>", "x\n\n|z\n\ny"),
+        ("a\n\nb\n\nc", "2dap", "|c"),
+        ("a\n```\nx\ny\n```\nb", "jjdic", "a\n```\n|```\nb"),
+        ("a\n```rs\nx\n```\nb", "jjdac", "a\n|b"),
+        ("# A\na\n## B\nb\n# C\nc", "jjjdah", "# A\na\n|# C\nc"),
+        ("# A\na\n## B\nb\n# C\nc", "jjj2dah", "|# C\nc"),
+        ("# A\na\nb\n# C", "jcihz<Esc>", "# A\n|z\n# C"),
+        ("- one\n- two\n  - sub\n- three", "jdal", "- one\n|- three"),
+        ("- [ ] task one\n- x", "cilnew<Esc>", "- [ ] ne|w\n- x"),
+        ("1. a\n   more\n2. b", "jdil", "1. |\n2. b"),
+    ]);
+}
+
+#[test]
+fn undo_and_redo() {
+    check(&[
+        ("one\ntwo\nthree", "jddu", "one\n|two\nthree"),
+        ("one\ntwo\nthree", "jddu<C-r>", "one\n|three"),
+        ("ab", "ixyz<Esc>u", "|ab"),
+        ("ab", "ix<Esc>iy<Esc>u", "|xab"),
+        ("abcdef", "vlldu", "|abcdef"),
+    ]);
+}
+
+#[test]
+fn registers_and_macros() {
+    check(&[
+        ("one\ntwo", "\"ayyjyy\"ap", "one\ntwo\none|"),
+        ("one\ntwo\nx", "\"ayyj\"Ayyj\"aP", "one\ntwo\none\ntwo\n|x"),
+        ("a\nb\nc", "ddyy\"1p", "b\na\n|c"),
+        ("abcd", "qaxq@a", "|cd"),
+        ("abcdef", "qaxq2@a", "|def"),
+        ("abcde", "qaxq@a@@", "|de"),
+        ("a\nb\nc", "qaIX<Esc>jq@a", "Xa\nXb\n|c"),
+        ("abc", "qaix<lt>y<Esc>q\"ap", "x<yix<lt>y<Esc>|abc"),
+        ("1\n2\n3\n4\n5", "qaddq3@a", "|5"),
+    ]);
+}
+
+#[test]
+fn marks_and_changes() {
+    check(&[
+        ("  one\ntwo\nthree", "lllmajj'a", "  |one\ntwo\nthree"),
+        ("  one\ntwo\nthree", "lllmajj`a", "  o|ne\ntwo\nthree"),
+        ("one\ntwo\nthree", "majj'a''", "one\ntwo\n|three"),
+        ("one\ntwo", "jlmakOnew<Esc>`a", "new\none\nt|wo"),
+        ("one\ntwo\nthree", "xjjxkg;", "ne\ntwo\n|hree"),
+        ("one\ntwo\nthree", "xjjxkg;g;", "|ne\ntwo\nhree"),
+    ]);
+}
+
+#[test]
+fn search_and_substitute() {
+    check(&[
+        ("one two\nthree two", "/two<CR>", "one |two\nthree two"),
+        ("one two\nthree two", "/two<CR>nn", "one |two\nthree two"),
+        ("one two\nthree two", "jl?two<CR>", "one |two\nthree two"),
+        ("one two\nthree", "l/thr<Esc>", "o|ne two\nthree"),
+        ("foo foobar foo", "*", "foo foobar |foo"),
+        ("foo foobar foo", "ww#", "|foo foobar foo"),
+        ("a a\na a", ":s/a/b/g<CR>", "|b b\na a"),
+        ("a a\na a", ":%s/a/b<CR>", "b a\n|b a"),
+        ("bob@x", ":s/(\\w+)@/\\1 at /<CR>", "|bob at x"),
+        ("a\na\na", "jvj:s/a/b/<CR>", "a\nb\n|b"),
+    ]);
+}
+
+#[test]
+fn modes() {
+    let mut script = Script::new("one");
+    script.keys("A");
+    assert_eq!(script.editor.vim_mode, VimMode::Insert);
+    script.keys("!<Esc>");
+    assert_eq!(script.editor.vim_mode, VimMode::Normal);
+    assert_eq!(script.state(), "one|!");
+
+    // An operator waiting for its motion is cancelled by Escape
+    script.keys("d<Esc>0x");
+    assert_eq!(script.state(), "|ne!");
+}
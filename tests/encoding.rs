@@ -0,0 +1,30 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use minimalist_notes::encoding::{self, Encoding};
+
+// A note that isn't UTF-8 is decoded for the editor, and saving it must
+// leave its bytes alone until it's converted, whatever `:set noreadonly` says.
+
+#[test]
+fn decoded_note_is_not_written_back() {
+    let dir = env::temp_dir().join(format!("vimnote-encoding-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("old.txt");
+    let bytes = b"caf\xe9 \x93quoted\x94\n";
+    fs::write(&path, bytes).unwrap();
+
+    let (text, read_as) = encoding::decode(&fs::read(&path).unwrap());
+    assert_eq!(read_as, Encoding::Windows1252);
+    assert_eq!(text, "café “quoted”\n");
+
+    let edited = format!("{}more\n", text);
+    assert!(encoding::write_note(&path, &edited, Some(read_as)).is_err());
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+
+    // Once converted it's saved as UTF-8
+    encoding::write_note(&path, &edited, None).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+    fs::remove_dir_all(&dir).unwrap();
+}
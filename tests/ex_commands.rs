@@ -0,0 +1,54 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use minimalist_notes::editor::Script;
+
+// Golden file test of the ex commands: each command in
+// tests/golden/ex_commands.txt is typed on the same note, and what it does,
+// the action the editor gives the app and the note if it changed, has to
+// match tests/golden/ex_commands.golden. After changing what a command does
+// on purpose, write the new results with
+// `UPDATE_GOLDEN=1 cargo test --test ex_commands` and check the diff.
+
+const NOTE: &str = "# Title\none two\nthree four";
+
+// e.g. `:w => save` or `:s/one/1/ => - | "# Title\n|1 two\nthree four"`
+fn run(command: &str) -> String {
+    let mut script = Script::new(NOTE);
+    script.keys("j");
+    let before = script.state();
+    let action = script.command(command.strip_prefix(':').unwrap_or(command));
+    let mut result = format!("{} => {}", command, action.as_deref().unwrap_or("-"));
+    if script.state() != before {
+        result.push_str(&format!(" | {:?}", script.state()));
+    }
+    result
+}
+
+#[test]
+fn ex_commands_match_golden_file() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let commands = fs::read_to_string(dir.join("ex_commands.txt")).expect("reading ex_commands.txt");
+    let mut results = String::new();
+    for line in commands.lines() {
+        // Comments and blank lines are kept, to keep the two files alike
+        if line.starts_with(':') {
+            results.push_str(&run(line));
+        } else {
+            results.push_str(line);
+        }
+        results.push('\n');
+    }
+
+    let golden_path = dir.join("ex_commands.golden");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_path, &results).expect("writing ex_commands.golden");
+        return;
+    }
+    let golden = fs::read_to_string(&golden_path).expect("reading ex_commands.golden; write it with UPDATE_GOLDEN=1");
+    for (line, (expected, actual)) in golden.lines().zip(results.lines()).enumerate() {
+        assert_eq!(actual, expected, "ex_commands.golden line {} differs", line + 1);
+    }
+    assert_eq!(results.lines().count(), golden.lines().count(), "ex_commands.golden has a different number of lines");
+}
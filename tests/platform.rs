@@ -0,0 +1,35 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use minimalist_notes::platform;
+
+// gf hands files that aren't notes to the OS, which would run a program
+// instead of showing it, so those are kept from it.
+
+#[test]
+fn programs_and_scripts_are_executable() {
+    for name in ["setup.exe", "invoice.PDF.bat", "run.sh", "x.ps1", "App.app", "a.jar"] {
+        assert!(platform::is_executable(Path::new(name)), "{}", name);
+    }
+    for name in ["report.pdf", "photo.JPG", "notes.md", "data.csv"] {
+        assert!(!platform::is_executable(Path::new(name)), "{}", name);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn files_without_extension_go_by_their_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = env::temp_dir().join(format!("vimnote-platform-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("tool");
+    fs::write(&file, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(!platform::is_executable(&file));
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(platform::is_executable(&file));
+    fs::remove_dir_all(&dir).unwrap();
+}